@@ -12,7 +12,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut window = RenderWindow::new("Basic Triangle (Instanced)", 800, 600);
     
     // Register the shader with the render window
-    let shader_id = window.register_shader(shader_descriptor);
+    let shader_id = window.register_shader(shader_descriptor)?;
     
     // Create a camera for proper view and projection matrices
     let mut camera = Camera::perspective(800.0 / 600.0);
@@ -11,7 +11,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     
     // Create a render window and register the shader
     let mut window = RenderWindow::new("Spinning/Scaling Triangle (Instanced)", 800, 600);
-    let shader_id = window.register_shader(shader_descriptor);
+    let shader_id = window.register_shader(shader_descriptor)?;
       // Create and position camera
     let mut camera = Camera::perspective(800.0 / 600.0);
     camera.look_at_from(
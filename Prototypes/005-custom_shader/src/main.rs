@@ -35,7 +35,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     
     // Create a render window and register the shader
     let mut window = RenderWindow::new("Full-Screen Plasma Effect", 800, 600);
-    let shader_id = window.register_shader(shader_descriptor);
+    let shader_id = window.register_shader(shader_descriptor)?;
     
     window.with_frame_callback(move |canvas| {
         let time_value = canvas.time().as_secs_f32();
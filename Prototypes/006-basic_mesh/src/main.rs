@@ -11,7 +11,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Create a render window and register the standard shader
     let mut window = RenderWindow::new("006 - Basic Mesh (Instanced Cubes)", 1024, 768);
     let shader_descriptor = StandardShader::descriptor(); // Use StandardShader
-    let shader_id = window.register_shader(shader_descriptor);    // Create cube geometry using the GeometryFactory
+    let shader_id = window.register_shader(shader_descriptor)?;    // Create cube geometry using the GeometryFactory
     // Use StandardShaderVertex (re-exported in standard_geometry::prelude)
     let cube_geometry = GeometryFactory::cube(1.0, Vec3::ONE); // Create a 1x1x1 white cube
 
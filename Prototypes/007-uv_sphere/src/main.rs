@@ -9,7 +9,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Create a render window and register the standard shader
     let mut window = RenderWindow::new("007 - UV Sphere (Improved Caps & Orbiting Camera)", 1024, 768);
     let shader_descriptor = StandardShader::descriptor(); // Use StandardShader
-    let shader_id = window.register_shader(shader_descriptor);    // Create improved UV sphere geometry that properly handles poles
+    let shader_id = window.register_shader(shader_descriptor)?;    // Create improved UV sphere geometry that properly handles poles
     // We can customize the resolution with sectors (longitude) and stacks (latitude)
     let sphere_geometry = create_improved_uv_sphere(
         1.0,           // radius
@@ -11,7 +11,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     
     // Register standard shader
     let shader_descriptor = StandardShader::descriptor();
-    let shader_id = window.register_shader(shader_descriptor);
+    let shader_id = window.register_shader(shader_descriptor)?;
     
     // Create teapot mesh using the Bezier patch system (from standard-geometry)
     let teapot_geometry = create_utah_teapot(
@@ -6,8 +6,9 @@ use rustica_render::{
     RenderWindow, ShaderDescriptor, Vertex
 };
 use rustica_graphics::Camera;
+use rustica_foundation::geometry::BoundingSphere;
 
-use glam::{Mat4, Vec3};
+use glam::{Mat4, Vec2, Vec3};
 use rustica_standard_shader::{StandardShader, StandardShaderInstances};
 use rustica_standard_geometry::GeometryFactory;
 
@@ -35,7 +36,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut window = RenderWindow::new("Conway's Game of Life - Infinity Board", 800, 600);
     
     // Register the shader
-    let shader_id = window.register_shader(StandardShader::descriptor());
+    let shader_id = window.register_shader(StandardShader::descriptor())?;
       // Create a sphere mesh to reuse for all cells
     let sphere_mesh = GeometryFactory::uv_sphere(
         0.5,           // radius
@@ -44,6 +45,10 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         Vec3::ONE      // white color base (will be tinted by instance color)
     );
     
+    // Upload the sphere once so every frame only streams instance data
+    let sphere_mesh = window.upload_geometry(&sphere_mesh);
+    let sphere_bounds = BoundingSphere::new(Vec3::ZERO, 0.5);
+    
     
     // Add the life system to the world with wraparound enabled
     world.add_system(LifeSystem {
@@ -55,6 +60,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Set up simulation timing
     let simulation_interval = Duration::from_millis(200);
     let mut last_update = Instant::now();
+    let mut generation = 0u64;
     
     // Set up animation parameters
     let animation_duration = 0.5; // Half-second transitions
@@ -79,6 +85,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             // Run the simulation step (only LifeSystem)
             world.run_systems();
             last_update = now;
+            generation += 1;
         }
 
         // Run spawner system first
@@ -129,6 +136,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         let camera_matrices = camera.get_render_matrices();
         let view = camera_matrices.view;
         let projection = camera_matrices.projection;
+        let frustum = camera.frustum();
         
         
         // Collect instance data for all cells with visuals
@@ -188,8 +196,19 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                   .uniform("view", view)
                   .uniform("projection", projection)
                   .uniform("time", time)
-                  .pump_geometry(&sphere_mesh, &instances);
+                  .draw_mesh_culled(sphere_mesh, &instances, &frustum, |instance| {
+                      sphere_bounds.transformed(&Mat4::from_cols_array_2d(&instance.model_matrix))
+                  });
         }
+
+        // Show the simulation's progress and the frame rate in the top left corner
+        let population = world.query_one::<CellState>().iter().filter(|(_, state)| state.alive).count();
+        let frame_time = canvas.frame_stats().encode_time.as_secs_f32();
+        let fps = if delta_time > 0.0 { 1.0 / delta_time } else { 0.0 };
+        let hud = format!("Generation {}\nPopulation {}\n{:.0} fps ({:.2} ms encode)", generation, population, fps, frame_time * 1000.0);
+        let size = canvas.measure_text(18.0, &hud);
+        canvas.draw_2d().fill_rounded_rect(Vec2::splat(8.0), size + Vec2::splat(16.0), 6.0, [0.0, 0.0, 0.0, 0.5]);
+        canvas.text(Vec2::splat(16.0), 18.0, [1.0; 4], &hud);
     }).run()?;
     
     Ok(())
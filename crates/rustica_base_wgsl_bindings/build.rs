@@ -1,11 +1,21 @@
-use wgsl_to_wgpu::{create_shader_module, MatrixVectorTypes, WriteOptions};
+use rustica_wgsl_preprocessor::WgslPreprocessor;
+use wgsl_to_wgpu::{create_shader_module_embedded, MatrixVectorTypes, WriteOptions};
 
 
 fn main()  {
-    println!("cargo:rerun-if-changed=src/shader.wgsl");
+    println!("cargo:rerun-if-changed=src/shaders");
 
-    // Read the shader source file.
-    let wgsl_source = std::fs::read_to_string("src/shaders/phong.wgsl").unwrap();
+    // Read the shader source file and expand its includes (the lighting code
+    // is shared with the engine's other shaders).
+    let preprocessed = WgslPreprocessor::new().process_file("src/shaders/phong.wgsl").unwrap_or_else(|error| panic!("{}", error));
+    let wgsl_source = preprocessed.source;
+
+    // Rebuild when any expanded file changes, including included ones. Files
+    // from the engine's shader library aren't on disk; they change with the
+    // preprocessor crate, which already triggers a rebuild.
+    for file in preprocessed.source_map.files().iter().filter(|file| std::path::Path::new(file).exists()) {
+        println!("cargo:rerun-if-changed={}", file);
+    }
 
     // Configure the output based on the dependencies for the project.
     let options = WriteOptions {
@@ -15,8 +25,8 @@ fn main()  {
         ..Default::default()
     };
 
-    // Generate the bindings.
-    let text = create_shader_module(&wgsl_source, "shaders/phong.wgsl", options).unwrap();
+    // Generate the bindings, embedding the expanded source.
+    let text = create_shader_module_embedded(&wgsl_source, options).unwrap();
     std::fs::write("src/phong.rs", text.as_bytes()).unwrap();
 
 }
@@ -325,7 +325,7 @@ pub fn fs_main_entry(targets: [Option<wgpu::ColorTargetState>; 1]) -> FragmentEn
         constants: Default::default(),
     }
 }
-pub const SOURCE: &str = include_str!("shaders/phong.wgsl");
+pub const SOURCE: &str = "// Basic Phong Lighting Shader\n\n// Lighting functions shared by the engine's shaders\n// Include with: #include \"lighting.wgsl\"\n\n// Diffuse (Lambert) term of a light, with normalized vectors\nfn lambert(normal: vec3<f32>, light_dir: vec3<f32>) -> f32 {\n    return max(dot(normal, light_dir), 0.0);\n}\n\n// Specular (Phong) term of a light seen from view_dir, with normalized vectors\nfn phong_specular(normal: vec3<f32>, light_dir: vec3<f32>, view_dir: vec3<f32>, shininess: f32) -> f32 {\n    let reflect_dir = reflect(-light_dir, normal);\n    return pow(max(dot(view_dir, reflect_dir), 0.0), shininess);\n}\n\n// Vertex Input\nstruct VertexInput {\n    @location(0) position: vec3<f32>,\n    @location(1) normal: vec3<f32>,\n};\n\n// Vertex Output\nstruct VertexOutput {\n    @builtin(position) clip_position: vec4<f32>,\n    @location(0) world_position: vec3<f32>,\n    @location(1) world_normal: vec3<f32>,\n};\n\n// Uniforms\nstruct CameraUniform {\n    view_proj: mat4x4<f32>,\n    position: vec3<f32>,\n};\n@group(0) @binding(0)\nvar<uniform> camera: CameraUniform;\n\nstruct ModelUniform {\n    model: mat4x4<f32>,\n    normal_transform: mat3x3<f32>, // Inverse transpose of model matrix (upper 3x3)\n};\n@group(1) @binding(0)\nvar<uniform> model: ModelUniform;\n\nstruct LightUniform {\n    position: vec3<f32>,\n    color: vec3<f32>,\n};\n@group(2) @binding(0)\nvar<uniform> light: LightUniform;\n\nstruct MaterialUniform {\n    ambient: vec3<f32>,\n    diffuse: vec3<f32>,\n    specular: vec3<f32>,\n    shininess: f32,\n};\n@group(2) @binding(1)\nvar<uniform> material: MaterialUniform;\n\n\n// Vertex Shader\n@vertex\nfn vs_main(in: VertexInput) -> VertexOutput {\n    var out: VertexOutput;\n    let world_pos4 = model.model * vec4<f32>(in.position, 1.0);\n    out.world_position = world_pos4.xyz;\n    out.clip_position = camera.view_proj * world_pos4;\n    out.world_normal = normalize(model.normal_transform * in.normal);\n    return out;\n}\n\n// Fragment Shader\n@fragment\nfn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {\n    // Ambient\n    let ambient_color = material.ambient * light.color;\n\n    // Diffuse\n    let light_dir = normalize(light.position - in.world_position);\n    let diff = lambert(in.world_normal, light_dir);\n    let diffuse_color = material.diffuse * diff * light.color;\n\n    // Specular\n    let view_dir = normalize(camera.position - in.world_position);\n    let spec = phong_specular(in.world_normal, light_dir, view_dir, material.shininess);\n    let specular_color = material.specular * spec * light.color;\n\n    let result = ambient_color + diffuse_color + specular_color;\n    return vec4<f32>(result, 1.0);\n}\n";
 pub fn create_shader_module(device: &wgpu::Device) -> wgpu::ShaderModule {
     let source = std::borrow::Cow::Borrowed(SOURCE);
     device
@@ -1,5 +1,7 @@
 // Basic Phong Lighting Shader
 
+#include "lighting.wgsl"
+
 // Vertex Input
 struct VertexInput {
     @location(0) position: vec3<f32>,
@@ -64,13 +66,12 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
 
     // Diffuse
     let light_dir = normalize(light.position - in.world_position);
-    let diff = max(dot(in.world_normal, light_dir), 0.0);
+    let diff = lambert(in.world_normal, light_dir);
     let diffuse_color = material.diffuse * diff * light.color;
 
     // Specular
     let view_dir = normalize(camera.position - in.world_position);
-    let reflect_dir = reflect(-light_dir, in.world_normal);
-    let spec = pow(max(dot(view_dir, reflect_dir), 0.0), material.shininess);
+    let spec = phong_specular(in.world_normal, light_dir, view_dir, material.shininess);
     let specular_color = material.specular * spec * light.color;
 
     let result = ambient_color + diffuse_color + specular_color;
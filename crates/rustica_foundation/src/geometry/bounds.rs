@@ -0,0 +1,258 @@
+// Bounding volumes and view frustums for culling
+
+use glam::{Mat4, Vec3, Vec4, Vec4Swizzles};
+
+/// A sphere enclosing an object
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingSphere {
+    /// Center of the sphere
+    pub center: Vec3,
+    /// Radius of the sphere
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    /// Create a bounding sphere
+    pub fn new(center: Vec3, radius: f32) -> Self {
+        Self { center, radius }
+    }
+
+    /// Get the smallest sphere around the center of the points' bounding box that encloses them
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Option<Self> {
+        let points: Vec<Vec3> = points.into_iter().collect();
+        let center = Aabb::from_points(points.iter().copied())?.center();
+        let radius = points.iter().map(|point| point.distance(center)).fold(0.0, f32::max);
+        Some(Self { center, radius })
+    }
+
+    /// Get the sphere enclosing this one after a transform, e.g. an instance's model matrix
+    ///
+    /// The radius grows with the largest scale of the transform, so the result
+    /// stays conservative for non-uniform scales.
+    pub fn transformed(&self, transform: &Mat4) -> Self {
+        let scale = transform.x_axis.xyz().length()
+            .max(transform.y_axis.xyz().length())
+            .max(transform.z_axis.xyz().length());
+        Self {
+            center: transform.transform_point3(self.center),
+            radius: self.radius * scale,
+        }
+    }
+}
+
+/// An axis-aligned bounding box
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    /// Corner with the smallest coordinates
+    pub min: Vec3,
+    /// Corner with the largest coordinates
+    pub max: Vec3,
+}
+
+impl Aabb {
+    /// Create a box from its corners
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    /// Get the box enclosing the points, or None if there are none
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        Some(points.fold(Self::new(first, first), |aabb, point| Self::new(aabb.min.min(point), aabb.max.max(point))))
+    }
+
+    /// Get the center of the box
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    /// Get the box enclosing this one after a transform
+    pub fn transformed(&self, transform: &Mat4) -> Self {
+        let corners = (0..8).map(|corner| {
+            let select = |bit: usize, min: f32, max: f32| if corner & bit == 0 { min } else { max };
+            transform.transform_point3(Vec3::new(
+                select(1, self.min.x, self.max.x),
+                select(2, self.min.y, self.max.y),
+                select(4, self.min.z, self.max.z),
+            ))
+        });
+        Self::from_points(corners).unwrap_or(*self)
+    }
+}
+
+/// The bounds of an object, as a sphere or a box
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Bounds {
+    /// A bounding sphere
+    Sphere(BoundingSphere),
+    /// An axis-aligned bounding box
+    Aabb(Aabb),
+}
+
+impl From<BoundingSphere> for Bounds {
+    fn from(sphere: BoundingSphere) -> Self {
+        Bounds::Sphere(sphere)
+    }
+}
+
+impl From<Aabb> for Bounds {
+    fn from(aabb: Aabb) -> Self {
+        Bounds::Aabb(aabb)
+    }
+}
+
+/// The six planes bounding the volume a camera sees
+///
+/// Each plane is stored as `(normal, distance)` with the normal pointing
+/// inwards, so points inside the frustum have a non-negative signed distance
+/// to every plane.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frustum {
+    /// Left, right, bottom, top, near and far planes
+    pub planes: [Vec4; 6],
+}
+
+impl Frustum {
+    /// Extract the planes of a view-projection matrix
+    ///
+    /// The matrix must map the visible depth range to 0..1 like wgpu's clip
+    /// space (e.g. `Mat4::perspective_rh`), not to -1..1 like OpenGL's.
+    pub fn from_view_projection(view_projection: &Mat4) -> Self {
+        let [x, y, z, w] = [0, 1, 2, 3].map(|row| view_projection.row(row));
+        let planes = [w + x, w - x, w + y, w - y, z, w - z]
+            .map(|plane| plane / plane.xyz().length());
+        Self { planes }
+    }
+
+    /// Get the corners of the frustum: the near plane's, then the far plane's
+    ///
+    /// Each set goes bottom left, bottom right, top right, top left as seen
+    /// from the camera.
+    pub fn corners(&self) -> [Vec3; 8] {
+        let [left, right, bottom, top, near, far] = self.planes;
+        [near, far].map(|depth| {
+            [(left, bottom), (right, bottom), (right, top), (left, top)]
+                .map(|(side, height)| Self::intersection(side, height, depth))
+        }).concat().try_into().unwrap()
+    }
+
+    /// Get the point where three planes meet
+    fn intersection(a: Vec4, b: Vec4, c: Vec4) -> Vec3 {
+        let (na, nb, nc) = (a.xyz(), b.xyz(), c.xyz());
+        let denominator = na.dot(nb.cross(nc));
+        -(a.w * nb.cross(nc) + b.w * nc.cross(na) + c.w * na.cross(nb)) / denominator
+    }
+
+    /// Check if a point is inside the frustum
+    pub fn contains_point(&self, point: Vec3) -> bool {
+        self.planes.iter().all(|plane| plane.xyz().dot(point) + plane.w >= 0.0)
+    }
+
+    /// Check if any part of a sphere may be inside the frustum
+    ///
+    /// Spheres near the corners outside the frustum can pass the test; it never
+    /// rejects a visible sphere.
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes.iter().all(|plane| plane.xyz().dot(sphere.center) + plane.w >= -sphere.radius)
+    }
+
+    /// Check if any part of a box may be inside the frustum
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the plane's normal
+            let corner = Vec3::select(plane.xyz().cmpge(Vec3::ZERO), aabb.max, aabb.min);
+            plane.xyz().dot(corner) + plane.w >= 0.0
+        })
+    }
+
+    /// Check if any part of a sphere or box may be inside the frustum
+    pub fn intersects(&self, bounds: &Bounds) -> bool {
+        match bounds {
+            Bounds::Sphere(sphere) => self.intersects_sphere(sphere),
+            Bounds::Aabb(aabb) => self.intersects_aabb(aabb),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A camera at the origin looking down -Z with a 90 degree field of view
+    fn frustum() -> Frustum {
+        let projection = Mat4::perspective_rh(90f32.to_radians(), 1.0, 1.0, 10.0);
+        Frustum::from_view_projection(&(projection * Mat4::look_at_rh(Vec3::ZERO, Vec3::NEG_Z, Vec3::Y)))
+    }
+
+    #[test]
+    fn test_planes_are_normalized() {
+        let frustum = frustum();
+        for plane in frustum.planes {
+            assert!((plane.xyz().length() - 1.0).abs() < 1e-5, "{:?}", plane);
+        }
+
+        // With unit normals, the plane offsets are signed distances from the camera
+        let [_, _, _, _, near, far] = frustum.planes;
+        assert!((near.dot(Vec3::ZERO.extend(1.0)) + 1.0).abs() < 1e-5);
+        assert!((far.dot(Vec3::ZERO.extend(1.0)) - 10.0).abs() < 1e-4);
+        assert!((frustum.corners()[4] - Vec3::new(-10.0, -10.0, -10.0)).length() < 1e-3);
+    }
+
+    #[test]
+    fn test_contains_point() {
+        let frustum = frustum();
+        assert!(frustum.contains_point(Vec3::new(0.0, 0.0, -5.0)));
+        assert!(frustum.contains_point(Vec3::new(4.9, -4.9, -5.0)));
+        assert!(!frustum.contains_point(Vec3::new(5.1, 0.0, -5.0)));
+        assert!(!frustum.contains_point(Vec3::new(0.0, 0.0, -0.5)));
+        assert!(!frustum.contains_point(Vec3::new(0.0, 0.0, -10.5)));
+        assert!(!frustum.contains_point(Vec3::new(0.0, 0.0, 5.0)));
+    }
+
+    #[test]
+    fn test_intersects_aabb() {
+        let frustum = frustum();
+        let aabb = |min: [f32; 3], max: [f32; 3]| Aabb::new(Vec3::from_array(min), Vec3::from_array(max));
+
+        assert!(frustum.intersects_aabb(&aabb([-1.0, -1.0, -6.0], [1.0, 1.0, -4.0])));
+        // Only a corner pokes into the view
+        assert!(frustum.intersects_aabb(&aabb([4.5, 4.5, -6.0], [8.0, 8.0, -4.0])));
+        // A box around the whole frustum has no corners inside it but still intersects
+        assert!(frustum.intersects_aabb(&aabb([-20.0, -20.0, -20.0], [20.0, 20.0, 20.0])));
+        assert!(!frustum.intersects_aabb(&aabb([7.0, -1.0, -6.0], [9.0, 1.0, -4.0])));
+        assert!(!frustum.intersects_aabb(&aabb([-1.0, -1.0, 1.0], [1.0, 1.0, 3.0])));
+        assert!(frustum.intersects(&Bounds::from(aabb([-1.0, -1.0, -6.0], [1.0, 1.0, -4.0]))));
+        assert!(!frustum.intersects(&Bounds::from(BoundingSphere::new(Vec3::new(0.0, 0.0, 3.0), 1.0))));
+    }
+
+    #[test]
+    fn test_sphere_transformed_by_non_uniform_scale() {
+        let sphere = BoundingSphere::new(Vec3::new(1.0, 0.0, 0.0), 2.0);
+        let transform = Mat4::from_scale_rotation_translation(
+            Vec3::new(1.0, 3.0, 0.5),
+            glam::Quat::from_rotation_z(90f32.to_radians()),
+            Vec3::new(0.0, 0.0, 4.0),
+        );
+        let transformed = sphere.transformed(&transform);
+
+        // The radius takes the largest scale, so the sphere still encloses the stretched one
+        assert!((transformed.radius - 6.0).abs() < 1e-5);
+        assert!((transformed.center - Vec3::new(0.0, 1.0, 4.0)).length() < 1e-5);
+        let stretched_tip = transform.transform_point3(Vec3::new(1.0, 2.0, 0.0));
+        assert!(stretched_tip.distance(transformed.center) <= transformed.radius + 1e-5);
+    }
+
+    #[test]
+    fn test_from_points() {
+        assert_eq!(Aabb::from_points(std::iter::empty()), None);
+        assert_eq!(BoundingSphere::from_points(Vec::new()), None);
+
+        let points = [Vec3::new(-1.0, 0.0, 2.0), Vec3::new(3.0, -2.0, 0.0), Vec3::new(1.0, 4.0, 1.0)];
+        let aabb = Aabb::from_points(points).unwrap();
+        assert_eq!((aabb.min, aabb.max), (Vec3::new(-1.0, -2.0, 0.0), Vec3::new(3.0, 4.0, 2.0)));
+        let sphere = BoundingSphere::from_points(points).unwrap();
+        assert_eq!(sphere.center, aabb.center());
+        assert!(points.iter().all(|point| point.distance(sphere.center) <= sphere.radius));
+    }
+}
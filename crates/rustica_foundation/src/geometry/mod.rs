@@ -3,7 +3,9 @@
 
 pub mod traits;
 pub mod geometry_builder;
+pub mod bounds;
 
 // Re-export common types
 pub use traits::{Vertex, VertexAttributeProvider, VertexAttribute, Triangle};
 pub use geometry_builder::{Geometry, GeometryBuilder};
+pub use bounds::{Aabb, BoundingSphere, Bounds, Frustum};
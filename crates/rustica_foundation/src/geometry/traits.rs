@@ -29,6 +29,7 @@ pub trait VertexAttributeProvider {
 }
 
 /// Vertex attribute descriptor
+#[derive(Debug, Clone)]
 pub struct VertexAttribute {
     /// Name of the attribute
     pub name: String,
@@ -9,6 +9,11 @@ pub use crate::geometry::{
     Triangle,    
     // Geometry
     geometry_builder::Geometry,
+    // Bounds
+    Aabb,
+    BoundingSphere,
+    Bounds,
+    Frustum,
 };
 
 pub use crate::geometry::traits::VertexSemantic;
@@ -1,6 +1,7 @@
 // Camera primitive - Represents a camera in 3D space
 
 use glam::{Mat4, Vec3};
+use rustica_foundation::geometry::Frustum;
 
 /// A camera in 3D space
 pub struct Camera {
@@ -121,6 +122,16 @@ impl Camera {
     pub fn view_projection_matrix(&self) -> Mat4 {
         self.projection_matrix() * self.view_matrix()
     }
+
+    /// Get the frustum of the volume the camera sees, for culling
+    ///
+    /// The planes come from the field of view and the near and far distances
+    /// projected to wgpu's 0..1 depth range, so the frustum encloses everything
+    /// `projection_matrix` lets through.
+    pub fn frustum(&self) -> Frustum {
+        let projection = Mat4::perspective_rh(self.fov.to_radians(), self.aspect, self.near, self.far);
+        Frustum::from_view_projection(&(projection * self.view_matrix()))
+    }
     
     /// Get the camera's position as a Vec3
     pub fn position_vector(&self) -> Vec3 {
@@ -158,3 +169,29 @@ pub struct CameraMatrices {
     /// Direction the camera is facing
     pub camera_direction: Vec3,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frustum_matches_the_view() {
+        let mut camera = Camera::perspective(2.0);
+        camera.look_at_from(Vec3::new(0.0, 0.0, 5.0), Vec3::ZERO);
+        let frustum = camera.frustum();
+
+        assert!(frustum.contains_point(Vec3::ZERO));
+        assert!(frustum.contains_point(Vec3::new(0.0, 0.0, 5.0 - 99.0)));
+        assert!(!frustum.contains_point(Vec3::new(0.0, 0.0, 5.0 - 101.0)));
+        assert!(!frustum.contains_point(Vec3::new(0.0, 0.0, 6.0)));
+
+        // At the target the view reaches tan(22.5 degrees) * 5 = 2.07 up and twice that sideways
+        assert!(frustum.contains_point(Vec3::new(4.0, 0.0, 0.0)));
+        assert!(!frustum.contains_point(Vec3::new(0.0, 2.2, 0.0)));
+        assert!(!frustum.contains_point(Vec3::new(4.3, 0.0, 0.0)));
+
+        // Points the GL projection keeps are inside the frustum too
+        let inside = camera.view_projection_matrix().inverse().project_point3(Vec3::new(0.9, -0.9, 0.5));
+        assert!(frustum.contains_point(inside));
+    }
+}
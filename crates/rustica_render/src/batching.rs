@@ -0,0 +1,220 @@
+// Sorting and merging of draw commands before they are staged
+
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use crate::draw_commands::DrawCommand;
+use crate::mesh::MeshHandle;
+use crate::shader_features::FeatureSet;
+use crate::texture::TextureHandle;
+
+/// Geometry a draw reads its vertices from
+#[derive(PartialEq, Eq, Hash)]
+enum GeometryKey {
+    /// A mesh uploaded with `upload_geometry`
+    Mesh(MeshHandle),
+    /// Geometry recorded with the draw, identified by a hash of its bytes
+    Recorded(u64),
+}
+
+/// Reorder and merge the draws of a frame so fewer pipelines are bound and fewer draw calls issued
+///
+/// Draws with the same shader, variant, geometry, instance size, uniforms and
+/// textures are merged into one instanced draw, and the remaining draws are
+/// grouped by pipeline, then by textures. Commands that aren't draws and draws
+/// of shaders for which `keeps_order` is true (e.g. blended shaders) stay
+/// where they were recorded, and no draw is moved across them.
+pub(crate) fn batch_draws(commands: Vec<DrawCommand>, keeps_order: impl Fn(usize) -> bool) -> Vec<DrawCommand> {
+    let mut batched = Vec::with_capacity(commands.len());
+    let mut run = Vec::new();
+    for command in commands {
+        match draw_state(&command) {
+            Some((shader_id, _, _)) if !keeps_order(shader_id) => run.push(command),
+            _ => {
+                batch_run(&mut run, &mut batched);
+                batched.push(command);
+            }
+        }
+    }
+    batch_run(&mut run, &mut batched);
+    batched
+}
+
+/// Merge and sort a run of reorderable draws, appending them to `batched`
+fn batch_run(run: &mut Vec<DrawCommand>, batched: &mut Vec<DrawCommand>) {
+    // Merge each draw into the first earlier draw it is compatible with
+    let mut merged: Vec<DrawCommand> = Vec::with_capacity(run.len());
+    let mut candidates: HashMap<(usize, GeometryKey), Vec<usize>> = HashMap::new();
+    for command in run.drain(..) {
+        let Some(key) = merge_key(&command) else {
+            merged.push(command);
+            continue;
+        };
+        let batches = candidates.entry(key).or_default();
+        match batches.iter().find(|&&index| can_merge(&merged[index], &command)) {
+            Some(&index) => append_instances(&mut merged[index], command),
+            None => {
+                batches.push(merged.len());
+                merged.push(command);
+            }
+        }
+    }
+
+    // Pipelines and texture sets are ranked by first use, so the sort keeps the recorded order otherwise
+    let mut pipelines = HashMap::new();
+    let mut texture_sets = HashMap::new();
+    let ranks: Vec<(usize, usize)> = merged.iter()
+        .filter_map(draw_state)
+        .map(|(shader_id, features, textures)| {
+            let next = pipelines.len();
+            let pipeline = *pipelines.entry((shader_id, features.clone())).or_insert(next);
+            let mut textures: Vec<(String, TextureHandle)> = textures.iter()
+                .map(|(name, texture)| (name.clone(), *texture))
+                .collect();
+            textures.sort_by(|a, b| a.0.cmp(&b.0));
+            let next = texture_sets.len();
+            (pipeline, *texture_sets.entry((pipeline, textures)).or_insert(next))
+        })
+        .collect();
+    let mut ranked: Vec<_> = ranks.into_iter().zip(merged).collect();
+    ranked.sort_by_key(|(rank, _)| *rank);
+    batched.extend(ranked.into_iter().map(|(_, command)| command));
+}
+
+/// Get the shader, variant and textures of a draw, or None for other commands
+fn draw_state(command: &DrawCommand) -> Option<(usize, &FeatureSet, &HashMap<String, TextureHandle>)> {
+    match command {
+        DrawCommand::GeometryWithInstances { shader_id, features, textures, .. }
+        | DrawCommand::MeshWithInstances { shader_id, features, textures, .. }
+        | DrawCommand::MeshWithStorageInstances { shader_id, features, textures, .. }
+        | DrawCommand::MeshIndirect { shader_id, features, textures, .. } => {
+            Some((*shader_id, features, textures))
+        }
+        _ => None,
+    }
+}
+
+/// Get the key under which a draw looks for draws to merge with, or None if it can't be merged
+fn merge_key(command: &DrawCommand) -> Option<(usize, GeometryKey)> {
+    match command {
+        DrawCommand::GeometryWithInstances { shader_id, vertices, indices, .. } => {
+            let mut hasher = DefaultHasher::new();
+            (vertices, indices).hash(&mut hasher);
+            Some((*shader_id, GeometryKey::Recorded(hasher.finish())))
+        }
+        DrawCommand::MeshWithInstances { shader_id, mesh, .. } => Some((*shader_id, GeometryKey::Mesh(*mesh))),
+        _ => None,
+    }
+}
+
+/// Check if a draw can be appended to a batch with the same merge key
+fn can_merge(batch: &DrawCommand, command: &DrawCommand) -> bool {
+    match (batch, command) {
+        (
+            DrawCommand::GeometryWithInstances { vertices, indices, instance_size, uniforms, textures, features, .. },
+            DrawCommand::GeometryWithInstances {
+                vertices: other_vertices, indices: other_indices, instance_size: other_size,
+                uniforms: other_uniforms, textures: other_textures, features: other_features, ..
+            },
+        ) => {
+            vertices == other_vertices && indices == other_indices && instance_size == other_size
+                && uniforms == other_uniforms && textures == other_textures && features == other_features
+        }
+        (
+            DrawCommand::MeshWithInstances { mesh, instance_size, uniforms, textures, features, .. },
+            DrawCommand::MeshWithInstances {
+                mesh: other_mesh, instance_size: other_size,
+                uniforms: other_uniforms, textures: other_textures, features: other_features, ..
+            },
+        ) => {
+            mesh == other_mesh && instance_size == other_size
+                && uniforms == other_uniforms && textures == other_textures && features == other_features
+        }
+        _ => false,
+    }
+}
+
+/// Append the instances of a draw to a compatible batch
+fn append_instances(batch: &mut DrawCommand, command: DrawCommand) {
+    if let (
+        DrawCommand::GeometryWithInstances { instances, instance_count, .. }
+        | DrawCommand::MeshWithInstances { instances, instance_count, .. },
+        DrawCommand::GeometryWithInstances { instances: more, instance_count: count, .. }
+        | DrawCommand::MeshWithInstances { instances: more, instance_count: count, .. },
+    ) = (batch, command) {
+        instances.extend_from_slice(&more);
+        *instance_count += count;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mesh_draw(shader_id: usize, mesh: usize, instance: u32, tint: f32) -> DrawCommand {
+        DrawCommand::MeshWithInstances {
+            shader_id,
+            mesh: MeshHandle(mesh),
+            instances: bytemuck::bytes_of(&instance).to_vec(),
+            instance_size: 4,
+            instance_count: 1,
+            uniforms: HashMap::from([("tint".to_string(), tint.into())]),
+            textures: HashMap::new(),
+            features: FeatureSet::new(),
+        }
+    }
+
+    fn summary(commands: &[DrawCommand]) -> Vec<(usize, Vec<u8>)> {
+        commands.iter().map(|command| match command {
+            DrawCommand::MeshWithInstances { shader_id, instances, .. } => (*shader_id, instances.clone()),
+            DrawCommand::UpdateStorage { .. } => (usize::MAX, Vec::new()),
+            other => panic!("Unexpected command: {:?}", other),
+        }).collect()
+    }
+
+    #[test]
+    fn test_compatible_draws_merge_and_group_by_shader() {
+        let commands = vec![
+            mesh_draw(0, 0, 1, 1.0),
+            mesh_draw(1, 0, 2, 1.0),
+            mesh_draw(0, 0, 3, 1.0),
+            mesh_draw(0, 0, 4, 0.5),
+            mesh_draw(1, 1, 5, 1.0),
+        ];
+
+        let batched = batch_draws(commands, |_| false);
+
+        assert_eq!(summary(&batched), vec![
+            (0, [1u32, 3].iter().flat_map(|i| i.to_ne_bytes()).collect()),
+            (0, 4u32.to_ne_bytes().to_vec()),
+            (1, 2u32.to_ne_bytes().to_vec()),
+            (1, 5u32.to_ne_bytes().to_vec()),
+        ]);
+        match &batched[0] {
+            DrawCommand::MeshWithInstances { instance_count, .. } => assert_eq!(*instance_count, 2),
+            other => panic!("Unexpected command: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_ordered_commands_are_barriers() {
+        let commands = vec![
+            mesh_draw(0, 0, 1, 1.0),
+            mesh_draw(2, 0, 2, 1.0),
+            mesh_draw(0, 0, 3, 1.0),
+            DrawCommand::UpdateStorage { shader_id: 0, name: "lights".to_string(), data: Vec::new() },
+            mesh_draw(0, 0, 4, 1.0),
+        ];
+
+        let batched = batch_draws(commands, |shader_id| shader_id == 2);
+
+        assert_eq!(summary(&batched), vec![
+            (0, 1u32.to_ne_bytes().to_vec()),
+            (2, 2u32.to_ne_bytes().to_vec()),
+            (0, 3u32.to_ne_bytes().to_vec()),
+            (usize::MAX, Vec::new()),
+            (0, 4u32.to_ne_bytes().to_vec()),
+        ]);
+    }
+}
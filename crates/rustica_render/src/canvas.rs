@@ -3,9 +3,20 @@
 
 use std::time::Duration;
 use std::collections::HashMap;
-use glam::Mat4;
+use std::sync::Arc;
+use glam::{Mat3, Mat4, Vec2, Vec3};
 use crate::draw_commands::{DrawCommand, UniformValue};
+use crate::compute_shader::ComputeHandle;
 use crate::custom_shader::CustomShader;
+use crate::debug_draw::DebugDraw;
+use crate::draw_2d::Draw2d;
+use crate::font::{FontAtlas, TextAlign};
+use crate::frame_stats::FrameStats;
+use crate::lod_mesh::LodMesh;
+use crate::mesh::MeshHandle;
+use crate::shader_error::ShaderError;
+use crate::shader_features::FeatureSet;
+use crate::texture::TextureHandle;
 use rustica_foundation::prelude::*;
 use wgpu::Queue;
 
@@ -15,6 +26,15 @@ pub struct Canvas<'a> {
     time: Duration,
     render_context: Option<&'a mut crate::render_context::RenderContext>,
     pub(crate) queue: Option<&'a Queue>,
+    /// Context compute storage is read back from (None outside a frame)
+    pub(crate) storage_source: Option<&'a crate::render_context::RenderContext>,
+    pub(crate) shader_errors: HashMap<usize, ShaderError>,
+    pub(crate) compute_shader_errors: HashMap<ComputeHandle, ShaderError>,
+    pub(crate) frame_stats: FrameStats,
+    debug: DebugDraw,
+    shapes: Draw2d,
+    pub(crate) font: Option<Arc<FontAtlas>>,
+    pub(crate) target_size: (u32, u32),
 }
 
 impl<'a> Canvas<'a> {
@@ -24,6 +44,14 @@ impl<'a> Canvas<'a> {
             time,
             render_context: None,
             queue: None,
+            storage_source: None,
+            shader_errors: HashMap::new(),
+            compute_shader_errors: HashMap::new(),
+            frame_stats: FrameStats::default(),
+            debug: DebugDraw::default(),
+            shapes: Draw2d::default(),
+            font: None,
+            target_size: (0, 0),
         }
     }    
     
@@ -36,6 +64,38 @@ impl<'a> Canvas<'a> {
         }
     }
     
+    /// Get the reason a shader is unavailable
+    ///
+    /// Returns Some if the shader failed to compile when the device was
+    /// created. Draws with an unavailable shader are skipped, so a frame can
+    /// use this to draw a fallback instead.
+    pub fn shader_error(&self, shader_id: usize) -> Option<&ShaderError> {
+        self.shader_errors.get(&shader_id)
+    }
+
+    /// Get the reason a compute shader is unavailable (dispatches of it are skipped)
+    pub fn compute_shader_error(&self, compute: ComputeHandle) -> Option<&ShaderError> {
+        self.compute_shader_errors.get(&compute)
+    }
+
+    /// Get the statistics of the previous frame
+    ///
+    /// All zero on the first frame.
+    pub fn frame_stats(&self) -> &FrameStats {
+        &self.frame_stats
+    }
+
+    /// Read the data of a compute shader's storage buffer back to the CPU
+    ///
+    /// Returns the elements as written by the previous frame, since this
+    /// frame's dispatches only run after the callback returns. Waits for the
+    /// GPU, so read sparingly. Fails if the shader has no storage buffer with
+    /// that name or its elements aren't `T`.
+    pub fn read_storage<T: bytemuck::Pod>(&self, compute: ComputeHandle, name: &str) -> Result<Vec<T>, String> {
+        let context = self.storage_source.ok_or_else(|| "Storage can only be read during a frame".to_string())?;
+        context.read_storage(compute, name)
+    }
+
     /// Get the queue for submitting GPU commands
     pub fn queue(&self) -> Option<&Queue> {
         self.queue
@@ -51,6 +111,123 @@ impl<'a> Canvas<'a> {
         InstancedShaderDrawBuilder::new(self, shader_id)
     }
     
+    /// Replace the contents of a shader's storage buffer, e.g. a light list
+    ///
+    /// The data is uploaded before any draw of the frame executes, so every
+    /// draw of the frame sees the last update. The buffer keeps its contents
+    /// in later frames until it is updated again. Empty data leaves a single
+    /// zeroed element bound, as `arrayLength` is always at least 1.
+    pub fn update_storage<S: Into<String>, T: bytemuck::Pod>(&mut self, shader_id: usize, name: S, data: &[T]) {
+        self.commands.push(DrawCommand::UpdateStorage {
+            shader_id,
+            name: name.into(),
+            data: bytemuck::cast_slice(data).to_vec(),
+        });
+    }
+
+    /// Create a builder for running a compute shader
+    ///
+    /// Dispatches run in the order they are recorded, before the frame's render
+    /// pass, so draws of the same frame see their results.
+    pub fn dispatch(&mut self, compute: ComputeHandle) -> ComputeDispatchBuilder<'_, 'a> {
+        ComputeDispatchBuilder::new(self, compute)
+    }
+
+    /// Replace the contents of a compute shader's storage buffer
+    ///
+    /// The data is uploaded before any dispatch of the frame executes and is
+    /// written to the buffer bound to `name` when this is called. Empty data
+    /// leaves a single zeroed element bound, as `arrayLength` is always at least 1.
+    pub fn update_compute_storage<S: Into<String>, T: bytemuck::Pod>(&mut self, compute: ComputeHandle, name: S, data: &[T]) {
+        self.commands.push(DrawCommand::UpdateComputeStorage {
+            compute,
+            name: name.into(),
+            data: bytemuck::cast_slice(data).to_vec(),
+        });
+    }
+
+    /// Exchange the buffers bound to two storage parameters of a compute shader
+    ///
+    /// Dispatches and draws recorded after the swap see the exchanged buffers,
+    /// which makes it easy to ping-pong between simulation steps.
+    pub fn swap_storage<S: Into<String>>(&mut self, compute: ComputeHandle, first: S, second: S) {
+        self.commands.push(DrawCommand::SwapStorage {
+            compute,
+            first: first.into(),
+            second: second.into(),
+        });
+    }
+
+    /// Get the frame's debug lines, drawn after everything else without a shader of your own
+    pub fn debug(&mut self) -> &mut DebugDraw {
+        &mut self.debug
+    }
+
+    /// Get the frame's 2D shapes, drawn over the scene and the debug lines, e.g. for a HUD
+    pub fn draw_2d(&mut self) -> &mut Draw2d {
+        &mut self.shapes
+    }
+
+    /// Draw text with its first line's top left corner at a position in pixels
+    ///
+    /// `size` is the height of a line in pixels, and each `\n` starts a new
+    /// line. Text is drawn over the scene with the 2D shapes, in the bundled
+    /// font unless another was set with `RenderWindow::set_font`.
+    pub fn text(&mut self, position: Vec2, size: f32, color: [f32; 4], text: &str) {
+        self.text_aligned(position, size, color, TextAlign::Left, text);
+    }
+
+    /// Draw text with each line aligned to the horizontal position
+    pub fn text_aligned(&mut self, position: Vec2, size: f32, color: [f32; 4], align: TextAlign, text: &str) {
+        if let Some(font) = &self.font {
+            let quads = font.layout(text, position, size, align);
+            self.shapes.glyphs(&quads, font.texture(), color);
+        }
+    }
+
+    /// Draw text centered on a point of the 3D scene, e.g. a label above an object
+    ///
+    /// Nothing is drawn for points behind the camera.
+    pub fn text_at(&mut self, view_projection: &Mat4, world_position: Vec3, size: f32, color: [f32; 4], text: &str) {
+        let clip = *view_projection * world_position.extend(1.0);
+        if clip.w <= 0.0 {
+            return;
+        }
+        let ndc = clip.truncate().truncate() / clip.w;
+        let (width, height) = (self.target_size.0 as f32, self.target_size.1 as f32);
+        let center = Vec2::new((ndc.x + 1.0) / 2.0 * width, (1.0 - ndc.y) / 2.0 * height);
+        let top = center.y - self.measure_text(size, text).y / 2.0;
+        self.text_aligned(Vec2::new(center.x, top), size, color, TextAlign::Center, text);
+    }
+
+    /// Get the width of the longest line and the total height in pixels of text drawn with lines `size` high
+    pub fn measure_text(&self, size: f32, text: &str) -> Vec2 {
+        self.font.as_ref().map_or(Vec2::ZERO, |font| font.measure(text, size))
+    }
+
+    /// Get the size in pixels of the frame being drawn
+    pub fn target_size(&self) -> (u32, u32) {
+        self.target_size
+    }
+
+    /// Take the recorded commands, followed by the debug lines and 2D shapes if any were drawn
+    pub(crate) fn take_commands(&mut self) -> Vec<DrawCommand> {
+        let mut commands = std::mem::take(&mut self.commands);
+        if !self.debug.is_empty() {
+            let (view_projection, depth_tested, overlay) = self.debug.take();
+            commands.push(DrawCommand::DebugLines {
+                view_projection: view_projection.to_cols_array_2d(),
+                depth_tested,
+                overlay,
+            });
+        }
+        if !self.shapes.is_empty() {
+            let (vertices, batches) = self.shapes.take();
+            commands.push(DrawCommand::Shapes2d { vertices, batches });
+        }
+        commands
+    }
+
     /// Get the elapsed time since the application started
     pub fn time(&self) -> Duration {
         self.time
@@ -63,6 +240,8 @@ pub struct InstancedShaderDrawBuilder<'b, 'a> {
     canvas: &'b mut Canvas<'a>,
     shader_id: usize,
     uniforms: HashMap<String, UniformValue>,
+    textures: HashMap<String, TextureHandle>,
+    features: FeatureSet,
 }
 
 impl<'b, 'a> InstancedShaderDrawBuilder<'b, 'a> {
@@ -72,6 +251,8 @@ impl<'b, 'a> InstancedShaderDrawBuilder<'b, 'a> {
             canvas,
             shader_id,
             uniforms: HashMap::new(),
+            textures: HashMap::new(),
+            features: FeatureSet::new(),
         }
     }
     
@@ -81,6 +262,24 @@ impl<'b, 'a> InstancedShaderDrawBuilder<'b, 'a> {
         self
     }
     
+    /// Bind a texture to a texture parameter
+    pub fn texture<S: Into<String>>(mut self, name: S, texture: TextureHandle) -> Self {
+        self.textures.insert(name.into(), texture);
+        self
+    }
+
+    /// Enable a feature flag of the shader, drawing with the variant compiled with it
+    pub fn feature(mut self, name: &str) -> Self {
+        self.features.enable(name);
+        self
+    }
+
+    /// Select the value of a shader feature, drawing with the variant compiled with it
+    pub fn feature_value(mut self, name: &str, value: &str) -> Self {
+        self.features.select(name, value);
+        self
+    }
+
     /// Draw geometry with instances using the configured shader and uniforms
     pub fn pump_geometry<V: Vertex, I: bytemuck::Pod>(
         self,
@@ -96,10 +295,168 @@ impl<'b, 'a> InstancedShaderDrawBuilder<'b, 'a> {
             vertices: vertices_bytes,
             indices: indices_bytes,
             instances: instances_bytes,
+            instance_size: std::mem::size_of::<I>(),
             vertex_count: geometry.vertices.len() as u32,
             index_count: geometry.indices.len() as u32,
             instance_count: instances.len() as u32,
             uniforms: self.uniforms,
+            textures: self.textures,
+            features: self.features,
+        });
+    }
+
+    /// Draw a mesh uploaded with `RenderWindow::upload_geometry` with instances
+    ///
+    /// Unlike `pump_geometry`, the vertex and index data already live on the GPU,
+    /// so only the instance data is copied each frame.
+    pub fn draw_mesh<I: bytemuck::Pod>(self, mesh: MeshHandle, instances: &[I]) {
+        let instances_bytes = bytemuck::cast_slice(instances).to_vec();
+
+        self.canvas.commands.push(DrawCommand::MeshWithInstances {
+            shader_id: self.shader_id,
+            mesh,
+            instances: instances_bytes,
+            instance_size: std::mem::size_of::<I>(),
+            instance_count: instances.len() as u32,
+            uniforms: self.uniforms,
+            textures: self.textures,
+            features: self.features,
+        });
+    }
+
+    /// Draw a mesh with only the instances whose bounds intersect a frustum
+    ///
+    /// `bounds` gives the world-space bounding sphere or `Aabb` of an instance,
+    /// e.g. the mesh's bounds transformed by the instance's model matrix.
+    /// Culled instances are never copied to the GPU.
+    pub fn draw_mesh_culled<I: bytemuck::Pod, B: Into<Bounds>>(
+        self,
+        mesh: MeshHandle,
+        instances: &[I],
+        frustum: &Frustum,
+        bounds: impl Fn(&I) -> B,
+    ) {
+        let visible: Vec<I> = instances.iter()
+            .filter(|instance| frustum.intersects(&bounds(instance).into()))
+            .copied()
+            .collect();
+        self.draw_mesh(mesh, &visible);
+    }
+
+    /// Draw the instances of a mesh with several levels of detail, one instanced draw per level
+    ///
+    /// Each instance is drawn with the level that fits the screen size of its
+    /// world-space bounding sphere (see `LodMesh`) seen from `eye` with the
+    /// vertical field of view `fov_y` in radians. Instances too small for
+    /// every level are skipped.
+    pub fn draw_lod<I: bytemuck::Pod>(
+        self,
+        lod: &LodMesh,
+        instances: &[I],
+        eye: Vec3,
+        fov_y: f32,
+        bounds: impl Fn(&I) -> BoundingSphere,
+    ) {
+        let mut buckets = vec![Vec::new(); lod.levels().len()];
+        for instance in instances {
+            let screen_size = LodMesh::screen_size(&bounds(instance), eye, fov_y);
+            if let Some(level) = lod.select(screen_size) {
+                buckets[level].extend_from_slice(bytemuck::bytes_of(instance));
+            }
+        }
+
+        for (level, instances_bytes) in lod.levels().iter().zip(buckets) {
+            if instances_bytes.is_empty() {
+                continue;
+            }
+            self.canvas.commands.push(DrawCommand::MeshWithInstances {
+                shader_id: self.shader_id,
+                mesh: level.mesh,
+                instance_count: (instances_bytes.len() / std::mem::size_of::<I>()) as u32,
+                instances: instances_bytes,
+                instance_size: std::mem::size_of::<I>(),
+                uniforms: self.uniforms.clone(),
+                textures: self.textures.clone(),
+                features: self.features.clone(),
+            });
+        }
+    }
+
+    /// Draw a mesh with one instance per element of a compute shader's storage buffer
+    ///
+    /// The instance data never leaves the GPU, so a compute shader can update
+    /// it every frame. The buffer's element size must match the shader's
+    /// instance layout.
+    pub fn draw_mesh_from_storage<S: Into<String>>(self, mesh: MeshHandle, compute: ComputeHandle, storage: S) {
+        self.canvas.commands.push(DrawCommand::MeshWithStorageInstances {
+            shader_id: self.shader_id,
+            mesh,
+            compute,
+            storage: storage.into(),
+            uniforms: self.uniforms,
+            textures: self.textures,
+            features: self.features,
+        });
+    }
+
+    /// Draw a mesh with arguments read from a compute shader's storage buffer
+    ///
+    /// Issues `draw_count` draws, each with the next `DrawIndexedIndirect`
+    /// element of `arguments` and instances from `storage`, so a compute
+    /// shader can decide what is drawn without a round trip to the CPU. Fill
+    /// `arguments` with `Canvas::update_compute_storage` to set them from the
+    /// CPU instead. Draws beyond the end of the buffer are skipped.
+    pub fn draw_mesh_indirect(
+        self,
+        mesh: MeshHandle,
+        compute: ComputeHandle,
+        storage: impl Into<String>,
+        arguments: impl Into<String>,
+        draw_count: u32,
+    ) {
+        self.canvas.commands.push(DrawCommand::MeshIndirect {
+            shader_id: self.shader_id,
+            mesh,
+            compute,
+            storage: storage.into(),
+            arguments: arguments.into(),
+            draw_count,
+            uniforms: self.uniforms,
+            textures: self.textures,
+            features: self.features,
+        });
+    }
+}
+
+/// Builder for compute shader dispatches
+pub struct ComputeDispatchBuilder<'b, 'a> {
+    canvas: &'b mut Canvas<'a>,
+    compute: ComputeHandle,
+    uniforms: HashMap<String, UniformValue>,
+}
+
+impl<'b, 'a> ComputeDispatchBuilder<'b, 'a> {
+    /// Create a new compute dispatch builder
+    fn new(canvas: &'b mut Canvas<'a>, compute: ComputeHandle) -> Self {
+        Self {
+            canvas,
+            compute,
+            uniforms: HashMap::new(),
+        }
+    }
+
+    /// Add a uniform value
+    pub fn uniform<S: Into<String>, V: Into<UniformValue>>(mut self, name: S, value: V) -> Self {
+        self.uniforms.insert(name.into(), value.into());
+        self
+    }
+
+    /// Run the compute shader with the given number of workgroups
+    pub fn workgroups(self, x: u32, y: u32, z: u32) {
+        self.canvas.commands.push(DrawCommand::Dispatch {
+            compute: self.compute,
+            workgroups: [x, y, z],
+            uniforms: self.uniforms,
         });
     }
 }
@@ -141,6 +498,18 @@ impl From<u32> for UniformValue {
     }
 }
 
+impl From<[[f32; 3]; 3]> for UniformValue {
+    fn from(value: [[f32; 3]; 3]) -> Self {
+        UniformValue::Mat3(value)
+    }
+}
+
+impl From<Mat3> for UniformValue {
+    fn from(matrix: Mat3) -> Self {
+        UniformValue::Mat3(matrix.to_cols_array_2d())
+    }
+}
+
 impl From<Mat4> for UniformValue {
     fn from(matrix: Mat4) -> Self {
         // Convert Mat4 to array format for the shader
@@ -163,4 +532,131 @@ mod tests {
         assert!(canvas.commands.is_empty());
     }
 
+    #[test]
+    fn test_draw_mesh_records_command() {
+        let mut canvas = Canvas::new(Duration::ZERO);
+        let instances = [[1.0f32, 2.0, 3.0], [4.0, 5.0, 6.0]];
+        canvas.draw_with_instances(2)
+              .uniform("time", 1.5)
+              .texture("albedo", TextureHandle(3))
+              .feature("USE_TEXTURE")
+              .draw_mesh(MeshHandle(7), &instances);
+
+        assert_eq!(canvas.commands.len(), 1);
+        match &canvas.commands[0] {
+            DrawCommand::MeshWithInstances { shader_id, mesh, instances, instance_size, instance_count, uniforms, textures, features } => {
+                assert_eq!(*shader_id, 2);
+                assert_eq!(*mesh, MeshHandle(7));
+                assert_eq!(instances.len(), 24);
+                assert_eq!(*instance_size, 12);
+                assert_eq!(*instance_count, 2);
+                assert!(uniforms.contains_key("time"));
+                assert_eq!(textures.get("albedo"), Some(&TextureHandle(3)));
+                assert_eq!(*features, FeatureSet::new().with_flag("USE_TEXTURE"));
+            }
+            other => panic!("Unexpected command: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_draw_mesh_culled_skips_instances_outside_the_frustum() {
+        let projection = Mat4::perspective_rh(90f32.to_radians(), 1.0, 0.1, 10.0);
+        let view = Mat4::look_at_rh(Vec3::ZERO, Vec3::NEG_Z, Vec3::Y);
+        let frustum = Frustum::from_view_projection(&(projection * view));
+        let positions = [
+            [0.0f32, 0.0, -5.0], // In front of the camera
+            [0.0, 0.0, 5.0],     // Behind it
+            [0.0, 0.0, -10.4],   // Past the far plane, but within the radius
+            [0.0, 0.0, -11.0],   // Past the far plane
+            [7.0, 0.0, -5.0],    // Right of the view
+            [0.0, 0.05, 0.0],    // Closer than the near plane, but within the radius
+        ];
+
+        let mut canvas = Canvas::new(Duration::ZERO);
+        canvas.draw_with_instances(0)
+              .draw_mesh_culled(MeshHandle(0), &positions, &frustum, |position| {
+                  BoundingSphere::new(Vec3::from_array(*position), 0.5)
+              });
+
+        match &canvas.commands[0] {
+            DrawCommand::MeshWithInstances { instances, instance_count, .. } => {
+                assert_eq!(*instance_count, 3);
+                let visible: &[[f32; 3]] = bytemuck::cast_slice(instances);
+                assert_eq!(visible, [positions[0], positions[2], positions[5]]);
+            }
+            other => panic!("Unexpected command: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_draw_mesh_culled_accepts_boxes() {
+        let projection = Mat4::perspective_rh(90f32.to_radians(), 1.0, 0.1, 10.0);
+        let view = Mat4::look_at_rh(Vec3::ZERO, Vec3::NEG_Z, Vec3::Y);
+        let frustum = Frustum::from_view_projection(&(projection * view));
+        // Long boxes along x, which a sphere would have to enclose with a large radius
+        let centers = [[0.0f32, 0.0, -5.0], [9.0, 0.0, -5.0], [0.0, 9.0, -5.0]];
+
+        let mut canvas = Canvas::new(Duration::ZERO);
+        canvas.draw_with_instances(0)
+              .draw_mesh_culled(MeshHandle(0), &centers, &frustum, |center| {
+                  let center = Vec3::from_array(*center);
+                  Aabb::new(center - Vec3::new(4.5, 0.5, 0.5), center + Vec3::new(4.5, 0.5, 0.5))
+              });
+
+        match &canvas.commands[0] {
+            DrawCommand::MeshWithInstances { instances, .. } => {
+                let visible: &[[f32; 3]] = bytemuck::cast_slice(instances);
+                assert_eq!(visible, [centers[0], centers[1]]);
+            }
+            other => panic!("Unexpected command: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_draw_lod_buckets_instances_by_level() {
+        let lod = LodMesh::new()
+            .with_level(MeshHandle(0), 0.5)
+            .with_level(MeshHandle(1), 0.1)
+            .with_level(MeshHandle(2), 0.02);
+        // Unit spheres seen with a 90 degree field of view
+        let distances = [1.5f32, 8.0, 40.0, 5.0, 100.0];
+
+        let mut canvas = Canvas::new(Duration::ZERO);
+        canvas.draw_with_instances(0)
+              .uniform("time", 1.0)
+              .draw_lod(&lod, &distances, Vec3::ZERO, 90f32.to_radians(), |distance| {
+                  BoundingSphere::new(Vec3::new(0.0, 0.0, -distance), 1.0)
+              });
+
+        let draws: Vec<_> = canvas.commands.iter().map(|command| match command {
+            DrawCommand::MeshWithInstances { mesh, instances, uniforms, .. } => {
+                assert!(uniforms.contains_key("time"));
+                (*mesh, bytemuck::cast_slice::<u8, f32>(instances).to_vec())
+            }
+            other => panic!("Unexpected command: {:?}", other),
+        }).collect();
+        assert_eq!(draws, [
+            (MeshHandle(0), vec![1.5]),
+            (MeshHandle(1), vec![8.0, 5.0]),
+            (MeshHandle(2), vec![40.0]),
+        ]);
+    }
+
+    #[test]
+    fn test_dispatch_records_command() {
+        let mut canvas = Canvas::new(Duration::ZERO);
+        canvas.dispatch(ComputeHandle(1))
+              .uniform("delta", 0.5)
+              .workgroups(4, 2, 1);
+
+        match &canvas.commands[0] {
+            DrawCommand::Dispatch { compute, workgroups, uniforms } => {
+                assert_eq!(*compute, ComputeHandle(1));
+                assert_eq!(*workgroups, [4, 2, 1]);
+                assert!(uniforms.contains_key("delta"));
+            }
+            other => panic!("Unexpected command: {:?}", other),
+        }
+    }
+
 }
@@ -0,0 +1,602 @@
+// Compute shaders dispatched from the canvas before the render pass
+
+use std::collections::{HashMap, HashSet};
+use std::num::NonZeroU64;
+use wgpu::{
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBinding, BufferBindingType,
+    BufferDescriptor, BufferUsages, ComputePipeline, ComputePipelineDescriptor, Device,
+    PipelineLayout, PipelineLayoutDescriptor, Queue, ShaderModuleDescriptor, ShaderSource, ShaderStages,
+};
+
+use crate::custom_shader::{CustomShader, StorageParameter, UniformParameter};
+use crate::draw_commands::UniformValue;
+use rustica_wgsl_preprocessor::SourceMap;
+use crate::diagnostics::label;
+use crate::shader_error::ShaderError;
+use crate::uniform_ring::UniformRing;
+
+/// Uniform block alignment used before the device limits are known
+const DEFAULT_UNIFORM_ALIGNMENT: u64 = 256;
+
+/// Handle to a compute shader
+///
+/// Returned by `RenderWindow::register_compute_shader` and passed to
+/// `Canvas::dispatch` and the other compute commands of the canvas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ComputeHandle(pub(crate) usize);
+
+impl ComputeHandle {
+    /// Get the index of the compute shader in the registry
+    pub fn id(&self) -> usize {
+        self.0
+    }
+}
+
+/// Descriptor for a WGSL compute shader
+#[derive(Clone)]
+pub struct ComputeShaderDescriptor {
+    /// Name of the shader
+    pub name: String,
+    /// WGSL shader source code
+    pub shader_source: String,
+    /// Path of the WGSL file the source was loaded from, used in error messages
+    pub source_path: Option<String>,
+    /// Where the lines of preprocessed source came from, used in error messages
+    pub source_map: Option<SourceMap>,
+    /// Name of the `@compute` entry point
+    pub entry_point: String,
+    /// Uniform parameters (if any)
+    pub uniforms: Vec<UniformParameter>,
+    /// Storage buffer parameters (if any)
+    pub storage: Vec<StorageParameter>,
+}
+
+impl ComputeShaderDescriptor {
+    /// Check that no two uniforms or storage buffers share a binding
+    pub fn validate(&self) -> Result<(), String> {
+        let mut bindings = HashSet::new();
+        let resources = self.uniforms.iter().map(|uniform| (uniform.group, uniform.binding))
+            .chain(self.storage.iter().map(|storage| (storage.group, storage.binding)));
+        for (group, binding) in resources {
+            if !bindings.insert((group, binding)) {
+                return Err(format!(
+                    "Compute shader '{}' declares group {} binding {} more than once",
+                    self.name, group, binding
+                ));
+            }
+        }
+
+        if let Some(storage) = self.storage.iter().find(|storage| storage.element_size == 0) {
+            return Err(format!("Compute shader '{}': storage buffer '{}' has zero-sized elements", self.name, storage.name));
+        }
+        Ok(())
+    }
+}
+
+/// Uniform offsets and storage buffers of one dispatch
+pub struct ComputeBindings {
+    /// Dynamic offsets per group, ordered by binding
+    pub uniform_offsets: Vec<Vec<u32>>,
+    /// Buffer bound to each storage parameter
+    pub storage_slots: Vec<usize>,
+}
+
+/// A compute shader and the storage buffers it reads and writes
+///
+/// Storage buffers live as long as the shader and keep their contents between
+/// frames, so a dispatch can build on the results of the previous frame. They
+/// can also be drawn as instance buffers, used as indirect draw arguments or
+/// read back to the CPU.
+pub struct ComputeShader {
+    /// Name of the shader
+    pub name: String,
+    /// Compiled pipeline
+    pipeline: Option<ComputePipeline>,
+    /// Layout the pipeline was created with (kept to recompile the pipeline on reload)
+    pipeline_layout: Option<PipelineLayout>,
+    /// Bind group layouts, indexed by group (unused groups get an empty layout)
+    bind_group_layouts: Vec<BindGroupLayout>,
+    /// Bind groups, indexed by group and keyed by the storage slots they bind
+    bind_groups: Vec<HashMap<Vec<usize>, BindGroup>>,
+    /// Storage slots bound by the dispatches of the current frame
+    frame_slot_sets: HashSet<Vec<usize>>,
+    /// Binding and size of every uniform buffer binding, indexed by group
+    uniform_groups: Vec<Vec<(u32, u64)>>,
+    /// Uniform parameters
+    uniforms: Vec<UniformParameter>,
+    /// Values used for uniforms that a dispatch doesn't set
+    uniform_defaults: HashMap<String, Vec<u8>>,
+    /// Storage buffer parameters
+    storage: Vec<StorageParameter>,
+    /// Index into `storage_buffers` of the buffer bound to each storage parameter
+    storage_slots: Vec<usize>,
+    /// Storage buffers and the length of their data (empty until initialized)
+    storage_buffers: Vec<(Buffer, u64)>,
+    /// Data waiting to be written to storage buffers during the next upload, keyed by buffer
+    pending_storage: HashMap<usize, Vec<u8>>,
+    /// Per-dispatch uniform blocks of the current frame
+    uniform_ring: UniformRing,
+    /// Original descriptor (for deferred initialization and reloading)
+    descriptor: Option<ComputeShaderDescriptor>,
+    /// Why deferred initialization failed (the shader is unavailable until it is replaced)
+    error: Option<ShaderError>,
+}
+
+impl ComputeShader {
+    /// Check a descriptor and its WGSL before any GPU object is created
+    pub fn check(descriptor: &ComputeShaderDescriptor) -> Result<(), ShaderError> {
+        let file = descriptor.source_path.as_deref();
+        descriptor.validate().map_err(|message| ShaderError::new(&descriptor.name, file, message))?;
+        ShaderError::check_wgsl(&descriptor.name, file, &descriptor.shader_source)
+            .map_err(|error| error.remap(descriptor.source_map.as_ref()))
+    }
+
+    /// Create a new compute shader from a descriptor
+    ///
+    /// Fails if the WGSL doesn't compile or the pipeline can't be created
+    /// from the descriptor's layout.
+    pub fn new(device: &Device, descriptor: ComputeShaderDescriptor) -> Result<Self, ShaderError> {
+        Self::check(&descriptor)?;
+        let name = descriptor.name.clone();
+        let file = descriptor.source_path.clone();
+        ShaderError::capture(device, &name, file.as_deref(), || Self::create(device, descriptor))
+    }
+
+    /// Create the GPU objects of a shader whose descriptor has been checked
+    fn create(device: &Device, descriptor: ComputeShaderDescriptor) -> Self {
+        let group_count = Self::group_count(&descriptor);
+
+        let uniforms = descriptor.uniforms.clone();
+        let storage = descriptor.storage.clone();
+        let mut uniform_groups = CustomShader::uniform_groups(&uniforms, None);
+        uniform_groups.resize(group_count, Vec::new());
+        let mut uniform_ring = UniformRing::new(device.limits().min_uniform_buffer_offset_alignment as u64);
+        if !uniforms.is_empty() {
+            uniform_ring.reserve(device, 0);
+        }
+
+        let bind_group_layouts = uniform_groups.iter().enumerate().map(|(group, bindings)| {
+            let uniform_entries = bindings.iter().map(|&(binding, size)| BindGroupLayoutEntry {
+                binding,
+                visibility: ShaderStages::COMPUTE,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: NonZeroU64::new(size),
+                },
+                count: None,
+            });
+            let storage_entries = storage.iter().filter(|storage| storage.group as usize == group).map(|storage| BindGroupLayoutEntry {
+                binding: storage.binding,
+                visibility: ShaderStages::COMPUTE,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Storage { read_only: storage.read_only },
+                    has_dynamic_offset: false,
+                    min_binding_size: NonZeroU64::new(storage.element_size),
+                },
+                count: None,
+            });
+            let entries = uniform_entries.chain(storage_entries).collect::<Vec<_>>();
+
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some(&label(&["compute", &descriptor.name, &format!("bind group layout {}", group)])),
+                entries: &entries,
+            })
+        }).collect::<Vec<_>>();
+
+        // Until the first update, each storage buffer holds a single zeroed element
+        let storage_buffers = storage.iter().map(|storage| {
+            (Self::create_storage_buffer(device, &descriptor.name, storage, storage.element_size), storage.element_size)
+        }).collect();
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some(&label(&["compute", &descriptor.name, "pipeline layout"])),
+            bind_group_layouts: &bind_group_layouts.iter().collect::<Vec<_>>(),
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = Self::create_pipeline(device, &descriptor, &pipeline_layout);
+
+        Self {
+            name: descriptor.name.clone(),
+            pipeline: Some(pipeline),
+            pipeline_layout: Some(pipeline_layout),
+            bind_group_layouts,
+            bind_groups: (0..group_count).map(|_| HashMap::new()).collect(),
+            frame_slot_sets: HashSet::new(),
+            uniform_groups,
+            uniforms,
+            uniform_defaults: HashMap::new(),
+            storage_slots: (0..storage.len()).collect(),
+            storage,
+            storage_buffers,
+            pending_storage: HashMap::new(),
+            uniform_ring,
+            descriptor: Some(descriptor),
+            error: None,
+        }
+    }
+
+    /// Compile the WGSL of a descriptor into a compute pipeline with the given layout
+    fn create_pipeline(device: &Device, descriptor: &ComputeShaderDescriptor, pipeline_layout: &PipelineLayout) -> ComputePipeline {
+        let shader_module = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some(&label(&["compute", &descriptor.name, "module"])),
+            source: ShaderSource::Wgsl(descriptor.shader_source.as_str().into()),
+        });
+
+        device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some(&label(&["compute", &descriptor.name, "pipeline"])),
+            layout: Some(pipeline_layout),
+            module: &shader_module,
+            entry_point: Some(&descriptor.entry_point),
+            compilation_options: Default::default(),
+            cache: None,
+        })
+    }
+
+    /// Create a placeholder shader that will be initialized later
+    pub fn new_placeholder(descriptor: ComputeShaderDescriptor) -> Self {
+        let mut uniform_groups = CustomShader::uniform_groups(&descriptor.uniforms, None);
+        uniform_groups.resize(Self::group_count(&descriptor), Vec::new());
+        Self {
+            name: descriptor.name.clone(),
+            pipeline: None,
+            pipeline_layout: None,
+            bind_group_layouts: Vec::new(),
+            bind_groups: Vec::new(),
+            frame_slot_sets: HashSet::new(),
+            uniform_groups,
+            uniforms: descriptor.uniforms.clone(),
+            uniform_defaults: HashMap::new(),
+            storage: descriptor.storage.clone(),
+            storage_slots: (0..descriptor.storage.len()).collect(),
+            storage_buffers: Vec::new(),
+            pending_storage: HashMap::new(),
+            uniform_ring: UniformRing::new(DEFAULT_UNIFORM_ALIGNMENT),
+            descriptor: Some(descriptor),
+            error: None,
+        }
+    }
+
+    /// Initialize the shader with a device
+    ///
+    /// Returns Ok(false) if there was nothing to initialize. On failure the
+    /// shader stays unavailable and `error` returns the reason.
+    pub fn initialize(&mut self, device: &Device) -> Result<bool, ShaderError> {
+        if self.pipeline.is_some() {
+            return Ok(false); // Already initialized
+        }
+
+        // The descriptor is kept on failure so a fixed source can be reloaded
+        if let Some(descriptor) = self.descriptor.clone() {
+            let shader = match Self::new(device, descriptor) {
+                Ok(shader) => shader,
+                Err(error) => {
+                    self.error = Some(error.clone());
+                    return Err(error);
+                }
+            };
+
+            // Keep defaults, swaps and data that were set before the device existed
+            let uniform_defaults = std::mem::take(&mut self.uniform_defaults);
+            let storage_slots = std::mem::take(&mut self.storage_slots);
+            let pending_storage = std::mem::take(&mut self.pending_storage);
+            *self = shader;
+            self.uniform_defaults = uniform_defaults;
+            self.storage_slots = storage_slots;
+            self.pending_storage = pending_storage;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Recompile the shader from new WGSL, keeping its bindings and storage buffers
+    ///
+    /// On failure the previous pipeline stays in use and the error is returned.
+    pub fn reload(&mut self, device: &Device, source: String, source_map: Option<SourceMap>) -> Result<(), ShaderError> {
+        let Some(descriptor) = &self.descriptor else {
+            return Err(ShaderError::new(&self.name, None, "the shader has not been created from a descriptor"));
+        };
+        let descriptor = ComputeShaderDescriptor { shader_source: source, source_map, ..descriptor.clone() };
+
+        let Some(pipeline_layout) = &self.pipeline_layout else {
+            self.descriptor = Some(descriptor);
+            self.error = None;
+            return self.initialize(device).map(|_| ());
+        };
+
+        Self::check(&descriptor)?;
+        let pipeline = ShaderError::capture(device, &descriptor.name, descriptor.source_path.as_deref(), || {
+            Self::create_pipeline(device, &descriptor, pipeline_layout)
+        })?;
+        self.pipeline = Some(pipeline);
+        self.descriptor = Some(descriptor);
+        Ok(())
+    }
+
+    /// Set the default value of a uniform parameter
+    pub fn set_uniform<T: bytemuck::Pod>(&mut self, name: &str, value: T) {
+        self.uniform_defaults.insert(name.to_string(), bytemuck::bytes_of(&value).to_vec());
+    }
+
+    /// Replace the contents of the buffer currently bound to a storage parameter
+    ///
+    /// The data is written during the next upload, before any dispatch of the
+    /// frame executes, and the buffer grows as needed. `data` must hold a whole
+    /// number of elements. An empty update binds a single zeroed element, since
+    /// `arrayLength` is always at least 1.
+    pub fn update_storage(&mut self, name: &str, data: &[u8]) -> Result<(), String> {
+        let index = self.storage_index(name)?;
+        let element_size = self.storage[index].element_size;
+        if !(data.len() as u64).is_multiple_of(element_size) {
+            return Err(format!(
+                "Storage data for '{}' of compute shader '{}' is {} bytes, which isn't a multiple of its {}-byte elements",
+                name, self.name, data.len(), element_size
+            ));
+        }
+
+        self.pending_storage.insert(self.storage_slots[index], data.to_vec());
+        Ok(())
+    }
+
+    /// Exchange the buffers bound to two storage parameters
+    ///
+    /// Used to ping-pong between an input and an output buffer, e.g. for
+    /// simulation steps. Both parameters must have the same element size.
+    pub fn swap_storage(&mut self, first: &str, second: &str) -> Result<(), String> {
+        let (first, second) = (self.storage_index(first)?, self.storage_index(second)?);
+        if self.storage[first].element_size != self.storage[second].element_size {
+            return Err(format!(
+                "Compute shader '{}' can't swap '{}' and '{}' because their element sizes differ",
+                self.name, self.storage[first].name, self.storage[second].name
+            ));
+        }
+
+        self.storage_slots.swap(first, second);
+        Ok(())
+    }
+
+    /// Get the buffer currently bound to a storage parameter
+    pub fn storage_slot(&self, name: &str) -> Result<usize, String> {
+        Ok(self.storage_slots[self.storage_index(name)?])
+    }
+
+    /// Get a storage buffer, the length of its data and its element size
+    pub fn storage_buffer(&self, slot: usize) -> Option<(&Buffer, u64, u64)> {
+        let (buffer, length) = self.storage_buffers.get(slot)?;
+        Some((buffer, *length, self.storage[slot].element_size))
+    }
+
+    /// Discard the uniform blocks staged for the previous frame
+    pub fn begin_frame(&mut self) {
+        self.uniform_ring.clear();
+        self.frame_slot_sets.clear();
+    }
+
+    /// Stage the uniform values of one dispatch and capture its storage buffers
+    ///
+    /// Values missing from `values` fall back to the defaults set with
+    /// `set_uniform`, then to zero.
+    pub fn stage_dispatch(&mut self, values: &HashMap<String, UniformValue>) -> ComputeBindings {
+        let mut uniforms = self.uniforms.iter().collect::<Vec<_>>();
+        uniforms.sort_by_key(|uniform| (uniform.group, uniform.binding));
+
+        let mut uniform_offsets = vec![Vec::new(); self.uniform_groups.len()];
+        for uniform in uniforms {
+            let mut block = values.get(&uniform.name)
+                .map(|value| value.as_bytes().to_vec())
+                .or_else(|| self.uniform_defaults.get(&uniform.name).cloned())
+                .unwrap_or_default();
+            block.resize(uniform.size as usize, 0);
+            uniform_offsets[uniform.group as usize].push(self.uniform_ring.push(&block));
+        }
+
+        self.frame_slot_sets.insert(self.storage_slots.clone());
+        ComputeBindings {
+            uniform_offsets,
+            storage_slots: self.storage_slots.clone(),
+        }
+    }
+
+    /// Upload the staged uniform blocks and pending storage data, and create the bind groups the frame's dispatches need
+    ///
+    /// Returns the number of bytes written.
+    pub fn upload_bindings(&mut self, device: &Device, queue: &Queue) -> u64 {
+        if self.bind_group_layouts.is_empty() {
+            return 0;
+        }
+
+        let mut uploaded = self.uniform_ring.staged_bytes();
+        let mut buffers_changed = self.uniform_ring.upload(device, queue);
+        for (slot, data) in self.pending_storage.drain() {
+            let storage = &self.storage[slot];
+            let (buffer, length) = &mut self.storage_buffers[slot];
+
+            // Bind exactly the written elements so `arrayLength` matches the data.
+            // A binding can't be empty, so an empty update binds one zeroed element.
+            let data = if data.is_empty() { vec![0; storage.element_size as usize] } else { data };
+            let new_length = data.len() as u64;
+            if new_length > buffer.size() {
+                *buffer = Self::create_storage_buffer(device, &self.name, storage, new_length.next_power_of_two());
+            }
+            queue.write_buffer(buffer, 0, &data);
+            uploaded += data.len() as u64;
+            if new_length != *length {
+                *length = new_length;
+                buffers_changed = true;
+            }
+        }
+
+        for group in 0..self.bind_groups.len() {
+            if buffers_changed {
+                self.bind_groups[group].clear();
+            }
+            self.bind_groups[group].retain(|slots, _| self.frame_slot_sets.contains(slots));
+
+            for slots in &self.frame_slot_sets {
+                if self.bind_groups[group].contains_key(slots) {
+                    continue;
+                }
+                if let Some(bind_group) = self.create_bind_group(device, group, slots) {
+                    self.bind_groups[group].insert(slots.clone(), bind_group);
+                }
+            }
+        }
+        uploaded
+    }
+
+    /// Get the number of bind groups used by uniforms and storage buffers
+    fn group_count(descriptor: &ComputeShaderDescriptor) -> usize {
+        descriptor.uniforms.iter().map(|uniform| uniform.group)
+            .chain(descriptor.storage.iter().map(|storage| storage.group))
+            .map(|group| group as usize + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Find a storage parameter by name
+    fn storage_index(&self, name: &str) -> Result<usize, String> {
+        self.storage.iter().position(|storage| storage.name == name)
+            .ok_or_else(|| format!("Compute shader '{}' has no storage buffer '{}'", self.name, name))
+    }
+
+    /// Create a storage buffer of `size` bytes that can also be drawn as instances or indirect arguments and read back
+    fn create_storage_buffer(device: &Device, name: &str, storage: &StorageParameter, size: u64) -> Buffer {
+        device.create_buffer(&BufferDescriptor {
+            label: Some(&label(&["compute", name, &format!("storage {}", storage.name)])),
+            size: size.next_multiple_of(4),
+            usage: BufferUsages::STORAGE | BufferUsages::VERTEX | BufferUsages::INDIRECT
+                | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Create the bind group of a group with the storage parameters bound to `slots`
+    fn create_bind_group(&self, device: &Device, group: usize, slots: &[usize]) -> Option<BindGroup> {
+        let mut entries = Vec::new();
+        for &(binding, size) in &self.uniform_groups[group] {
+            entries.push(BindGroupEntry {
+                binding,
+                resource: BindingResource::Buffer(BufferBinding {
+                    buffer: self.uniform_ring.buffer()?,
+                    offset: 0,
+                    size: NonZeroU64::new(size),
+                }),
+            });
+        }
+        for (storage, &slot) in self.storage.iter().zip(slots).filter(|(storage, _)| storage.group as usize == group) {
+            let (buffer, length) = self.storage_buffers.get(slot)?;
+            entries.push(BindGroupEntry {
+                binding: storage.binding,
+                resource: BindingResource::Buffer(BufferBinding {
+                    buffer,
+                    offset: 0,
+                    size: NonZeroU64::new(*length),
+                }),
+            });
+        }
+
+        if entries.is_empty() {
+            return None;
+        }
+        Some(device.create_bind_group(&BindGroupDescriptor {
+            label: Some(&label(&["compute", &self.name, &format!("bind group {}", group)])),
+            layout: &self.bind_group_layouts[group],
+            entries: &entries,
+        }))
+    }
+
+    /// Get the compute pipeline
+    pub fn pipeline(&self) -> Option<&ComputePipeline> {
+        self.pipeline.as_ref()
+    }
+
+    /// Get the number of bind groups in the pipeline layout
+    pub fn bind_group_count(&self) -> usize {
+        self.uniform_groups.len()
+    }
+
+    /// Get the bind group of a group for a set of storage slots (None for groups without bindings)
+    pub fn bind_group(&self, group: usize, slots: &[usize]) -> Option<&BindGroup> {
+        self.bind_groups.get(group)?.get(slots)
+    }
+
+    /// Check if the shader is initialized
+    pub fn is_initialized(&self) -> bool {
+        self.pipeline.is_some()
+    }
+
+    /// Get the reason the shader is unavailable, if its initialization failed
+    pub fn error(&self) -> Option<&ShaderError> {
+        self.error.as_ref()
+    }
+
+    /// Get the path of the WGSL file the shader was loaded from
+    pub fn source_path(&self) -> Option<&str> {
+        self.descriptor.as_ref()?.source_path.as_deref()
+    }
+
+    /// Get the source map of the shader's preprocessed WGSL
+    pub fn source_map(&self) -> Option<&SourceMap> {
+        self.descriptor.as_ref()?.source_map.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn life_descriptor() -> ComputeShaderDescriptor {
+        ComputeShaderDescriptor {
+            name: "Life".to_string(),
+            shader_source: String::new(),
+            source_path: None,
+            source_map: None,
+            entry_point: "cs_main".to_string(),
+            uniforms: vec![UniformParameter { name: "size".to_string(), group: 0, binding: 0, size: 8 }],
+            storage: vec![
+                StorageParameter { name: "cells_in".to_string(), group: 0, binding: 1, read_only: true, element_size: 4 },
+                StorageParameter { name: "cells_out".to_string(), group: 0, binding: 2, read_only: false, element_size: 4 },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_validate_rejects_shared_binding() {
+        let mut descriptor = life_descriptor();
+        assert!(descriptor.validate().is_ok());
+
+        descriptor.storage[1].binding = 0;
+        assert!(descriptor.validate().unwrap_err().contains("group 0 binding 0"));
+    }
+
+    #[test]
+    fn test_swap_storage_exchanges_buffers() {
+        let mut shader = ComputeShader::new_placeholder(life_descriptor());
+        assert_eq!(shader.stage_dispatch(&HashMap::new()).storage_slots, vec![0, 1]);
+
+        shader.swap_storage("cells_in", "cells_out").unwrap();
+        assert_eq!(shader.storage_slot("cells_in"), Ok(1));
+        assert_eq!(shader.stage_dispatch(&HashMap::new()).storage_slots, vec![1, 0]);
+
+        // Updates go to the buffer bound at the time of the update, which later swaps don't move
+        shader.update_storage("cells_in", &[0; 16]).unwrap();
+        assert!(shader.pending_storage.contains_key(&1));
+        assert!(shader.update_storage("cells_in", &[0; 6]).unwrap_err().contains("4-byte elements"));
+        assert!(shader.swap_storage("cells_in", "colors").unwrap_err().contains("no storage buffer 'colors'"));
+    }
+
+    #[test]
+    fn test_stage_dispatch_gives_each_dispatch_its_own_uniforms() {
+        let mut shader = ComputeShader::new_placeholder(life_descriptor());
+        let mut values = HashMap::new();
+        values.insert("size".to_string(), UniformValue::Vec2([8.0, 8.0]));
+        assert_eq!(shader.stage_dispatch(&values).uniform_offsets, vec![vec![0]]);
+        assert_eq!(shader.stage_dispatch(&values).uniform_offsets, vec![vec![256]]);
+
+        shader.begin_frame();
+        assert_eq!(shader.stage_dispatch(&values).uniform_offsets, vec![vec![0]]);
+    }
+}
@@ -1,39 +1,188 @@
 // Custom shader implementation
 
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::num::NonZeroU64;
 use wgpu::{
     BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
-    BindGroupLayoutEntry, BindingType, Buffer, BufferBindingType, BufferDescriptor,
-    BufferUsages, Device, PipelineLayoutDescriptor, RenderPipeline,
-    RenderPipelineDescriptor, ShaderModuleDescriptor, ShaderSource, ShaderStages,
-    TextureFormat, VertexBufferLayout, VertexState, FragmentState, ColorTargetState,
-    BlendState, ColorWrites, PrimitiveState, PrimitiveTopology, FrontFace, Face, PolygonMode,
-    MultisampleState, Queue,
+    BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBinding, BufferBindingType,
+    BufferDescriptor, BufferUsages, Device, PipelineLayout, PipelineLayoutDescriptor, RenderPipeline,
+    RenderPipelineDescriptor, Sampler, SamplerBindingType, ShaderModuleDescriptor, ShaderSource, ShaderStages,
+    TextureFormat, TextureSampleType, TextureViewDimension, VertexBufferLayout, VertexState, FragmentState, ColorTargetState,
+    ColorWrites, PrimitiveState, MultisampleState, Queue,
 };
 
 // Import core geometry traits from Foundation
 use rustica_foundation::geometry::VertexAttribute;
 
+use crate::diagnostics::{label, LOG_SHADER};
+use crate::draw_commands::UniformValue;
+use crate::pipeline_state::PipelineState;
+use rustica_wgsl_preprocessor::{SourceMap, WgslPreprocessor};
+use crate::shader_error::ShaderError;
+use crate::shader_features::{FeatureSet, ShaderFeature};
+use crate::texture::{GpuTexture, SamplerConfig, TextureHandle};
+use crate::uniform_layout::UniformBlock;
+use crate::uniform_ring::UniformRing;
+
+/// Uniform block alignment used before the device limits are known
+const DEFAULT_UNIFORM_ALIGNMENT: u64 = 256;
+
 /// Uniform parameter descriptor
+#[derive(Debug, Clone)]
 pub struct UniformParameter {
     /// Name of the parameter
     pub name: String,
-    /// Binding index
+    /// Bind group index
+    pub group: u32,
+    /// Binding index within the group
     pub binding: u32,
     /// Size of the parameter in bytes
     pub size: u64,
 }
 
+/// Texture parameter descriptor (a `texture_2d<f32>` binding)
+#[derive(Debug, Clone)]
+pub struct TextureParameter {
+    /// Name of the parameter, as used with `InstancedShaderDrawBuilder::texture`
+    pub name: String,
+    /// Bind group index
+    pub group: u32,
+    /// Binding index within the group
+    pub binding: u32,
+}
+
+/// Sampler parameter descriptor (a filtering `sampler` binding)
+#[derive(Debug, Clone)]
+pub struct SamplerParameter {
+    /// Name of the parameter
+    pub name: String,
+    /// Bind group index
+    pub group: u32,
+    /// Binding index within the group
+    pub binding: u32,
+    /// Filtering and addressing of the sampler
+    pub config: SamplerConfig,
+}
+
+/// Storage buffer parameter descriptor (a runtime-sized `var<storage>` array)
+#[derive(Debug, Clone)]
+pub struct StorageParameter {
+    /// Name of the parameter, as used with `Canvas::update_storage`
+    pub name: String,
+    /// Bind group index
+    pub group: u32,
+    /// Binding index within the group
+    pub binding: u32,
+    /// Whether the shader only reads the buffer (`var<storage, read>`)
+    pub read_only: bool,
+    /// Size in bytes of one array element
+    pub element_size: u64,
+}
+
 /// Descriptor for a custom WGSL shader
+#[derive(Clone)]
 pub struct ShaderDescriptor {
     /// Name of the shader
     pub name: String,
     /// WGSL shader source code
     pub shader_source: String,
+    /// Path of the WGSL file the source was loaded from, used in error messages
+    pub source_path: Option<String>,
+    /// Where the lines of preprocessed source came from, used in error messages
+    pub source_map: Option<SourceMap>,
+    /// Features the shader can be compiled with, selected per draw
+    pub features: Vec<ShaderFeature>,
     /// Vertex attributes
     pub vertex_attributes: Vec<VertexAttribute>,
     /// Uniform parameters (if any)
     pub uniforms: Vec<UniformParameter>,
+    /// Uniforms packed into one struct buffer, as an alternative to one binding per uniform
+    pub uniform_block: Option<UniformBlock>,
+    /// Texture parameters (if any)
+    pub textures: Vec<TextureParameter>,
+    /// Sampler parameters (if any)
+    pub samplers: Vec<SamplerParameter>,
+    /// Storage buffer parameters (if any)
+    pub storage: Vec<StorageParameter>,
+    /// Instance attributes, one per shader location (empty if the shader isn't instanced)
+    pub instance_attributes: Vec<VertexAttribute>,
+    /// Size in bytes of one instance, used as the instance buffer stride
+    pub instance_stride: u64,
+    /// Topology, culling, blending, depth and entry point settings of the pipeline
+    pub pipeline_state: PipelineState,
+}
+
+impl ShaderDescriptor {
+    /// Check the uniform block layout and that no two uniforms, textures or samplers share a binding
+    pub fn validate(&self) -> Result<(), String> {
+        let mut bindings = std::collections::HashSet::new();
+        let resources = self.uniforms.iter().map(|uniform| (uniform.group, uniform.binding))
+            .chain(self.textures.iter().map(|texture| (texture.group, texture.binding)))
+            .chain(self.samplers.iter().map(|sampler| (sampler.group, sampler.binding)))
+            .chain(self.storage.iter().map(|storage| (storage.group, storage.binding)));
+        for (group, binding) in resources {
+            if !bindings.insert((group, binding)) {
+                return Err(format!(
+                    "Shader '{}' declares group {} binding {} more than once",
+                    self.name, group, binding
+                ));
+            }
+        }
+
+        if let Some(storage) = self.storage.iter().find(|storage| storage.element_size == 0) {
+            return Err(format!("Shader '{}': storage buffer '{}' has zero-sized elements", self.name, storage.name));
+        }
+
+        if let Some(block) = &self.uniform_block {
+            block.validate().map_err(|e| format!("Shader '{}': {}", self.name, e))?;
+            if !bindings.insert((block.group, block.binding)) {
+                return Err(format!(
+                    "Shader '{}' uses group {} binding {} for both its uniform block and a separate uniform",
+                    self.name, block.group, block.binding
+                ));
+            }
+        }
+
+        let mut features = HashSet::new();
+        if let Some(feature) = self.features.iter().find(|feature| !features.insert(&feature.name)) {
+            return Err(format!("Shader '{}' declares feature '{}' more than once", self.name, feature.name));
+        }
+        Ok(())
+    }
+
+    /// Get the descriptor of the variant compiled with a set of features
+    ///
+    /// The source is preprocessed with the defines of the resolved features:
+    /// `NAME` for enabled flags and `NAME_VALUE` for features with values.
+    pub fn variant(&self, features: &FeatureSet) -> Result<ShaderDescriptor, ShaderError> {
+        let file = self.source_path.as_deref();
+        let features = features.resolve(&self.features)
+            .map_err(|message| ShaderError::new(&self.name, file, message))?;
+        let defines = features.defines();
+
+        // Sources expanded from a file are expanded again; others are expanded as they are
+        let preprocessed = match self.source_map.as_ref().and_then(|map| map.reprocess_with(&defines)) {
+            Some(result) => result?,
+            None => defines.iter()
+                .fold(WgslPreprocessor::new(), |preprocessor, name| preprocessor.with_define(name, ""))
+                .process_source(&self.name, file.unwrap_or(&self.name), &self.shader_source)?,
+        };
+        Ok(ShaderDescriptor {
+            shader_source: preprocessed.source,
+            source_map: Some(preprocessed.source_map),
+            ..self.clone()
+        })
+    }
+
+    /// Get the descriptor of the variant compiled when a draw selects no features
+    fn default_variant(&self) -> Result<Cow<'_, ShaderDescriptor>, ShaderError> {
+        if self.features.is_empty() {
+            Ok(Cow::Borrowed(self))
+        } else {
+            self.variant(&FeatureSet::new()).map(Cow::Owned)
+        }
+    }
 }
 
 /// A custom shader implementation
@@ -42,159 +191,214 @@ pub struct CustomShader {
     pub name: String,
     /// Compiled pipeline
     pipeline: Option<RenderPipeline>,
-    /// Bind group layout
-    bind_group_layout: Option<BindGroupLayout>,
-    /// Bind group
-    bind_group: Option<BindGroup>,
-    /// Uniform buffers (if any)
-    uniform_buffers: HashMap<String, Buffer>,
-    /// Original descriptor (for deferred initialization)
+    /// Layout the pipeline was created with (kept to recompile the pipeline on reload)
+    pipeline_layout: Option<PipelineLayout>,
+    /// Resolved features of the variant compiled into `pipeline`
+    default_features: FeatureSet,
+    /// Pipelines of the other variants, compiled when a draw first selects them
+    variants: HashMap<FeatureSet, Result<RenderPipeline, ShaderError>>,
+    /// Bind group layouts, indexed by group (unused groups get an empty layout)
+    bind_group_layouts: Vec<BindGroupLayout>,
+    /// Bind groups, indexed by group and keyed by the textures they bind
+    /// (bound with one dynamic offset per uniform)
+    bind_groups: Vec<HashMap<Vec<TextureHandle>, BindGroup>>,
+    /// Texture sets bound by the draws of the current frame, per group
+    frame_texture_sets: Vec<HashSet<Vec<TextureHandle>>>,
+    /// Binding and size of every uniform buffer binding, indexed by group
+    uniform_groups: Vec<Vec<(u32, u64)>>,
+    /// Data and offsets last staged for each group, reused while the data is unchanged
+    last_staged: Vec<Option<(Vec<u8>, Vec<u32>)>>,
+    /// Uniform parameters
+    uniforms: Vec<UniformParameter>,
+    /// Packed uniform block (if any)
+    uniform_block: Option<UniformBlock>,
+    /// Values used for uniforms that a draw doesn't set
+    uniform_defaults: HashMap<String, Vec<u8>>,
+    /// Texture parameters, sorted by group and binding
+    textures: Vec<TextureParameter>,
+    /// Textures used for texture parameters that a draw doesn't set
+    texture_defaults: HashMap<String, TextureHandle>,
+    /// Sampler parameters
+    samplers: Vec<SamplerParameter>,
+    /// Samplers created from the sampler parameters (empty until initialized)
+    sampler_objects: Vec<Sampler>,
+    /// Storage buffer parameters
+    storage: Vec<StorageParameter>,
+    /// Storage buffers and the length of their data, one per storage parameter (empty until initialized)
+    storage_buffers: Vec<(Buffer, u64)>,
+    /// Storage data waiting to be written during the next upload
+    pending_storage: HashMap<String, Vec<u8>>,
+    /// Per-draw uniform blocks of the current frame
+    uniform_ring: UniformRing,
+    /// Size in bytes of one instance expected by the pipeline
+    instance_stride: u64,
+    /// Original descriptor (for deferred initialization and reloading)
     descriptor: Option<ShaderDescriptor>,
+    /// Why deferred initialization failed (the shader is unavailable until it is replaced)
+    error: Option<ShaderError>,
 }
 
-/// Create the instance buffer attributes for model matrices (4 rows of vec4)
-/// Plus an instance color attribute at the end
-/// Takes a start_location parameter to determine where to begin assigning locations
-pub fn get_instance_attributes(start_location: u32) -> [wgpu::VertexAttribute; 5] {
-    use std::mem;
-    
-    [
-        wgpu::VertexAttribute {
-            offset: 0,
-            shader_location: start_location, // Start at the provided location
-            format: wgpu::VertexFormat::Float32x4,
-        },
-        wgpu::VertexAttribute {
-            offset: mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
-            shader_location: start_location + 1,
-            format: wgpu::VertexFormat::Float32x4,
-        },
-        wgpu::VertexAttribute {
-            offset: mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
-            shader_location: start_location + 2,
-            format: wgpu::VertexFormat::Float32x4,
-        },
-        wgpu::VertexAttribute {
-            offset: mem::size_of::<[f32; 12]>() as wgpu::BufferAddress,
-            shader_location: start_location + 3,
-            format: wgpu::VertexFormat::Float32x4,
-        },
-        wgpu::VertexAttribute {
-            offset: mem::size_of::<[f32; 16]>() as wgpu::BufferAddress,
-            shader_location: start_location + 4, // Location for instance color
-            format: wgpu::VertexFormat::Float32x3,
-        },
-    ]
-}
+impl CustomShader {
+    /// Check a descriptor and its WGSL before any GPU object is created
+    ///
+    /// Catches inconsistent descriptors and WGSL syntax or type errors, with
+    /// the line and column of the problem. Needs no device.
+    pub fn check(descriptor: &ShaderDescriptor) -> Result<(), ShaderError> {
+        Self::checked_default_variant(descriptor).map(|_| ())
+    }
 
-/// Create an instance buffer layout for model matrices (4 rows of vec4)
-/// Takes the count of vertex attributes to correctly assign instance attribute locations
-pub fn create_instance_buffer_layout<'a>(vertex_attr_count: u32) -> wgpu::VertexBufferLayout<'a> {
-    use std::mem;
-    
-    // Create the instance attributes with dynamic starting location
-    let instance_attributes = get_instance_attributes(vertex_attr_count);
-    
-    // Create a boxed slice and leak it to extend its lifetime
-    // This is safe because the attributes are only needed during pipeline creation
-    let attributes = Box::leak(Box::new(instance_attributes));
-    
-    // Dynamically calculate stride based on the actual instance data
-    // The instance data should have a 4x4 matrix (16 floats) and color (3 floats)
-    // This calculates to 19 floats total (no padding)
-    wgpu::VertexBufferLayout {
-        array_stride: mem::size_of::<[f32; 19]>() as wgpu::BufferAddress,
-        step_mode: wgpu::VertexStepMode::Instance,
-        attributes,
+    /// Check a descriptor and get its default variant, with the WGSL checked
+    fn checked_default_variant(descriptor: &ShaderDescriptor) -> Result<Cow<'_, ShaderDescriptor>, ShaderError> {
+        let file = descriptor.source_path.as_deref();
+        descriptor.validate().map_err(|message| ShaderError::new(&descriptor.name, file, message))?;
+        let compiled = descriptor.default_variant()?;
+        Self::check_wgsl(&compiled)?;
+        Ok(compiled)
+    }
+
+    /// Check the WGSL of a descriptor, pointing errors at the original files
+    fn check_wgsl(descriptor: &ShaderDescriptor) -> Result<(), ShaderError> {
+        ShaderError::check_wgsl(&descriptor.name, descriptor.source_path.as_deref(), &descriptor.shader_source)
+            .map_err(|error| error.remap(descriptor.source_map.as_ref()))
     }
-}
 
-impl CustomShader {
     /// Create a new custom shader from a descriptor
-    pub fn new(device: &Device, format: TextureFormat, descriptor: ShaderDescriptor) -> Self {
-        // Create shader module from the provided source code
-        let shader_source = ShaderSource::Wgsl(descriptor.shader_source.into());
-        
-        let shader_module = device.create_shader_module(ShaderModuleDescriptor {
-            label: Some(&format!("{} Shader", descriptor.name)),
-            source: shader_source,
-        });
+    ///
+    /// Fails if the WGSL doesn't compile or the pipeline can't be created
+    /// from the descriptor's layout.
+    pub fn new(device: &Device, format: TextureFormat, descriptor: ShaderDescriptor) -> Result<Self, ShaderError> {
+        let compiled = Self::checked_default_variant(&descriptor)?.into_owned();
+        let name = descriptor.name.clone();
+        let file = descriptor.source_path.clone();
+        ShaderError::capture(device, &name, file.as_deref(), || Self::create(device, format, descriptor, &compiled))
+    }
 
-        // Create bind group layout and uniform buffers if needed
-        let (bind_group_layout, bind_group, uniform_buffers) = if !descriptor.uniforms.is_empty() {
-            // Create bind group layout entries
-            let mut entries = Vec::new();
-            let mut uniform_buffers = HashMap::new();
-            let mut bind_group_entries = Vec::new();
-
-            for uniform in &descriptor.uniforms {
-                // Create bind group layout entry
-                entries.push(BindGroupLayoutEntry {
-                    binding: uniform.binding,
-                    visibility: ShaderStages::VERTEX_FRAGMENT,
-                    ty: BindingType::Buffer {
-                        ty: BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                });
-
-                // Create uniform buffer
-                let buffer = device.create_buffer(&BufferDescriptor {
-                    label: Some(&format!("{} Uniform Buffer", uniform.name)),
-                    size: uniform.size,
-                    usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
-                    mapped_at_creation: false,
-                });
-
-                // Add to uniform buffers map
-                uniform_buffers.insert(uniform.name.clone(), buffer);
-            }
+    /// Create the GPU objects of a shader whose descriptor has been checked
+    ///
+    /// The pipeline is compiled from `compiled`, the descriptor's default variant.
+    fn create(device: &Device, format: TextureFormat, descriptor: ShaderDescriptor, compiled: &ShaderDescriptor) -> Self {
+        // Resolve the instance stride and group count before the descriptor's fields are moved out
+        let instance_stride = Self::instance_stride_of(&descriptor);
+        let group_count = Self::group_count(&descriptor);
 
-            // Create bind group layout
-            let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-                label: Some(&format!("{} Bind Group Layout", descriptor.name)),
-                entries: &entries,
+        // Every draw gets its own uniform blocks in the ring, selected with dynamic offsets
+        let uniforms = descriptor.uniforms.clone();
+        let uniform_block = descriptor.uniform_block.clone();
+        let textures = Self::sorted_textures(descriptor.textures.clone());
+        let samplers = descriptor.samplers.clone();
+        let storage = descriptor.storage.clone();
+        let mut uniform_groups = Self::uniform_groups(&uniforms, uniform_block.as_ref());
+        uniform_groups.resize(group_count, Vec::new());
+        let mut uniform_ring = UniformRing::new(device.limits().min_uniform_buffer_offset_alignment as u64);
+        if uniform_groups.iter().any(|bindings| !bindings.is_empty()) {
+            uniform_ring.reserve(device, 0);
+        }
+
+        // Create one bind group layout per group, including empty ones for gaps
+        let bind_group_layouts = uniform_groups.iter().enumerate().map(|(group, bindings)| {
+            let uniform_entries = bindings.iter().map(|&(binding, size)| BindGroupLayoutEntry {
+                binding,
+                visibility: ShaderStages::VERTEX_FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: NonZeroU64::new(size),
+                },
+                count: None,
+            });
+            let texture_entries = textures.iter().filter(|texture| texture.group as usize == group).map(|texture| BindGroupLayoutEntry {
+                binding: texture.binding,
+                visibility: ShaderStages::VERTEX_FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            });
+            let sampler_entries = samplers.iter().filter(|sampler| sampler.group as usize == group).map(|sampler| BindGroupLayoutEntry {
+                binding: sampler.binding,
+                visibility: ShaderStages::VERTEX_FRAGMENT,
+                ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                count: None,
+            });
+            let storage_entries = storage.iter().filter(|storage| storage.group as usize == group).map(|storage| BindGroupLayoutEntry {
+                binding: storage.binding,
+                // Vertex shaders can't write to storage buffers
+                visibility: if storage.read_only { ShaderStages::VERTEX_FRAGMENT } else { ShaderStages::FRAGMENT },
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Storage { read_only: storage.read_only },
+                    has_dynamic_offset: false,
+                    min_binding_size: NonZeroU64::new(storage.element_size),
+                },
+                count: None,
             });
+            let entries = uniform_entries.chain(texture_entries).chain(sampler_entries).chain(storage_entries).collect::<Vec<_>>();
 
-            // Create bind group entries
-            for uniform in &descriptor.uniforms {
-                if let Some(buffer) = uniform_buffers.get(&uniform.name) {
-                    bind_group_entries.push(BindGroupEntry {
-                        binding: uniform.binding,
-                        resource: buffer.as_entire_binding(),
-                    });
-                }
-            }
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some(&label(&["shader", &descriptor.name, &format!("bind group layout {}", group)])),
+                entries: &entries,
+            })
+        }).collect::<Vec<_>>();
 
-            // Create bind group
-            let bind_group = device.create_bind_group(&BindGroupDescriptor {
-                label: Some(&format!("{} Bind Group", descriptor.name)),
-                layout: &bind_group_layout,
-                entries: &bind_group_entries,
-            });
+        let sampler_objects = samplers.iter().map(|sampler| {
+            device.create_sampler(&sampler.config.descriptor(Some(&label(&["shader", &descriptor.name, &format!("sampler {}", sampler.name)]))))
+        }).collect();
 
-            (Some(bind_group_layout), Some(bind_group), uniform_buffers)
-        } else {
-            (None, None, HashMap::new())
-        };
+        // Until the first update, each storage buffer holds a single zeroed element
+        let storage_buffers = storage.iter().map(|storage| {
+            (Self::create_storage_buffer(device, &descriptor.name, storage, storage.element_size), storage.element_size)
+        }).collect();
 
         // Create pipeline layout
-        let pipeline_layout = if let Some(ref layout) = bind_group_layout {
-            let bind_group_layouts = &[layout];
-            device.create_pipeline_layout(&PipelineLayoutDescriptor {
-                label: Some(&format!("{} Pipeline Layout", descriptor.name)),
-                bind_group_layouts,
-                push_constant_ranges: &[],
-            })
-        } else {
-            device.create_pipeline_layout(&PipelineLayoutDescriptor {
-                label: Some(&format!("{} Pipeline Layout", descriptor.name)),
-                bind_group_layouts: &[],
-                push_constant_ranges: &[],
-            })
-        };
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some(&label(&["shader", &descriptor.name, "pipeline layout"])),
+            bind_group_layouts: &bind_group_layouts.iter().collect::<Vec<_>>(),
+            push_constant_ranges: &[],
+        });
+
+        let default_features = Self::default_features(&descriptor);
+        let pipeline = Self::create_pipeline(device, format, compiled, &default_features, &pipeline_layout);
+
+        Self {
+            name: descriptor.name.clone(),
+            pipeline: Some(pipeline),
+            pipeline_layout: Some(pipeline_layout),
+            default_features,
+            variants: HashMap::new(),
+            bind_group_layouts,
+            bind_groups: (0..group_count).map(|_| HashMap::new()).collect(),
+            frame_texture_sets: vec![HashSet::new(); group_count],
+            last_staged: vec![None; group_count],
+            uniform_groups,
+            uniforms,
+            uniform_block,
+            uniform_defaults: HashMap::new(),
+            textures,
+            texture_defaults: HashMap::new(),
+            samplers,
+            sampler_objects,
+            storage,
+            storage_buffers,
+            pending_storage: HashMap::new(),
+            uniform_ring,
+            instance_stride,
+            descriptor: Some(descriptor),
+            error: None,
+        }
+    }
+
+    /// Compile the WGSL of a descriptor into a render pipeline with the given layout
+    ///
+    /// `variant` names the features the WGSL was preprocessed with, for the debug labels.
+    fn create_pipeline(device: &Device, format: TextureFormat, descriptor: &ShaderDescriptor, variant: &FeatureSet, pipeline_layout: &PipelineLayout) -> RenderPipeline {
+        let instance_stride = Self::instance_stride_of(descriptor);
+
+        // Create shader module from the provided source code
+        let shader_module = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some(&label(&["shader", &descriptor.name, &format!("module ({})", variant)])),
+            source: ShaderSource::Wgsl(descriptor.shader_source.as_str().into()),
+        });
 
         // Create vertex buffer layout
         let vertex_buffer_layout = VertexBufferLayout {
@@ -208,52 +412,66 @@ impl CustomShader {
             }).collect::<Vec<_>>(),
         };
 
-        // Calculate the number of vertex attributes
-        let vertex_attribute_count = descriptor.vertex_attributes.len() as u32;
+        // Create instance buffer layout from the attributes generated for the instance type
+        let instance_attributes = descriptor.instance_attributes.iter().map(|attr| wgpu::VertexAttribute {
+            offset: attr.offset,
+            shader_location: attr.location,
+            format: attr.format,
+        }).collect::<Vec<_>>();
 
-        // Add the instance buffer layout with dynamic location starting after vertex attributes
-        let instance_layout = create_instance_buffer_layout(vertex_attribute_count);
-        
-        // We need to collect the buffer layouts into a Vec because they need to live long enough
-        let mut buffer_layouts = Vec::new();
-        buffer_layouts.push(vertex_buffer_layout);
-        buffer_layouts.push(instance_layout);
+        let mut buffer_layouts = vec![vertex_buffer_layout];
+        if instance_stride != 0 {
+            buffer_layouts.push(VertexBufferLayout {
+                array_stride: instance_stride,
+                step_mode: wgpu::VertexStepMode::Instance,
+                attributes: &instance_attributes,
+            });
+        }
         
         // Create render pipeline with both vertex and instance buffers
-        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
-            label: Some(&format!("{} Pipeline", descriptor.name)),
-            layout: Some(&pipeline_layout),
+        let mut state = descriptor.pipeline_state.clone();
+        if !device.features().contains(state.required_features()) {
+            log::warn!(
+                target: LOG_SHADER,
+                "Shader '{}' uses {:?} polygons, which this device doesn't support; falling back to Fill",
+                descriptor.name, state.polygon_mode
+            );
+            state.polygon_mode = wgpu::PolygonMode::Fill;
+        }
+        device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some(&label(&["shader", &descriptor.name, &format!("pipeline ({})", variant)])),
+            layout: Some(pipeline_layout),
             vertex: VertexState {
                 module: &shader_module,
-                entry_point: Some("vs_main"),
+                entry_point: Some(&state.vertex_entry),
                 buffers: &buffer_layouts,
                 compilation_options: Default::default(),
             },
             fragment: Some(FragmentState {
                 module: &shader_module,
-                entry_point: Some("fs_main"),
+                entry_point: Some(&state.fragment_entry),
                 targets: &[Some(ColorTargetState {
                     format,
-                    blend: Some(BlendState::REPLACE),
+                    blend: Some(state.blend.blend_state()),
                     write_mask: ColorWrites::ALL,
                 })],
                 compilation_options: Default::default(),
             }),
             primitive: PrimitiveState {
-                topology: PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: FrontFace::Ccw,
-                cull_mode: Some(Face::Back),
-                polygon_mode: PolygonMode::Fill,
+                topology: state.topology,
+                strip_index_format: state.strip_index_format(),
+                front_face: state.front_face,
+                cull_mode: state.cull_mode,
+                polygon_mode: state.polygon_mode,
                 unclipped_depth: false,
                 conservative: false,
             },
             depth_stencil: Some(wgpu::DepthStencilState {
                 format: TextureFormat::Depth32Float,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less, // Standard depth test
+                depth_write_enabled: state.depth_write,
+                depth_compare: state.effective_depth_compare(),
                 stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
+                bias: state.depth_bias,
             }),
             multisample: MultisampleState {
                 count: 1,
@@ -262,67 +480,672 @@ impl CustomShader {
             },
             multiview: None,
             cache: None,
-        });
-
-        Self {
-            name: descriptor.name.clone(),
-            pipeline: Some(pipeline),
-            bind_group_layout,
-            bind_group,
-            uniform_buffers,
-            descriptor: None, // No need to store the descriptor since we've already initialized
-        }
+        })
     }
     
     /// Create a placeholder shader that will be initialized later
     pub fn new_placeholder(descriptor: ShaderDescriptor) -> Self {
+        let group_count = Self::group_count(&descriptor);
+        let mut uniform_groups = Self::uniform_groups(&descriptor.uniforms, descriptor.uniform_block.as_ref());
+        uniform_groups.resize(group_count, Vec::new());
         Self {
             name: descriptor.name.clone(),
             pipeline: None,
-            bind_group_layout: None,
-            bind_group: None,
-            uniform_buffers: HashMap::new(),
+            pipeline_layout: None,
+            default_features: Self::default_features(&descriptor),
+            variants: HashMap::new(),
+            bind_group_layouts: Vec::new(),
+            bind_groups: Vec::new(),
+            frame_texture_sets: vec![HashSet::new(); group_count],
+            last_staged: vec![None; group_count],
+            uniform_groups,
+            uniforms: descriptor.uniforms.clone(),
+            uniform_block: descriptor.uniform_block.clone(),
+            uniform_defaults: HashMap::new(),
+            textures: Self::sorted_textures(descriptor.textures.clone()),
+            texture_defaults: HashMap::new(),
+            samplers: descriptor.samplers.clone(),
+            sampler_objects: Vec::new(),
+            storage: descriptor.storage.clone(),
+            storage_buffers: Vec::new(),
+            pending_storage: HashMap::new(),
+            uniform_ring: UniformRing::new(DEFAULT_UNIFORM_ALIGNMENT),
+            instance_stride: Self::instance_stride_of(&descriptor),
             descriptor: Some(descriptor),
+            error: None,
         }
     }
     
     /// Initialize the shader with a device and format
-    pub fn initialize(&mut self, device: &Device, format: TextureFormat) -> bool {
+    ///
+    /// Returns Ok(false) if there was nothing to initialize. On failure the
+    /// shader stays unavailable and `error` returns the reason.
+    pub fn initialize(&mut self, device: &Device, format: TextureFormat) -> Result<bool, ShaderError> {
         if self.pipeline.is_some() {
-            return false; // Already initialized
+            return Ok(false); // Already initialized
         }
         
-        if let Some(descriptor) = self.descriptor.take() {
-            let initialized = Self::new(device, format, descriptor);
-            self.pipeline = initialized.pipeline;
-            self.bind_group_layout = initialized.bind_group_layout;
-            self.bind_group = initialized.bind_group;
-            self.uniform_buffers = initialized.uniform_buffers;
-            true
+        // The descriptor is kept on failure so a fixed source can be reloaded
+        if let Some(descriptor) = self.descriptor.clone() {
+            let shader = match Self::new(device, format, descriptor) {
+                Ok(shader) => shader,
+                Err(error) => {
+                    self.error = Some(error.clone());
+                    return Err(error);
+                }
+            };
+
+            // Keep defaults that were set before the device existed
+            let uniform_defaults = std::mem::take(&mut self.uniform_defaults);
+            let texture_defaults = std::mem::take(&mut self.texture_defaults);
+            let pending_storage = std::mem::take(&mut self.pending_storage);
+            *self = shader;
+            self.uniform_defaults = uniform_defaults;
+            self.texture_defaults = texture_defaults;
+            self.pending_storage = pending_storage;
+            Ok(true)
         } else {
-            false
+            Ok(false)
+        }
+    }
+
+    /// Recompile the shader from new WGSL, keeping its bindings, defaults and storage
+    ///
+    /// The new source must fit the existing layout. On failure the previous
+    /// pipeline stays in use and the error is returned. A shader whose
+    /// initialization failed is initialized from the new source instead.
+    pub fn reload(&mut self, device: &Device, format: TextureFormat, source: String, source_map: Option<SourceMap>) -> Result<(), ShaderError> {
+        let Some(descriptor) = &self.descriptor else {
+            return Err(ShaderError::new(&self.name, None, "the shader has not been created from a descriptor"));
+        };
+        let descriptor = ShaderDescriptor { shader_source: source, source_map, ..descriptor.clone() };
+
+        let Some(pipeline_layout) = &self.pipeline_layout else {
+            self.descriptor = Some(descriptor);
+            self.error = None;
+            return self.initialize(device, format).map(|_| ());
+        };
+
+        let compiled = Self::checked_default_variant(&descriptor)?;
+        let pipeline = ShaderError::capture(device, &descriptor.name, descriptor.source_path.as_deref(), || {
+            Self::create_pipeline(device, format, &compiled, &self.default_features, pipeline_layout)
+        })?;
+        self.pipeline = Some(pipeline);
+        self.variants.clear();
+        self.descriptor = Some(descriptor);
+        Ok(())
+    }
+
+    /// Compile the variant of the shader for a set of features, unless it is cached
+    ///
+    /// Returns the resolved features, which select the variant's pipeline in
+    /// `variant_pipeline`. Variants that fail to compile are cached as well, so
+    /// each is only compiled once.
+    pub fn prepare_variant(&mut self, device: &Device, format: TextureFormat, features: &FeatureSet) -> Result<FeatureSet, ShaderError> {
+        let (Some(descriptor), Some(pipeline_layout)) = (&self.descriptor, &self.pipeline_layout) else {
+            return Err(ShaderError::new(&self.name, None, "the shader is not initialized"));
+        };
+        let features = features.resolve(&descriptor.features)
+            .map_err(|message| ShaderError::new(&self.name, descriptor.source_path.as_deref(), message))?;
+        if features == self.default_features {
+            return Ok(features);
+        }
+
+        let pipeline = self.variants.entry(features.clone()).or_insert_with(|| {
+            let variant = descriptor.variant(&features)?;
+            Self::check_wgsl(&variant)?;
+            ShaderError::capture(device, &variant.name, variant.source_path.as_deref(), || {
+                Self::create_pipeline(device, format, &variant, &features, pipeline_layout)
+            })
+        });
+        match pipeline {
+            Ok(_) => Ok(features),
+            Err(error) => Err(ShaderError { message: format!("variant {}: {}", features, error.message), ..error.clone() }),
         }
     }
 
-    /// Set a uniform parameter value
-    pub fn set_uniform<T: bytemuck::Pod>(&self, name: &str, value: T, queue: Option<&Queue>) {
-        if let (Some(buffer), Some(q)) = (self.uniform_buffers.get(name), queue) {
-            q.write_buffer(buffer, 0, bytemuck::cast_slice(&[value]));
+    /// Get the pipeline of a variant prepared with `prepare_variant`
+    pub fn variant_pipeline(&self, features: &FeatureSet) -> Option<&RenderPipeline> {
+        if *features == self.default_features {
+            self.pipeline.as_ref()
+        } else {
+            self.variants.get(features)?.as_ref().ok()
         }
     }
 
+    /// Get the number of variants compiled besides the default one
+    pub fn variant_count(&self) -> usize {
+        self.variants.values().filter(|pipeline| pipeline.is_ok()).count()
+    }
+
+    /// Get the resolved features of the default variant
+    fn default_features(descriptor: &ShaderDescriptor) -> FeatureSet {
+        FeatureSet::new().resolve(&descriptor.features).unwrap_or_default()
+    }
+
+    /// Set the default value of a uniform parameter
+    ///
+    /// The default persists across frames and is used by every draw that
+    /// doesn't set the uniform itself.
+    pub fn set_uniform<T: bytemuck::Pod>(&mut self, name: &str, value: T) {
+        self.uniform_defaults.insert(name.to_string(), bytemuck::bytes_of(&value).to_vec());
+    }
+
+    /// Set the default texture of a texture parameter
+    ///
+    /// The default is used by every draw that doesn't set the texture itself.
+    /// Texture parameters without a default sample a 1x1 white texture.
+    pub fn set_texture(&mut self, name: &str, texture: TextureHandle) {
+        self.texture_defaults.insert(name.to_string(), texture);
+    }
+
+    /// Replace the contents of a storage buffer
+    ///
+    /// The data is written during the next upload, before the frame's draws
+    /// execute, and the buffer grows as needed. `data` must hold a whole number
+    /// of elements. An empty update binds a single zeroed element, since
+    /// `arrayLength` is always at least 1.
+    pub fn update_storage(&mut self, name: &str, data: &[u8]) -> Result<(), String> {
+        let Some(storage) = self.storage.iter().find(|storage| storage.name == name) else {
+            return Err(format!("Shader '{}' has no storage buffer '{}'", self.name, name));
+        };
+        if !(data.len() as u64).is_multiple_of(storage.element_size) {
+            return Err(format!(
+                "Storage data for '{}' of shader '{}' is {} bytes, which isn't a multiple of its {}-byte elements",
+                name, self.name, data.len(), storage.element_size
+            ));
+        }
+
+        self.pending_storage.insert(name.to_string(), data.to_vec());
+        Ok(())
+    }
+
+    /// Discard the uniform blocks staged for the previous frame
+    pub fn begin_frame(&mut self) {
+        self.uniform_ring.clear();
+        self.last_staged = vec![None; self.uniform_groups.len()];
+        for texture_sets in &mut self.frame_texture_sets {
+            texture_sets.clear();
+        }
+    }
+
+    /// Resolve the textures of one draw, per group and ordered by binding
+    ///
+    /// Textures missing from `values` fall back to the defaults set with
+    /// `set_texture`, then to `TextureHandle::WHITE`. Fails if a handle doesn't
+    /// refer to a texture in `textures`.
+    pub fn stage_textures(&mut self, values: &HashMap<String, TextureHandle>, textures: &[GpuTexture]) -> Result<Vec<Vec<TextureHandle>>, String> {
+        let mut texture_sets = vec![Vec::new(); self.uniform_groups.len()];
+        for texture in &self.textures {
+            let handle = values.get(&texture.name)
+                .or_else(|| self.texture_defaults.get(&texture.name))
+                .copied()
+                .unwrap_or(TextureHandle::WHITE);
+            if handle.0 >= textures.len() {
+                return Err(format!("Shader '{}': texture '{}' refers to unknown texture {}", self.name, texture.name, handle.0));
+            }
+            texture_sets[texture.group as usize].push(handle);
+        }
+
+        for (group, texture_set) in texture_sets.iter().enumerate() {
+            self.frame_texture_sets[group].insert(texture_set.clone());
+        }
+        Ok(texture_sets)
+    }
+
+    /// Stage the uniform values of one draw and return its dynamic offsets
+    ///
+    /// Values missing from `values` fall back to the defaults set with
+    /// `set_uniform`, then to zero. The offsets are returned per group and
+    /// ordered by binding, as expected by `set_bind_group`. A group whose data
+    /// didn't change since the previous draw reuses its offsets, so per-frame
+    /// groups like the camera stay bound while per-object groups change. Fails
+    /// if a value doesn't match the type declared in the uniform block.
+    pub fn stage_uniforms(&mut self, values: &HashMap<String, UniformValue>) -> Result<Vec<Vec<u32>>, String> {
+        let lookup = |name: &str| values.get(name)
+            .map(UniformValue::as_bytes)
+            .or_else(|| self.uniform_defaults.get(name).map(Vec::as_slice));
+
+        let mut blocks = Vec::with_capacity(self.uniforms.len() + 1);
+        for uniform in &self.uniforms {
+            let mut block = lookup(&uniform.name).map(<[u8]>::to_vec).unwrap_or_default();
+            block.resize(uniform.size as usize, 0);
+            blocks.push((uniform.group, uniform.binding, block));
+        }
+        if let Some(uniform_block) = &self.uniform_block {
+            let block = uniform_block.pack(lookup)
+                .map_err(|e| format!("Shader '{}': {}", self.name, e))?;
+            blocks.push((uniform_block.group, uniform_block.binding, block));
+        }
+        blocks.sort_by_key(|(group, binding, _)| (*group, *binding));
+
+        let mut offsets = vec![Vec::new(); self.uniform_groups.len()];
+        for (group, group_offsets) in offsets.iter_mut().enumerate() {
+            let group_blocks = blocks.iter()
+                .filter(|(block_group, _, _)| *block_group as usize == group)
+                .map(|(_, _, block)| block.as_slice())
+                .collect::<Vec<_>>();
+            let data = group_blocks.concat();
+
+            if let Some((last_data, last_offsets)) = &self.last_staged[group] && *last_data == data {
+                group_offsets.clone_from(last_offsets);
+                continue;
+            }
+
+            *group_offsets = group_blocks.iter().map(|block| self.uniform_ring.push(block)).collect();
+            self.last_staged[group] = Some((data, group_offsets.clone()));
+        }
+        Ok(offsets)
+    }
+
+    /// Upload the staged uniform blocks and pending storage data, and create the bind groups the frame's draws need
+    ///
+    /// Bind groups are cached per set of textures. The cache is dropped if the
+    /// uniform ring grew or a storage buffer changed size, and bind groups no
+    /// draw used this frame are evicted. Returns the number of bytes written.
+    pub fn upload_bindings(&mut self, device: &Device, queue: &Queue, textures: &[GpuTexture]) -> u64 {
+        if self.bind_group_layouts.is_empty() {
+            return 0;
+        }
+
+        let mut uploaded = self.uniform_ring.staged_bytes();
+        let mut buffers_changed = self.uniform_ring.upload(device, queue);
+        for (storage, (buffer, length)) in self.storage.iter().zip(&mut self.storage_buffers) {
+            let Some(data) = self.pending_storage.remove(&storage.name) else {
+                continue;
+            };
+
+            // Bind exactly the written elements so `arrayLength` matches the data.
+            // A binding can't be empty, so an empty update binds one zeroed element.
+            let data = if data.is_empty() { vec![0; storage.element_size as usize] } else { data };
+            let new_length = data.len() as u64;
+            if new_length > buffer.size() {
+                *buffer = Self::create_storage_buffer(device, &self.name, storage, new_length.next_power_of_two());
+            }
+            queue.write_buffer(buffer, 0, &data);
+            uploaded += data.len() as u64;
+            if new_length != *length {
+                *length = new_length;
+                buffers_changed = true;
+            }
+        }
+
+        if buffers_changed {
+            for bind_groups in &mut self.bind_groups {
+                bind_groups.clear();
+            }
+        }
+
+        for group in 0..self.bind_groups.len() {
+            let texture_sets = &self.frame_texture_sets[group];
+            self.bind_groups[group].retain(|texture_set, _| texture_sets.contains(texture_set));
+
+            for texture_set in texture_sets {
+                if self.bind_groups[group].contains_key(texture_set) {
+                    continue;
+                }
+                if let Some(bind_group) = self.create_bind_group(device, group, texture_set, textures) {
+                    self.bind_groups[group].insert(texture_set.clone(), bind_group);
+                }
+            }
+        }
+        uploaded
+    }
+
+    /// Get the binding and size of every uniform buffer binding, per group and sorted by binding
+    pub(crate) fn uniform_groups(uniforms: &[UniformParameter], uniform_block: Option<&UniformBlock>) -> Vec<Vec<(u32, u64)>> {
+        let bindings = uniforms.iter()
+            .map(|uniform| (uniform.group, uniform.binding, uniform.size))
+            .chain(uniform_block.map(|block| (block.group, block.binding, block.size)))
+            .collect::<Vec<_>>();
+
+        let group_count = bindings.iter().map(|(group, _, _)| *group as usize + 1).max().unwrap_or(0);
+        let mut groups = vec![Vec::new(); group_count];
+        for (group, binding, size) in bindings {
+            groups[group as usize].push((binding, size));
+        }
+        for group in &mut groups {
+            group.sort_by_key(|(binding, _)| *binding);
+        }
+        groups
+    }
+
+    /// Get the number of bind groups used by uniforms, textures, samplers and storage buffers
+    fn group_count(descriptor: &ShaderDescriptor) -> usize {
+        descriptor.uniforms.iter().map(|uniform| uniform.group)
+            .chain(descriptor.uniform_block.as_ref().map(|block| block.group))
+            .chain(descriptor.textures.iter().map(|texture| texture.group))
+            .chain(descriptor.samplers.iter().map(|sampler| sampler.group))
+            .chain(descriptor.storage.iter().map(|storage| storage.group))
+            .map(|group| group as usize + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Create a storage buffer of `size` bytes (rounded up to the required 4-byte multiple)
+    fn create_storage_buffer(device: &Device, name: &str, storage: &StorageParameter, size: u64) -> Buffer {
+        device.create_buffer(&BufferDescriptor {
+            label: Some(&label(&["shader", name, &format!("storage {}", storage.name)])),
+            size: size.next_multiple_of(4),
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Sort texture parameters by group and binding, the order `stage_textures` resolves them in
+    fn sorted_textures(mut textures: Vec<TextureParameter>) -> Vec<TextureParameter> {
+        textures.sort_by_key(|texture| (texture.group, texture.binding));
+        textures
+    }
+
+    /// Create the bind group of a group for one set of textures
+    ///
+    /// Uniform bindings expose a window of the uniform ring each. Returns None
+    /// for groups without any binding, or if a texture isn't uploaded yet.
+    fn create_bind_group(&self, device: &Device, group: usize, texture_set: &[TextureHandle], textures: &[GpuTexture]) -> Option<BindGroup> {
+        let group_textures = self.textures.iter().filter(|texture| texture.group as usize == group);
+        let group_samplers = self.samplers.iter().zip(&self.sampler_objects).filter(|(sampler, _)| sampler.group as usize == group);
+
+        let mut entries = Vec::new();
+        for &(binding, size) in &self.uniform_groups[group] {
+            entries.push(BindGroupEntry {
+                binding,
+                resource: BindingResource::Buffer(BufferBinding {
+                    buffer: self.uniform_ring.buffer()?,
+                    offset: 0,
+                    size: NonZeroU64::new(size),
+                }),
+            });
+        }
+        for (texture, handle) in group_textures.zip(texture_set) {
+            entries.push(BindGroupEntry {
+                binding: texture.binding,
+                resource: BindingResource::TextureView(textures.get(handle.0)?.view()?),
+            });
+        }
+        for (sampler, sampler_object) in group_samplers {
+            entries.push(BindGroupEntry {
+                binding: sampler.binding,
+                resource: BindingResource::Sampler(sampler_object),
+            });
+        }
+        let group_storage = self.storage.iter().zip(&self.storage_buffers).filter(|(storage, _)| storage.group as usize == group);
+        for (storage, (buffer, length)) in group_storage {
+            entries.push(BindGroupEntry {
+                binding: storage.binding,
+                resource: BindingResource::Buffer(BufferBinding {
+                    buffer,
+                    offset: 0,
+                    size: NonZeroU64::new(*length),
+                }),
+            });
+        }
+
+        if entries.is_empty() {
+            return None;
+        }
+        Some(device.create_bind_group(&BindGroupDescriptor {
+            label: Some(&label(&["shader", &self.name, &format!("bind group {}", group)])),
+            layout: &self.bind_group_layouts[group],
+            entries: &entries,
+        }))
+    }
+
+    /// Get the instance stride of a descriptor (zero if it has no instance attributes)
+    fn instance_stride_of(descriptor: &ShaderDescriptor) -> u64 {
+        if descriptor.instance_attributes.is_empty() {
+            0
+        } else {
+            descriptor.instance_stride
+        }
+    }
+
+    /// Check that an instance type of `size` bytes matches the instance layout
+    pub fn validate_instance_size(&self, size: usize) -> Result<(), String> {
+        if self.instance_stride != 0 && self.instance_stride != size as u64 {
+            return Err(format!(
+                "Instance data for shader '{}' is {} bytes per instance, but the shader's instance layout expects {} bytes",
+                self.name, size, self.instance_stride
+            ));
+        }
+        Ok(())
+    }
+
+    /// Get the size in bytes of one instance (zero if the shader isn't instanced)
+    pub fn instance_stride(&self) -> u64 {
+        self.instance_stride
+    }
+
+    /// Check if the shader reads per-instance attributes
+    pub fn has_instance_layout(&self) -> bool {
+        self.instance_stride != 0
+    }
+
+    /// Get the topology, culling, blending and depth settings of the pipeline
+    pub fn pipeline_state(&self) -> Option<&PipelineState> {
+        self.descriptor.as_ref().map(|descriptor| &descriptor.pipeline_state)
+    }
+
     /// Get the render pipeline
     pub fn pipeline(&self) -> Option<&RenderPipeline> {
         self.pipeline.as_ref()
     }
 
-    /// Get the bind group
-    pub fn bind_group(&self) -> Option<&BindGroup> {
-        self.bind_group.as_ref()
+    /// Check if a group has no uniform, texture, sampler or storage bindings
+    pub fn group_is_empty(&self, group: u32) -> bool {
+        self.uniform_groups.get(group as usize).is_none_or(Vec::is_empty)
+            && !self.textures.iter().any(|texture| texture.group == group)
+            && !self.samplers.iter().any(|sampler| sampler.group == group)
+            && !self.storage.iter().any(|storage| storage.group == group)
+    }
+
+    /// Get the bind group of a group for a set of textures (None for groups without bindings)
+    pub fn bind_group(&self, group: u32, texture_set: &[TextureHandle]) -> Option<&BindGroup> {
+        self.bind_groups.get(group as usize)?.get(texture_set)
     }
     
     /// Check if the shader is initialized
     pub fn is_initialized(&self) -> bool {
         self.pipeline.is_some()
     }
+
+    /// Get the reason the shader is unavailable, if its initialization failed
+    pub fn error(&self) -> Option<&ShaderError> {
+        self.error.as_ref()
+    }
+
+    /// Get the path of the WGSL file the shader was loaded from
+    pub fn source_path(&self) -> Option<&str> {
+        self.descriptor.as_ref()?.source_path.as_deref()
+    }
+
+    /// Get the source map of the shader's preprocessed WGSL
+    pub fn source_map(&self) -> Option<&SourceMap> {
+        self.descriptor.as_ref()?.source_map.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uniform_layout::UniformType;
+    use wgpu::VertexFormat;
+
+    fn instanced_descriptor() -> ShaderDescriptor {
+        ShaderDescriptor {
+            name: "Instanced".to_string(),
+            shader_source: String::new(),
+            source_path: None,
+            source_map: None,
+            features: Vec::new(),
+            vertex_attributes: Vec::new(),
+            uniforms: Vec::new(),
+            uniform_block: None,
+            textures: Vec::new(),
+            samplers: Vec::new(),
+            storage: Vec::new(),
+            instance_attributes: vec![VertexAttribute {
+                name: "color".to_string(),
+                location: 0,
+                format: VertexFormat::Float32x3,
+                offset: 0,
+                semantic: None,
+            }],
+            instance_stride: 12,
+            pipeline_state: PipelineState::default(),
+        }
+    }
+
+    #[test]
+    fn test_validate_instance_size() {
+        let shader = CustomShader::new_placeholder(instanced_descriptor());
+        assert!(shader.has_instance_layout());
+        assert!(shader.validate_instance_size(12).is_ok());
+
+        // A padded instance type must be rejected instead of silently misreading the buffer
+        let error = shader.validate_instance_size(16).unwrap_err();
+        assert!(error.contains("16 bytes"));
+        assert!(error.contains("expects 12 bytes"));
+    }
+
+    #[test]
+    fn test_shader_without_instance_attributes_accepts_any_instance_size() {
+        let mut descriptor = instanced_descriptor();
+        descriptor.instance_attributes.clear();
+
+        let shader = CustomShader::new_placeholder(descriptor);
+        assert!(!shader.has_instance_layout());
+        assert!(shader.validate_instance_size(64).is_ok());
+    }
+
+    #[test]
+    fn test_stage_uniforms_uses_draw_values_then_defaults() {
+        let mut descriptor = instanced_descriptor();
+        descriptor.uniforms = vec![
+            UniformParameter { name: "time".to_string(), group: 0, binding: 1, size: 4 },
+            UniformParameter { name: "tint".to_string(), group: 0, binding: 0, size: 16 },
+        ];
+
+        let mut shader = CustomShader::new_placeholder(descriptor);
+        shader.set_uniform("time", 2.0f32);
+
+        // Offsets follow binding order, one aligned block per uniform
+        let mut values = HashMap::new();
+        values.insert("tint".to_string(), UniformValue::Vec4([1.0, 0.0, 0.0, 1.0]));
+        assert_eq!(shader.stage_uniforms(&values).unwrap(), vec![vec![0, 256]]);
+        assert_eq!(shader.stage_uniforms(&HashMap::new()).unwrap(), vec![vec![512, 768]]);
+
+        shader.begin_frame();
+        assert_eq!(shader.stage_uniforms(&values).unwrap(), vec![vec![0, 256]]);
+    }
+
+    #[test]
+    fn test_uniform_block_is_staged_as_one_binding() {
+        let mut descriptor = instanced_descriptor();
+        descriptor.uniforms = vec![UniformParameter { name: "tint".to_string(), group: 0, binding: 1, size: 16 }];
+        descriptor.uniform_block = Some(UniformBlock::new(0, vec![
+            ("view", UniformType::Mat4),
+            ("time", UniformType::F32),
+        ]));
+        assert!(descriptor.validate().is_ok());
+
+        let mut shader = CustomShader::new_placeholder(descriptor);
+        let mut values = HashMap::new();
+        values.insert("time".to_string(), UniformValue::Float(1.0));
+        assert_eq!(shader.stage_uniforms(&values).unwrap(), vec![vec![0, 256]]);
+
+        // A value of the wrong type is rejected instead of corrupting the block
+        values.insert("time".to_string(), UniformValue::Vec2([1.0, 2.0]));
+        assert!(shader.stage_uniforms(&values).unwrap_err().contains("'time' is 8 bytes"));
+    }
+
+    #[test]
+    fn test_uniform_block_packs_mat3_per_draw() {
+        let mut descriptor = instanced_descriptor();
+        descriptor.uniform_block = Some(UniformBlock::new(0, vec![
+            ("time", UniformType::F32),
+            ("normal_matrix", UniformType::Mat3),
+        ]));
+        assert!(descriptor.validate().is_ok());
+
+        // Each draw gets its own block, with the columns padded by `pack`
+        let mut shader = CustomShader::new_placeholder(descriptor);
+        let mut values = HashMap::new();
+        values.insert("normal_matrix".to_string(), UniformValue::from(glam::Mat3::IDENTITY));
+        assert_eq!(shader.stage_uniforms(&values).unwrap(), vec![vec![0]]);
+        values.insert("normal_matrix".to_string(), UniformValue::from([[2.0f32, 0.0, 0.0], [0.0, 2.0, 0.0], [0.0, 0.0, 2.0]]));
+        assert_eq!(shader.stage_uniforms(&values).unwrap(), vec![vec![256]]);
+        assert_eq!(values["normal_matrix"].as_bytes().len(), 36);
+    }
+
+    #[test]
+    fn test_validate_rejects_shared_binding() {
+        let mut descriptor = instanced_descriptor();
+        descriptor.uniforms = vec![UniformParameter { name: "tint".to_string(), group: 0, binding: 0, size: 16 }];
+        descriptor.uniform_block = Some(UniformBlock::new(0, vec![("time", UniformType::F32)]));
+        assert!(descriptor.validate().unwrap_err().contains("binding 0"));
+    }
+
+    #[test]
+    fn test_unchanged_groups_reuse_their_offsets() {
+        let mut descriptor = instanced_descriptor();
+        descriptor.uniforms = vec![
+            UniformParameter { name: "view_proj".to_string(), group: 0, binding: 0, size: 64 },
+            UniformParameter { name: "model".to_string(), group: 2, binding: 0, size: 64 },
+        ];
+        assert!(descriptor.validate().is_ok());
+
+        let mut shader = CustomShader::new_placeholder(descriptor);
+        let mut values = HashMap::new();
+        values.insert("view_proj".to_string(), UniformValue::Mat4([[1.0; 4]; 4]));
+        values.insert("model".to_string(), UniformValue::Mat4([[2.0; 4]; 4]));
+        assert_eq!(shader.stage_uniforms(&values).unwrap(), vec![vec![0], vec![], vec![256]]);
+
+        // Only the per-object group gets a new block
+        values.insert("model".to_string(), UniformValue::Mat4([[3.0; 4]; 4]));
+        assert_eq!(shader.stage_uniforms(&values).unwrap(), vec![vec![0], vec![], vec![512]]);
+    }
+
+    #[test]
+    fn test_validate_allows_same_binding_in_different_groups() {
+        let mut descriptor = instanced_descriptor();
+        descriptor.uniforms = vec![
+            UniformParameter { name: "camera".to_string(), group: 0, binding: 0, size: 64 },
+            UniformParameter { name: "model".to_string(), group: 1, binding: 0, size: 64 },
+        ];
+        assert!(descriptor.validate().is_ok());
+
+        descriptor.uniforms[1].group = 0;
+        assert!(descriptor.validate().unwrap_err().contains("group 0 binding 0"));
+    }
+
+    #[test]
+    fn test_variant_defines_selected_features() {
+        let mut descriptor = instanced_descriptor();
+        descriptor.features = vec![ShaderFeature::flag("USE_TEXTURE"), ShaderFeature::choice("LIGHTING", &["PHONG", "UNLIT"])];
+        descriptor.shader_source = "#ifdef USE_TEXTURE\ntextured\n#endif\n#ifdef LIGHTING_UNLIT\nunlit\n#else\nlit\n#endif\n".to_string();
+
+        let default = descriptor.variant(&FeatureSet::new()).unwrap();
+        assert_eq!(default.shader_source, "lit\n");
+        let features = FeatureSet::new().with_flag("USE_TEXTURE").with_value("LIGHTING", "UNLIT");
+        let variant = descriptor.variant(&features).unwrap();
+        assert_eq!(variant.shader_source, "textured\nunlit\n");
+        assert_eq!(variant.source_map.unwrap().locate(2).map(|(_, line)| line), Some(5));
+        assert!(descriptor.variant(&FeatureSet::new().with_flag("SHADOWS")).is_err());
+
+        descriptor.features.push(ShaderFeature::flag("USE_TEXTURE"));
+        assert!(descriptor.validate().unwrap_err().contains("feature 'USE_TEXTURE'"));
+    }
+
+    #[test]
+    fn test_update_storage_checks_name_and_element_size() {
+        let mut descriptor = instanced_descriptor();
+        descriptor.storage = vec![StorageParameter { name: "lights".to_string(), group: 0, binding: 0, read_only: true, element_size: 32 }];
+        assert!(descriptor.validate().is_ok());
+
+        let mut shader = CustomShader::new_placeholder(descriptor);
+        assert!(shader.update_storage("lights", &[0; 64]).is_ok());
+        assert!(shader.update_storage("lights", &[0; 48]).unwrap_err().contains("32-byte elements"));
+        assert!(shader.update_storage("cells", &[0; 32]).unwrap_err().contains("no storage buffer 'cells'"));
+    }
 }
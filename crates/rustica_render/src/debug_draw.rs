@@ -0,0 +1,189 @@
+// Immediate-mode debug lines and shapes
+
+use glam::{Mat4, Vec3};
+use rustica_foundation::geometry::{Aabb, BoundingSphere, Frustum};
+
+/// Segments used for each circle of a wire sphere
+const CIRCLE_SEGMENTS: usize = 24;
+
+/// A colored end point of a debug line
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct DebugVertex {
+    pub position: [f32; 3],
+    pub color: [f32; 4],
+}
+
+/// Colored lines and wireframe shapes collected during a frame
+///
+/// Get it with `Canvas::debug`. Everything is drawn after the frame's other
+/// draws with a line-list pipeline, in world space transformed by the view
+/// projection, so bounding volumes and paths can be shown without writing a
+/// shader. Lines are depth tested against the scene unless depth testing is
+/// turned off, which draws the following lines on top of everything.
+#[derive(Debug, Clone)]
+pub struct DebugDraw {
+    view_projection: Mat4,
+    depth_test: bool,
+    /// Vertices of depth-tested lines, two per line
+    depth_tested: Vec<DebugVertex>,
+    /// Vertices of lines drawn on top of the scene, two per line
+    overlay: Vec<DebugVertex>,
+}
+
+impl Default for DebugDraw {
+    fn default() -> Self {
+        Self {
+            view_projection: Mat4::IDENTITY,
+            depth_test: true,
+            depth_tested: Vec::new(),
+            overlay: Vec::new(),
+        }
+    }
+}
+
+impl DebugDraw {
+    /// Set the matrix transforming world positions to clip space (identity by default)
+    pub fn view_projection(&mut self, view_projection: Mat4) -> &mut Self {
+        self.view_projection = view_projection;
+        self
+    }
+
+    /// Test the following lines against the depth buffer, or draw them on top of the scene
+    pub fn depth_test(&mut self, enabled: bool) -> &mut Self {
+        self.depth_test = enabled;
+        self
+    }
+
+    /// Draw a line segment
+    pub fn line(&mut self, start: Vec3, end: Vec3, color: [f32; 4]) -> &mut Self {
+        let lines = if self.depth_test { &mut self.depth_tested } else { &mut self.overlay };
+        lines.push(DebugVertex { position: start.to_array(), color });
+        lines.push(DebugVertex { position: end.to_array(), color });
+        self
+    }
+
+    /// Draw lines through consecutive points, back to the first if `closed`
+    pub fn polyline(&mut self, points: &[Vec3], closed: bool, color: [f32; 4]) -> &mut Self {
+        for pair in points.windows(2) {
+            self.line(pair[0], pair[1], color);
+        }
+        if closed && let (Some(&first), Some(&last)) = (points.first(), points.last()) {
+            self.line(last, first, color);
+        }
+        self
+    }
+
+    /// Draw the edges of an axis-aligned box
+    pub fn aabb(&mut self, aabb: &Aabb, color: [f32; 4]) -> &mut Self {
+        let (min, max) = (aabb.min, aabb.max);
+        self.box_edges([
+            Vec3::new(min.x, min.y, min.z), Vec3::new(max.x, min.y, min.z),
+            Vec3::new(max.x, max.y, min.z), Vec3::new(min.x, max.y, min.z),
+            Vec3::new(min.x, min.y, max.z), Vec3::new(max.x, min.y, max.z),
+            Vec3::new(max.x, max.y, max.z), Vec3::new(min.x, max.y, max.z),
+        ], color)
+    }
+
+    /// Draw a sphere as three circles around its axes
+    pub fn sphere(&mut self, sphere: &BoundingSphere, color: [f32; 4]) -> &mut Self {
+        for (u, v) in [(Vec3::X, Vec3::Y), (Vec3::Y, Vec3::Z), (Vec3::Z, Vec3::X)] {
+            let circle: Vec<Vec3> = (0..CIRCLE_SEGMENTS).map(|segment| {
+                let angle = segment as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+                sphere.center + (u * angle.cos() + v * angle.sin()) * sphere.radius
+            }).collect();
+            self.polyline(&circle, true, color);
+        }
+        self
+    }
+
+    /// Draw the X, Y and Z axes of a transform in red, green and blue
+    pub fn axes(&mut self, transform: &Mat4, length: f32) -> &mut Self {
+        let origin = transform.transform_point3(Vec3::ZERO);
+        for (axis, color) in [(Vec3::X, [1.0, 0.0, 0.0, 1.0]), (Vec3::Y, [0.0, 1.0, 0.0, 1.0]), (Vec3::Z, [0.0, 0.0, 1.0, 1.0])] {
+            self.line(origin, transform.transform_point3(axis * length), color);
+        }
+        self
+    }
+
+    /// Draw a square grid on the XZ plane, centered on the origin
+    pub fn grid(&mut self, size: f32, divisions: u32, color: [f32; 4]) -> &mut Self {
+        let half = size / 2.0;
+        let divisions = divisions.max(1);
+        for line in 0..=divisions {
+            let offset = line as f32 / divisions as f32 * size - half;
+            self.line(Vec3::new(offset, 0.0, -half), Vec3::new(offset, 0.0, half), color);
+            self.line(Vec3::new(-half, 0.0, offset), Vec3::new(half, 0.0, offset), color);
+        }
+        self
+    }
+
+    /// Draw the edges of a camera's frustum, e.g. from `Camera::frustum`
+    pub fn frustum(&mut self, frustum: &Frustum, color: [f32; 4]) -> &mut Self {
+        self.box_edges(frustum.corners(), color)
+    }
+
+    /// Draw an arrow from `start` to `end` with a head a fifth of its length
+    pub fn arrow(&mut self, start: Vec3, end: Vec3, color: [f32; 4]) -> &mut Self {
+        let direction = end - start;
+        let length = direction.length();
+        if length == 0.0 {
+            return self;
+        }
+        self.line(start, end, color);
+
+        let back = direction / length * (length * 0.2);
+        let side = direction.any_orthonormal_vector() * (length * 0.08);
+        let up = direction.cross(side).normalize() * side.length();
+        for wing in [side, -side, up, -up] {
+            self.line(end, end - back + wing, color);
+        }
+        self
+    }
+
+    /// Check if no lines were drawn
+    pub fn is_empty(&self) -> bool {
+        self.depth_tested.is_empty() && self.overlay.is_empty()
+    }
+
+    /// Take the lines drawn so far, as the view projection and the depth-tested and overlay vertices
+    pub(crate) fn take(&mut self) -> (Mat4, Vec<DebugVertex>, Vec<DebugVertex>) {
+        (self.view_projection, std::mem::take(&mut self.depth_tested), std::mem::take(&mut self.overlay))
+    }
+
+    /// Draw the 12 edges between eight corners: four of one face, then the four opposite them
+    fn box_edges(&mut self, corners: [Vec3; 8], color: [f32; 4]) -> &mut Self {
+        for face in 0..4 {
+            let next = (face + 1) % 4;
+            self.line(corners[face], corners[next], color);
+            self.line(corners[face + 4], corners[next + 4], color);
+            self.line(corners[face], corners[face + 4], color);
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shapes_add_lines_to_the_current_depth_mode() {
+        let mut debug = DebugDraw::default();
+        debug.aabb(&Aabb::new(Vec3::ZERO, Vec3::ONE), [1.0; 4])
+            .grid(10.0, 4, [0.5; 4])
+            .depth_test(false)
+            .arrow(Vec3::ZERO, Vec3::Y, [1.0; 4])
+            .sphere(&BoundingSphere::new(Vec3::ZERO, 1.0), [1.0; 4]);
+
+        let (_, depth_tested, overlay) = debug.take();
+        assert_eq!(depth_tested.len(), 2 * (12 + 10));
+        assert_eq!(overlay.len(), 2 * (5 + 3 * CIRCLE_SEGMENTS));
+        assert!(debug.is_empty());
+
+        let frustum = Frustum::from_view_projection(&Mat4::orthographic_rh(-1.0, 1.0, -2.0, 2.0, 1.0, 5.0));
+        let corners = frustum.corners();
+        assert!(corners[0].abs_diff_eq(Vec3::new(-1.0, -2.0, -1.0), 1e-5));
+        assert!(corners[6].abs_diff_eq(Vec3::new(1.0, 2.0, -5.0), 1e-5));
+    }
+}
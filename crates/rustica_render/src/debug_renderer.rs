@@ -0,0 +1,174 @@
+// Line-list pipelines drawing the debug lines of a frame
+
+use glam::Mat4;
+use wgpu::{
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType,
+    BlendState, Buffer, BufferBindingType, BufferDescriptor, BufferUsages, ColorTargetState, ColorWrites,
+    CompareFunction, DepthStencilState, Device, FragmentState, MultisampleState, PipelineLayoutDescriptor,
+    PrimitiveState, PrimitiveTopology, Queue, RenderPass, RenderPipeline, RenderPipelineDescriptor,
+    ShaderModuleDescriptor, ShaderSource, ShaderStages, TextureFormat, VertexBufferLayout, VertexState,
+    VertexStepMode,
+};
+
+use crate::debug_draw::DebugVertex;
+use crate::diagnostics::label;
+use crate::instance_buffer::InstanceBuffer;
+
+const SHADER: &str = r#"
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+}
+
+@group(0) @binding(0) var<uniform> view_projection: mat4x4<f32>;
+
+@vertex
+fn vs_main(@location(0) position: vec3<f32>, @location(1) color: vec4<f32>) -> VertexOutput {
+    return VertexOutput(view_projection * vec4<f32>(position, 1.0), color);
+}
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    return input.color;
+}
+"#;
+
+/// Draws the lines collected by `DebugDraw` in the frame's render pass
+pub(crate) struct DebugRenderer {
+    /// Pipeline testing lines against the depth buffer
+    depth_tested: RenderPipeline,
+    /// Pipeline drawing lines on top of the scene
+    overlay: RenderPipeline,
+    uniform_buffer: Buffer,
+    bind_group: BindGroup,
+    /// Vertex buffers of the depth-tested and overlay lines, reused across
+    /// frames, with the current frame's vertex count of each
+    frame_lines: [(InstanceBuffer, u32); 2],
+}
+
+impl DebugRenderer {
+    /// Create the pipelines for a color target format and the frame's depth buffer
+    pub fn new(device: &Device, format: TextureFormat) -> Self {
+        let module = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some(&label(&["debug lines", "module"])),
+            source: ShaderSource::Wgsl(SHADER.into()),
+        });
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some(&label(&["debug lines", "bind group layout"])),
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::VERTEX,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some(&label(&["debug lines", "pipeline layout"])),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let uniform_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some(&label(&["debug lines", "view projection"])),
+            size: std::mem::size_of::<Mat4>() as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some(&label(&["debug lines", "bind group"])),
+            layout: &bind_group_layout,
+            entries: &[BindGroupEntry { binding: 0, resource: uniform_buffer.as_entire_binding() }],
+        });
+
+        let pipeline = |name: &str, depth_compare: CompareFunction| {
+            device.create_render_pipeline(&RenderPipelineDescriptor {
+                label: Some(&label(&["debug lines", name])),
+                layout: Some(&layout),
+                vertex: VertexState {
+                    module: &module,
+                    entry_point: Some("vs_main"),
+                    buffers: &[VertexBufferLayout {
+                        array_stride: std::mem::size_of::<DebugVertex>() as u64,
+                        step_mode: VertexStepMode::Vertex,
+                        attributes: &wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x4],
+                    }],
+                    compilation_options: Default::default(),
+                },
+                fragment: Some(FragmentState {
+                    module: &module,
+                    entry_point: Some("fs_main"),
+                    targets: &[Some(ColorTargetState {
+                        format,
+                        blend: Some(BlendState::ALPHA_BLENDING),
+                        write_mask: ColorWrites::ALL,
+                    })],
+                    compilation_options: Default::default(),
+                }),
+                primitive: PrimitiveState {
+                    topology: PrimitiveTopology::LineList,
+                    ..Default::default()
+                },
+                // Lines never write depth, so they don't hide each other or later overlays
+                depth_stencil: Some(DepthStencilState {
+                    format: TextureFormat::Depth32Float,
+                    depth_write_enabled: false,
+                    depth_compare,
+                    stencil: Default::default(),
+                    bias: Default::default(),
+                }),
+                multisample: MultisampleState::default(),
+                multiview: None,
+                cache: None,
+            })
+        };
+
+        Self {
+            depth_tested: pipeline("depth tested pipeline", CompareFunction::LessEqual),
+            overlay: pipeline("overlay pipeline", CompareFunction::Always),
+            uniform_buffer,
+            bind_group,
+            frame_lines: [
+                (InstanceBuffer::with_label(label(&["debug lines", "depth tested", "vertices"])), 0),
+                (InstanceBuffer::with_label(label(&["debug lines", "overlay", "vertices"])), 0),
+            ],
+        }
+    }
+
+    /// Upload the lines of a frame, returning the number of bytes written
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        view_projection: &Mat4,
+        depth_tested: &[DebugVertex],
+        overlay: &[DebugVertex],
+    ) -> u64 {
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(view_projection));
+        for ((buffer, count), vertices) in self.frame_lines.iter_mut().zip([depth_tested, overlay]) {
+            buffer.clear();
+            buffer.push(bytemuck::cast_slice(vertices));
+            buffer.upload(device, queue);
+            *count = vertices.len() as u32;
+        }
+        (std::mem::size_of::<Mat4>() + std::mem::size_of_val(depth_tested) + std::mem::size_of_val(overlay)) as u64
+    }
+
+    /// Draw the prepared lines, returning the number of draw calls
+    pub fn draw(&self, render_pass: &mut RenderPass<'_>) -> u32 {
+        let mut draw_calls = 0;
+        for (pipeline, (buffer, vertex_count)) in [&self.depth_tested, &self.overlay].into_iter().zip(&self.frame_lines) {
+            let Some(buffer) = buffer.buffer().filter(|_| *vertex_count > 0) else {
+                continue;
+            };
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, &self.bind_group, &[]);
+            render_pass.set_vertex_buffer(0, buffer.slice(..));
+            render_pass.draw(0..*vertex_count, 0..1);
+            draw_calls += 1;
+        }
+        draw_calls
+    }
+}
@@ -0,0 +1,26 @@
+// Log targets and debug labels of the renderer
+//
+// The renderer logs through the `log` facade; install any logger (e.g.
+// `env_logger`) to see the messages. Filter on these targets to pick what
+// gets reported, e.g. `RUST_LOG=rustica::frame=debug`.
+
+/// Shader compilation, initialization and reloading
+pub const LOG_SHADER: &str = "rustica::shader";
+
+/// Draws, dispatches and storage updates that were skipped, and per-draw traces
+pub const LOG_DRAW: &str = "rustica::draw";
+
+/// A summary of every frame, at debug level
+pub const LOG_FRAME: &str = "rustica::frame";
+
+/// Adapter and device setup, and surface errors
+pub const LOG_DEVICE: &str = "rustica::device";
+
+/// Build the debug label of a GPU object
+///
+/// Labels are `rustica/` followed by the parts joined with `/`, from the
+/// owner to the object, e.g. `rustica/shader/Phong/pipeline`. Graphics
+/// debuggers show them in captures and wgpu quotes them in validation errors.
+pub(crate) fn label(parts: &[&str]) -> String {
+    format!("rustica/{}", parts.join("/"))
+}
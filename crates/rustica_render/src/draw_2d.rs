@@ -0,0 +1,281 @@
+// Immediate-mode 2D shapes and images drawn over the 3D scene
+
+use glam::{Mat4, Vec2};
+
+use crate::font::GlyphQuad;
+use crate::texture::TextureHandle;
+
+/// Segments used for a full circle
+const CIRCLE_SEGMENTS: usize = 48;
+
+/// Segments used for each corner of a rounded rectangle
+const CORNER_SEGMENTS: usize = 8;
+
+/// Longest miter of a polyline joint, in multiples of half the line width
+const MITER_LIMIT: f32 = 4.0;
+
+/// Units of the positions and sizes passed to `Draw2d`
+///
+/// Both spaces put the origin at the top left corner of the target with y
+/// pointing down.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CoordinateSpace {
+    /// Pixels of the render target
+    #[default]
+    Pixels,
+    /// Fractions of the target's width and height, from 0 to 1
+    Normalized,
+}
+
+impl CoordinateSpace {
+    /// Get the orthographic projection from this space to clip space for a target size
+    pub(crate) fn projection(&self, target_size: (u32, u32)) -> Mat4 {
+        let (width, height) = match self {
+            CoordinateSpace::Pixels => (target_size.0.max(1) as f32, target_size.1.max(1) as f32),
+            CoordinateSpace::Normalized => (1.0, 1.0),
+        };
+        Mat4::orthographic_rh(0.0, width, height, 0.0, -1.0, 1.0)
+    }
+}
+
+/// A textured, colored corner of a 2D triangle
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct Vertex2d {
+    pub position: [f32; 2],
+    pub uv: [f32; 2],
+    pub color: [f32; 4],
+}
+
+/// Consecutive triangles sharing a texture and coordinate space
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Batch2d {
+    pub texture: TextureHandle,
+    pub space: CoordinateSpace,
+    pub vertex_count: u32,
+}
+
+/// Filled and stroked shapes and textured quads collected during a frame
+///
+/// Get it with `Canvas::draw_2d`. Everything is drawn in the order it was
+/// recorded after the 3D scene and the debug lines, with alpha blending and no
+/// depth test. Shapes are merged into one draw; only switching between
+/// textures starts a new one.
+#[derive(Debug, Clone, Default)]
+pub struct Draw2d {
+    space: CoordinateSpace,
+    vertices: Vec<Vertex2d>,
+    batches: Vec<Batch2d>,
+}
+
+impl Draw2d {
+    /// Set the units of the following shapes (pixels by default)
+    pub fn coordinates(&mut self, space: CoordinateSpace) -> &mut Self {
+        self.space = space;
+        self
+    }
+
+    /// Fill a rectangle given its top left corner and size
+    pub fn fill_rect(&mut self, position: Vec2, size: Vec2, color: [f32; 4]) -> &mut Self {
+        self.fill_convex(&rect_outline(position, size), color)
+    }
+
+    /// Draw the outline of a rectangle, centered on its edges
+    pub fn stroke_rect(&mut self, position: Vec2, size: Vec2, width: f32, color: [f32; 4]) -> &mut Self {
+        self.stroke_rounded_rect(position, size, 0.0, width, color)
+    }
+
+    /// Fill a rectangle with corners rounded to `radius`
+    pub fn fill_rounded_rect(&mut self, position: Vec2, size: Vec2, radius: f32, color: [f32; 4]) -> &mut Self {
+        self.fill_convex(&rounded_outline(position, size, radius), color)
+    }
+
+    /// Draw the outline of a rounded rectangle, centered on its edges
+    pub fn stroke_rounded_rect(&mut self, position: Vec2, size: Vec2, radius: f32, width: f32, color: [f32; 4]) -> &mut Self {
+        let half = Vec2::splat(width / 2.0);
+        let outer = rounded_outline(position - half, size + half * 2.0, radius + width / 2.0);
+        let inner = rounded_outline(position + half, size - half * 2.0, radius - width / 2.0);
+        self.fill_ring(&outer, &inner, color)
+    }
+
+    /// Fill a circle
+    pub fn fill_circle(&mut self, center: Vec2, radius: f32, color: [f32; 4]) -> &mut Self {
+        self.fill_convex(&circle_outline(center, radius), color)
+    }
+
+    /// Draw the outline of a circle, centered on its edge
+    pub fn stroke_circle(&mut self, center: Vec2, radius: f32, width: f32, color: [f32; 4]) -> &mut Self {
+        let outer = circle_outline(center, radius + width / 2.0);
+        let inner = circle_outline(center, (radius - width / 2.0).max(0.0));
+        self.fill_ring(&outer, &inner, color)
+    }
+
+    /// Draw a line of the given width through consecutive points, back to the first if `closed`
+    ///
+    /// Joints are mitered, with the miter cut short at very sharp corners.
+    pub fn polyline(&mut self, points: &[Vec2], closed: bool, width: f32, color: [f32; 4]) -> &mut Self {
+        if points.len() < 2 {
+            return self;
+        }
+        let count = points.len();
+        let direction = |from: usize, to: usize| (points[to] - points[from]).normalize_or_zero();
+        let offsets: Vec<Vec2> = (0..count).map(|index| {
+            let before = (closed || index > 0).then(|| direction((index + count - 1) % count, index));
+            let after = (closed || index + 1 < count).then(|| direction(index, (index + 1) % count));
+            let normal_before = before.or(after).unwrap_or_default().perp();
+            let normal_after = after.or(before).unwrap_or_default().perp();
+            let miter = (normal_before + normal_after).normalize_or(normal_after);
+            let scale = (1.0 / miter.dot(normal_after).max(1e-3)).min(MITER_LIMIT);
+            miter * scale * width / 2.0
+        }).collect();
+
+        let segments = if closed { count } else { count - 1 };
+        for segment in 0..segments {
+            let (start, end) = (segment, (segment + 1) % count);
+            self.quad([
+                points[start] + offsets[start], points[end] + offsets[end],
+                points[end] - offsets[end], points[start] - offsets[start],
+            ], [Vec2::ZERO; 4], TextureHandle::WHITE, color);
+        }
+        self
+    }
+
+    /// Draw a whole texture stretched over a rectangle, multiplied by `tint`
+    pub fn textured_quad(&mut self, position: Vec2, size: Vec2, texture: TextureHandle, tint: [f32; 4]) -> &mut Self {
+        self.textured_quad_region(position, size, texture, Vec2::ZERO, Vec2::ONE, tint)
+    }
+
+    /// Draw the part of a texture between two texture coordinates over a rectangle, e.g. a sprite of an atlas
+    pub fn textured_quad_region(
+        &mut self,
+        position: Vec2,
+        size: Vec2,
+        texture: TextureHandle,
+        uv_min: Vec2,
+        uv_max: Vec2,
+        tint: [f32; 4],
+    ) -> &mut Self {
+        let uvs = [uv_min, Vec2::new(uv_max.x, uv_min.y), uv_max, Vec2::new(uv_min.x, uv_max.y)];
+        self.quad(rect_outline(position, size), uvs, texture, tint)
+    }
+
+    /// Draw laid out glyphs of a font atlas, positioned in pixels
+    pub(crate) fn glyphs(&mut self, quads: &[GlyphQuad], atlas: TextureHandle, color: [f32; 4]) -> &mut Self {
+        let space = std::mem::replace(&mut self.space, CoordinateSpace::Pixels);
+        for quad in quads {
+            self.textured_quad_region(quad.position, quad.size, atlas, quad.uv_min, quad.uv_max, color);
+        }
+        self.space = space;
+        self
+    }
+
+    /// Check if nothing was drawn
+    pub fn is_empty(&self) -> bool {
+        self.vertices.is_empty()
+    }
+
+    /// Take the triangles drawn so far and the batches they are split into
+    pub(crate) fn take(&mut self) -> (Vec<Vertex2d>, Vec<Batch2d>) {
+        (std::mem::take(&mut self.vertices), std::mem::take(&mut self.batches))
+    }
+
+    /// Fill a convex outline as a fan of triangles
+    fn fill_convex(&mut self, outline: &[Vec2], color: [f32; 4]) -> &mut Self {
+        for index in 1..outline.len().saturating_sub(1) {
+            self.triangle([outline[0], outline[index], outline[index + 1]], [Vec2::ZERO; 3], TextureHandle::WHITE, color);
+        }
+        self
+    }
+
+    /// Fill the area between two closed outlines with the same number of points
+    fn fill_ring(&mut self, outer: &[Vec2], inner: &[Vec2], color: [f32; 4]) -> &mut Self {
+        let count = outer.len().min(inner.len());
+        for index in 0..count {
+            let next = (index + 1) % count;
+            self.quad([outer[index], outer[next], inner[next], inner[index]], [Vec2::ZERO; 4], TextureHandle::WHITE, color);
+        }
+        self
+    }
+
+    /// Add a quad given its corners in order around it
+    fn quad(&mut self, corners: [Vec2; 4], uvs: [Vec2; 4], texture: TextureHandle, color: [f32; 4]) -> &mut Self {
+        self.triangle([corners[0], corners[1], corners[2]], [uvs[0], uvs[1], uvs[2]], texture, color);
+        self.triangle([corners[0], corners[2], corners[3]], [uvs[0], uvs[2], uvs[3]], texture, color)
+    }
+
+    /// Add a triangle, extending the last batch if it has the same texture and space
+    fn triangle(&mut self, corners: [Vec2; 3], uvs: [Vec2; 3], texture: TextureHandle, color: [f32; 4]) -> &mut Self {
+        for (corner, uv) in corners.into_iter().zip(uvs) {
+            self.vertices.push(Vertex2d { position: corner.to_array(), uv: uv.to_array(), color });
+        }
+        match self.batches.last_mut() {
+            Some(batch) if batch.texture == texture && batch.space == self.space => batch.vertex_count += 3,
+            _ => self.batches.push(Batch2d { texture, space: self.space, vertex_count: 3 }),
+        }
+        self
+    }
+}
+
+/// Get the corners of a rectangle, clockwise on screen from the top left
+fn rect_outline(position: Vec2, size: Vec2) -> [Vec2; 4] {
+    [position, position + Vec2::new(size.x, 0.0), position + size, position + Vec2::new(0.0, size.y)]
+}
+
+/// Get points around a circle
+fn circle_outline(center: Vec2, radius: f32) -> Vec<Vec2> {
+    (0..CIRCLE_SEGMENTS).map(|segment| {
+        let angle = segment as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+        center + Vec2::from_angle(angle) * radius
+    }).collect()
+}
+
+/// Get points around a rounded rectangle, always the same number for any radius
+fn rounded_outline(position: Vec2, size: Vec2, radius: f32) -> Vec<Vec2> {
+    let size = size.max(Vec2::ZERO);
+    let radius = radius.clamp(0.0, size.min_element() / 2.0);
+    let corners = [
+        (position + Vec2::new(size.x - radius, size.y - radius), 0.0),
+        (position + Vec2::new(radius, size.y - radius), 1.0),
+        (position + Vec2::splat(radius), 2.0),
+        (position + Vec2::new(size.x - radius, radius), 3.0),
+    ];
+    corners.into_iter().flat_map(|(center, quarter)| {
+        (0..=CORNER_SEGMENTS).map(move |segment| {
+            let angle = (quarter + segment as f32 / CORNER_SEGMENTS as f32) * std::f32::consts::FRAC_PI_2;
+            center + Vec2::from_angle(angle) * radius
+        })
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shapes_batch_by_texture_and_space() {
+        let texture = TextureHandle(1);
+        let mut shapes = Draw2d::default();
+        shapes.fill_rect(Vec2::ZERO, Vec2::splat(10.0), [1.0; 4])
+            .stroke_circle(Vec2::splat(20.0), 5.0, 2.0, [1.0; 4])
+            .polyline(&[Vec2::ZERO, Vec2::X, Vec2::ONE], false, 1.0, [1.0; 4])
+            .textured_quad(Vec2::ZERO, Vec2::ONE, texture, [1.0; 4])
+            .coordinates(CoordinateSpace::Normalized)
+            .fill_rounded_rect(Vec2::ZERO, Vec2::splat(0.5), 0.1, [1.0; 4]);
+
+        let (vertices, batches) = shapes.take();
+        let counts: Vec<_> = batches.iter().map(|batch| (batch.texture, batch.space, batch.vertex_count)).collect();
+        let rounded_points = 4 * (CORNER_SEGMENTS + 1);
+        assert_eq!(counts, [
+            (TextureHandle::WHITE, CoordinateSpace::Pixels, (6 + 6 * CIRCLE_SEGMENTS + 12) as u32),
+            (texture, CoordinateSpace::Pixels, 6),
+            (TextureHandle::WHITE, CoordinateSpace::Normalized, (3 * (rounded_points - 2)) as u32),
+        ]);
+        assert_eq!(vertices.len() as u32, counts.iter().map(|count| count.2).sum::<u32>());
+        assert!(shapes.is_empty());
+
+        // Pixels map from the top left corner to clip space
+        let projection = CoordinateSpace::Pixels.projection((200, 100));
+        assert!(projection.transform_point3(glam::Vec3::ZERO).truncate().abs_diff_eq(Vec2::new(-1.0, 1.0), 1e-6));
+        assert!(projection.transform_point3(glam::Vec3::new(200.0, 100.0, 0.0)).truncate().abs_diff_eq(Vec2::new(1.0, -1.0), 1e-6));
+    }
+}
@@ -1,9 +1,15 @@
 // Drawing commands for the rendering system
 
 use std::collections::HashMap;
+use crate::compute_shader::ComputeHandle;
+use crate::debug_draw::DebugVertex;
+use crate::draw_2d::{Batch2d, Vertex2d};
+use crate::mesh::MeshHandle;
+use crate::shader_features::FeatureSet;
+use crate::texture::TextureHandle;
 
 /// Uniform value that can be passed to a shader
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum UniformValue {
     /// Float value
     Float(f32),
@@ -13,6 +19,8 @@ pub enum UniformValue {
     Vec3([f32; 3]),
     /// Vec4 value
     Vec4([f32; 4]),
+    /// Mat3 value, packed with padded columns in uniform blocks
+    Mat3([[f32; 3]; 3]),
     /// Mat4 value
     Mat4([[f32; 4]; 4]),
     /// Integer value
@@ -21,6 +29,22 @@ pub enum UniformValue {
     UInt(u32),
 }
 
+impl UniformValue {
+    /// Get the raw bytes of the value as laid out in a uniform buffer
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            UniformValue::Float(val) => bytemuck::bytes_of(val),
+            UniformValue::Vec2(val) => bytemuck::bytes_of(val),
+            UniformValue::Vec3(val) => bytemuck::bytes_of(val),
+            UniformValue::Vec4(val) => bytemuck::bytes_of(val),
+            UniformValue::Mat3(val) => bytemuck::bytes_of(val),
+            UniformValue::Mat4(val) => bytemuck::bytes_of(val),
+            UniformValue::Int(val) => bytemuck::bytes_of(val),
+            UniformValue::UInt(val) => bytemuck::bytes_of(val),
+        }
+    }
+}
+
 /// A draw command for the rendering system
 #[derive(Debug)]
 pub enum DrawCommand {
@@ -30,9 +54,95 @@ pub enum DrawCommand {
         vertices: Vec<u8>,         // Raw vertex data
         indices: Vec<u8>,          // Index data for indexed rendering
         instances: Vec<u8>,        // Raw instance data
+        instance_size: usize,      // Size of one instance in bytes
         vertex_count: u32,
         index_count: u32,
         instance_count: u32,
         uniforms: HashMap<String, UniformValue>, // Global uniforms
+        textures: HashMap<String, TextureHandle>, // Textures by parameter name
+        features: FeatureSet,      // Features selecting the shader variant
+    },
+    /// Draw a mesh that was uploaded with `RenderWindow::upload_geometry`
+    MeshWithInstances {
+        shader_id: usize,          // Reference to the shader in the registry
+        mesh: MeshHandle,          // Reference to the mesh in the registry
+        instances: Vec<u8>,        // Raw instance data
+        instance_size: usize,      // Size of one instance in bytes
+        instance_count: u32,
+        uniforms: HashMap<String, UniformValue>, // Global uniforms
+        textures: HashMap<String, TextureHandle>, // Textures by parameter name
+        features: FeatureSet,      // Features selecting the shader variant
+    },
+    /// Replace the contents of a shader's storage buffer before the frame's draws execute
+    UpdateStorage {
+        shader_id: usize,          // Reference to the shader in the registry
+        name: String,              // Name of the storage parameter
+        data: Vec<u8>,             // Raw element data
+    },
+    /// Draw a mesh with one instance per element of a compute shader's storage buffer
+    MeshWithStorageInstances {
+        shader_id: usize,          // Reference to the shader in the registry
+        mesh: MeshHandle,          // Reference to the mesh in the registry
+        compute: ComputeHandle,    // Compute shader owning the instance data
+        storage: String,           // Name of the storage parameter holding the instances
+        uniforms: HashMap<String, UniformValue>, // Global uniforms
+        textures: HashMap<String, TextureHandle>, // Textures by parameter name
+        features: FeatureSet,      // Features selecting the shader variant
+    },
+    /// Draw a mesh with draw arguments and instances from a compute shader's storage buffers
+    MeshIndirect {
+        shader_id: usize,          // Reference to the shader in the registry
+        mesh: MeshHandle,          // Reference to the mesh in the registry
+        compute: ComputeHandle,    // Compute shader owning the instances and arguments
+        storage: String,           // Name of the storage parameter holding the instances
+        arguments: String,         // Name of the storage parameter holding `DrawIndexedIndirect` elements
+        draw_count: u32,           // Number of argument elements to draw, from the first
+        uniforms: HashMap<String, UniformValue>, // Global uniforms
+        textures: HashMap<String, TextureHandle>, // Textures by parameter name
+        features: FeatureSet,      // Features selecting the shader variant
+    },
+    /// Draw the lines collected by `Canvas::debug` after the frame's other draws
+    DebugLines {
+        view_projection: [[f32; 4]; 4], // World to clip space transform
+        depth_tested: Vec<DebugVertex>, // Line vertices tested against the depth buffer
+        overlay: Vec<DebugVertex>,      // Line vertices drawn on top of the scene
+    },
+    /// Draw the shapes collected by `Canvas::draw_2d` over everything else
+    Shapes2d {
+        vertices: Vec<Vertex2d>, // Triangle corners in the coordinates they were recorded in
+        batches: Vec<Batch2d>,   // Texture and coordinate space of consecutive vertices
     },
+    /// Run a compute shader before the frame's render pass
+    Dispatch {
+        compute: ComputeHandle,    // Reference to the compute shader in the registry
+        workgroups: [u32; 3],      // Number of workgroups in x, y and z
+        uniforms: HashMap<String, UniformValue>, // Uniforms of this dispatch
+    },
+    /// Replace the contents of a compute shader's storage buffer before the frame's dispatches execute
+    UpdateComputeStorage {
+        compute: ComputeHandle,    // Reference to the compute shader in the registry
+        name: String,              // Name of the storage parameter
+        data: Vec<u8>,             // Raw element data
+    },
+    /// Exchange the buffers bound to two storage parameters of a compute shader
+    SwapStorage {
+        compute: ComputeHandle,    // Reference to the compute shader in the registry
+        first: String,             // Name of the first storage parameter
+        second: String,            // Name of the second storage parameter
+    },
+}
+
+impl DrawCommand {
+    /// Check if the command draws something, rather than updating or dispatching
+    pub fn is_draw(&self) -> bool {
+        matches!(
+            self,
+            DrawCommand::GeometryWithInstances { .. }
+                | DrawCommand::MeshWithInstances { .. }
+                | DrawCommand::MeshWithStorageInstances { .. }
+                | DrawCommand::MeshIndirect { .. }
+                | DrawCommand::DebugLines { .. }
+                | DrawCommand::Shapes2d { .. }
+        )
+    }
 }
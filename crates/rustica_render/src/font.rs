@@ -0,0 +1,301 @@
+// Fonts, glyph atlases and text layout
+
+use std::collections::HashMap;
+use std::path::Path;
+use ab_glyph::{point, Font as _, FontArc, GlyphId, PxScale, ScaleFont};
+use glam::Vec2;
+use image::{Rgba, RgbaImage};
+
+use crate::texture::TextureHandle;
+
+/// DejaVu Sans Mono, see assets/fonts/DejaVuSansMono-LICENSE.txt
+const BUNDLED_FONT: &[u8] = include_bytes!("../assets/fonts/DejaVuSansMono.ttf");
+
+/// Width in pixels of a glyph atlas
+const ATLAS_WIDTH: u32 = 512;
+
+/// Empty pixels around each glyph, so filtering doesn't pick up its neighbours
+const GLYPH_PADDING: u32 = 1;
+
+/// Character drawn in place of those missing from the atlas
+const FALLBACK: char = '?';
+
+/// A TrueType or OpenType font to draw text with
+///
+/// Pass it to `RenderWindow::set_font` to replace the bundled DejaVu Sans Mono.
+#[derive(Debug, Clone)]
+pub struct Font {
+    font: FontArc,
+}
+
+impl Font {
+    /// Parse a font from the contents of a .ttf or .otf file
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, String> {
+        let font = FontArc::try_from_vec(bytes).map_err(|error| format!("Invalid font: {}", error))?;
+        Ok(Self { font })
+    }
+
+    /// Load a .ttf or .otf file
+    pub fn load_file<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).map_err(|error| format!("Failed to read font {}: {}", path.display(), error))?;
+        Self::from_bytes(bytes)
+    }
+
+    /// Get the font bundled with the renderer, DejaVu Sans Mono
+    pub fn bundled() -> Self {
+        Self { font: FontArc::try_from_slice(BUNDLED_FONT).expect("The bundled font is valid") }
+    }
+}
+
+/// Horizontal alignment of each line of text relative to its position
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TextAlign {
+    /// Lines start at the position
+    #[default]
+    Left,
+    /// Lines are centered on the position
+    Center,
+    /// Lines end at the position
+    Right,
+}
+
+/// Where a character was rasterized in the atlas, in atlas pixels
+#[derive(Debug, Clone, Copy)]
+struct AtlasGlyph {
+    id: GlyphId,
+    /// Texture coordinates of the glyph's top left and bottom right corners
+    uv_min: Vec2,
+    uv_max: Vec2,
+    /// Top left corner of the bitmap relative to the pen at the top of the line
+    offset: Vec2,
+    size: Vec2,
+    advance: f32,
+}
+
+/// A glyph of laid out text, as a rectangle of the target and of the atlas texture
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct GlyphQuad {
+    pub position: Vec2,
+    pub size: Vec2,
+    pub uv_min: Vec2,
+    pub uv_max: Vec2,
+}
+
+/// The Latin-1 characters of a font rasterized into one texture at a fixed size
+///
+/// Text of other sizes scales the glyphs, so the atlas is sharpest at its own
+/// pixel size and best rasterized at the largest size text is drawn at.
+#[derive(Debug, Clone)]
+pub(crate) struct FontAtlas {
+    font: FontArc,
+    texture: TextureHandle,
+    pixel_size: f32,
+    /// Distance between the tops of consecutive lines, in atlas pixels
+    line_height: f32,
+    glyphs: HashMap<char, AtlasGlyph>,
+}
+
+impl FontAtlas {
+    /// Rasterize the printable Latin-1 characters of a font with lines `pixel_size` high
+    ///
+    /// Returns the atlas and the image to upload as its texture with `with_texture`.
+    /// Fails if the size isn't positive or the glyphs don't fit in a texture of
+    /// `max_dimension` pixels per side.
+    pub fn new(font: &Font, pixel_size: f32, max_dimension: u32) -> Result<(Self, RgbaImage), String> {
+        if !(pixel_size.is_finite() && pixel_size > 0.0) {
+            return Err(format!("Font size must be a positive number of pixels, not {}", pixel_size));
+        }
+        let font = font.font.clone();
+        let scaled = font.as_scaled(PxScale::from(pixel_size));
+        let ascent = scaled.ascent();
+
+        let mut outlines = Vec::new();
+        let mut glyphs = Vec::new();
+        for character in (' '..='~').chain('\u{a0}'..='\u{ff}') {
+            let id = font.glyph_id(character);
+            let mut glyph = AtlasGlyph {
+                id,
+                uv_min: Vec2::ZERO,
+                uv_max: Vec2::ZERO,
+                offset: Vec2::ZERO,
+                size: Vec2::ZERO,
+                advance: scaled.h_advance(id),
+            };
+            if let Some(outline) = font.outline_glyph(id.with_scale_and_position(pixel_size, point(0.0, ascent))) {
+                let bounds = outline.px_bounds();
+                glyph.offset = Vec2::new(bounds.min.x, bounds.min.y);
+                glyph.size = Vec2::new(bounds.width(), bounds.height());
+                outlines.push((character, outline));
+            }
+            glyphs.push((character, glyph));
+        }
+
+        // Start at the usual width, widen the atlas for large glyphs, then to keep it within the limit
+        let widest = glyphs.iter().map(|(_, glyph)| glyph.size.x as u32).max().unwrap_or(0);
+        let mut width = ATLAS_WIDTH.max((widest + 2 * GLYPH_PADDING).next_power_of_two());
+        let mut height = Self::pack(&mut glyphs, width);
+        while height > max_dimension && width < max_dimension {
+            width *= 2;
+            height = Self::pack(&mut glyphs, width);
+        }
+        if width > max_dimension || height > max_dimension {
+            return Err(format!(
+                "Glyphs {} pixels high need a {}x{} atlas, larger than the device's {} pixel limit",
+                pixel_size, width, height, max_dimension
+            ));
+        }
+
+        let mut glyphs: HashMap<char, AtlasGlyph> = glyphs.into_iter().collect();
+        let mut image = RgbaImage::from_pixel(width, height, Rgba([255, 255, 255, 0]));
+        for (character, outline) in outlines {
+            let corner = glyphs[&character].uv_min.as_uvec2();
+            outline.draw(|x, y, coverage| {
+                image.put_pixel(corner.x + x, corner.y + y, Rgba([255, 255, 255, (coverage * 255.0).round() as u8]));
+            });
+        }
+        let atlas_size = Vec2::new(width as f32, height as f32);
+        for glyph in glyphs.values_mut() {
+            glyph.uv_max = (glyph.uv_min + glyph.size) / atlas_size;
+            glyph.uv_min /= atlas_size;
+        }
+
+        let atlas = Self {
+            texture: TextureHandle::WHITE,
+            pixel_size,
+            line_height: scaled.height() + scaled.line_gap(),
+            glyphs,
+            font,
+        };
+        Ok((atlas, image))
+    }
+
+    /// Place glyphs in rows from the top left of an atlas `width` pixels wide
+    ///
+    /// Sets each glyph's top left corner in pixels and returns the atlas height.
+    fn pack(glyphs: &mut [(char, AtlasGlyph)], width: u32) -> u32 {
+        let (mut x, mut y, mut row_height) = (GLYPH_PADDING, GLYPH_PADDING, 0);
+        for (_, glyph) in glyphs.iter_mut().filter(|(_, glyph)| glyph.size != Vec2::ZERO) {
+            let (glyph_width, glyph_height) = (glyph.size.x as u32, glyph.size.y as u32);
+            if x + glyph_width + GLYPH_PADDING > width {
+                (x, y, row_height) = (GLYPH_PADDING, y + row_height + GLYPH_PADDING, 0);
+            }
+            glyph.uv_min = Vec2::new(x as f32, y as f32);
+            x += glyph_width + GLYPH_PADDING;
+            row_height = row_height.max(glyph_height);
+        }
+        (y + row_height + GLYPH_PADDING).next_power_of_two()
+    }
+
+    /// Set the texture the atlas image was uploaded to
+    pub fn with_texture(mut self, texture: TextureHandle) -> Self {
+        self.texture = texture;
+        self
+    }
+
+    /// Get the texture of the atlas
+    pub fn texture(&self) -> TextureHandle {
+        self.texture
+    }
+
+    /// Get the width of the longest line and the height of all lines of text drawn with lines `size` high
+    pub fn measure(&self, text: &str, size: f32) -> Vec2 {
+        let scale = size / self.pixel_size;
+        let lines: Vec<f32> = text.split('\n').map(|line| self.line_width(line)).collect();
+        Vec2::new(lines.iter().copied().fold(0.0, f32::max), lines.len() as f32 * self.line_height) * scale
+    }
+
+    /// Lay out text with its first line's top at `position`, returning a quad per visible glyph
+    pub fn layout(&self, text: &str, position: Vec2, size: f32, align: TextAlign) -> Vec<GlyphQuad> {
+        let scale = size / self.pixel_size;
+        let mut quads = Vec::new();
+        for (index, line) in text.split('\n').enumerate() {
+            let width = self.line_width(line) * scale;
+            let start = match align {
+                TextAlign::Left => position.x,
+                TextAlign::Center => position.x - width / 2.0,
+                TextAlign::Right => position.x - width,
+            };
+            let mut pen = Vec2::new(start, position.y + index as f32 * self.line_height * scale);
+            let mut previous = None;
+            for glyph in line.chars().filter_map(|character| self.glyph(character)) {
+                pen.x += self.kerning(previous, glyph) * scale;
+                if glyph.size != Vec2::ZERO {
+                    quads.push(GlyphQuad {
+                        position: pen + glyph.offset * scale,
+                        size: glyph.size * scale,
+                        uv_min: glyph.uv_min,
+                        uv_max: glyph.uv_max,
+                    });
+                }
+                pen.x += glyph.advance * scale;
+                previous = Some(glyph);
+            }
+        }
+        quads
+    }
+
+    /// Get the glyph of a character, or the fallback for characters not in the atlas
+    fn glyph(&self, character: char) -> Option<&AtlasGlyph> {
+        match character {
+            '\r' => None,
+            '\t' => self.glyphs.get(&' '),
+            _ => self.glyphs.get(&character).or_else(|| self.glyphs.get(&FALLBACK)),
+        }
+    }
+
+    /// Get the width of one line of text in atlas pixels
+    fn line_width(&self, line: &str) -> f32 {
+        let mut width = 0.0;
+        let mut previous = None;
+        for glyph in line.chars().filter_map(|character| self.glyph(character)) {
+            width += self.kerning(previous, glyph) + glyph.advance;
+            previous = Some(glyph);
+        }
+        width
+    }
+
+    /// Get the adjustment between two consecutive glyphs in atlas pixels
+    fn kerning(&self, previous: Option<&AtlasGlyph>, glyph: &AtlasGlyph) -> f32 {
+        previous.map_or(0.0, |previous| self.font.as_scaled(PxScale::from(self.pixel_size)).kern(previous.id, glyph.id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_is_measured_and_aligned_per_line() {
+        let (atlas, image) = FontAtlas::new(&Font::bundled(), 32.0, 8192).unwrap();
+        assert_eq!(image.width(), ATLAS_WIDTH);
+        assert!(image.pixels().any(|pixel| pixel.0[3] == 255));
+
+        // The bundled font is monospaced, so lines are as wide as their characters
+        let size = atlas.measure("ab\nabcd", 16.0);
+        let advance = atlas.measure("a", 16.0).x;
+        assert!((size.x - 4.0 * advance).abs() < 1e-3);
+        assert!((size.y - 2.0 * atlas.measure("a", 16.0).y).abs() < 1e-3);
+
+        // Spaces take room but draw nothing, and the second line is right aligned under the first
+        let quads = atlas.layout("a b\nc", Vec2::new(100.0, 10.0), 16.0, TextAlign::Right);
+        assert_eq!(quads.len(), 3);
+        assert!(quads[0].position.x >= 100.0 - 3.0 * advance && quads[1].position.x < 100.0);
+        assert!(quads[2].position.x > quads[1].position.x - advance && quads[2].position.y > quads[1].position.y);
+        assert!(quads.iter().all(|quad| quad.uv_min.cmplt(quad.uv_max).all() && quad.uv_max.cmple(Vec2::ONE).all()));
+    }
+
+    #[test]
+    fn test_atlas_grows_for_large_glyphs_within_the_limit() {
+        // Glyphs wider than the usual atlas width
+        let (atlas, image) = FontAtlas::new(&Font::bundled(), 480.0, 8192).unwrap();
+        assert!(image.width() > ATLAS_WIDTH && image.width() <= 8192 && image.height() <= 8192);
+        let quads = atlas.layout("W@", Vec2::ZERO, 480.0, TextAlign::Left);
+        assert!(quads.iter().all(|quad| quad.uv_max.cmple(Vec2::ONE).all()));
+
+        let error = FontAtlas::new(&Font::bundled(), 480.0, 1024).unwrap_err();
+        assert!(error.contains("1024 pixel limit"), "{}", error);
+        assert!(FontAtlas::new(&Font::bundled(), 0.0, 8192).is_err());
+        assert!(FontAtlas::new(&Font::bundled(), f32::NAN, 8192).is_err());
+    }
+}
@@ -0,0 +1,254 @@
+// Per-frame statistics and GPU pass timing
+
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::Duration;
+use wgpu::{
+    Buffer, BufferDescriptor, BufferUsages, CommandEncoder, ComputePassTimestampWrites, Device, Features,
+    MapMode, QuerySet, QuerySetDescriptor, QueryType, Queue, RenderPassTimestampWrites, QUERY_SIZE,
+};
+
+use crate::custom_shader::CustomShader;
+use crate::diagnostics::label;
+
+/// Timestamps written per frame: the start and end of the render pass, then of the compute pass
+const QUERY_COUNT: u32 = 4;
+
+/// States of the timestamp readback, set by the map callback
+const READBACK_PENDING: u8 = 0;
+const READBACK_MAPPED: u8 = 1;
+const READBACK_FAILED: u8 = 2;
+
+/// What the renderer submitted in one frame and how long it took
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FrameStats {
+    /// Draw commands recorded on the canvas
+    pub draw_commands: u32,
+    /// Draw calls submitted (draws with errors are skipped)
+    pub draw_calls: u32,
+    /// Compute dispatches submitted
+    pub dispatches: u32,
+    /// Times a pipeline was bound in the render pass
+    pub pipelines_bound: u32,
+    /// Triangles drawn, counting every instance (indirect draws aren't counted)
+    pub triangles: u64,
+    /// Instances drawn (indirect draws aren't counted)
+    pub instances: u64,
+    /// Bytes written to GPU buffers: vertices, instances, uniforms and storage
+    pub bytes_uploaded: u64,
+    /// CPU time spent staging and encoding the frame
+    pub encode_time: Duration,
+    /// GPU time of the compute and render passes, if the adapter supports timestamp queries
+    ///
+    /// Timings are read back without waiting for the GPU, so this is the most
+    /// recent measurement, which may be a frame or two older than the rest.
+    pub gpu_time: Option<Duration>,
+}
+
+impl FrameStats {
+    /// Count a submitted draw call
+    pub(crate) fn record_draw(&mut self, shader: &CustomShader, index_count: u32, instance_count: u32) {
+        let triangles = shader.pipeline_state().map_or(0, |state| state.triangle_count(index_count));
+        self.draw_calls += 1;
+        self.triangles += triangles as u64 * instance_count as u64;
+        self.instances += instance_count as u64;
+    }
+}
+
+impl fmt::Display for FrameStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} draws, {} dispatches, {} pipeline binds, {} triangles, {} instances, {} bytes uploaded, encoded in {:.2} ms",
+            self.draw_calls, self.draw_commands, self.dispatches, self.pipelines_bound,
+            self.triangles, self.instances, self.bytes_uploaded, self.encode_time.as_secs_f64() * 1000.0
+        )?;
+        if let Some(gpu_time) = self.gpu_time {
+            write!(f, ", GPU {:.2} ms", gpu_time.as_secs_f64() * 1000.0)?;
+        }
+        Ok(())
+    }
+}
+
+/// Measures the GPU time of a frame's passes with timestamp queries
+///
+/// One measurement is in flight at a time: frames submitted while the
+/// previous readback is still mapping aren't timed.
+pub(crate) struct GpuTimer {
+    query_set: QuerySet,
+    resolve_buffer: Buffer,
+    readback_buffer: Buffer,
+    /// Nanoseconds per timestamp tick
+    period: f32,
+    /// Set by the map callback once the readback buffer can be read or failed to map
+    readback: Arc<AtomicU8>,
+    /// Number of queries resolved for the measurement in flight
+    in_flight: Option<u32>,
+    /// Whether the current frame writes timestamps
+    timing: bool,
+    /// The last completed measurement
+    last: Option<Duration>,
+}
+
+impl GpuTimer {
+    /// Create a timer, or None if the device can't write timestamps
+    pub fn new(device: &Device, queue: &Queue) -> Option<Self> {
+        if !device.features().contains(Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+        let size = (QUERY_COUNT * QUERY_SIZE) as u64;
+        Some(Self {
+            query_set: device.create_query_set(&QuerySetDescriptor {
+                label: Some(&label(&["frame", "timestamps"])),
+                ty: QueryType::Timestamp,
+                count: QUERY_COUNT,
+            }),
+            resolve_buffer: device.create_buffer(&BufferDescriptor {
+                label: Some(&label(&["frame", "timestamp resolve"])),
+                size,
+                usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }),
+            readback_buffer: device.create_buffer(&BufferDescriptor {
+                label: Some(&label(&["frame", "timestamp readback"])),
+                size,
+                usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            }),
+            period: queue.get_timestamp_period(),
+            readback: Arc::new(AtomicU8::new(READBACK_PENDING)),
+            in_flight: None,
+            timing: false,
+            last: None,
+        })
+    }
+
+    /// Collect a finished measurement and decide whether the next frame is timed
+    pub fn begin_frame(&mut self, device: &Device) {
+        self.collect(device);
+        self.timing = self.in_flight.is_none();
+    }
+
+    /// Read the measurement in flight if the GPU has finished it, without waiting
+    ///
+    /// A readback that failed to map is dropped so later frames are timed again.
+    pub fn collect(&mut self, device: &Device) {
+        let Some(query_count) = self.in_flight else {
+            return;
+        };
+        let _ = device.poll(wgpu::Maintain::Poll);
+        match self.readback.swap(READBACK_PENDING, Ordering::AcqRel) {
+            READBACK_MAPPED => {}
+            READBACK_FAILED => {
+                self.in_flight = None;
+                return;
+            }
+            _ => return,
+        }
+
+        let ticks = {
+            let data = self.readback_buffer.slice(..).get_mapped_range();
+            let timestamps = bytemuck::cast_slice::<u8, u64>(&data[..(query_count * QUERY_SIZE) as usize]);
+            timestamps.chunks_exact(2).map(|pass| pass[1].saturating_sub(pass[0])).sum::<u64>()
+        };
+        self.readback_buffer.unmap();
+        self.in_flight = None;
+        self.last = Some(Duration::from_nanos((ticks as f64 * self.period as f64) as u64));
+    }
+
+    /// Timestamp writes for the render pass of a timed frame
+    pub fn render_pass_writes(&self) -> Option<RenderPassTimestampWrites<'_>> {
+        self.timing.then_some(RenderPassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(0),
+            end_of_pass_write_index: Some(1),
+        })
+    }
+
+    /// Timestamp writes for the compute pass of a timed frame
+    pub fn compute_pass_writes(&self) -> Option<ComputePassTimestampWrites<'_>> {
+        self.timing.then_some(ComputePassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(2),
+            end_of_pass_write_index: Some(3),
+        })
+    }
+
+    /// Copy the timestamps of a timed frame to the readback buffer
+    pub fn resolve(&mut self, encoder: &mut CommandEncoder, with_compute_pass: bool) {
+        if !self.timing {
+            return;
+        }
+        let query_count = if with_compute_pass { QUERY_COUNT } else { 2 };
+        let size = (query_count * QUERY_SIZE) as u64;
+        encoder.resolve_query_set(&self.query_set, 0..query_count, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(&self.resolve_buffer, 0, &self.readback_buffer, 0, size);
+        self.in_flight = Some(query_count);
+    }
+
+    /// Start reading back the timestamps once the frame has been submitted
+    pub fn after_submit(&mut self) {
+        if !std::mem::take(&mut self.timing) || self.in_flight.is_none() {
+            return;
+        }
+        let readback = Arc::clone(&self.readback);
+        self.readback_buffer.slice(..).map_async(MapMode::Read, move |result| {
+            readback.store(if result.is_ok() { READBACK_MAPPED } else { READBACK_FAILED }, Ordering::Release);
+        });
+    }
+
+    /// Get the last completed measurement
+    pub fn last(&self) -> Option<Duration> {
+        self.last
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_includes_gpu_time_when_measured() {
+        let mut stats = FrameStats {
+            draw_commands: 3,
+            draw_calls: 2,
+            triangles: 12,
+            encode_time: Duration::from_micros(1500),
+            ..Default::default()
+        };
+        assert_eq!(
+            stats.to_string(),
+            "2 of 3 draws, 0 dispatches, 0 pipeline binds, 12 triangles, 0 instances, 0 bytes uploaded, encoded in 1.50 ms"
+        );
+
+        stats.gpu_time = Some(Duration::from_micros(250));
+        assert!(stats.to_string().ends_with(", GPU 0.25 ms"));
+    }
+
+    #[test]
+    fn test_failed_readback_resumes_timing() {
+        let instance = wgpu::Instance::default();
+        let device = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))
+            .filter(|adapter| adapter.features().contains(Features::TIMESTAMP_QUERY))
+            .and_then(|adapter| pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+                required_features: Features::TIMESTAMP_QUERY,
+                ..Default::default()
+            }, None)).ok());
+        let Some((device, queue)) = device else {
+            eprintln!("Skipping GPU timer test: no adapter with timestamp queries available");
+            return;
+        };
+        let Some(mut timer) = GpuTimer::new(&device, &queue) else {
+            panic!("timer should be created on a device with timestamp queries");
+        };
+
+        // A measurement whose readback buffer failed to map
+        timer.in_flight = Some(2);
+        timer.readback.store(READBACK_FAILED, Ordering::Release);
+        timer.begin_frame(&device);
+        assert!(timer.in_flight.is_none());
+        assert!(timer.timing);
+        assert!(timer.last().is_none());
+    }
+}
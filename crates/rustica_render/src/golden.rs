@@ -0,0 +1,267 @@
+// Golden-image comparison for rendering regression tests
+
+use std::path::{Path, PathBuf};
+use image::{Rgba, RgbaImage};
+
+/// Environment variable that switches golden tests into "bless" mode
+///
+/// When set to `1`, `GoldenTest::check` writes the rendered image as the new
+/// reference instead of comparing against it.
+pub const BLESS_ENV_VAR: &str = "RUSTICA_BLESS";
+
+/// Largest possible YIQ distance between two colors, used to normalize deltas
+const MAX_YIQ_DELTA: f32 = 35215.0;
+
+/// Tolerance used when comparing a rendered image with its reference
+#[derive(Debug, Clone, Copy)]
+pub struct GoldenTolerance {
+    /// Perceptual color distance (0.0 - 1.0) below which two pixels count as equal
+    pub pixel_threshold: f32,
+    /// Fraction of pixels (0.0 - 1.0) allowed to differ before the comparison fails
+    pub max_differing_ratio: f32,
+}
+
+impl Default for GoldenTolerance {
+    fn default() -> Self {
+        Self {
+            pixel_threshold: 0.1,
+            max_differing_ratio: 0.005,
+        }
+    }
+}
+
+/// Result of comparing two images pixel by pixel
+pub struct ImageComparison {
+    /// Number of pixels whose perceptual distance exceeds the threshold
+    pub differing_pixels: u64,
+    /// Total number of pixels compared
+    pub total_pixels: u64,
+    /// Largest perceptual distance found (0.0 - 1.0)
+    pub max_delta: f32,
+    /// Image highlighting differing pixels in red over a faded copy of the reference
+    pub diff_image: RgbaImage,
+}
+
+impl ImageComparison {
+    /// Fraction of pixels that differ (0.0 - 1.0)
+    pub fn differing_ratio(&self) -> f32 {
+        if self.total_pixels == 0 {
+            0.0
+        } else {
+            self.differing_pixels as f32 / self.total_pixels as f32
+        }
+    }
+}
+
+/// Compare two images of the same size using a perceptual (YIQ) color distance
+///
+/// Returns `None` if the image sizes differ.
+pub fn compare_images(actual: &RgbaImage, expected: &RgbaImage, pixel_threshold: f32) -> Option<ImageComparison> {
+    if actual.dimensions() != expected.dimensions() {
+        return None;
+    }
+
+    let mut diff_image = RgbaImage::new(expected.width(), expected.height());
+    let mut differing_pixels = 0;
+    let mut max_delta: f32 = 0.0;
+
+    for (x, y, expected_pixel) in expected.enumerate_pixels() {
+        let delta = perceptual_delta(actual.get_pixel(x, y), expected_pixel);
+        max_delta = max_delta.max(delta);
+
+        let diff_pixel = if delta > pixel_threshold {
+            differing_pixels += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            // Faded grayscale reference so the differences stand out
+            let luma = (rgb_to_y(expected_pixel) * 0.25 + 191.0) as u8;
+            Rgba([luma, luma, luma, 255])
+        };
+        diff_image.put_pixel(x, y, diff_pixel);
+    }
+
+    Some(ImageComparison {
+        differing_pixels,
+        total_pixels: expected.width() as u64 * expected.height() as u64,
+        max_delta,
+        diff_image,
+    })
+}
+
+/// Perceptual distance between two pixels (0.0 - 1.0), blended over white
+///
+/// Uses the YIQ color space weighting from "Measuring perceived color
+/// difference using YIQ NTSC transmission color space" (Kotsarenko & Ramos).
+pub fn perceptual_delta(a: &Rgba<u8>, b: &Rgba<u8>) -> f32 {
+    let (ay, ai, aq) = rgb_to_yiq(blend_over_white(a));
+    let (by, bi, bq) = rgb_to_yiq(blend_over_white(b));
+
+    let dy = ay - by;
+    let di = ai - bi;
+    let dq = aq - bq;
+
+    ((0.5053 * dy * dy + 0.299 * di * di + 0.1957 * dq * dq) / MAX_YIQ_DELTA).min(1.0)
+}
+
+fn blend_over_white(pixel: &Rgba<u8>) -> [f32; 3] {
+    let alpha = pixel[3] as f32 / 255.0;
+    [0, 1, 2].map(|c| 255.0 + (pixel[c] as f32 - 255.0) * alpha)
+}
+
+fn rgb_to_yiq([r, g, b]: [f32; 3]) -> (f32, f32, f32) {
+    (
+        r * 0.298_895_3 + g * 0.586_622_5 + b * 0.114_482_3,
+        r * 0.595_977_9 - g * 0.274_176_4 - b * 0.321_801_5,
+        r * 0.211_470_2 - g * 0.522_617_2 + b * 0.311_147,
+    )
+}
+
+fn rgb_to_y(pixel: &Rgba<u8>) -> f32 {
+    rgb_to_yiq(blend_over_white(pixel)).0
+}
+
+/// Golden-image test harness comparing rendered frames with stored reference PNGs
+///
+/// References live in `reference_dir` as `<name>.png`. On failure the rendered
+/// image and a diff image are written to the output directory (by default
+/// `<reference_dir>/failures`). Run with `RUSTICA_BLESS=1` to create or update
+/// the references.
+pub struct GoldenTest {
+    reference_dir: PathBuf,
+    output_dir: PathBuf,
+    tolerance: GoldenTolerance,
+}
+
+impl GoldenTest {
+    /// Create a harness storing references in the given directory
+    pub fn new<P: AsRef<Path>>(reference_dir: P) -> Self {
+        let reference_dir = reference_dir.as_ref().to_path_buf();
+        Self {
+            output_dir: reference_dir.join("failures"),
+            reference_dir,
+            tolerance: GoldenTolerance::default(),
+        }
+    }
+
+    /// Set the directory where actual and diff images are written on failure
+    pub fn with_output_dir<P: AsRef<Path>>(mut self, output_dir: P) -> Self {
+        self.output_dir = output_dir.as_ref().to_path_buf();
+        self
+    }
+
+    /// Set the comparison tolerance
+    pub fn with_tolerance(mut self, tolerance: GoldenTolerance) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Check if bless mode is enabled through the environment
+    pub fn is_blessing() -> bool {
+        std::env::var(BLESS_ENV_VAR).is_ok_and(|value| value == "1")
+    }
+
+    /// Get the path of the reference image for a test
+    pub fn reference_path(&self, name: &str) -> PathBuf {
+        self.reference_dir.join(format!("{}.png", name))
+    }
+
+    /// Compare an image with its reference, or update the reference in bless mode
+    pub fn check(&self, name: &str, actual: &RgbaImage) -> Result<(), String> {
+        let reference_path = self.reference_path(name);
+
+        if Self::is_blessing() {
+            std::fs::create_dir_all(&self.reference_dir)
+                .map_err(|e| format!("Failed to create reference directory {:?}: {}", self.reference_dir, e))?;
+            return actual.save(&reference_path)
+                .map_err(|e| format!("Failed to write reference {:?}: {}", reference_path, e));
+        }
+
+        let expected = image::open(&reference_path)
+            .map_err(|e| format!(
+                "Failed to load reference {:?}: {}. Run with {}=1 to create it.",
+                reference_path, e, BLESS_ENV_VAR
+            ))?
+            .to_rgba8();
+
+        let Some(comparison) = compare_images(actual, &expected, self.tolerance.pixel_threshold) else {
+            let actual_path = self.write_output(name, "actual", actual)?;
+            return Err(format!(
+                "Golden image '{}' has size {:?} but the reference is {:?} (actual written to {:?})",
+                name, actual.dimensions(), expected.dimensions(), actual_path
+            ));
+        };
+
+        if comparison.differing_ratio() > self.tolerance.max_differing_ratio {
+            let actual_path = self.write_output(name, "actual", actual)?;
+            let diff_path = self.write_output(name, "diff", &comparison.diff_image)?;
+            return Err(format!(
+                "Golden image '{}' differs from {:?}: {} of {} pixels ({:.3}%) exceed the threshold, max delta {:.3}. \
+                 Actual: {:?}, diff: {:?}. Run with {}=1 to update the reference.",
+                name, reference_path, comparison.differing_pixels, comparison.total_pixels,
+                comparison.differing_ratio() * 100.0, comparison.max_delta,
+                actual_path, diff_path, BLESS_ENV_VAR
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Compare an image with its reference and panic with a report on mismatch
+    pub fn assert(&self, name: &str, actual: &RgbaImage) {
+        if let Err(message) = self.check(name, actual) {
+            panic!("{}", message);
+        }
+    }
+
+    fn write_output(&self, name: &str, suffix: &str, image: &RgbaImage) -> Result<PathBuf, String> {
+        std::fs::create_dir_all(&self.output_dir)
+            .map_err(|e| format!("Failed to create output directory {:?}: {}", self.output_dir, e))?;
+        let path = self.output_dir.join(format!("{}.{}.png", name, suffix));
+        image.save(&path)
+            .map_err(|e| format!("Failed to write {:?}: {}", path, e))?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: u32, height: u32, color: [u8; 4]) -> RgbaImage {
+        RgbaImage::from_pixel(width, height, Rgba(color))
+    }
+
+    #[test]
+    fn test_identical_images_match() {
+        let image = solid(8, 8, [10, 200, 30, 255]);
+        let comparison = compare_images(&image, &image, 0.0).unwrap();
+        assert_eq!(comparison.differing_pixels, 0);
+        assert_eq!(comparison.max_delta, 0.0);
+    }
+
+    #[test]
+    fn test_small_color_noise_is_tolerated() {
+        let expected = solid(8, 8, [100, 100, 100, 255]);
+        let actual = solid(8, 8, [102, 99, 101, 255]);
+        let comparison = compare_images(&actual, &expected, 0.1).unwrap();
+        assert_eq!(comparison.differing_pixels, 0);
+    }
+
+    #[test]
+    fn test_different_pixels_are_counted() {
+        let expected = solid(4, 4, [0, 0, 0, 255]);
+        let mut actual = expected.clone();
+        actual.put_pixel(1, 2, Rgba([255, 255, 255, 255]));
+
+        let comparison = compare_images(&actual, &expected, 0.1).unwrap();
+        assert_eq!(comparison.differing_pixels, 1);
+        assert_eq!(comparison.total_pixels, 16);
+        assert_eq!(comparison.diff_image.get_pixel(1, 2).0, [255, 0, 0, 255]);
+        assert!(comparison.max_delta > 0.9);
+    }
+
+    #[test]
+    fn test_size_mismatch() {
+        assert!(compare_images(&solid(4, 4, [0; 4]), &solid(4, 5, [0; 4]), 0.1).is_none());
+    }
+}
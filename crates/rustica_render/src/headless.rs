@@ -0,0 +1,1297 @@
+// Offscreen renderer without a window or surface
+
+use std::path::Path;
+use std::time::Duration;
+use image::RgbaImage;
+use wgpu::{
+    AdapterInfo, BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Extent3d, MapMode,
+    Origin3d, TexelCopyBufferInfo, TexelCopyBufferLayout, TexelCopyTextureInfo, Texture,
+    TextureAspect, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, TextureView,
+    TextureViewDescriptor, COPY_BYTES_PER_ROW_ALIGNMENT,
+};
+use rustica_foundation::geometry::{Geometry, Vertex};
+
+use crate::canvas::Canvas;
+use crate::diagnostics::label;
+use crate::font::Font;
+use crate::frame_stats::FrameStats;
+use crate::compute_shader::{ComputeHandle, ComputeShaderDescriptor};
+use crate::custom_shader::ShaderDescriptor;
+use crate::lod_mesh::LodMesh;
+use crate::mesh::MeshHandle;
+use crate::texture::{self, TextureHandle, TextureOptions};
+use crate::render_context::RenderContext;
+use crate::shader_error::ShaderError;
+
+/// Format of the offscreen color target (sRGB, like the window surfaces we pick)
+const TARGET_FORMAT: TextureFormat = TextureFormat::Rgba8UnormSrgb;
+
+/// Bytes per pixel of the offscreen color target
+const BYTES_PER_PIXEL: u32 = 4;
+
+/// A renderer that draws `Canvas` commands into an offscreen texture
+///
+/// Works without a window or surface and falls back to a software adapter
+/// when no GPU is available, which makes it suitable for CI screenshots,
+/// thumbnails and server-side rendering. Each call to `render` reads the
+/// frame back into an RGBA image.
+pub struct HeadlessRenderer {
+    render_context: RenderContext,
+    width: u32,
+    height: u32,
+    target: Texture,
+    target_view: TextureView,
+}
+
+impl HeadlessRenderer {
+    /// Create a new headless renderer producing images of the given size
+    pub fn new(width: u32, height: u32) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut render_context = RenderContext::new();
+        render_context.initialize_headless(width, height, TARGET_FORMAT)?;
+
+        let device = render_context.device.as_ref().ok_or("Headless device was not created")?;
+        let target = device.create_texture(&TextureDescriptor {
+            label: Some(&label(&["headless", "color target"])),
+            size: Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TARGET_FORMAT,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let target_view = target.create_view(&TextureViewDescriptor::default());
+
+        Ok(Self {
+            render_context,
+            width,
+            height,
+            target,
+            target_view,
+        })
+    }
+
+    /// Get the width of the rendered images
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Get the height of the rendered images
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Get information about the adapter used for rendering
+    pub fn adapter_info(&self) -> Option<&AdapterInfo> {
+        self.render_context.adapter_info.as_ref()
+    }
+
+    /// Set the clear color for the offscreen target
+    pub fn set_clear_color(&mut self, r: f64, g: f64, b: f64, a: f64) {
+        self.render_context.set_clear_color(r, g, b, a);
+    }
+
+    /// Register a custom shader and return its ID
+    ///
+    /// Fails with the location of the problem if the WGSL doesn't compile or
+    /// doesn't fit the descriptor's layout.
+    pub fn register_shader(&mut self, descriptor: ShaderDescriptor) -> Result<usize, ShaderError> {
+        self.render_context.register_descriptor(descriptor)
+    }
+
+    /// Register a compute shader and return its handle
+    ///
+    /// Dispatch it with `Canvas::dispatch`; its storage buffers persist between frames.
+    pub fn register_compute_shader(&mut self, descriptor: ComputeShaderDescriptor) -> Result<ComputeHandle, ShaderError> {
+        self.render_context.register_compute_descriptor(descriptor)
+    }
+
+    /// Reload shaders from their WGSL files when the files change (checked before each frame)
+    pub fn watch_shaders(&mut self, interval: Duration) {
+        self.render_context.watch_shaders(interval);
+    }
+
+    /// Upload geometry to the GPU once and return a handle for drawing it
+    pub fn upload_geometry<V: Vertex>(&mut self, geometry: &Geometry<V>) -> MeshHandle {
+        self.render_context.upload_mesh(
+            bytemuck::cast_slice(&geometry.vertices),
+            bytemuck::cast_slice(&geometry.indices),
+            geometry.indices.len() as u32,
+        )
+    }
+
+    /// Upload the levels of a mesh with several resolutions
+    ///
+    /// Each level is a geometry and the smallest screen size it is drawn at,
+    /// as a fraction of the viewport height (see `LodMesh`).
+    pub fn upload_lod_geometry<V: Vertex>(&mut self, levels: &[(&Geometry<V>, f32)]) -> LodMesh {
+        levels.iter().fold(LodMesh::new(), |lod, (geometry, min_screen_size)| {
+            lod.with_level(self.upload_geometry(geometry), *min_screen_size)
+        })
+    }
+
+    /// Upload an image as a texture and return a handle for drawing with it
+    ///
+    /// Fails if the image is empty or larger than the device's texture size limit.
+    pub fn upload_texture(&mut self, image: RgbaImage, options: TextureOptions) -> Result<TextureHandle, String> {
+        self.render_context.upload_texture(image, options)
+    }
+
+    /// Decode a PNG or JPEG image from memory and upload it as a texture
+    pub fn load_texture(&mut self, bytes: &[u8], options: TextureOptions) -> Result<TextureHandle, String> {
+        self.upload_texture(texture::decode_image(bytes)?, options)
+    }
+
+    /// Load a PNG or JPEG file and upload it as a texture
+    pub fn load_texture_file<P: AsRef<Path>>(&mut self, path: P, options: TextureOptions) -> Result<TextureHandle, String> {
+        self.upload_texture(texture::load_image(path)?, options)
+    }
+
+    /// Render a frame at time zero and read it back
+    pub fn render<F>(&mut self, draw: F) -> RgbaImage
+    where
+        F: FnOnce(&mut Canvas),
+    {
+        self.render_at(Duration::ZERO, draw)
+    }
+
+    /// Render a frame with the canvas reporting the given time and read it back
+    pub fn render_at<F>(&mut self, time: Duration, draw: F) -> RgbaImage
+    where
+        F: FnOnce(&mut Canvas),
+    {
+        // Collect draw commands exactly like a window frame
+        self.render_context.reload_changed_shaders();
+        let frame_stats = self.render_context.frame_stats();
+        let mut canvas = Canvas::new(time);
+        canvas.frame_stats = frame_stats;
+        canvas.font = Some(self.render_context.font());
+        canvas.target_size = self.render_context.target_size();
+        if let Some(queue) = &self.render_context.queue {
+            canvas.set_queue(queue);
+        }
+        canvas.shader_errors = self.render_context.shader_errors();
+        canvas.compute_shader_errors = self.render_context.compute_shader_errors();
+        canvas.storage_source = Some(&self.render_context);
+        draw(&mut canvas);
+        let commands = canvas.take_commands();
+        drop(canvas);
+
+        self.render_context.render_to_view(&self.target_view, commands);
+        self.read_target()
+    }
+
+    /// Get the statistics of the last rendered frame
+    pub fn frame_stats(&mut self) -> FrameStats {
+        self.render_context.frame_stats()
+    }
+
+    /// Show or hide a graph of recent CPU and GPU frame times in the top left corner
+    pub fn show_stats_overlay(&mut self, visible: bool) {
+        self.render_context.set_stats_overlay(visible);
+    }
+
+    /// Draw `Canvas::text` with a font rasterized with lines `pixel_size` high
+    ///
+    /// Fails if the glyphs don't fit in a texture, keeping the current font.
+    pub fn set_font(&mut self, font: &Font, pixel_size: f32) -> Result<(), String> {
+        self.render_context.set_font(font, pixel_size)
+    }
+
+    /// Enable or disable automatic draw batching (see `RenderWindow::set_draw_batching`)
+    pub fn set_draw_batching(&mut self, enabled: bool) {
+        self.render_context.set_draw_batching(enabled);
+    }
+
+    /// Read the data of a compute shader's storage buffer back to the CPU
+    ///
+    /// Returns the elements written by the last rendered frame. Fails if the
+    /// shader has no storage buffer with that name or its elements aren't `T`.
+    pub fn read_storage<T: bytemuck::Pod>(&self, compute: ComputeHandle, name: &str) -> Result<Vec<T>, String> {
+        self.render_context.read_storage(compute, name)
+    }
+
+    /// Copy the color target into a CPU-side image
+    fn read_target(&self) -> RgbaImage {
+        let (Some(device), Some(queue)) = (&self.render_context.device, &self.render_context.queue) else {
+            return RgbaImage::new(self.width, self.height);
+        };
+
+        // Rows of a texture copy must be aligned, so the readback buffer may be wider than the image
+        let unpadded_bytes_per_row = self.width * BYTES_PER_PIXEL;
+        let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(COPY_BYTES_PER_ROW_ALIGNMENT) * COPY_BYTES_PER_ROW_ALIGNMENT;
+
+        let readback = device.create_buffer(&BufferDescriptor {
+            label: Some(&label(&["headless", "readback"])),
+            size: (padded_bytes_per_row * self.height) as u64,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some(&label(&["headless", "readback encoder"])),
+        });
+        encoder.copy_texture_to_buffer(
+            TexelCopyTextureInfo {
+                texture: &self.target,
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            TexelCopyBufferInfo {
+                buffer: &readback,
+                layout: TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(self.height),
+                },
+            },
+            Extent3d {
+                width: self.width,
+                height: self.height,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+
+        // Wait for the copy to finish and map the buffer
+        let slice = readback.slice(..);
+        slice.map_async(MapMode::Read, |_| {});
+        device.poll(wgpu::Maintain::Wait);
+
+        let mapped = slice.get_mapped_range();
+        let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * self.height) as usize);
+        for row in mapped.chunks(padded_bytes_per_row as usize) {
+            pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+        }
+        drop(mapped);
+        readback.unmap();
+
+        RgbaImage::from_raw(self.width, self.height, pixels)
+            .unwrap_or_else(|| RgbaImage::new(self.width, self.height))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute_shader::ComputeShaderDescriptor;
+    use crate::indirect::DrawIndexedIndirect;
+    use crate::draw_2d::CoordinateSpace;
+    use crate::custom_shader::{SamplerParameter, StorageParameter, TextureParameter, UniformParameter};
+    use crate::texture::SamplerConfig;
+    use crate::shader_features::ShaderFeature;
+    use crate::pipeline_state::{BlendMode, PipelineState};
+    use rustica_foundation::geometry::VertexAttribute;
+
+    #[repr(C)]
+    #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+    struct TestVertex {
+        position: [f32; 3],
+    }
+
+    impl Vertex for TestVertex {
+        fn layout() -> wgpu::VertexBufferLayout<'static> {
+            const ATTRIBUTES: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![0 => Float32x3];
+            wgpu::VertexBufferLayout {
+                array_stride: std::mem::size_of::<TestVertex>() as wgpu::BufferAddress,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &ATTRIBUTES,
+            }
+        }
+    }
+
+    /// Create a renderer, or None on machines without any adapter, including a software one
+    fn renderer(width: u32, height: u32) -> Option<HeadlessRenderer> {
+        let renderer = HeadlessRenderer::new(width, height).ok();
+        if renderer.is_none() {
+            eprintln!("Skipping headless test: no adapter available");
+        }
+        renderer
+    }
+
+    /// A triangle centered on the origin, pointing up, at depth 0.5
+    fn triangle(half_width: f32, half_height: f32) -> Geometry<TestVertex> {
+        Geometry {
+            vertices: vec![
+                TestVertex { position: [-half_width, -half_height, 0.5] },
+                TestVertex { position: [half_width, -half_height, 0.5] },
+                TestVertex { position: [0.0, half_height, 0.5] },
+            ],
+            indices: vec![0, 1, 2],
+        }
+    }
+
+    /// A shader that offsets a triangle horizontally and fills it with a uniform color
+    fn tinted_descriptor() -> ShaderDescriptor {
+        ShaderDescriptor {
+            name: "Tinted".to_string(),
+            source_path: None,
+            source_map: None,
+            features: Vec::new(),
+            shader_source: r#"
+                @group(0) @binding(0) var<uniform> tint: vec4<f32>;
+                @group(0) @binding(1) var<uniform> offset: f32;
+
+                @vertex
+                fn vs_main(@location(0) position: vec3<f32>) -> @builtin(position) vec4<f32> {
+                    return vec4<f32>(position.x + offset, position.y, position.z, 1.0);
+                }
+
+                @fragment
+                fn fs_main() -> @location(0) vec4<f32> {
+                    return tint;
+                }
+            "#.to_string(),
+            vertex_attributes: vec![VertexAttribute {
+                name: "position".to_string(),
+                location: 0,
+                format: wgpu::VertexFormat::Float32x3,
+                offset: 0,
+                semantic: None,
+            }],
+            uniforms: vec![
+                UniformParameter { name: "tint".to_string(), group: 0, binding: 0, size: 16 },
+                UniformParameter { name: "offset".to_string(), group: 0, binding: 1, size: 4 },
+            ],
+            uniform_block: None,
+            textures: Vec::new(),
+            samplers: Vec::new(),
+            storage: Vec::new(),
+            instance_attributes: Vec::new(),
+            instance_stride: 0,
+            pipeline_state: PipelineState::default(),
+        }
+    }
+
+    #[test]
+    fn test_headless_clear_color() {
+        let Some(mut renderer) = renderer(64, 32) else {
+            return;
+        };
+
+        renderer.set_clear_color(1.0, 0.0, 0.0, 1.0);
+        let image = renderer.render(|_canvas| {});
+
+        assert_eq!(image.dimensions(), (64, 32));
+        assert_eq!(image.get_pixel(0, 0).0, [255, 0, 0, 255]);
+        assert_eq!(image.get_pixel(63, 31).0, [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_uniforms_apply_per_draw() {
+        let Some(mut renderer) = renderer(64, 32) else {
+            return;
+        };
+
+        let shader_id = renderer.register_shader(tinted_descriptor()).unwrap();
+        let triangle = triangle(0.45, 0.9);
+
+        // Two draws in one frame with different uniform values
+        let image = renderer.render(|canvas| {
+            canvas.draw_with_instances(shader_id)
+                .uniform("tint", [1.0f32, 0.0, 0.0, 1.0])
+                .uniform("offset", -0.5f32)
+                .pump_geometry(&triangle, &[0u32]);
+            canvas.draw_with_instances(shader_id)
+                .uniform("tint", [0.0f32, 0.0, 1.0, 1.0])
+                .uniform("offset", 0.5f32)
+                .pump_geometry(&triangle, &[0u32]);
+        });
+
+        assert_eq!(image.get_pixel(16, 20).0, [255, 0, 0, 255]);
+        assert_eq!(image.get_pixel(48, 20).0, [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn test_frame_stats_count_the_previous_frame() {
+        let Some(mut renderer) = renderer(64, 32) else {
+            return;
+        };
+
+        let shader_id = renderer.register_shader(tinted_descriptor()).unwrap();
+        let triangle = triangle(0.45, 0.9);
+        let draw = |canvas: &mut Canvas| {
+            canvas.draw_with_instances(shader_id)
+                .uniform("tint", [1.0f32, 0.0, 0.0, 1.0])
+                .uniform("offset", -0.5f32)
+                .pump_geometry(&triangle, &[0u32, 1, 2]);
+            canvas.draw_with_instances(shader_id)
+                .uniform("tint", [0.0f32, 0.0, 1.0, 1.0])
+                .uniform("offset", 0.5f32)
+                .pump_geometry(&triangle, &[0u32]);
+        };
+
+        let mut first_frame_draws = None;
+        renderer.render(|canvas| {
+            first_frame_draws = Some(canvas.frame_stats().draw_calls);
+            draw(canvas);
+        });
+        assert_eq!(first_frame_draws, Some(0));
+
+        let stats = renderer.frame_stats();
+        assert_eq!(stats.draw_commands, 2);
+        assert_eq!(stats.draw_calls, 2);
+        assert_eq!(stats.pipelines_bound, 1);
+        assert_eq!(stats.triangles, 4);
+        assert_eq!(stats.instances, 4);
+        assert!(stats.bytes_uploaded > 0);
+
+        let mut previous = None;
+        renderer.render(|canvas| previous = Some(*canvas.frame_stats()));
+        assert_eq!(previous.map(|stats| stats.draw_calls), Some(2));
+        assert_eq!(renderer.frame_stats().draw_calls, 0);
+    }
+
+    #[test]
+    fn test_compatible_draws_are_batched_unless_disabled() {
+        let Some(mut renderer) = renderer(64, 32) else {
+            return;
+        };
+
+        let shader_id = renderer.register_shader(tinted_descriptor()).unwrap();
+        let triangle = triangle(0.45, 0.9);
+        let draw = |canvas: &mut Canvas| {
+            for offset in [-0.5f32, 0.5, -0.5] {
+                canvas.draw_with_instances(shader_id)
+                    .uniform("tint", [0.0f32, 1.0, 0.0, 1.0])
+                    .uniform("offset", offset)
+                    .pump_geometry(&triangle, &[0u32]);
+            }
+        };
+
+        let batched = renderer.render(draw);
+        let stats = renderer.frame_stats();
+        assert_eq!((stats.draw_commands, stats.draw_calls, stats.instances), (3, 2, 3));
+
+        renderer.set_draw_batching(false);
+        let unbatched = renderer.render(draw);
+        let stats = renderer.frame_stats();
+        assert_eq!((stats.draw_commands, stats.draw_calls, stats.instances), (3, 3, 3));
+        assert_eq!(batched, unbatched);
+    }
+
+    #[test]
+    fn test_draws_without_a_strict_depth_test_keep_their_order() {
+        let Some(mut renderer) = renderer(64, 32) else {
+            return;
+        };
+
+        // With `Always` the last draw wins, so the red draws must not be merged past the blue one
+        let mut descriptor = tinted_descriptor();
+        descriptor.pipeline_state = PipelineState { depth_compare: wgpu::CompareFunction::Always, ..Default::default() };
+        let shader_id = renderer.register_shader(descriptor).unwrap();
+        let triangle = triangle(0.45, 0.9);
+
+        let image = renderer.render(|canvas| {
+            for tint in [[1.0f32, 0.0, 0.0, 1.0], [0.0, 0.0, 1.0, 1.0], [1.0, 0.0, 0.0, 1.0]] {
+                canvas.draw_with_instances(shader_id)
+                    .uniform("tint", tint)
+                    .uniform("offset", 0.0f32)
+                    .pump_geometry(&triangle, &[0u32]);
+            }
+        });
+
+        assert_eq!(image.get_pixel(32, 20).0, [255, 0, 0, 255]);
+        assert_eq!(renderer.frame_stats().draw_calls, 3);
+    }
+
+    #[test]
+    fn test_stats_overlay_draws_over_the_frame() {
+        let Some(mut renderer) = renderer(64, 32) else {
+            return;
+        };
+
+        renderer.set_clear_color(1.0, 0.0, 0.0, 1.0);
+        renderer.show_stats_overlay(true);
+        let image = renderer.render(|_canvas| {});
+
+        assert_ne!(image.get_pixel(12, 12).0, [255, 0, 0, 255]);
+        assert_eq!(image.get_pixel(2, 2).0, [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_debug_lines_draw_depth_tested_and_on_top() {
+        let Some(mut renderer) = renderer(64, 32) else {
+            return;
+        };
+
+        // An opaque blue triangle in front of both lines, which are at depth 0.8
+        let shader_id = renderer.register_shader(tinted_descriptor()).unwrap();
+        let occluder = triangle(3.0, 1.0);
+        renderer.set_clear_color(0.0, 0.0, 0.0, 1.0);
+        let image = renderer.render(|canvas| {
+            canvas.draw_with_instances(shader_id)
+                .uniform("tint", [0.0f32, 0.0, 1.0, 1.0])
+                .uniform("offset", 0.0f32)
+                .pump_geometry(&occluder, &[0u32]);
+            canvas.debug()
+                .line(glam::Vec3::new(-1.0, 0.5, 0.8), glam::Vec3::new(1.0, 0.5, 0.8), [1.0, 0.0, 0.0, 1.0])
+                .depth_test(false)
+                .line(glam::Vec3::new(-1.0, -0.5, 0.8), glam::Vec3::new(1.0, -0.5, 0.8), [0.0, 1.0, 0.0, 1.0]);
+        });
+
+        // The depth-tested red line only shows beside the triangle, the overlay green line across it
+        let column = |x: u32| (0..32).map(|y| image.get_pixel(x, y).0).collect::<Vec<_>>();
+        assert!(!column(32).contains(&[255, 0, 0, 255]));
+        assert!(column(32).contains(&[0, 255, 0, 255]));
+        assert!(column(1).contains(&[255, 0, 0, 255]));
+        assert_eq!(image.get_pixel(32, 16).0, [0, 0, 255, 255]);
+        assert_eq!(renderer.frame_stats().draw_calls, 3);
+
+        // Lines are collected again each frame
+        let image = renderer.render(|_canvas| {});
+        assert!(image.pixels().all(|pixel| pixel.0 == [0, 0, 0, 255]));
+
+        // and drawn from the vertex buffers of earlier frames
+        let image = renderer.render(|canvas| {
+            canvas.debug().line(glam::Vec3::new(-1.0, 0.5, 0.8), glam::Vec3::new(1.0, 0.5, 0.8), [1.0, 0.0, 0.0, 1.0]);
+        });
+        assert!((0..32).any(|y| image.get_pixel(32, y).0 == [255, 0, 0, 255]));
+        assert_eq!(renderer.frame_stats().draw_calls, 1);
+    }
+
+    #[test]
+    fn test_2d_shapes_draw_in_pixels_and_normalized_coordinates() {
+        let Some(mut renderer) = renderer(64, 32) else {
+            return;
+        };
+
+        let blue = renderer.upload_texture(
+            RgbaImage::from_pixel(2, 2, image::Rgba([0, 0, 255, 255])),
+            TextureOptions { generate_mipmaps: false, srgb: false },
+        ).unwrap();
+        renderer.set_clear_color(0.0, 0.0, 0.0, 1.0);
+        let image = renderer.render(|canvas| {
+            canvas.draw_2d()
+                .fill_rect(glam::Vec2::ZERO, glam::Vec2::splat(16.0), [1.0, 0.0, 0.0, 1.0])
+                .textured_quad(glam::Vec2::new(32.0, 0.0), glam::Vec2::splat(16.0), blue, [1.0; 4])
+                .coordinates(CoordinateSpace::Normalized)
+                .fill_rect(glam::Vec2::splat(0.5), glam::Vec2::splat(0.5), [0.0, 1.0, 0.0, 1.0]);
+        });
+
+        assert_eq!(image.get_pixel(8, 8).0, [255, 0, 0, 255]);
+        assert_eq!(image.get_pixel(40, 8).0, [0, 0, 255, 255]);
+        assert_eq!(image.get_pixel(48, 24).0, [0, 255, 0, 255]);
+        assert_eq!(image.get_pixel(8, 24).0, [0, 0, 0, 255]);
+        assert_eq!(renderer.frame_stats().draw_calls, 3);
+
+        // Later frames draw only their own shapes from the same vertex buffer
+        let image = renderer.render(|_canvas| {});
+        assert!(image.pixels().all(|pixel| pixel.0 == [0, 0, 0, 255]));
+        let image = renderer.render(|canvas| {
+            canvas.draw_2d().fill_rect(glam::Vec2::new(0.0, 16.0), glam::Vec2::splat(16.0), [1.0, 0.0, 0.0, 1.0]);
+        });
+        assert_eq!(image.get_pixel(8, 24).0, [255, 0, 0, 255]);
+        assert_eq!(image.get_pixel(8, 8).0, [0, 0, 0, 255]);
+        assert_eq!(renderer.frame_stats().draw_calls, 1);
+    }
+
+    #[test]
+    fn test_text_draws_inside_its_measured_box() {
+        let Some(mut renderer) = renderer(64, 32) else {
+            return;
+        };
+
+        // A font too large for any texture is rejected, keeping the bundled one
+        assert!(renderer.set_font(&Font::bundled(), 100_000.0).unwrap_err().contains("pixel limit"));
+        renderer.set_clear_color(0.0, 0.0, 0.0, 1.0);
+        let mut measured = glam::Vec2::ZERO;
+        let image = renderer.render(|canvas| {
+            measured = canvas.measure_text(12.0, "##\n#");
+            canvas.text(glam::Vec2::new(4.0, 2.0), 12.0, [1.0; 4], "##\n#");
+            // Behind the camera, so not drawn
+            canvas.text_at(&glam::Mat4::perspective_rh(1.0, 2.0, 0.1, 10.0), glam::Vec3::Z, 12.0, [1.0; 4], "#");
+        });
+
+        assert!(measured.x > 0.0 && measured.y > 12.0);
+        let inside = |x: u32, y: u32| x >= 4 && (x as f32) < 4.0 + measured.x && y >= 2 && (y as f32) < 2.0 + measured.y;
+        let lit: Vec<_> = image.enumerate_pixels().filter(|(_, _, pixel)| pixel.0[0] > 0).collect();
+        assert!(lit.iter().any(|(_, _, pixel)| pixel.0 == [255, 255, 255, 255]));
+        assert!(lit.iter().all(|(x, y, _)| inside(*x, *y)));
+    }
+
+    #[test]
+    fn test_uniforms_in_multiple_groups() {
+        let Some(mut renderer) = renderer(64, 32) else {
+            return;
+        };
+
+        // Tint in group 0 and offset in group 1
+        let mut descriptor = tinted_descriptor();
+        descriptor.shader_source = descriptor.shader_source.replace("@group(0) @binding(1)", "@group(1) @binding(0)");
+        descriptor.uniforms[1].group = 1;
+        descriptor.uniforms[1].binding = 0;
+        let shader_id = renderer.register_shader(descriptor).unwrap();
+
+        let triangle = triangle(0.3, 0.9);
+
+        // The first two draws share the tint group, the last one changes both groups
+        let image = renderer.render(|canvas| {
+            for (tint, offset) in [([1.0f32, 0.0, 0.0, 1.0], -0.6f32), ([1.0, 0.0, 0.0, 1.0], 0.0), ([0.0, 1.0, 0.0, 1.0], 0.6)] {
+                canvas.draw_with_instances(shader_id)
+                    .uniform("tint", tint)
+                    .uniform("offset", offset)
+                    .pump_geometry(&triangle, &[0u32]);
+            }
+        });
+
+        assert_eq!(image.get_pixel(13, 20).0, [255, 0, 0, 255]);
+        assert_eq!(image.get_pixel(32, 20).0, [255, 0, 0, 255]);
+        assert_eq!(image.get_pixel(51, 20).0, [0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn test_draws_select_shader_variants() {
+        let Some(mut renderer) = renderer(64, 32) else {
+            return;
+        };
+
+        let mut descriptor = tinted_descriptor();
+        descriptor.features = vec![ShaderFeature::flag("INVERT")];
+        descriptor.shader_source = descriptor.shader_source.replace("return tint;", "
+            #ifdef INVERT
+                return vec4<f32>(1.0 - tint.rgb, tint.a);
+            #else
+                return tint;
+            #endif
+        ");
+        let shader_id = renderer.register_shader(descriptor).unwrap();
+
+        let triangle = triangle(0.3, 0.9);
+
+        // Default, inverted and default again; the draw with an unknown feature is skipped
+        let image = renderer.render(|canvas| {
+            for (offset, features) in [(-0.6f32, &["INVERT"][..]), (0.0, &[]), (0.6, &["INVERT"]), (0.0, &["UNKNOWN"])] {
+                let mut draw = canvas.draw_with_instances(shader_id)
+                    .uniform("tint", [1.0f32, 0.0, 0.0, 1.0])
+                    .uniform("offset", offset);
+                for feature in features {
+                    draw = draw.feature(feature);
+                }
+                draw.pump_geometry(&triangle, &[0u32]);
+            }
+        });
+
+        assert_eq!(image.get_pixel(13, 20).0, [0, 255, 255, 255]);
+        assert_eq!(image.get_pixel(32, 20).0, [255, 0, 0, 255]);
+        assert_eq!(image.get_pixel(51, 20).0, [0, 255, 255, 255]);
+    }
+
+    #[test]
+    fn test_alpha_blending_without_depth_test() {
+        let Some(mut renderer) = renderer(64, 32) else {
+            return;
+        };
+
+        // Overlapping draws at equal depth only both show up without the depth test
+        let mut descriptor = tinted_descriptor();
+        descriptor.pipeline_state = PipelineState {
+            blend: BlendMode::Alpha,
+            depth_test: false,
+            ..Default::default()
+        };
+        let shader_id = renderer.register_shader(descriptor).unwrap();
+
+        let triangle = triangle(0.9, 0.9);
+
+        let image = renderer.render(|canvas| {
+            for tint in [[1.0f32, 0.0, 0.0, 1.0], [0.0, 0.0, 1.0, 0.5]] {
+                canvas.draw_with_instances(shader_id)
+                    .uniform("tint", tint)
+                    .uniform("offset", 0.0f32)
+                    .pump_geometry(&triangle, &[0u32]);
+            }
+        });
+
+        // Half blue over red (blended in linear space, so both channels stay bright)
+        let [r, g, b, _] = image.get_pixel(32, 20).0;
+        assert!(r > 100 && b > 100 && g == 0, "unexpected blend result {:?}", [r, g, b]);
+    }
+
+    #[test]
+    fn test_textured_quad_samples_texture() {
+        let Some(mut renderer) = renderer(64, 32) else {
+            return;
+        };
+
+        let shader_id = renderer.register_shader(ShaderDescriptor {
+            name: "Textured".to_string(),
+            source_path: None,
+            source_map: None,
+            features: Vec::new(),
+            shader_source: r#"
+                @group(0) @binding(0) var image: texture_2d<f32>;
+                @group(0) @binding(1) var image_sampler: sampler;
+
+                struct VertexOutput {
+                    @builtin(position) position: vec4<f32>,
+                    @location(0) uv: vec2<f32>,
+                };
+
+                @vertex
+                fn vs_main(@location(0) position: vec3<f32>) -> VertexOutput {
+                    var output: VertexOutput;
+                    output.position = vec4<f32>(position, 1.0);
+                    output.uv = vec2<f32>(position.x * 0.5 + 0.5, 0.5 - position.y * 0.5);
+                    return output;
+                }
+
+                @fragment
+                fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+                    return textureSample(image, image_sampler, in.uv);
+                }
+            "#.to_string(),
+            textures: vec![TextureParameter { name: "image".to_string(), group: 0, binding: 0 }],
+            samplers: vec![SamplerParameter {
+                name: "image_sampler".to_string(),
+                group: 0,
+                binding: 1,
+                config: SamplerConfig::nearest(),
+            }],
+            uniforms: Vec::new(),
+            pipeline_state: PipelineState { cull_mode: None, ..Default::default() },
+            ..tinted_descriptor()
+        }).unwrap();
+
+        // Red, green / blue, white quadrants
+        let mut image = RgbaImage::new(2, 2);
+        image.put_pixel(0, 0, image::Rgba([255, 0, 0, 255]));
+        image.put_pixel(1, 0, image::Rgba([0, 255, 0, 255]));
+        image.put_pixel(0, 1, image::Rgba([0, 0, 255, 255]));
+        image.put_pixel(1, 1, image::Rgba([255, 255, 255, 255]));
+        let texture = renderer.upload_texture(image, TextureOptions { generate_mipmaps: false, srgb: true }).unwrap();
+
+        let quad = Geometry {
+            vertices: vec![
+                TestVertex { position: [-1.0, -1.0, 0.5] },
+                TestVertex { position: [1.0, -1.0, 0.5] },
+                TestVertex { position: [1.0, 1.0, 0.5] },
+                TestVertex { position: [-1.0, 1.0, 0.5] },
+            ],
+            indices: vec![0, 1, 2, 0, 2, 3],
+        };
+
+        let frame = renderer.render(|canvas| {
+            canvas.draw_with_instances(shader_id)
+                .texture("image", texture)
+                .pump_geometry(&quad, &[0u32]);
+        });
+        assert_eq!(frame.get_pixel(16, 8).0, [255, 0, 0, 255]);
+        assert_eq!(frame.get_pixel(48, 8).0, [0, 255, 0, 255]);
+        assert_eq!(frame.get_pixel(16, 24).0, [0, 0, 255, 255]);
+        assert_eq!(frame.get_pixel(48, 24).0, [255, 255, 255, 255]);
+
+        // Without a texture the draw samples the white default
+        let frame = renderer.render(|canvas| {
+            canvas.draw_with_instances(shader_id).pump_geometry(&quad, &[0u32]);
+        });
+        assert_eq!(frame.get_pixel(16, 8).0, [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn test_storage_buffer_updates_and_grows() {
+        let Some(mut renderer) = renderer(64, 32) else {
+            return;
+        };
+
+        // Fill the triangle with the last color of a runtime-sized array
+        let shader_id = renderer.register_shader(ShaderDescriptor {
+            name: "Palette".to_string(),
+            source_path: None,
+            source_map: None,
+            features: Vec::new(),
+            shader_source: r#"
+                @group(0) @binding(0) var<storage, read> palette: array<vec4<f32>>;
+
+                @vertex
+                fn vs_main(@location(0) position: vec3<f32>) -> @builtin(position) vec4<f32> {
+                    return vec4<f32>(position, 1.0);
+                }
+
+                @fragment
+                fn fs_main() -> @location(0) vec4<f32> {
+                    return palette[arrayLength(&palette) - 1u];
+                }
+            "#.to_string(),
+            uniforms: Vec::new(),
+            storage: vec![StorageParameter { name: "palette".to_string(), group: 0, binding: 0, read_only: true, element_size: 16 }],
+            ..tinted_descriptor()
+        }).unwrap();
+
+        let triangle = triangle(0.9, 0.9);
+
+        let red = [1.0f32, 0.0, 0.0, 1.0];
+        let green = [0.0f32, 1.0, 0.0, 1.0];
+        let blue = [0.0f32, 0.0, 1.0, 1.0];
+
+        let frame = renderer.render(|canvas| {
+            canvas.update_storage(shader_id, "palette", &[red, green]);
+            canvas.draw_with_instances(shader_id).pump_geometry(&triangle, &[0u32]);
+        });
+        assert_eq!(frame.get_pixel(32, 20).0, [0, 255, 0, 255]);
+
+        // A longer array grows the buffer, and the binding tracks the new length
+        let frame = renderer.render(|canvas| {
+            canvas.update_storage(shader_id, "palette", &[red, green, blue, red, blue]);
+            canvas.draw_with_instances(shader_id).pump_geometry(&triangle, &[0u32]);
+        });
+        assert_eq!(frame.get_pixel(32, 20).0, [0, 0, 255, 255]);
+
+        // The contents persist until the next update
+        let frame = renderer.render(|canvas| {
+            canvas.draw_with_instances(shader_id).pump_geometry(&triangle, &[0u32]);
+        });
+        assert_eq!(frame.get_pixel(32, 20).0, [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn test_empty_storage_update_binds_one_zeroed_element() {
+        let Some(mut renderer) = renderer(64, 32) else {
+            return;
+        };
+
+        // Blue flags an array that isn't exactly one element long
+        let shader_id = renderer.register_shader(ShaderDescriptor {
+            name: "Lights".to_string(),
+            source_path: None,
+            source_map: None,
+            features: Vec::new(),
+            shader_source: r#"
+                @group(0) @binding(0) var<storage, read> lights: array<vec4<f32>>;
+
+                @vertex
+                fn vs_main(@location(0) position: vec3<f32>) -> @builtin(position) vec4<f32> {
+                    return vec4<f32>(position, 1.0);
+                }
+
+                @fragment
+                fn fs_main() -> @location(0) vec4<f32> {
+                    if arrayLength(&lights) != 1u {
+                        return vec4<f32>(0.0, 0.0, 1.0, 1.0);
+                    }
+                    return vec4<f32>(lights[0].rgb, 1.0);
+                }
+            "#.to_string(),
+            uniforms: Vec::new(),
+            storage: vec![StorageParameter { name: "lights".to_string(), group: 0, binding: 0, read_only: true, element_size: 16 }],
+            ..tinted_descriptor()
+        }).unwrap();
+
+        let triangle = triangle(0.9, 0.9);
+        let red = [1.0f32, 0.0, 0.0, 1.0];
+
+        let frame = renderer.render(|canvas| {
+            canvas.update_storage(shader_id, "lights", &[red, red]);
+            canvas.draw_with_instances(shader_id).pump_geometry(&triangle, &[0u32]);
+        });
+        assert_eq!(frame.get_pixel(32, 20).0, [0, 0, 255, 255]);
+
+        // Clearing the list leaves no trace of the old lights
+        let frame = renderer.render(|canvas| {
+            canvas.update_storage(shader_id, "lights", &[] as &[[f32; 4]]);
+            canvas.draw_with_instances(shader_id).pump_geometry(&triangle, &[0u32]);
+        });
+        assert_eq!(frame.get_pixel(32, 20).0, [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_empty_compute_storage_update_binds_one_zeroed_element() {
+        let Some(mut renderer) = renderer(8, 8) else {
+            return;
+        };
+
+        // Report the length and first element of the input
+        let probe = renderer.register_compute_shader(ComputeShaderDescriptor {
+            name: "Probe".to_string(),
+            source_path: None,
+            source_map: None,
+            shader_source: r#"
+                @group(0) @binding(0) var<storage, read> values: array<u32>;
+                @group(0) @binding(1) var<storage, read_write> report: array<u32>;
+
+                @compute @workgroup_size(1)
+                fn cs_main() {
+                    report[0] = arrayLength(&values);
+                    report[1] = values[0];
+                }
+            "#.to_string(),
+            entry_point: "cs_main".to_string(),
+            uniforms: Vec::new(),
+            storage: vec![
+                StorageParameter { name: "values".to_string(), group: 0, binding: 0, read_only: true, element_size: 4 },
+                StorageParameter { name: "report".to_string(), group: 0, binding: 1, read_only: false, element_size: 4 },
+            ],
+        }).unwrap();
+
+        renderer.render(|canvas| {
+            canvas.update_compute_storage(probe, "values", &[7u32, 7]);
+            canvas.update_compute_storage(probe, "report", &[0u32, 0]);
+            canvas.dispatch(probe).workgroups(1, 1, 1);
+        });
+        assert_eq!(renderer.read_storage::<u32>(probe, "report").unwrap(), vec![2, 7]);
+
+        // Clearing the input leaves no trace of the old values
+        renderer.render(|canvas| {
+            canvas.update_compute_storage(probe, "values", &[] as &[u32]);
+            canvas.dispatch(probe).workgroups(1, 1, 1);
+        });
+        assert_eq!(renderer.read_storage::<u32>(probe, "values").unwrap(), vec![0]);
+        assert_eq!(renderer.read_storage::<u32>(probe, "report").unwrap(), vec![1, 0]);
+    }
+
+    #[test]
+    fn test_compute_conway_step_ping_pongs_buffers() {
+        let Some(mut renderer) = renderer(8, 8) else {
+            return;
+        };
+
+        // One Game of Life step on a wrapping square grid
+        let life = renderer.register_compute_shader(ComputeShaderDescriptor {
+            name: "Life".to_string(),
+            source_path: None,
+            source_map: None,
+            shader_source: r#"
+                @group(0) @binding(0) var<uniform> width: u32;
+                @group(0) @binding(1) var<storage, read> cells_in: array<u32>;
+                @group(0) @binding(2) var<storage, read_write> cells_out: array<u32>;
+
+                @compute @workgroup_size(8, 8)
+                fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+                    if (id.x >= width || id.y >= width) {
+                        return;
+                    }
+                    var neighbors = 0u;
+                    for (var dy = 0u; dy < 3u; dy++) {
+                        for (var dx = 0u; dx < 3u; dx++) {
+                            if (dx != 1u || dy != 1u) {
+                                let x = (id.x + dx + width - 1u) % width;
+                                let y = (id.y + dy + width - 1u) % width;
+                                neighbors += cells_in[y * width + x];
+                            }
+                        }
+                    }
+                    let alive = cells_in[id.y * width + id.x];
+                    cells_out[id.y * width + id.x] = select(0u, 1u, neighbors == 3u || (alive == 1u && neighbors == 2u));
+                }
+            "#.to_string(),
+            entry_point: "cs_main".to_string(),
+            uniforms: vec![UniformParameter { name: "width".to_string(), group: 0, binding: 0, size: 4 }],
+            storage: vec![
+                StorageParameter { name: "cells_in".to_string(), group: 0, binding: 1, read_only: true, element_size: 4 },
+                StorageParameter { name: "cells_out".to_string(), group: 0, binding: 2, read_only: false, element_size: 4 },
+            ],
+        }).unwrap();
+
+        // A horizontal blinker in a 5x5 grid
+        let mut cells = [0u32; 25];
+        cells[2 * 5 + 1..2 * 5 + 4].fill(1);
+        let step = |canvas: &mut Canvas| {
+            canvas.dispatch(life).uniform("width", 5u32).workgroups(1, 1, 1);
+            canvas.swap_storage(life, "cells_in", "cells_out");
+        };
+
+        renderer.render(|canvas| {
+            canvas.update_compute_storage(life, "cells_in", &cells);
+            canvas.update_compute_storage(life, "cells_out", &cells);
+            step(canvas);
+        });
+        let vertical = renderer.read_storage::<u32>(life, "cells_in").unwrap();
+        let alive = vertical.iter().enumerate().filter(|(_, cell)| **cell == 1).map(|(i, _)| i).collect::<Vec<_>>();
+        assert_eq!(alive, vec![7, 12, 17]);
+
+        // The next frame sees the previous frame's results and continues from the swapped buffers
+        let mut previous = Vec::new();
+        renderer.render(|canvas| {
+            previous = canvas.read_storage::<u32>(life, "cells_in").unwrap();
+            step(canvas);
+        });
+        assert_eq!(previous, vertical);
+        assert_eq!(renderer.read_storage::<u32>(life, "cells_in").unwrap(), cells);
+        assert!(renderer.read_storage::<u32>(life, "cells").unwrap_err().contains("no storage buffer 'cells'"));
+    }
+
+    #[test]
+    fn test_compute_particles_drawn_as_instances() {
+        let Some(mut renderer) = renderer(64, 32) else {
+            return;
+        };
+
+        // Move every particle to the right each frame
+        let particles = renderer.register_compute_shader(ComputeShaderDescriptor {
+            name: "Particles".to_string(),
+            source_path: None,
+            source_map: None,
+            shader_source: r#"
+                @group(0) @binding(0) var<uniform> velocity: vec4<f32>;
+                @group(0) @binding(1) var<storage, read_write> particles: array<vec4<f32>>;
+
+                @compute @workgroup_size(64)
+                fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+                    if (id.x < arrayLength(&particles)) {
+                        particles[id.x] += velocity;
+                    }
+                }
+            "#.to_string(),
+            entry_point: "cs_main".to_string(),
+            uniforms: vec![UniformParameter { name: "velocity".to_string(), group: 0, binding: 0, size: 16 }],
+            storage: vec![StorageParameter { name: "particles".to_string(), group: 0, binding: 1, read_only: false, element_size: 16 }],
+        }).unwrap();
+
+        // Draw a small triangle at each particle's position
+        let shader_id = renderer.register_shader(ShaderDescriptor {
+            name: "Particle".to_string(),
+            source_path: None,
+            source_map: None,
+            features: Vec::new(),
+            shader_source: r#"
+                @vertex
+                fn vs_main(@location(0) position: vec3<f32>, @location(1) offset: vec4<f32>) -> @builtin(position) vec4<f32> {
+                    return vec4<f32>(position.xy + offset.xy, position.z, 1.0);
+                }
+
+                @fragment
+                fn fs_main() -> @location(0) vec4<f32> {
+                    return vec4<f32>(1.0, 1.0, 1.0, 1.0);
+                }
+            "#.to_string(),
+            uniforms: Vec::new(),
+            instance_attributes: vec![VertexAttribute {
+                name: "offset".to_string(),
+                location: 1,
+                format: wgpu::VertexFormat::Float32x4,
+                offset: 0,
+                semantic: None,
+            }],
+            instance_stride: 16,
+            ..tinted_descriptor()
+        }).unwrap();
+        let mesh = renderer.upload_geometry(&triangle(0.2, 0.2));
+
+        let frame = renderer.render(|canvas| {
+            canvas.update_compute_storage(particles, "particles", &[[-0.5f32, 0.0, 0.0, 0.0]]);
+            canvas.dispatch(particles).uniform("velocity", [1.0f32, 0.0, 0.0, 0.0]).workgroups(1, 1, 1);
+            canvas.draw_with_instances(shader_id).draw_mesh_from_storage(mesh, particles, "particles");
+        });
+
+        // The draw sees the particle after this frame's dispatch, not where it was uploaded
+        assert_eq!(frame.get_pixel(16, 16).0, [0, 0, 0, 255]);
+        assert_eq!(frame.get_pixel(48, 16).0, [255, 255, 255, 255]);
+        assert_eq!(renderer.read_storage::<[f32; 4]>(particles, "particles").unwrap(), vec![[0.5, 0.0, 0.0, 0.0]]);
+    }
+
+    #[test]
+    fn test_indirect_draws_read_arguments_written_by_cpu_or_compute() {
+        let Some(mut renderer) = renderer(64, 32) else {
+            return;
+        };
+
+        // Limit the instance count of the first draw, like a GPU culling pass would
+        let culler = renderer.register_compute_shader(ComputeShaderDescriptor {
+            name: "Culler".to_string(),
+            source_path: None,
+            source_map: None,
+            shader_source: r#"
+                struct DrawIndexedIndirect {
+                    index_count: u32,
+                    instance_count: u32,
+                    first_index: u32,
+                    base_vertex: i32,
+                    first_instance: u32,
+                }
+
+                @group(0) @binding(0) var<uniform> visible: u32;
+                @group(0) @binding(1) var<storage, read_write> offsets: array<vec4<f32>>;
+                @group(0) @binding(2) var<storage, read_write> arguments: array<DrawIndexedIndirect>;
+
+                @compute @workgroup_size(1)
+                fn cs_main() {
+                    arguments[0].instance_count = min(visible, arrayLength(&offsets));
+                }
+            "#.to_string(),
+            entry_point: "cs_main".to_string(),
+            uniforms: vec![UniformParameter { name: "visible".to_string(), group: 0, binding: 0, size: 4 }],
+            storage: vec![
+                StorageParameter { name: "offsets".to_string(), group: 0, binding: 1, read_only: false, element_size: 16 },
+                StorageParameter {
+                    name: "arguments".to_string(), group: 0, binding: 2, read_only: false,
+                    element_size: DrawIndexedIndirect::SIZE,
+                },
+            ],
+        }).unwrap();
+        let shader_id = renderer.register_shader(ShaderDescriptor {
+            name: "Offset".to_string(),
+            source_path: None,
+            source_map: None,
+            features: Vec::new(),
+            shader_source: r#"
+                @vertex
+                fn vs_main(@location(0) position: vec3<f32>, @location(1) offset: vec4<f32>) -> @builtin(position) vec4<f32> {
+                    return vec4<f32>(position.xy + offset.xy, position.z, 1.0);
+                }
+
+                @fragment
+                fn fs_main() -> @location(0) vec4<f32> {
+                    return vec4<f32>(1.0, 1.0, 1.0, 1.0);
+                }
+            "#.to_string(),
+            uniforms: Vec::new(),
+            instance_attributes: vec![VertexAttribute {
+                name: "offset".to_string(),
+                location: 1,
+                format: wgpu::VertexFormat::Float32x4,
+                offset: 0,
+                semantic: None,
+            }],
+            instance_stride: 16,
+            ..tinted_descriptor()
+        }).unwrap();
+        let mesh = renderer.upload_geometry(&triangle(0.2, 0.2));
+
+        // Arguments filled on the CPU draw both instances
+        let frame = renderer.render(|canvas| {
+            canvas.update_compute_storage(culler, "offsets", &[[-0.5f32, 0.0, 0.0, 0.0], [0.5, 0.0, 0.0, 0.0]]);
+            canvas.update_compute_storage(culler, "arguments", &[DrawIndexedIndirect::new(3, 2)]);
+            canvas.draw_with_instances(shader_id).draw_mesh_indirect(mesh, culler, "offsets", "arguments", 1);
+        });
+        assert_eq!(frame.get_pixel(16, 16).0, [255, 255, 255, 255]);
+        assert_eq!(frame.get_pixel(48, 16).0, [255, 255, 255, 255]);
+        assert_eq!(renderer.frame_stats().draw_calls, 1);
+
+        // A dispatch rewrites the arguments; draws past the single element are skipped
+        let frame = renderer.render(|canvas| {
+            canvas.dispatch(culler).uniform("visible", 1u32).workgroups(1, 1, 1);
+            canvas.draw_with_instances(shader_id).draw_mesh_indirect(mesh, culler, "offsets", String::from("arguments"), 4);
+        });
+        assert_eq!(frame.get_pixel(16, 16).0, [255, 255, 255, 255]);
+        assert_eq!(frame.get_pixel(48, 16).0, [0, 0, 0, 255]);
+        assert_eq!(
+            renderer.read_storage::<DrawIndexedIndirect>(culler, "arguments").unwrap(),
+            vec![DrawIndexedIndirect::new(3, 1)]
+        );
+    }
+
+    #[test]
+    fn test_register_shader_reports_wgsl_errors() {
+        let Some(mut renderer) = renderer(16, 16) else {
+            return;
+        };
+
+        let mut descriptor = tinted_descriptor();
+        descriptor.shader_source = descriptor.shader_source.replace("position.y,", "position.y");
+        let error = renderer.register_shader(descriptor).unwrap_err();
+        assert_eq!(error.shader, "Tinted");
+        assert!(error.line.is_some() && error.column.is_some(), "{}", error);
+    }
+
+    #[test]
+    fn test_register_shader_reports_layout_errors() {
+        let Some(mut renderer) = renderer(16, 16) else {
+            return;
+        };
+
+        // The WGSL reads `offset`, but the descriptor doesn't bind it
+        let mut descriptor = tinted_descriptor();
+        descriptor.uniforms.retain(|uniform| uniform.name != "offset");
+        let error = renderer.register_shader(descriptor).unwrap_err();
+        assert_eq!(error.line, None);
+        assert!(!error.message.is_empty());
+
+        // The device survives the failed pipeline
+        renderer.set_clear_color(0.0, 1.0, 0.0, 1.0);
+        assert_eq!(renderer.render(|_canvas| {}).get_pixel(0, 0).0, [0, 255, 0, 255]);
+        assert!(renderer.register_shader(tinted_descriptor()).is_ok());
+    }
+
+    #[test]
+    fn test_watched_shader_reloads_under_same_id() {
+        let Some(mut renderer) = renderer(16, 16) else {
+            return;
+        };
+
+        let path = std::env::temp_dir().join(format!("rustica_reload_{}.wgsl", std::process::id()));
+        let mut descriptor = tinted_descriptor();
+        std::fs::write(&path, &descriptor.shader_source).unwrap();
+        descriptor.source_path = Some(path.display().to_string());
+        let shader_id = renderer.register_shader(descriptor).unwrap();
+        renderer.watch_shaders(Duration::ZERO);
+
+        // Rewrite the file with a newer modification time than the original
+        let mut modified = std::time::SystemTime::now();
+        let mut rewrite = |source: String| {
+            modified += Duration::from_secs(5);
+            std::fs::write(&path, source).unwrap();
+            std::fs::File::options().write(true).open(&path).unwrap().set_modified(modified).unwrap();
+        };
+        let triangle = triangle(0.9, 0.9);
+        let draw = |renderer: &mut HeadlessRenderer| {
+            renderer.render(|canvas| {
+                canvas.draw_with_instances(shader_id)
+                    .uniform("tint", [1.0f32, 0.0, 0.0, 1.0])
+                    .uniform("offset", 0.0f32)
+                    .pump_geometry(&triangle, &[0u32]);
+            }).get_pixel(8, 8).0
+        };
+        assert_eq!(draw(&mut renderer), [255, 0, 0, 255]);
+
+        let source = tinted_descriptor().shader_source;
+        rewrite(source.replace("return tint;", "return tint.bgra;"));
+        assert_eq!(draw(&mut renderer), [0, 0, 255, 255]);
+
+        // A broken edit keeps the last working pipeline
+        rewrite(source.replace("return tint;", "return tint"));
+        assert_eq!(draw(&mut renderer), [0, 0, 255, 255]);
+
+        rewrite(source);
+        assert_eq!(draw(&mut renderer), [255, 0, 0, 255]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_reload_watches_newly_included_files() {
+        let Some(mut renderer) = renderer(16, 16) else {
+            return;
+        };
+
+        let directory = std::env::temp_dir().join(format!("rustica_include_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let (main, swizzle) = (directory.join("main.wgsl"), directory.join("swizzle.wgsl"));
+        let source = tinted_descriptor().shader_source;
+        std::fs::write(&main, &source).unwrap();
+        let preprocessed = crate::WgslPreprocessor::new().process_file(&main).unwrap();
+        let shader_id = renderer.register_shader(ShaderDescriptor {
+            source_path: Some(main.display().to_string()),
+            source_map: Some(preprocessed.source_map),
+            ..tinted_descriptor()
+        }).unwrap();
+        renderer.watch_shaders(Duration::ZERO);
+
+        let mut modified = std::time::SystemTime::now();
+        let mut rewrite = |path: &std::path::Path, source: &str| {
+            modified += Duration::from_secs(5);
+            std::fs::write(path, source).unwrap();
+            std::fs::File::options().write(true).open(path).unwrap().set_modified(modified).unwrap();
+        };
+        let triangle = triangle(0.9, 0.9);
+        let mut draw = || {
+            renderer.render(|canvas| {
+                canvas.draw_with_instances(shader_id)
+                    .uniform("tint", [1.0f32, 0.0, 0.0, 1.0])
+                    .uniform("offset", 0.0f32)
+                    .pump_geometry(&triangle, &[0u32]);
+            }).get_pixel(8, 8).0
+        };
+
+        // The edit starts including a file, whose later changes are picked up too
+        rewrite(&swizzle, "fn swizzle(color: vec4<f32>) -> vec4<f32> { return color.bgra; }\n");
+        rewrite(&main, &format!("#include \"swizzle.wgsl\"\n{}", source.replace("return tint;", "return swizzle(tint);")));
+        assert_eq!(draw(), [0, 0, 255, 255]);
+        rewrite(&swizzle, "fn swizzle(color: vec4<f32>) -> vec4<f32> { return color.grba; }\n");
+        assert_eq!(draw(), [0, 255, 0, 255]);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
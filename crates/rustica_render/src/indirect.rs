@@ -0,0 +1,46 @@
+// Arguments of indirect draws
+
+/// Arguments of one indexed draw, read from a GPU buffer by an indirect draw
+///
+/// Laid out like wgpu's `DrawIndexedIndirectArgs`, so a compute shader can
+/// write them as this WGSL struct:
+///
+/// ```wgsl
+/// struct DrawIndexedIndirect {
+///     index_count: u32,
+///     instance_count: u32,
+///     first_index: u32,
+///     base_vertex: i32,
+///     first_instance: u32,
+/// }
+/// ```
+///
+/// A non-zero `first_instance` needs an adapter with `INDIRECT_FIRST_INSTANCE`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct DrawIndexedIndirect {
+    /// Number of indices to draw
+    pub index_count: u32,
+    /// Number of instances to draw
+    pub instance_count: u32,
+    /// First index read from the index buffer
+    pub first_index: u32,
+    /// Value added to each index before reading the vertex buffer
+    pub base_vertex: i32,
+    /// First instance read from the instance buffer
+    pub first_instance: u32,
+}
+
+impl DrawIndexedIndirect {
+    /// Size in bytes of one set of arguments
+    pub const SIZE: u64 = std::mem::size_of::<Self>() as u64;
+
+    /// Draw the first `index_count` indices of a mesh `instance_count` times
+    pub fn new(index_count: u32, instance_count: u32) -> Self {
+        Self {
+            index_count,
+            instance_count,
+            ..Default::default()
+        }
+    }
+}
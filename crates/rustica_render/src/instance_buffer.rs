@@ -0,0 +1,130 @@
+// Reusable, growable instance buffer shared by all draws in a frame
+
+use std::ops::Range;
+use wgpu::{Buffer, BufferAddress, BufferDescriptor, BufferUsages, Device, Queue, COPY_BUFFER_ALIGNMENT};
+
+use crate::diagnostics::label;
+
+/// Smallest allocation made for the instance buffer and the uniform ring
+pub(crate) const MIN_CAPACITY: BufferAddress = 4096;
+
+/// Instance data for every draw of a frame, packed into one GPU buffer
+///
+/// Each frame the draws append their instance bytes, the whole block is written
+/// with a single `write_buffer` call, and every draw binds its own slice. The
+/// GPU buffer is only reallocated when a frame needs more space than any frame
+/// before it. The built-in 2D and debug line passes use their own instances of
+/// it for their per-frame vertices.
+pub struct InstanceBuffer {
+    /// Debug label of the GPU buffer
+    label: String,
+    /// GPU buffer (None until the first upload)
+    buffer: Option<Buffer>,
+    /// Size of the GPU buffer in bytes
+    capacity: BufferAddress,
+    /// CPU-side data for the current frame
+    staging: Vec<u8>,
+}
+
+impl InstanceBuffer {
+    /// Create an empty instance buffer
+    pub fn new() -> Self {
+        Self::with_label(label(&["frame", "instances"]))
+    }
+
+    /// Create an empty buffer whose GPU allocation carries the given debug label
+    pub fn with_label(label: String) -> Self {
+        Self {
+            label,
+            buffer: None,
+            capacity: 0,
+            staging: Vec::new(),
+        }
+    }
+
+    /// Discard the data of the previous frame, keeping all allocations
+    pub fn clear(&mut self) {
+        self.staging.clear();
+    }
+
+    /// Append instance data and return its byte range within the buffer
+    pub fn push(&mut self, data: &[u8]) -> Range<BufferAddress> {
+        let start = self.staging.len() as BufferAddress;
+        self.staging.extend_from_slice(data);
+        let end = self.staging.len() as BufferAddress;
+
+        // Keep every slice aligned so both the copy and the vertex binding are valid
+        let aligned = end.next_multiple_of(COPY_BUFFER_ALIGNMENT);
+        self.staging.resize(aligned as usize, 0);
+
+        start..end
+    }
+
+    /// Write the staged data to the GPU, growing the buffer if needed
+    pub fn upload(&mut self, device: &Device, queue: &Queue) {
+        if self.staging.is_empty() {
+            return;
+        }
+
+        let required = self.staging.len() as BufferAddress;
+        if self.buffer.is_none() || required > self.capacity {
+            self.capacity = grown_capacity(self.capacity, required);
+            self.buffer = Some(device.create_buffer(&BufferDescriptor {
+                label: Some(&self.label),
+                size: self.capacity,
+                usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }));
+        }
+
+        if let Some(buffer) = &self.buffer {
+            queue.write_buffer(buffer, 0, &self.staging);
+        }
+    }
+
+    /// Get the GPU buffer
+    pub fn buffer(&self) -> Option<&Buffer> {
+        self.buffer.as_ref()
+    }
+}
+
+impl Default for InstanceBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Compute the new capacity for a buffer that must hold `required` bytes
+pub(crate) fn grown_capacity(current: BufferAddress, required: BufferAddress) -> BufferAddress {
+    required.max(current).max(MIN_CAPACITY).next_power_of_two()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_returns_aligned_ranges() {
+        let mut instances = InstanceBuffer::new();
+        let first = instances.push(&[1, 2, 3, 4, 5, 6]);
+        let second = instances.push(&[7, 8, 9, 10]);
+
+        assert_eq!(first, 0..6);
+        assert_eq!(second, 8..12);
+    }
+
+    #[test]
+    fn test_clear_keeps_capacity() {
+        let mut instances = InstanceBuffer::new();
+        instances.push(&[0; 64]);
+        instances.clear();
+        assert_eq!(instances.push(&[0; 4]), 0..4);
+    }
+
+    #[test]
+    fn test_grown_capacity() {
+        assert_eq!(grown_capacity(0, 100), MIN_CAPACITY);
+        assert_eq!(grown_capacity(MIN_CAPACITY, 5000), 8192);
+        assert_eq!(grown_capacity(8192, 8193), 16384);
+    }
+}
@@ -6,18 +6,57 @@ pub use rustica_foundation::geometry::{Vertex, VertexAttributeProvider, VertexAt
 // Re-export public API
 pub use canvas::Canvas;
 pub use render_window::RenderWindow;
-pub use custom_shader::{CustomShader, ShaderDescriptor, UniformParameter};
+pub use compute_shader::{ComputeHandle, ComputeShader, ComputeShaderDescriptor};
+pub use custom_shader::{CustomShader, SamplerParameter, ShaderDescriptor, StorageParameter, TextureParameter, UniformParameter};
+pub use reflection::{BindingKind, MismatchReport, ReflectedBinding, ReflectedInput, ShaderReflection};
+pub use shader_error::ShaderError;
+pub use frame_stats::FrameStats;
+pub use shader_features::{FeatureSet, ShaderFeature};
+pub use rustica_wgsl_preprocessor::{PreprocessError, PreprocessedWgsl, SourceMap, WgslPreprocessor};
+pub use resources::{get_resource_manager, ResourceManager};
+pub use pipeline_state::{BlendMode, PipelineState};
+pub use uniform_layout::{UniformBlock, UniformField, UniformType};
+pub use mesh::MeshHandle;
+pub use indirect::DrawIndexedIndirect;
+pub use debug_draw::DebugDraw;
+pub use draw_2d::{CoordinateSpace, Draw2d};
+pub use font::{Font, TextAlign};
+pub use lod_mesh::{LodLevel, LodMesh};
+pub use texture::{SamplerConfig, TextureHandle, TextureOptions};
+pub use headless::HeadlessRenderer;
+
+// Re-export the image type returned by offscreen rendering
+pub use image::RgbaImage;
 
 // Internal modules
 mod draw_commands;
+mod batching;
 mod canvas;
 mod render_context;
 mod render_window;
-mod shaders; // Simplified module, no built-in shaders
 mod custom_shader;
+mod compute_shader;
+mod mesh;
+mod lod_mesh;
+pub mod texture;
+mod instance_buffer;
+mod indirect;
+mod uniform_ring;
+mod uniform_layout;
+mod pipeline_state;
+mod reflection;
+mod resources;
+mod shader_error;
+mod shader_features;
+mod shader_watcher;
+mod frame_stats;
+mod stats_overlay;
+mod debug_draw;
+mod debug_renderer;
+mod draw_2d;
+mod renderer_2d;
+mod font;
+mod headless;
+pub mod golden;
+pub mod diagnostics;
 pub mod prelude;
-
-#[cfg(test)]
-mod tests {
-    // Re-export tests module
-}
@@ -0,0 +1,88 @@
+// Meshes with several levels of detail
+
+use rustica_foundation::geometry::BoundingSphere;
+use glam::Vec3;
+
+use crate::mesh::MeshHandle;
+
+/// One resolution of a `LodMesh`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LodLevel {
+    /// Uploaded geometry of this level
+    pub mesh: MeshHandle,
+    /// Smallest screen size drawn with this level, as a fraction of the viewport height
+    pub min_screen_size: f32,
+}
+
+/// A mesh uploaded at several resolutions, drawn with the one that fits its size on screen
+///
+/// Levels are kept from the most to the least detailed. An object uses the
+/// first level whose `min_screen_size` its bounding sphere's projected
+/// diameter reaches; objects smaller than every level's threshold aren't drawn.
+/// Give the last level a threshold of 0 to always draw something.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LodMesh {
+    levels: Vec<LodLevel>,
+}
+
+impl LodMesh {
+    /// Create a mesh without levels
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a level drawn when the object covers at least `min_screen_size` of the viewport height
+    pub fn with_level(mut self, mesh: MeshHandle, min_screen_size: f32) -> Self {
+        let index = self.levels.partition_point(|level| level.min_screen_size >= min_screen_size);
+        self.levels.insert(index, LodLevel { mesh, min_screen_size });
+        self
+    }
+
+    /// Get the levels, from the most to the least detailed
+    pub fn levels(&self) -> &[LodLevel] {
+        &self.levels
+    }
+
+    /// Get the index of the level to draw an object of the given screen size with
+    pub fn select(&self, screen_size: f32) -> Option<usize> {
+        self.levels.iter().position(|level| screen_size >= level.min_screen_size)
+    }
+
+    /// Get the fraction of the viewport height covered by a sphere seen from `eye`
+    ///
+    /// `fov_y` is the camera's vertical field of view in radians. A sphere
+    /// containing the eye covers the whole viewport.
+    pub fn screen_size(sphere: &BoundingSphere, eye: Vec3, fov_y: f32) -> f32 {
+        let distance = sphere.center.distance(eye);
+        if distance <= sphere.radius {
+            return f32::INFINITY;
+        }
+        sphere.radius / (distance * (fov_y / 2.0).tan())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levels_are_selected_by_screen_size() {
+        let lod = LodMesh::new()
+            .with_level(MeshHandle(2), 0.05)
+            .with_level(MeshHandle(0), 0.5)
+            .with_level(MeshHandle(1), 0.2);
+        let meshes: Vec<_> = lod.levels().iter().map(|level| level.mesh).collect();
+        assert_eq!(meshes, [MeshHandle(0), MeshHandle(1), MeshHandle(2)]);
+
+        assert_eq!(lod.select(0.8), Some(0));
+        assert_eq!(lod.select(0.2), Some(1));
+        assert_eq!(lod.select(0.1), Some(2));
+        assert_eq!(lod.select(0.01), None);
+
+        // A unit sphere 10 units away with a 90 degree field of view covers a tenth of the height
+        let sphere = BoundingSphere::new(Vec3::new(0.0, 0.0, -10.0), 1.0);
+        let size = LodMesh::screen_size(&sphere, Vec3::ZERO, 90f32.to_radians());
+        assert!((size - 0.1).abs() < 1e-6);
+        assert_eq!(LodMesh::screen_size(&sphere, sphere.center, 1.0), f32::INFINITY);
+    }
+}
@@ -0,0 +1,117 @@
+// GPU mesh storage for persistent geometry
+
+use wgpu::{Buffer, BufferUsages, Device};
+use wgpu::util::DeviceExt;
+
+use crate::diagnostics::label;
+
+/// Handle to geometry that has been uploaded to the GPU
+///
+/// Returned by `RenderWindow::upload_geometry` and passed to
+/// `InstancedShaderDrawBuilder::draw_mesh`. The underlying buffers stay alive
+/// for the lifetime of the window, so drawing with a handle never re-uploads
+/// the vertex or index data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MeshHandle(pub(crate) usize);
+
+impl MeshHandle {
+    /// Get the index of the mesh in the registry
+    pub fn id(&self) -> usize {
+        self.0
+    }
+}
+
+/// Vertex and index buffers that persist between frames
+pub struct GpuMesh {
+    /// Vertex buffer (None until the device is available)
+    vertex_buffer: Option<Buffer>,
+    /// Index buffer (None until the device is available)
+    index_buffer: Option<Buffer>,
+    /// Number of indices in the mesh
+    index_count: u32,
+    /// Raw vertex and index data (for deferred initialization)
+    pending: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+impl GpuMesh {
+    /// Create a new mesh and upload its data to the GPU
+    pub fn new(device: &Device, vertices: &[u8], indices: &[u8], index_count: u32) -> Self {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&label(&["mesh", "vertices"])),
+            contents: vertices,
+            usage: BufferUsages::VERTEX,
+        });
+
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&label(&["mesh", "indices"])),
+            contents: indices,
+            usage: BufferUsages::INDEX,
+        });
+
+        Self {
+            vertex_buffer: Some(vertex_buffer),
+            index_buffer: Some(index_buffer),
+            index_count,
+            pending: None,
+        }
+    }
+
+    /// Create a placeholder mesh that will be uploaded once a device exists
+    pub fn new_placeholder(vertices: Vec<u8>, indices: Vec<u8>, index_count: u32) -> Self {
+        Self {
+            vertex_buffer: None,
+            index_buffer: None,
+            index_count,
+            pending: Some((vertices, indices)),
+        }
+    }
+
+    /// Upload the pending data of a placeholder mesh
+    pub fn initialize(&mut self, device: &Device) -> bool {
+        if self.is_initialized() {
+            return false; // Already uploaded
+        }
+
+        if let Some((vertices, indices)) = self.pending.take() {
+            let uploaded = Self::new(device, &vertices, &indices, self.index_count);
+            self.vertex_buffer = uploaded.vertex_buffer;
+            self.index_buffer = uploaded.index_buffer;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Get the vertex buffer
+    pub fn vertex_buffer(&self) -> Option<&Buffer> {
+        self.vertex_buffer.as_ref()
+    }
+
+    /// Get the index buffer
+    pub fn index_buffer(&self) -> Option<&Buffer> {
+        self.index_buffer.as_ref()
+    }
+
+    /// Get the number of indices
+    pub fn index_count(&self) -> u32 {
+        self.index_count
+    }
+
+    /// Check if the mesh has been uploaded to the GPU
+    pub fn is_initialized(&self) -> bool {
+        self.vertex_buffer.is_some() && self.index_buffer.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_placeholder_mesh_is_not_initialized() {
+        let mesh = GpuMesh::new_placeholder(vec![0; 36], vec![0; 12], 3);
+        assert!(!mesh.is_initialized());
+        assert_eq!(mesh.index_count(), 3);
+        assert!(mesh.vertex_buffer().is_none());
+    }
+}
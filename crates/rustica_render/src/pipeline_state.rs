@@ -0,0 +1,195 @@
+// Fixed-function pipeline state for custom shaders
+
+use wgpu::{
+    BlendComponent, BlendFactor, BlendOperation, BlendState, CompareFunction, DepthBiasState,
+    Face, Features, FrontFace, IndexFormat, PolygonMode, PrimitiveTopology,
+};
+
+/// How a shader's output is combined with the color already in the target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BlendMode {
+    /// Overwrite the target color
+    #[default]
+    Replace,
+    /// Standard alpha blending (`src * a + dst * (1 - a)`)
+    Alpha,
+    /// Add the source color to the target (`src * a + dst`)
+    Additive,
+    /// Alpha blending for colors already multiplied by alpha (`src + dst * (1 - a)`)
+    Premultiplied,
+}
+
+impl BlendMode {
+    /// Get the wgpu blend state for this mode
+    pub fn blend_state(&self) -> BlendState {
+        match self {
+            BlendMode::Replace => BlendState::REPLACE,
+            BlendMode::Alpha => BlendState::ALPHA_BLENDING,
+            BlendMode::Premultiplied => BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+            BlendMode::Additive => BlendState {
+                color: BlendComponent {
+                    src_factor: BlendFactor::SrcAlpha,
+                    dst_factor: BlendFactor::One,
+                    operation: BlendOperation::Add,
+                },
+                alpha: BlendComponent {
+                    src_factor: BlendFactor::One,
+                    dst_factor: BlendFactor::One,
+                    operation: BlendOperation::Add,
+                },
+            },
+        }
+    }
+}
+
+/// Rasterization, blending and depth settings of a custom shader's pipeline
+///
+/// The defaults match what every pipeline used before this was configurable:
+/// triangle lists, back-face culling of counter-clockwise geometry, no blending
+/// and a `Less` depth test with depth writes.
+#[derive(Debug, Clone)]
+pub struct PipelineState {
+    /// Primitive topology (triangles, lines or points)
+    pub topology: PrimitiveTopology,
+    /// Faces to cull (None draws both sides)
+    pub cull_mode: Option<Face>,
+    /// Winding order of front faces
+    pub front_face: FrontFace,
+    /// Fill, wireframe or point rasterization
+    pub polygon_mode: PolygonMode,
+    /// Blend mode of the color target
+    pub blend: BlendMode,
+    /// Whether fragments are tested against the depth buffer
+    pub depth_test: bool,
+    /// Whether fragments write to the depth buffer
+    pub depth_write: bool,
+    /// Comparison used by the depth test
+    pub depth_compare: CompareFunction,
+    /// Constant and slope-scaled depth bias
+    pub depth_bias: DepthBiasState,
+    /// Name of the vertex entry point
+    pub vertex_entry: String,
+    /// Name of the fragment entry point
+    pub fragment_entry: String,
+}
+
+impl Default for PipelineState {
+    fn default() -> Self {
+        Self {
+            topology: PrimitiveTopology::TriangleList,
+            cull_mode: Some(Face::Back),
+            front_face: FrontFace::Ccw,
+            polygon_mode: PolygonMode::Fill,
+            blend: BlendMode::Replace,
+            depth_test: true,
+            depth_write: true,
+            depth_compare: CompareFunction::Less,
+            depth_bias: DepthBiasState::default(),
+            vertex_entry: "vs_main".to_string(),
+            fragment_entry: "fs_main".to_string(),
+        }
+    }
+}
+
+impl PipelineState {
+    /// Get the depth comparison, taking a disabled depth test into account
+    pub fn effective_depth_compare(&self) -> CompareFunction {
+        if self.depth_test {
+            self.depth_compare
+        } else {
+            CompareFunction::Always
+        }
+    }
+
+    /// Get the index format for strip topologies (list topologies need none)
+    pub fn strip_index_format(&self) -> Option<IndexFormat> {
+        match self.topology {
+            PrimitiveTopology::LineStrip | PrimitiveTopology::TriangleStrip => Some(IndexFormat::Uint32),
+            _ => None,
+        }
+    }
+
+    /// Get the device features this state needs
+    pub fn required_features(&self) -> Features {
+        match self.polygon_mode {
+            PolygonMode::Fill => Features::empty(),
+            PolygonMode::Line => Features::POLYGON_MODE_LINE,
+            PolygonMode::Point => Features::POLYGON_MODE_POINT,
+        }
+    }
+
+    /// Get the number of triangles drawn from `index_count` indices (zero for lines and points)
+    pub fn triangle_count(&self, index_count: u32) -> u32 {
+        match self.topology {
+            PrimitiveTopology::TriangleList => index_count / 3,
+            PrimitiveTopology::TriangleStrip => index_count.saturating_sub(2),
+            _ => 0,
+        }
+    }
+
+    /// Check if draws give the same image in any order: opaque and depth tested
+    ///
+    /// Only strict comparisons qualify; with `Always`, `Equal` or `LessEqual`
+    /// the last of two overlapping draws can win.
+    pub fn is_order_independent(&self) -> bool {
+        self.blend == BlendMode::Replace
+            && self.depth_write
+            && matches!(self.effective_depth_compare(), CompareFunction::Less | CompareFunction::Greater)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_matches_previous_pipeline() {
+        let state = PipelineState::default();
+        assert_eq!(state.topology, PrimitiveTopology::TriangleList);
+        assert_eq!(state.cull_mode, Some(Face::Back));
+        assert_eq!(state.blend.blend_state(), BlendState::REPLACE);
+        assert_eq!(state.effective_depth_compare(), CompareFunction::Less);
+        assert_eq!(state.strip_index_format(), None);
+        assert_eq!(state.vertex_entry, "vs_main");
+        assert!(state.is_order_independent());
+    }
+
+    #[test]
+    fn test_disabled_depth_test_always_passes() {
+        let state = PipelineState {
+            depth_test: false,
+            depth_compare: CompareFunction::LessEqual,
+            ..Default::default()
+        };
+        assert_eq!(state.effective_depth_compare(), CompareFunction::Always);
+        assert!(!state.is_order_independent());
+    }
+
+    #[test]
+    fn test_only_strict_depth_compares_are_order_independent() {
+        for (depth_compare, independent) in [
+            (CompareFunction::Less, true),
+            (CompareFunction::Greater, true),
+            (CompareFunction::LessEqual, false),
+            (CompareFunction::Equal, false),
+            (CompareFunction::NotEqual, false),
+            (CompareFunction::Always, false),
+        ] {
+            let state = PipelineState { depth_compare, ..Default::default() };
+            assert_eq!(state.is_order_independent(), independent, "{:?}", depth_compare);
+        }
+    }
+
+    #[test]
+    fn test_strip_topologies_use_an_index_format() {
+        let state = PipelineState {
+            topology: PrimitiveTopology::LineStrip,
+            polygon_mode: PolygonMode::Line,
+            ..Default::default()
+        };
+        assert_eq!(state.strip_index_format(), Some(IndexFormat::Uint32));
+        assert_eq!(state.required_features(), Features::POLYGON_MODE_LINE);
+        assert_eq!(state.triangle_count(12), 0);
+        assert_eq!(PipelineState { topology: PrimitiveTopology::TriangleStrip, ..Default::default() }.triangle_count(6), 4);
+    }
+}
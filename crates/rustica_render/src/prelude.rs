@@ -6,7 +6,29 @@ pub use crate::{
     RenderWindow,
     CustomShader,
     ShaderDescriptor,
+    ComputeShaderDescriptor,
+    ComputeHandle,
+    ShaderError,
+    ShaderFeature,
+    FeatureSet,
+    FrameStats,
     UniformParameter,
+    UniformBlock,
+    UniformType,
+    PipelineState,
+    BlendMode,
+    MeshHandle,
+    LodMesh,
+    DrawIndexedIndirect,
+    DebugDraw,
+    Draw2d,
+    CoordinateSpace,
+    Font,
+    TextAlign,
+    TextureHandle,
+    TextureOptions,
+    SamplerConfig,
+    HeadlessRenderer,
 };
 
 // Re-export geometry traits
@@ -0,0 +1,508 @@
+// WGSL reflection: build shader descriptors from source and check them against it
+
+use std::collections::HashSet;
+use std::fmt;
+use wgpu::naga::{self, AddressSpace, ArraySize, Binding, ImageClass, ImageDimension, Module, ScalarKind, ShaderStage, StorageAccess, TypeInner};
+use wgpu::VertexFormat;
+use rustica_foundation::geometry::VertexAttribute;
+
+use crate::custom_shader::{SamplerParameter, ShaderDescriptor, StorageParameter, TextureParameter, UniformParameter};
+use crate::pipeline_state::PipelineState;
+
+/// A `@location` input of the vertex entry point
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReflectedInput {
+    /// Name of the argument or struct member
+    pub name: String,
+    /// Shader location
+    pub location: u32,
+    /// Vertex format matching the WGSL type
+    pub format: VertexFormat,
+}
+
+/// Kind of resource bound to a `@group`/`@binding` pair
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindingKind {
+    /// `var<uniform>` of the given size in bytes
+    Uniform { size: u64 },
+    /// `var<storage>` runtime-sized array with elements of the given size in bytes
+    Storage { read_only: bool, element_size: u64 },
+    /// `texture_2d<f32>`
+    Texture,
+    /// Filtering `sampler`
+    Sampler,
+}
+
+impl fmt::Display for BindingKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BindingKind::Uniform { size } => write!(f, "a uniform of {} bytes", size),
+            BindingKind::Storage { read_only: true, element_size } => write!(f, "a read-only storage buffer of {}-byte elements", element_size),
+            BindingKind::Storage { read_only: false, element_size } => write!(f, "a read-write storage buffer of {}-byte elements", element_size),
+            BindingKind::Texture => write!(f, "a texture"),
+            BindingKind::Sampler => write!(f, "a sampler"),
+        }
+    }
+}
+
+/// A resource binding used by the shader's entry points
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReflectedBinding {
+    /// Name of the global variable
+    pub name: String,
+    /// Bind group index
+    pub group: u32,
+    /// Binding index within the group
+    pub binding: u32,
+    /// Kind of resource
+    pub kind: BindingKind,
+}
+
+/// What a WGSL module declares: entry points, vertex inputs and resource bindings
+#[derive(Debug, Clone)]
+pub struct ShaderReflection {
+    /// Names of the `@vertex` entry points
+    pub vertex_entries: Vec<String>,
+    /// Names of the `@fragment` entry points
+    pub fragment_entries: Vec<String>,
+    /// Names of the `@compute` entry points
+    pub compute_entries: Vec<String>,
+    /// Location inputs of the vertex entry point, sorted by location
+    pub vertex_inputs: Vec<ReflectedInput>,
+    /// Bindings used by the vertex and fragment entry points, sorted by group and binding
+    pub bindings: Vec<ReflectedBinding>,
+}
+
+/// Differences between a `ShaderDescriptor` and the WGSL it describes
+#[derive(Debug, Clone, PartialEq)]
+pub struct MismatchReport {
+    /// Name of the shader
+    pub shader: String,
+    /// One line per mismatch
+    pub mismatches: Vec<String>,
+}
+
+impl fmt::Display for MismatchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Shader '{}' doesn't match its WGSL:", self.shader)?;
+        for mismatch in &self.mismatches {
+            write!(f, "\n  - {}", mismatch)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for MismatchReport {}
+
+impl ShaderReflection {
+    /// Parse and validate WGSL, reflecting the given vertex and fragment entry points
+    ///
+    /// Entry points that don't exist are skipped, and the vertex inputs are
+    /// then empty; `check` reports them as missing.
+    pub fn from_wgsl(source: &str, vertex_entry: &str, fragment_entry: &str) -> Result<Self, String> {
+        let module = naga::front::wgsl::parse_str(source)
+            .map_err(|e| format!("Failed to parse WGSL: {}", e.emit_to_string(source)))?;
+        let info = naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::all())
+            .validate(&module)
+            .map_err(|e| format!("Invalid WGSL: {}", e.emit_to_string(source)))?;
+
+        let entries = |stage: ShaderStage| module.entry_points.iter()
+            .filter(|entry| entry.stage == stage)
+            .map(|entry| entry.name.clone())
+            .collect::<Vec<_>>();
+
+        let mut vertex_inputs = Vec::new();
+        let mut used_globals = HashSet::new();
+        for (index, entry) in module.entry_points.iter().enumerate() {
+            let selected = match entry.stage {
+                ShaderStage::Vertex => entry.name == vertex_entry,
+                ShaderStage::Fragment => entry.name == fragment_entry,
+                ShaderStage::Compute => false,
+            };
+            if !selected {
+                continue;
+            }
+
+            let function_info = info.get_entry_point(index);
+            for (handle, _) in module.global_variables.iter() {
+                if !function_info[handle].is_empty() {
+                    used_globals.insert(handle);
+                }
+            }
+
+            if entry.stage == ShaderStage::Vertex {
+                for argument in &entry.function.arguments {
+                    let name = argument.name.clone().unwrap_or_default();
+                    Self::collect_inputs(&module, &name, argument.ty, argument.binding.as_ref(), &mut vertex_inputs)?;
+                }
+            }
+        }
+        vertex_inputs.sort_by_key(|input| input.location);
+
+        let mut bindings = Vec::new();
+        for (handle, global) in module.global_variables.iter() {
+            let (Some(binding), true) = (&global.binding, used_globals.contains(&handle)) else {
+                continue;
+            };
+            let name = global.name.clone().unwrap_or_default();
+            let inner = &module.types[global.ty].inner;
+            let kind = match (global.space, inner) {
+                (AddressSpace::Uniform, _) => BindingKind::Uniform { size: inner.size(module.to_ctx()) as u64 },
+                (AddressSpace::Storage { access }, _) => BindingKind::Storage {
+                    read_only: !access.contains(StorageAccess::STORE),
+                    element_size: match inner {
+                        TypeInner::Array { size: ArraySize::Dynamic, stride, .. } => *stride as u64,
+                        _ => return Err(format!("Storage buffer '{}' must be a runtime-sized array", name)),
+                    },
+                },
+                (AddressSpace::Handle, TypeInner::Image { dim: ImageDimension::D2, arrayed: false, class: ImageClass::Sampled { kind: ScalarKind::Float, multi: false } }) => BindingKind::Texture,
+                (AddressSpace::Handle, TypeInner::Sampler { comparison: false }) => BindingKind::Sampler,
+                _ => return Err(format!("Binding '{}' has a type that shader descriptors can't describe", name)),
+            };
+            bindings.push(ReflectedBinding { name, group: binding.group, binding: binding.binding, kind });
+        }
+        bindings.sort_by_key(|binding| (binding.group, binding.binding));
+
+        Ok(Self {
+            vertex_entries: entries(ShaderStage::Vertex),
+            fragment_entries: entries(ShaderStage::Fragment),
+            compute_entries: entries(ShaderStage::Compute),
+            vertex_inputs,
+            bindings,
+        })
+    }
+
+    /// Build a descriptor for the reflected shader
+    ///
+    /// Inputs at `instance_locations` become instance attributes, all others
+    /// vertex attributes; both are packed tightly in location order. Uniforms,
+    /// textures, samplers and storage buffers are named after their WGSL
+    /// variables, and samplers use the default `SamplerConfig`.
+    pub fn descriptor(&self, name: &str, source: &str, vertex_entry: &str, fragment_entry: &str, instance_locations: &[u32]) -> ShaderDescriptor {
+        let pack = |instance: bool| {
+            let mut offset = 0;
+            let attributes = self.vertex_inputs.iter()
+                .filter(|input| instance_locations.contains(&input.location) == instance)
+                .map(|input| {
+                    let attribute = VertexAttribute {
+                        name: input.name.clone(),
+                        location: input.location,
+                        format: input.format,
+                        offset,
+                        semantic: None,
+                    };
+                    offset += input.format.size();
+                    attribute
+                })
+                .collect::<Vec<_>>();
+            (attributes, offset)
+        };
+        let (vertex_attributes, _) = pack(false);
+        let (instance_attributes, instance_stride) = pack(true);
+
+        let mut descriptor = ShaderDescriptor {
+            name: name.to_string(),
+            shader_source: source.to_string(),
+            source_path: None,
+            source_map: None,
+            features: Vec::new(),
+            vertex_attributes,
+            uniforms: Vec::new(),
+            uniform_block: None,
+            textures: Vec::new(),
+            samplers: Vec::new(),
+            storage: Vec::new(),
+            instance_attributes,
+            instance_stride,
+            pipeline_state: PipelineState {
+                vertex_entry: vertex_entry.to_string(),
+                fragment_entry: fragment_entry.to_string(),
+                ..Default::default()
+            },
+        };
+        for binding in &self.bindings {
+            let (name, group, binding_index) = (binding.name.clone(), binding.group, binding.binding);
+            match binding.kind {
+                BindingKind::Uniform { size } => descriptor.uniforms.push(UniformParameter { name, group, binding: binding_index, size }),
+                BindingKind::Storage { read_only, element_size } => {
+                    descriptor.storage.push(StorageParameter { name, group, binding: binding_index, read_only, element_size });
+                }
+                BindingKind::Texture => descriptor.textures.push(TextureParameter { name, group, binding: binding_index }),
+                BindingKind::Sampler => {
+                    descriptor.samplers.push(SamplerParameter { name, group, binding: binding_index, config: Default::default() });
+                }
+            }
+        }
+        descriptor
+    }
+
+    /// Compare a descriptor with the reflected shader
+    ///
+    /// Reports everything that would fail pipeline creation or make the shader
+    /// misread its inputs: missing entry points, vertex inputs without a
+    /// matching attribute, and bindings that are missing, of the wrong kind or
+    /// too small. Descriptor entries the shader doesn't use are allowed.
+    pub fn check(&self, descriptor: &ShaderDescriptor) -> Result<(), MismatchReport> {
+        let mut mismatches = Vec::new();
+        let state = &descriptor.pipeline_state;
+        if !self.vertex_entries.contains(&state.vertex_entry) {
+            mismatches.push(format!("vertex entry point '{}' not found (the shader has {:?})", state.vertex_entry, self.vertex_entries));
+        }
+        if !self.fragment_entries.contains(&state.fragment_entry) {
+            mismatches.push(format!("fragment entry point '{}' not found (the shader has {:?})", state.fragment_entry, self.fragment_entries));
+        }
+
+        // Vertex inputs must be fed by exactly one attribute of a compatible format
+        let attributes = descriptor.vertex_attributes.iter().map(|attribute| ("vertex", attribute))
+            .chain(descriptor.instance_attributes.iter().map(|attribute| ("instance", attribute)))
+            .collect::<Vec<_>>();
+        let mut locations = HashSet::new();
+        for (kind, attribute) in &attributes {
+            if !locations.insert(attribute.location) {
+                mismatches.push(format!("location {} is used by more than one attribute ({} attribute '{}')", attribute.location, kind, attribute.name));
+            }
+        }
+        for input in &self.vertex_inputs {
+            match attributes.iter().find(|(_, attribute)| attribute.location == input.location) {
+                None => mismatches.push(format!("vertex input '{}' at location {} has no attribute", input.name, input.location)),
+                Some((kind, attribute)) if shader_type(attribute.format) != shader_type(input.format) => mismatches.push(format!(
+                    "{} attribute '{}' at location {} is {:?}, but the shader reads {:?}",
+                    kind, attribute.name, input.location, attribute.format, input.format
+                )),
+                Some(_) => {}
+            }
+        }
+
+        // Every binding the shader uses must be declared with the same kind and at least its size
+        let declared = descriptor.uniforms.iter().map(|uniform| (uniform.name.as_str(), uniform.group, uniform.binding, BindingKind::Uniform { size: uniform.size }))
+            .chain(descriptor.uniform_block.as_ref().map(|block| ("uniform block", block.group, block.binding, BindingKind::Uniform { size: block.size })))
+            .chain(descriptor.textures.iter().map(|texture| (texture.name.as_str(), texture.group, texture.binding, BindingKind::Texture)))
+            .chain(descriptor.samplers.iter().map(|sampler| (sampler.name.as_str(), sampler.group, sampler.binding, BindingKind::Sampler)))
+            .chain(descriptor.storage.iter().map(|storage| (storage.name.as_str(), storage.group, storage.binding, BindingKind::Storage {
+                read_only: storage.read_only,
+                element_size: storage.element_size,
+            })))
+            .collect::<Vec<_>>();
+        for binding in &self.bindings {
+            let Some((name, _, _, kind)) = declared.iter().find(|(_, group, index, _)| *group == binding.group && *index == binding.binding) else {
+                mismatches.push(format!(
+                    "'{}' at group {} binding {} ({}) isn't declared",
+                    binding.name, binding.group, binding.binding, binding.kind
+                ));
+                continue;
+            };
+            let compatible = match (kind, binding.kind) {
+                (BindingKind::Uniform { size }, BindingKind::Uniform { size: required }) => *size >= required,
+                // A writable binding may be read-only in the shader, but not the other way around
+                (BindingKind::Storage { read_only, element_size }, BindingKind::Storage { read_only: shader_read_only, element_size: required }) => {
+                    (!read_only || shader_read_only) && *element_size == required
+                }
+                (declared, reflected) => *declared == reflected,
+            };
+            if !compatible {
+                mismatches.push(format!(
+                    "'{}' at group {} binding {} is declared as {}, but the shader's '{}' is {}",
+                    name, binding.group, binding.binding, kind, binding.name, binding.kind
+                ));
+            }
+        }
+
+        if mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(MismatchReport { shader: descriptor.name.clone(), mismatches })
+        }
+    }
+
+    /// Collect the location inputs of an entry point argument, looking into structs
+    fn collect_inputs(module: &Module, name: &str, ty: naga::Handle<naga::Type>, binding: Option<&Binding>, inputs: &mut Vec<ReflectedInput>) -> Result<(), String> {
+        match (binding, &module.types[ty].inner) {
+            (Some(Binding::Location { location, .. }), inner) => {
+                let format = vertex_format(inner)
+                    .ok_or_else(|| format!("Vertex input '{}' at location {} has a type no vertex format matches", name, location))?;
+                inputs.push(ReflectedInput { name: name.to_string(), location: *location, format });
+            }
+            (None, TypeInner::Struct { members, .. }) => {
+                for member in members {
+                    let member_name = member.name.clone().unwrap_or_default();
+                    Self::collect_inputs(module, &member_name, member.ty, member.binding.as_ref(), inputs)?;
+                }
+            }
+            _ => {} // Built-ins like the vertex index
+        }
+        Ok(())
+    }
+}
+
+impl ShaderDescriptor {
+    /// Build a descriptor from WGSL with `vs_main` and `fs_main` entry points
+    ///
+    /// See `ShaderReflection::descriptor` for how inputs and bindings are mapped.
+    pub fn from_wgsl(name: &str, source: &str, instance_locations: &[u32]) -> Result<Self, String> {
+        let state = PipelineState::default();
+        let reflection = ShaderReflection::from_wgsl(source, &state.vertex_entry, &state.fragment_entry)
+            .map_err(|e| format!("Shader '{}': {}", name, e))?;
+        let descriptor = reflection.descriptor(name, source, &state.vertex_entry, &state.fragment_entry, instance_locations);
+        reflection.check(&descriptor).map_err(|report| report.to_string())?;
+        Ok(descriptor)
+    }
+
+    /// Check the descriptor against its WGSL source
+    pub fn check_source(&self) -> Result<(), MismatchReport> {
+        let state = &self.pipeline_state;
+        let reflection = ShaderReflection::from_wgsl(&self.shader_source, &state.vertex_entry, &state.fragment_entry)
+            .map_err(|e| MismatchReport { shader: self.name.clone(), mismatches: vec![e] })?;
+        reflection.check(self)
+    }
+}
+
+/// Get the vertex format of a WGSL scalar or vector type
+fn vertex_format(inner: &TypeInner) -> Option<VertexFormat> {
+    let (scalar, components) = match inner {
+        TypeInner::Scalar(scalar) => (scalar, 1),
+        TypeInner::Vector { size, scalar } => (scalar, *size as u8),
+        _ => return None,
+    };
+    if scalar.width != 4 {
+        return None;
+    }
+    Some(match (scalar.kind, components) {
+        (ScalarKind::Float, 1) => VertexFormat::Float32,
+        (ScalarKind::Float, 2) => VertexFormat::Float32x2,
+        (ScalarKind::Float, 3) => VertexFormat::Float32x3,
+        (ScalarKind::Float, 4) => VertexFormat::Float32x4,
+        (ScalarKind::Uint, 1) => VertexFormat::Uint32,
+        (ScalarKind::Uint, 2) => VertexFormat::Uint32x2,
+        (ScalarKind::Uint, 3) => VertexFormat::Uint32x3,
+        (ScalarKind::Uint, 4) => VertexFormat::Uint32x4,
+        (ScalarKind::Sint, 1) => VertexFormat::Sint32,
+        (ScalarKind::Sint, 2) => VertexFormat::Sint32x2,
+        (ScalarKind::Sint, 3) => VertexFormat::Sint32x3,
+        (ScalarKind::Sint, 4) => VertexFormat::Sint32x4,
+        _ => return None,
+    })
+}
+
+/// Get the kind and component count a vertex format is read as in a shader
+///
+/// Normalized and half-precision formats are read as floats.
+fn shader_type(format: VertexFormat) -> (ScalarKind, u64) {
+    use VertexFormat::*;
+    let kind = match format {
+        Uint8 | Uint8x2 | Uint8x4 | Uint16 | Uint16x2 | Uint16x4 | Uint32 | Uint32x2 | Uint32x3 | Uint32x4 => ScalarKind::Uint,
+        Sint8 | Sint8x2 | Sint8x4 | Sint16 | Sint16x2 | Sint16x4 | Sint32 | Sint32x2 | Sint32x3 | Sint32x4 => ScalarKind::Sint,
+        _ => ScalarKind::Float,
+    };
+    let components = match format {
+        Unorm10_10_10_2 | Unorm8x4Bgra => 4,
+        _ => format.size() / component_size(format),
+    };
+    (kind, components)
+}
+
+/// Get the size in bytes of one component of a vertex format
+fn component_size(format: VertexFormat) -> u64 {
+    use VertexFormat::*;
+    match format {
+        Uint8 | Uint8x2 | Uint8x4 | Sint8 | Sint8x2 | Sint8x4 | Unorm8 | Unorm8x2 | Unorm8x4 | Snorm8 | Snorm8x2 | Snorm8x4 => 1,
+        Uint16 | Uint16x2 | Uint16x4 | Sint16 | Sint16x2 | Sint16x4 | Unorm16 | Unorm16x2 | Unorm16x4 | Snorm16 | Snorm16x2 | Snorm16x4 | Float16 | Float16x2 | Float16x4 => 2,
+        Float64 | Float64x2 | Float64x3 | Float64x4 => 8,
+        _ => 4,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r#"
+        struct Camera {
+            view_proj: mat4x4<f32>,
+            position: vec3<f32>,
+        }
+
+        struct VertexInput {
+            @location(0) position: vec3<f32>,
+            @location(1) uv: vec2<f32>,
+        }
+
+        @group(0) @binding(0) var<uniform> camera: Camera;
+        @group(0) @binding(1) var albedo: texture_2d<f32>;
+        @group(0) @binding(2) var albedo_sampler: sampler;
+        @group(1) @binding(0) var<storage, read> lights: array<vec4<f32>>;
+        @group(2) @binding(0) var<uniform> unused: vec4<f32>;
+
+        @vertex
+        fn vs_main(in: VertexInput, @location(2) offset: vec3<f32>, @builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+            return camera.view_proj * vec4<f32>(in.position + offset, 1.0);
+        }
+
+        @fragment
+        fn fs_main() -> @location(0) vec4<f32> {
+            return textureSample(albedo, albedo_sampler, vec2<f32>(0.5)) * lights[0];
+        }
+    "#;
+
+    #[test]
+    fn test_reflects_inputs_and_used_bindings() {
+        let reflection = ShaderReflection::from_wgsl(SOURCE, "vs_main", "fs_main").unwrap();
+        assert_eq!(reflection.vertex_entries, vec!["vs_main"]);
+        assert_eq!(
+            reflection.vertex_inputs.iter().map(|input| (input.name.as_str(), input.location, input.format)).collect::<Vec<_>>(),
+            vec![("position", 0, VertexFormat::Float32x3), ("uv", 1, VertexFormat::Float32x2), ("offset", 2, VertexFormat::Float32x3)]
+        );
+
+        // The uniform no entry point uses is left out
+        let kinds = reflection.bindings.iter().map(|binding| (binding.name.as_str(), binding.kind)).collect::<Vec<_>>();
+        assert_eq!(kinds, vec![
+            ("camera", BindingKind::Uniform { size: 80 }),
+            ("albedo", BindingKind::Texture),
+            ("albedo_sampler", BindingKind::Sampler),
+            ("lights", BindingKind::Storage { read_only: true, element_size: 16 }),
+        ]);
+    }
+
+    #[test]
+    fn test_generated_descriptor_matches_source() {
+        let descriptor = ShaderDescriptor::from_wgsl("Lit", SOURCE, &[2]).unwrap();
+        assert_eq!(descriptor.vertex_attributes.iter().map(|attribute| attribute.offset).collect::<Vec<_>>(), vec![0, 12]);
+        assert_eq!(descriptor.instance_attributes[0].name, "offset");
+        assert_eq!(descriptor.instance_stride, 12);
+        assert_eq!(descriptor.uniforms[0].size, 80);
+        assert!(descriptor.validate().is_ok());
+        assert!(descriptor.check_source().is_ok());
+    }
+
+    #[test]
+    fn test_check_reports_every_mismatch() {
+        let mut descriptor = ShaderDescriptor::from_wgsl("Lit", SOURCE, &[2]).unwrap();
+        descriptor.vertex_attributes[1].format = VertexFormat::Uint32x2;
+        descriptor.instance_attributes[0].location = 3;
+        descriptor.uniforms[0].size = 64;
+        descriptor.textures[0].binding = 3;
+        descriptor.storage[0].read_only = false;
+        descriptor.storage[0].element_size = 32;
+
+        let report = descriptor.check_source().unwrap_err();
+        assert_eq!(report.mismatches.len(), 5, "{}", report);
+        let message = report.to_string();
+        assert!(message.contains("'uv' at location 1 is Uint32x2, but the shader reads Float32x2"));
+        assert!(message.contains("vertex input 'offset' at location 2 has no attribute"));
+        assert!(message.contains("'camera' at group 0 binding 0 is declared as a uniform of 64 bytes, but the shader's 'camera' is a uniform of 80 bytes"));
+        assert!(message.contains("'albedo' at group 0 binding 1 (a texture) isn't declared"));
+        assert!(message.contains("declared as a read-write storage buffer of 32-byte elements"));
+
+        // Bindings of a missing entry point can't be checked, so only the entry point is reported
+        descriptor.pipeline_state.fragment_entry = "main".to_string();
+        let report = descriptor.check_source().unwrap_err();
+        assert!(report.mismatches[0].contains("fragment entry point 'main' not found"));
+    }
+
+    #[test]
+    fn test_invalid_wgsl_is_reported() {
+        let Err(error) = ShaderDescriptor::from_wgsl("Broken", "fn vs_main( {", &[]) else {
+            panic!("Broken WGSL should not produce a descriptor");
+        };
+        assert!(error.contains("Shader 'Broken': Failed to parse WGSL"));
+    }
+}
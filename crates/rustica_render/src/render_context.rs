@@ -1,9 +1,12 @@
 // Internal rendering context that manages WGPU resources
 
-use std::time::Instant;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use wgpu::{
-    Backends, Color, CommandEncoderDescriptor, Device, Features, Instance, InstanceDescriptor, 
-    Limits, LoadOp, MemoryHints, Operations, PowerPreference, Queue, RenderPassColorAttachment,
+    AdapterInfo, Backends, BufferAddress, BufferDescriptor, BufferUsages, Color, CommandEncoderDescriptor, ComputePassDescriptor, Device, Features, Instance, InstanceDescriptor, 
+    Limits, LoadOp, MapMode, MemoryHints, Operations, PowerPreference, Queue, RenderPassColorAttachment,
     RenderPassDepthStencilAttachment, RenderPassDescriptor, RequestAdapterOptions, 
     StoreOp, Surface, SurfaceConfiguration, SurfaceTargetUnsafe, TextureUsages, TextureViewDescriptor,
     TextureDescriptor, TextureDimension, TextureFormat, TextureView, Extent3d
@@ -12,8 +15,110 @@ use wgpu::util::DeviceExt;
 use winit::dpi::PhysicalSize;
 use rustica_window::WindowApp;
 
+use crate::batching::batch_draws;
 use crate::draw_commands::DrawCommand;
-use crate::custom_shader::CustomShader;
+use crate::compute_shader::{ComputeBindings, ComputeHandle, ComputeShader, ComputeShaderDescriptor};
+use crate::custom_shader::{CustomShader, ShaderDescriptor};
+use crate::diagnostics::{label, LOG_DEVICE, LOG_DRAW, LOG_FRAME, LOG_SHADER};
+use crate::debug_renderer::DebugRenderer;
+use crate::renderer_2d::Renderer2d;
+use crate::font::{Font, FontAtlas};
+use crate::frame_stats::{FrameStats, GpuTimer};
+use crate::indirect::DrawIndexedIndirect;
+use crate::shader_error::ShaderError;
+use crate::stats_overlay::StatsOverlay;
+use crate::shader_features::FeatureSet;
+use crate::shader_watcher::{self, ShaderWatcher, WatchedShader};
+use crate::instance_buffer::InstanceBuffer;
+use crate::mesh::{GpuMesh, MeshHandle};
+use crate::texture::{GpuTexture, TextureHandle, TextureOptions};
+
+/// Device features enabled whenever the adapter supports them
+///
+/// Wireframe and point polygon modes of `PipelineState` need the polygon
+/// modes, the GPU time of `FrameStats` needs timestamp queries, and indirect
+/// draws use multi-draw (emulated without it) and a non-zero first instance.
+const OPTIONAL_FEATURES: Features = Features::POLYGON_MODE_LINE
+    .union(Features::POLYGON_MODE_POINT)
+    .union(Features::TIMESTAMP_QUERY)
+    .union(Features::MULTI_DRAW_INDIRECT)
+    .union(Features::INDIRECT_FIRST_INSTANCE);
+
+/// Line height in pixels of the bundled font's glyph atlas
+const DEFAULT_FONT_SIZE: f32 = 32.0;
+
+/// Pipeline and bind groups currently bound in a render pass
+#[derive(Default)]
+struct PassBindings {
+    /// Shader and variant whose pipeline is bound
+    pipeline: Option<(usize, FeatureSet)>,
+    /// Number of times a pipeline was bound
+    pipelines_bound: u32,
+    /// Dynamic offsets and textures bound for each group of that shader
+    group_bindings: Vec<Option<(Vec<u32>, Vec<TextureHandle>)>>,
+}
+
+impl PassBindings {
+    /// Bind a shader's pipeline and the groups whose offsets or textures changed since the last draw
+    ///
+    /// Returns false if the shader isn't ready to draw.
+    fn bind(&mut self, render_pass: &mut wgpu::RenderPass<'_>, shader_id: usize, shader: &CustomShader, staged: &StagedBindings) -> bool {
+        let Some(pipeline) = shader.variant_pipeline(&staged.features) else {
+            return false;
+        };
+
+        if self.pipeline.as_ref().is_none_or(|(id, features)| *id != shader_id || *features != staged.features) {
+            render_pass.set_pipeline(pipeline);
+            self.pipelines_bound += 1;
+            // Variants share the shader's layout, so its bind groups stay valid
+            if self.pipeline.as_ref().is_none_or(|(id, _)| *id != shader_id) {
+                self.group_bindings = vec![None; staged.uniform_offsets.len()];
+            }
+            self.pipeline = Some((shader_id, staged.features.clone()));
+        }
+
+        for (group, (offsets, texture_set)) in staged.uniform_offsets.iter().zip(&staged.texture_sets).enumerate() {
+            let binding = (offsets.clone(), texture_set.clone());
+            if self.group_bindings[group].as_ref() == Some(&binding) {
+                continue;
+            }
+            let Some(bind_group) = shader.bind_group(group as u32, texture_set) else {
+                // A group with bindings but no bind group uses a texture that isn't uploaded
+                if !shader.group_is_empty(group as u32) {
+                    return false;
+                }
+                continue;
+            };
+            render_pass.set_bind_group(group as u32, bind_group, offsets);
+            self.group_bindings[group] = Some(binding);
+        }
+        true
+    }
+}
+
+/// Uniform offsets and textures of one draw, per group, and its resolved shader features
+struct StagedBindings {
+    uniform_offsets: Vec<Vec<u32>>,
+    texture_sets: Vec<Vec<TextureHandle>>,
+    features: FeatureSet,
+}
+
+/// Instance data of a draw command
+#[derive(Clone, Copy)]
+enum DrawInstances<'a> {
+    /// Instances recorded with the draw
+    Data { bytes: &'a [u8], instance_size: usize },
+    /// One instance per element of a compute shader's storage buffer
+    Storage { compute: usize, slot: usize },
+}
+
+/// Where the instances of a staged draw are read from
+enum InstanceSource {
+    /// A range of the frame's shared instance buffer
+    Frame(Range<BufferAddress>),
+    /// A storage buffer of a compute shader
+    Storage { compute: usize, slot: usize },
+}
 
 /// Internal rendering context that manages WGPU resources
 pub struct RenderContext {
@@ -22,10 +127,31 @@ pub struct RenderContext {
     pub(crate) device: Option<Device>,
     pub(crate) queue: Option<Queue>,
     pub(crate) config: Option<SurfaceConfiguration>,
+    color_format: Option<TextureFormat>,
+    pub(crate) adapter_info: Option<AdapterInfo>,
     clear_color: Color,
     pub(crate) start_time: Instant,
     custom_shaders: Vec<CustomShader>,
+    compute_shaders: Vec<ComputeShader>,
+    meshes: Vec<GpuMesh>,
+    textures: Vec<GpuTexture>,
+    instance_buffer: InstanceBuffer,
+    reported_errors: HashSet<String>,
     depth_texture: Option<TextureView>,
+    /// Size of the color and depth targets
+    target_size: (u32, u32),
+    shader_watcher: Option<ShaderWatcher>,
+    /// Statistics of the last rendered frame
+    frame_stats: FrameStats,
+    gpu_timer: Option<GpuTimer>,
+    stats_overlay: Option<StatsOverlay>,
+    show_stats_overlay: bool,
+    debug_renderer: Option<DebugRenderer>,
+    renderer_2d: Option<Renderer2d>,
+    /// Glyph atlas of `Canvas::text`, rasterized from the bundled font on first use
+    font: Option<Arc<FontAtlas>>,
+    /// Whether compatible draws are merged and draws sorted by pipeline
+    draw_batching: bool,
 }
 
 impl RenderContext {
@@ -41,6 +167,8 @@ impl RenderContext {
             device: None,
             queue: None,
             config: None,
+            color_format: None,
+            adapter_info: None,
             clear_color: Color {
                 r: 0.0,
                 g: 0.0,
@@ -49,22 +177,258 @@ impl RenderContext {
             },
             start_time: Instant::now(),
             custom_shaders: Vec::new(),
+            compute_shaders: Vec::new(),
+            meshes: Vec::new(),
+            // The white texture is always TextureHandle::WHITE
+            textures: vec![GpuTexture::new_placeholder(vec![image::RgbaImage::from_pixel(1, 1, image::Rgba([255; 4]))], true)],
+            instance_buffer: InstanceBuffer::new(),
+            reported_errors: HashSet::new(),
             depth_texture: None,
+            target_size: (0, 0),
+            shader_watcher: None,
+            frame_stats: FrameStats::default(),
+            gpu_timer: None,
+            stats_overlay: None,
+            show_stats_overlay: false,
+            debug_renderer: None,
+            renderer_2d: None,
+            font: None,
+            draw_batching: true,
         }
     }
     
     /// Register a custom shader and return its ID
     pub fn register_shader(&mut self, shader: CustomShader) -> usize {
         let id = self.custom_shaders.len();
+        if let Some(watcher) = &mut self.shader_watcher {
+            watcher.watch(WatchedShader::Render(id), shader.source_path(), shader.source_map());
+        }
         self.custom_shaders.push(shader);
         id
     }
     
+    /// Create a shader from a descriptor and register it
+    ///
+    /// If the device isn't initialized yet, the descriptor and WGSL are checked
+    /// and a placeholder shader is registered, which is compiled during
+    /// initialization. Errors found then make the shader unavailable.
+    pub fn register_descriptor(&mut self, descriptor: ShaderDescriptor) -> Result<usize, ShaderError> {
+        let shader = if let (Some(device), Some(format)) = (&self.device, self.color_format) {
+            CustomShader::new(device, format, descriptor)?
+        } else {
+            CustomShader::check(&descriptor)?;
+            CustomShader::new_placeholder(descriptor)
+        };
+        Ok(self.register_shader(shader))
+    }
+    
+    /// Create a compute shader from a descriptor and register it
+    ///
+    /// If the device isn't initialized yet, a placeholder is registered and
+    /// compiled during initialization.
+    pub fn register_compute_descriptor(&mut self, descriptor: ComputeShaderDescriptor) -> Result<ComputeHandle, ShaderError> {
+        let shader = if let Some(device) = &self.device {
+            ComputeShader::new(device, descriptor)?
+        } else {
+            ComputeShader::check(&descriptor)?;
+            ComputeShader::new_placeholder(descriptor)
+        };
+        let handle = ComputeHandle(self.compute_shaders.len());
+        if let Some(watcher) = &mut self.shader_watcher {
+            watcher.watch(WatchedShader::Compute(handle), shader.source_path(), shader.source_map());
+        }
+        self.compute_shaders.push(shader);
+        Ok(handle)
+    }
+
+    /// Watch the WGSL files of all shaders loaded from files, including ones registered later
+    ///
+    /// Files included by preprocessed shaders are watched as well.
+    ///
+    /// Changed files are checked at most once per interval by `reload_changed_shaders`.
+    pub fn watch_shaders(&mut self, interval: Duration) {
+        let mut watcher = ShaderWatcher::new(interval);
+        for (id, shader) in self.custom_shaders.iter().enumerate() {
+            watcher.watch(WatchedShader::Render(id), shader.source_path(), shader.source_map());
+        }
+        for (id, shader) in self.compute_shaders.iter().enumerate() {
+            watcher.watch(WatchedShader::Compute(ComputeHandle(id)), shader.source_path(), shader.source_map());
+        }
+        self.shader_watcher = Some(watcher);
+    }
+
+    /// Recompile watched shaders whose files changed, keeping their IDs
+    ///
+    /// A shader whose new source fails to compile keeps its previous pipeline;
+    /// the errors are logged and returned.
+    pub fn reload_changed_shaders(&mut self) -> Vec<ShaderError> {
+        let (Some(watcher), Some(device), Some(format)) = (&mut self.shader_watcher, &self.device, self.color_format) else {
+            return Vec::new();
+        };
+
+        let mut errors = Vec::new();
+        for shader in watcher.poll(Instant::now()) {
+            let (name, result) = match shader {
+                WatchedShader::Render(id) => {
+                    let shader = &mut self.custom_shaders[id];
+                    let source = shader_watcher::load_source(&shader.name, shader.source_path(), shader.source_map());
+                    (shader.name.clone(), source.and_then(|(source, source_map)| shader.reload(device, format, source, source_map)))
+                }
+                WatchedShader::Compute(handle) => {
+                    let shader = &mut self.compute_shaders[handle.0];
+                    let source = shader_watcher::load_source(&shader.name, shader.source_path(), shader.source_map());
+                    (shader.name.clone(), source.and_then(|(source, source_map)| shader.reload(device, source, source_map)))
+                }
+            };
+            match result {
+                Ok(()) => {
+                    log::info!(target: LOG_SHADER, "Reloaded shader '{}'", name);
+                    // Watch the files the new version includes as well
+                    match shader {
+                        WatchedShader::Render(id) => {
+                            let shader = &self.custom_shaders[id];
+                            watcher.watch(WatchedShader::Render(id), shader.source_path(), shader.source_map());
+                        }
+                        WatchedShader::Compute(handle) => {
+                            let shader = &self.compute_shaders[handle.0];
+                            watcher.watch(WatchedShader::Compute(handle), shader.source_path(), shader.source_map());
+                        }
+                    }
+                }
+                Err(error) => {
+                    log::warn!(target: LOG_SHADER, "Keeping the previous version of shader '{}': {}", name, error);
+                    errors.push(error);
+                }
+            }
+        }
+        errors
+    }
+
+    /// Get the errors of shaders whose deferred initialization failed, by shader ID
+    pub fn shader_errors(&self) -> HashMap<usize, ShaderError> {
+        self.custom_shaders.iter().enumerate()
+            .filter_map(|(id, shader)| Some((id, shader.error()?.clone())))
+            .collect()
+    }
+
+    /// Get the errors of compute shaders whose deferred initialization failed
+    pub fn compute_shader_errors(&self) -> HashMap<ComputeHandle, ShaderError> {
+        self.compute_shaders.iter().enumerate()
+            .filter_map(|(id, shader)| Some((ComputeHandle(id), shader.error()?.clone())))
+            .collect()
+    }
+
+    /// Copy the elements of a compute shader's storage buffer back to the CPU
+    ///
+    /// Waits for all submitted work, including the dispatches of the last frame.
+    /// Fails if the shader has no storage buffer with that name or its elements
+    /// aren't `T`.
+    pub fn read_storage<T: bytemuck::Pod>(&self, compute: ComputeHandle, name: &str) -> Result<Vec<T>, String> {
+        let bytes = self.read_storage_bytes(compute, name)?;
+        let size = std::mem::size_of::<T>();
+        if size == 0 || bytes.len() % size != 0 {
+            return Err(format!("Storage buffer '{}' holds {} bytes, which isn't a whole number of {}-byte elements", name, bytes.len(), size));
+        }
+        Ok(bytes.chunks_exact(size).map(bytemuck::pod_read_unaligned).collect())
+    }
+
+    /// Copy the raw data of a compute shader's storage buffer back to the CPU
+    fn read_storage_bytes(&self, compute: ComputeHandle, name: &str) -> Result<Vec<u8>, String> {
+        let (Some(device), Some(queue)) = (&self.device, &self.queue) else {
+            return Err("The device isn't initialized".to_string());
+        };
+        let shader = self.compute_shaders.get(compute.0)
+            .ok_or_else(|| format!("Unknown compute shader {}", compute.0))?;
+        let (buffer, length, _) = shader.storage_buffer(shader.storage_slot(name)?)
+            .ok_or_else(|| format!("Compute shader '{}' isn't initialized", shader.name))?;
+
+        // Copies must be a multiple of 4 bytes, which storage buffers always are
+        let size = length.next_multiple_of(4);
+        let readback = device.create_buffer(&BufferDescriptor {
+            label: Some(&label(&["compute", &shader.name, "storage readback"])),
+            size,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some(&label(&["compute", &shader.name, "storage readback encoder"])),
+        });
+        encoder.copy_buffer_to_buffer(buffer, 0, &readback, 0, size);
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = readback.slice(..);
+        slice.map_async(MapMode::Read, |_| {});
+        device.poll(wgpu::Maintain::Wait);
+
+        let data = slice.get_mapped_range()[..length as usize].to_vec();
+        readback.unmap();
+        Ok(data)
+    }
+
     /// Get a mutable reference to a custom shader by ID
     pub fn get_shader_mut(&mut self, id: usize) -> Option<&mut CustomShader> {
         self.custom_shaders.get_mut(id)
     }
 
+    /// Upload mesh data and return a handle to it
+    ///
+    /// If the device isn't initialized yet, the data is kept on the CPU and
+    /// uploaded during `initialize`.
+    pub fn upload_mesh(&mut self, vertices: &[u8], indices: &[u8], index_count: u32) -> MeshHandle {
+        let mesh = if let Some(device) = &self.device {
+            GpuMesh::new(device, vertices, indices, index_count)
+        } else {
+            GpuMesh::new_placeholder(vertices.to_vec(), indices.to_vec(), index_count)
+        };
+
+        let handle = MeshHandle(self.meshes.len());
+        self.meshes.push(mesh);
+        handle
+    }
+
+    /// Upload an image as a texture and return a handle to it
+    ///
+    /// If the device isn't initialized yet, the mip levels are kept on the CPU
+    /// and uploaded during `initialize`. Fails if the image is empty or larger
+    /// than the device's texture size limit.
+    pub fn upload_texture(&mut self, image: image::RgbaImage, options: TextureOptions) -> Result<TextureHandle, String> {
+        let max_dimension = self.max_texture_dimension();
+        let (width, height) = image.dimensions();
+        if width == 0 || height == 0 {
+            return Err(format!("Cannot create a texture from an empty {}x{} image", width, height));
+        }
+        if width > max_dimension || height > max_dimension {
+            return Err(format!(
+                "A {}x{} image is larger than the device's {} pixel texture limit",
+                width, height, max_dimension
+            ));
+        }
+
+        let levels = GpuTexture::levels(image, &options);
+        let texture = if let (Some(device), Some(queue)) = (&self.device, &self.queue) {
+            GpuTexture::new(device, queue, &levels, options.srgb)
+        } else {
+            GpuTexture::new_placeholder(levels, options.srgb)
+        };
+
+        let handle = TextureHandle(self.textures.len());
+        self.textures.push(texture);
+        Ok(handle)
+    }
+
+    /// The largest width or height of a texture on this device
+    ///
+    /// Before the device exists, this is the limit a window device is created with.
+    fn max_texture_dimension(&self) -> u32 {
+        self.device.as_ref()
+            .map_or(Limits::default().max_texture_dimension_2d, |device| device.limits().max_texture_dimension_2d)
+    }
+
+    /// Get a mesh by handle
+    pub fn get_mesh(&self, handle: MeshHandle) -> Option<&GpuMesh> {
+        self.meshes.get(handle.0)
+    }
+
     pub fn initialize(&mut self, window_app: &WindowApp) -> Result<(), Box<dyn std::error::Error>> {
         let window = window_app.window().expect("Window should be created");
         let st = unsafe { SurfaceTargetUnsafe::from_window(window)? };
@@ -76,12 +440,15 @@ impl RenderContext {
             force_fallback_adapter: false,
         }))
         .ok_or("Failed to find an appropriate adapter")?;
+        let adapter_info = adapter.get_info();
+        log::info!(target: LOG_DEVICE, "Using adapter '{}' ({:?})", adapter_info.name, adapter_info.backend);
+        self.adapter_info = Some(adapter_info);
 
         let (device, queue) = pollster::block_on(adapter.request_device(
             &wgpu::DeviceDescriptor {
-                label: None,
+                label: Some(&label(&["device"])),
                 memory_hints: MemoryHints::default(),
-                required_features: Features::empty(),
+                required_features: adapter.features() & OPTIONAL_FEATURES,
                 required_limits: Limits::default(),
             },
             None,
@@ -115,19 +482,84 @@ impl RenderContext {
         self.surface = Some(surface);
         self.device = Some(device);
         self.queue = Some(queue);
+        self.color_format = Some(config.format);
         self.config = Some(config);
         self.depth_texture = Some(depth_texture);
+        self.target_size = (window_size.width, window_size.height);
         
-        // Initialize any custom shaders
-        if let (Some(device), Some(config)) = (&self.device, &self.config) {
+        self.initialize_resources();
+
+        Ok(())
+    }
+
+    /// Initialize the context without a window, rendering into offscreen textures
+    ///
+    /// A hardware adapter is preferred, but the fallback (software) adapter is
+    /// used when no GPU is available.
+    pub fn initialize_headless(&mut self, width: u32, height: u32, format: TextureFormat) -> Result<(), Box<dyn std::error::Error>> {
+        let adapter = [false, true].into_iter().find_map(|force_fallback_adapter| {
+            pollster::block_on(self.instance.request_adapter(&RequestAdapterOptions {
+                power_preference: PowerPreference::default(),
+                compatible_surface: None,
+                force_fallback_adapter,
+            }))
+        })
+        .ok_or("Failed to find a hardware or fallback adapter")?;
+
+        // Software and GL adapters may not reach the default limits, so ask for what the adapter offers
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some(&label(&["headless device"])),
+                memory_hints: MemoryHints::default(),
+                required_features: adapter.features() & OPTIONAL_FEATURES,
+                required_limits: adapter.limits(),
+            },
+            None,
+        ))?;
+
+        let depth_texture = self.create_depth_texture(&device, width, height);
+
+        let adapter_info = adapter.get_info();
+        log::info!(target: LOG_DEVICE, "Using adapter '{}' ({:?})", adapter_info.name, adapter_info.backend);
+        self.adapter_info = Some(adapter_info);
+        self.device = Some(device);
+        self.queue = Some(queue);
+        self.color_format = Some(format);
+        self.depth_texture = Some(depth_texture);
+        self.target_size = (width, height);
+
+        self.initialize_resources();
+
+        Ok(())
+    }
+
+    /// Compile placeholder shaders and upload meshes registered before the device existed
+    fn initialize_resources(&mut self) {
+        if let (Some(device), Some(format)) = (&self.device, self.color_format) {
+            // Failed shaders stay registered so their IDs remain valid, but draws with them are skipped
             for shader in &mut self.custom_shaders {
-                if !shader.is_initialized() {
-                    shader.initialize(device, config.format);
+                if let Err(error) = shader.initialize(device, format) {
+                    log::error!(target: LOG_SHADER, "{}", error);
                 }
             }
+
+            for shader in &mut self.compute_shaders {
+                if let Err(error) = shader.initialize(device) {
+                    log::error!(target: LOG_SHADER, "{}", error);
+                }
+            }
+
+            for mesh in &mut self.meshes {
+                mesh.initialize(device);
+            }
         }
 
-        Ok(())
+        if let (Some(device), Some(queue)) = (&self.device, &self.queue) {
+            for texture in &mut self.textures {
+                texture.initialize(device, queue);
+            }
+            self.gpu_timer = GpuTimer::new(device, queue);
+        }
     }
 
     pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
@@ -141,6 +573,7 @@ impl RenderContext {
                 
                 // Recreate depth texture with new size
                 self.depth_texture = Some(self.create_depth_texture(device, new_size.width, new_size.height));
+                self.target_size = (new_size.width, new_size.height);
             }
         }
     }
@@ -154,21 +587,235 @@ impl RenderContext {
         };
     }
     
-    pub fn process_draw_commands(&mut self, commands: &[DrawCommand]) -> Result<(), wgpu::SurfaceError> {
-        if self.device.is_none() || self.surface.is_none() || self.queue.is_none() {
-            return Ok(());
-        }
+    pub fn process_draw_commands(&mut self, commands: Vec<DrawCommand>) -> Result<(), wgpu::SurfaceError> {
+        let output = match &self.surface {
+            Some(surface) if self.device.is_some() && self.queue.is_some() => surface.get_current_texture()?,
+            _ => return Ok(()),
+        };
+        let view = output.texture.create_view(&TextureViewDescriptor::default());
         
-        let device = self.device.as_ref().unwrap();
-        let surface = self.surface.as_ref().unwrap();
-        let queue = self.queue.as_ref().unwrap();
+        self.render_to_view(&view, commands);
+        output.present();
         
-        let output = surface.get_current_texture()?;
-        let view = output.texture.create_view(&TextureViewDescriptor::default());
+        Ok(())
+    }
+
+    /// Encode and submit the draw commands, rendering into the given color view
+    pub fn render_to_view(&mut self, view: &TextureView, commands: Vec<DrawCommand>) {
+        let (Some(device), Some(queue), Some(format)) = (&self.device, &self.queue, self.color_format) else {
+            return;
+        };
+        let started = Instant::now();
+        let mut stats = FrameStats {
+            draw_commands: commands.iter().filter(|command| command.is_draw()).count() as u32,
+            ..Default::default()
+        };
+
+        // Draws that can be reordered are merged and grouped by pipeline
+        let commands = if self.draw_batching {
+            let shaders = &self.custom_shaders;
+            batch_draws(commands, |shader_id| {
+                shaders.get(shader_id).and_then(CustomShader::pipeline_state).is_none_or(|state| !state.is_order_independent())
+            })
+        } else {
+            commands
+        };
+        if let Some(timer) = &mut self.gpu_timer {
+            timer.begin_frame(device);
+        }
+        
+        // Pack the instance data and uniforms of every valid draw into shared buffers,
+        // so each draw reads its own values when the render pass executes
+        self.instance_buffer.clear();
+        for shader in &mut self.custom_shaders {
+            shader.begin_frame();
+        }
+        for shader in &mut self.compute_shaders {
+            shader.begin_frame();
+        }
+
+        let mut staged_draws = Vec::with_capacity(commands.len());
+        // Argument buffer (compute shader and slot) of each indirect draw, by command index
+        let mut indirect_arguments = HashMap::new();
+        let mut staged_dispatches = Vec::new();
+        for (index, command) in commands.iter().enumerate() {
+            let (shader_id, instances, uniforms, textures, features) = match command {
+                DrawCommand::GeometryWithInstances { shader_id, instances, instance_size, uniforms, textures, features, .. }
+                | DrawCommand::MeshWithInstances { shader_id, instances, instance_size, uniforms, textures, features, .. } => {
+                    (shader_id, DrawInstances::Data { bytes: instances, instance_size: *instance_size }, uniforms, textures, features)
+                }
+                DrawCommand::MeshWithStorageInstances { shader_id, compute, storage, uniforms, textures, features, .. }
+                | DrawCommand::MeshIndirect { shader_id, compute, storage, uniforms, textures, features, .. } => {
+                    let slot = self.compute_shaders.get(compute.0)
+                        .ok_or_else(|| format!("Unknown compute shader {}", compute.0))
+                        .and_then(|shader| {
+                            // Indirect draws read their arguments from the buffer bound when they were recorded
+                            if let DrawCommand::MeshIndirect { arguments, .. } = command {
+                                let slot = shader.storage_slot(arguments)?;
+                                let element_size = shader.storage_buffer(slot).map_or(0, |(_, _, size)| size);
+                                if element_size != DrawIndexedIndirect::SIZE {
+                                    return Err(format!(
+                                        "Storage '{}' of compute shader '{}' has {}-byte elements, but indirect arguments are {} bytes",
+                                        arguments, shader.name, element_size, DrawIndexedIndirect::SIZE
+                                    ));
+                                }
+                                indirect_arguments.insert(index, (compute.0, slot));
+                            }
+                            shader.storage_slot(storage)
+                        });
+                    match slot {
+                        Ok(slot) => (shader_id, DrawInstances::Storage { compute: compute.0, slot }, uniforms, textures, features),
+                        Err(message) => {
+                            if self.reported_errors.insert(message.clone()) {
+                                log::warn!(target: LOG_DRAW, "Skipping draw: {}", message);
+                            }
+                            staged_draws.push(None);
+                            continue;
+                        }
+                    }
+                }
+                DrawCommand::DebugLines { view_projection, depth_tested, overlay } => {
+                    let renderer = self.debug_renderer.get_or_insert_with(|| DebugRenderer::new(device, format));
+                    let view_projection = glam::Mat4::from_cols_array_2d(view_projection);
+                    stats.bytes_uploaded += renderer.prepare(device, queue, &view_projection, depth_tested, overlay);
+                    staged_draws.push(None);
+                    continue;
+                }
+                DrawCommand::Shapes2d { vertices, batches } => {
+                    let renderer = self.renderer_2d.get_or_insert_with(|| Renderer2d::new(device, format));
+                    let (bytes, skipped) = renderer.prepare(device, queue, self.target_size, vertices, batches, &self.textures);
+                    stats.bytes_uploaded += bytes;
+                    for texture in skipped {
+                        let message = format!("Texture {} isn't uploaded", texture.id());
+                        if self.reported_errors.insert(message.clone()) {
+                            log::warn!(target: LOG_DRAW, "Skipping 2D shapes: {}", message);
+                        }
+                    }
+                    staged_draws.push(None);
+                    continue;
+                }
+                DrawCommand::UpdateStorage { shader_id, name, data } => {
+                    let result = self.custom_shaders.get_mut(*shader_id)
+                        .ok_or_else(|| format!("Unknown shader {}", shader_id))
+                        .and_then(|shader| shader.update_storage(name, data));
+                    if let Err(message) = result && self.reported_errors.insert(message.clone()) {
+                        log::warn!(target: LOG_DRAW, "Skipping storage update: {}", message);
+                    }
+                    staged_draws.push(None);
+                    continue;
+                }
+                DrawCommand::Dispatch { compute, workgroups, uniforms } => {
+                    let message = match self.compute_shaders.get_mut(compute.0) {
+                        Some(shader) if shader.error().is_none() => {
+                            staged_dispatches.push((compute.0, shader.stage_dispatch(uniforms), *workgroups));
+                            None
+                        }
+                        Some(shader) => shader.error().map(|error| format!("unavailable: {}", error)),
+                        None => Some(format!("Unknown compute shader {}", compute.0)),
+                    };
+                    if let Some(message) = message && self.reported_errors.insert(message.clone()) {
+                        log::warn!(target: LOG_DRAW, "Skipping dispatch: {}", message);
+                    }
+                    staged_draws.push(None);
+                    continue;
+                }
+                DrawCommand::UpdateComputeStorage { compute, name, data } => {
+                    let result = self.compute_shaders.get_mut(compute.0)
+                        .ok_or_else(|| format!("Unknown compute shader {}", compute.0))
+                        .and_then(|shader| shader.update_storage(name, data));
+                    if let Err(message) = result && self.reported_errors.insert(message.clone()) {
+                        log::warn!(target: LOG_DRAW, "Skipping storage update: {}", message);
+                    }
+                    staged_draws.push(None);
+                    continue;
+                }
+                DrawCommand::SwapStorage { compute, first, second } => {
+                    let result = self.compute_shaders.get_mut(compute.0)
+                        .ok_or_else(|| format!("Unknown compute shader {}", compute.0))
+                        .and_then(|shader| shader.swap_storage(first, second));
+                    if let Err(message) = result && self.reported_errors.insert(message.clone()) {
+                        log::warn!(target: LOG_DRAW, "Skipping storage swap: {}", message);
+                    }
+                    staged_draws.push(None);
+                    continue;
+                }
+            };
+
+            let Some(shader) = self.custom_shaders.get_mut(*shader_id) else {
+                staged_draws.push(None);
+                continue;
+            };
+
+            // Instances come either from the draw itself or from a compute shader's storage buffer
+            let validated = match (shader.error(), instances) {
+                (Some(error), _) => Err(format!("unavailable: {}", error)),
+                (None, DrawInstances::Data { instance_size, .. }) => shader.validate_instance_size(instance_size),
+                (None, DrawInstances::Storage { .. }) if !shader.has_instance_layout() => {
+                    Err(format!("Shader '{}' has no instance layout to draw a storage buffer with", shader.name))
+                }
+                (None, DrawInstances::Storage { compute, slot }) => {
+                    let element_size = self.compute_shaders[compute].storage_buffer(slot).map_or(0, |(_, _, size)| size);
+                    shader.validate_instance_size(element_size as usize)
+                }
+            };
+            // Variants are compiled the first time a draw selects them
+            let features = validated
+                .and_then(|()| shader.prepare_variant(device, format, features).map_err(|error| error.to_string()));
+            let staged = features
+                .and_then(|features| Ok((features, shader.stage_uniforms(uniforms)?)))
+                .and_then(|(features, uniform_offsets)| Ok(StagedBindings {
+                    uniform_offsets,
+                    texture_sets: shader.stage_textures(textures, &self.textures)?,
+                    features,
+                }));
+            let staged = match staged {
+                Ok(bindings) => {
+                    let source = match instances {
+                        DrawInstances::Data { bytes, .. } => {
+                            stats.bytes_uploaded += bytes.len() as u64;
+                            InstanceSource::Frame(self.instance_buffer.push(bytes))
+                        }
+                        DrawInstances::Storage { compute, slot } => InstanceSource::Storage { compute, slot },
+                    };
+                    Some((source, bindings))
+                }
+                Err(message) => {
+                    // Report each invalid draw once instead of every frame
+                    if self.reported_errors.insert(message.clone()) {
+                        log::warn!(target: LOG_DRAW, "Skipping draw: {}", message);
+                    }
+                    None
+                }
+            };
+            staged_draws.push(staged);
+        }
+        stats.dispatches = staged_dispatches.len() as u32;
+
+        self.instance_buffer.upload(device, queue);
+        for shader in &mut self.custom_shaders {
+            stats.bytes_uploaded += shader.upload_bindings(device, queue, &self.textures);
+        }
+        for shader in &mut self.compute_shaders {
+            stats.bytes_uploaded += shader.upload_bindings(device, queue);
+        }
         
         let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
-            label: Some("Render Encoder"),
+            label: Some(&label(&["frame", "encoder"])),
         });
+
+        // Run the frame's dispatches in order before any draw reads their results
+        if !staged_dispatches.is_empty() {
+            let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: Some(&label(&["frame", "compute pass"])),
+                timestamp_writes: self.gpu_timer.as_ref().and_then(GpuTimer::compute_pass_writes),
+            });
+            for (compute, bindings, [x, y, z]) in &staged_dispatches {
+                let shader = &self.compute_shaders[*compute];
+                if Self::bind_compute(&mut compute_pass, shader, bindings) {
+                    compute_pass.dispatch_workgroups(*x, *y, *z);
+                }
+            }
+        }
         
         {
             // Create render pass with depth attachment
@@ -186,9 +833,9 @@ impl RenderContext {
             };
             
             let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
-                label: Some("Render Pass"),
+                label: Some(&label(&["frame", "render pass"])),
                 color_attachments: &[Some(RenderPassColorAttachment {
-                    view: &view,
+                    view,
                     resolve_target: None,
                     ops: Operations {
                         load: LoadOp::Clear(self.clear_color),
@@ -196,127 +843,286 @@ impl RenderContext {
                     },
                 })],
                 depth_stencil_attachment,
-                timestamp_writes: None,
+                timestamp_writes: self.gpu_timer.as_ref().and_then(GpuTimer::render_pass_writes),
                 occlusion_query_set: None,
             });
             
             // Process each draw command
-            for command in commands {
+            let mut bindings = PassBindings::default();
+            for (index, (command, staged)) in commands.iter().zip(staged_draws).enumerate() {
+                // Debug lines were uploaded while staging and draw with their own pipelines
+                if let (DrawCommand::DebugLines { .. }, Some(renderer)) = (command, &self.debug_renderer) {
+                    let draw_calls = renderer.draw(&mut render_pass);
+                    stats.draw_calls += draw_calls;
+                    bindings.pipelines_bound += draw_calls;
+                    bindings.pipeline = None;
+                    continue;
+                }
+                if let (DrawCommand::Shapes2d { .. }, Some(renderer)) = (command, &self.renderer_2d) {
+                    let draw_calls = renderer.draw(&mut render_pass);
+                    stats.draw_calls += draw_calls;
+                    bindings.pipelines_bound += draw_calls.min(1);
+                    bindings.pipeline = None;
+                    continue;
+                }
+                // Draws with invalid instance data were rejected while staging
+                let Some((source, staged_bindings)) = staged else {
+                    continue;
+                };
+                let (instance_buffer, instance_range) = match source {
+                    InstanceSource::Frame(range) => (self.instance_buffer.buffer(), range),
+                    InstanceSource::Storage { compute, slot } => {
+                        let Some((buffer, length, _)) = self.compute_shaders[compute].storage_buffer(slot) else {
+                            continue;
+                        };
+                        (Some(buffer), 0..length)
+                    }
+                };
+
                 match command {
                     DrawCommand::GeometryWithInstances { 
-                        shader_id, vertices, indices, instances, 
+                        shader_id, vertices, indices, 
                         vertex_count, index_count, instance_count, 
-                        uniforms 
+                        ..
                     } => {
                         // Get the custom shader from the registry
                         if let Some(shader) = self.custom_shaders.get(*shader_id) {
-                            // Apply uniforms if any
-                            if !uniforms.is_empty() && self.queue.is_some() {
-                                let queue = self.queue.as_ref().unwrap();
-                                for (name, value) in uniforms {
-                                    match value {
-                                        crate::draw_commands::UniformValue::Float(val) => {
-                                            shader.set_uniform(name, *val, Some(queue));
-                                        },
-                                        crate::draw_commands::UniformValue::Vec2(val) => {
-                                            shader.set_uniform(name, *val, Some(queue));
-                                        },
-                                        crate::draw_commands::UniformValue::Vec3(val) => {
-                                            shader.set_uniform(name, *val, Some(queue));
-                                        },
-                                        crate::draw_commands::UniformValue::Vec4(val) => {
-                                            shader.set_uniform(name, *val, Some(queue));
-                                        },
-                                        crate::draw_commands::UniformValue::Mat4(val) => {
-                                            shader.set_uniform(name, *val, Some(queue));
-                                        },
-                                        crate::draw_commands::UniformValue::Int(val) => {
-                                            shader.set_uniform(name, *val, Some(queue));
-                                        },
-                                        crate::draw_commands::UniformValue::UInt(val) => {
-                                            shader.set_uniform(name, *val, Some(queue));
-                                        },
-                                    }
-                                }
+                            if !bindings.bind(&mut render_pass, *shader_id, shader, &staged_bindings) {
+                                continue;
                             }
-                            
+
                             // Create vertex buffer
+                            stats.bytes_uploaded += (vertices.len() + indices.len()) as u64;
                             let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                                label: Some(&format!("{} Vertex Buffer", shader.name)),
+                                label: Some(&label(&["shader", &shader.name, "geometry vertices"])),
                                 contents: vertices,
                                 usage: wgpu::BufferUsages::VERTEX,
                             });
                             
-                            // Create instance buffer
-                            let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                                label: Some(&format!("{} Instance Buffer", shader.name)),
-                                contents: instances,
-                                usage: wgpu::BufferUsages::VERTEX,
-                            });
-                            
                             // Create index buffer
                             let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                                label: Some(&format!("{} Index Buffer", shader.name)),
+                                label: Some(&label(&["shader", &shader.name, "geometry indices"])),
                                 contents: indices,
                                 usage: wgpu::BufferUsages::INDEX,
                             });
                             
-                            // Debug print information about the geometry being drawn
-                            println!("[DEBUG] GeometryWithInstances:");
-                            println!("  - Shader: {}", shader.name);
-                            println!("  - Vertices: {} ({} bytes)", vertex_count, vertices.len());
-                            println!("  - Indices: {} ({} bytes)", index_count, indices.len());
-                            println!("  - Triangles: {}", index_count / 3);
-                            println!("  - Instances: {}", instance_count);
-                            
-                            // Print first few indices if any exist to debug
-                            if indices.len() >= 4 * 6 { // Assuming at least 6 u32 indices
-                                let indices_slice = bytemuck::cast_slice::<u8, u32>(&indices[0..24]);
-                                println!("  - First indices: {:?}", &indices_slice);
-                            }
-                            
-                            // Print first few vertices to debug (just positions, assuming each vertex has at least 3 f32 values for position)
-                            if vertices.len() >= 4 * 3 * 4 { // 4 vertices, each with at least 3 f32 values (4 bytes each)
-                                let vertices_slice = bytemuck::cast_slice::<u8, f32>(&vertices[0..48]);
-                                println!("  - Vertex positions:");
-                                for i in 0..4 {
-                                    if i * 3 + 2 < vertices_slice.len() {
-                                        println!("    [{}]: ({}, {}, {})", 
-                                            i, 
-                                            vertices_slice[i * 3], 
-                                            vertices_slice[i * 3 + 1], 
-                                            vertices_slice[i * 3 + 2]);
-                                    }
-                                }
-                            }
+                            log::trace!(
+                                target: LOG_DRAW,
+                                "Drawing {} vertices, {} indices and {} instances with shader '{}'",
+                                vertex_count, index_count, instance_count, shader.name
+                            );
+
+                            // Draw the indexed geometry
+                            Self::draw_instanced(
+                                &mut render_pass, shader, &vertex_buffer, &index_buffer, *index_count,
+                                instance_buffer, instance_range, *instance_count,
+                            );
+                            stats.record_draw(shader, *index_count, *instance_count);
+                        }
+                    }
+                    DrawCommand::MeshWithInstances { shader_id, mesh, .. }
+                    | DrawCommand::MeshWithStorageInstances { shader_id, mesh, .. } => {
+                        let (Some(shader), Some(mesh)) = (self.custom_shaders.get(*shader_id), self.get_mesh(*mesh)) else {
+                            continue;
+                        };
+                        if !bindings.bind(&mut render_pass, *shader_id, shader, &staged_bindings) {
+                            continue;
+                        }
 
-                            // Draw the indexed geometry if the shader is initialized
-                            if let Some(pipeline) = shader.pipeline() {
-                                render_pass.set_pipeline(pipeline);
-                                render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
-                                render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
-                                render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-                                
-                                // Set bind group if available
-                                if let Some(bind_group) = shader.bind_group() {
-                                    render_pass.set_bind_group(0, bind_group, &[]);
+                        // Storage buffer draws have one instance per element
+                        let instance_count = match command {
+                            DrawCommand::MeshWithInstances { instance_count, .. } => *instance_count,
+                            _ => (instance_range.end / shader.instance_stride()) as u32,
+                        };
+
+                        // Draw with the persistent mesh buffers once they are on the GPU
+                        if let (Some(vertex_buffer), Some(index_buffer)) = (mesh.vertex_buffer(), mesh.index_buffer()) {
+                            Self::draw_instanced(
+                                &mut render_pass, shader, vertex_buffer, index_buffer, mesh.index_count(),
+                                instance_buffer, instance_range, instance_count,
+                            );
+                            stats.record_draw(shader, mesh.index_count(), instance_count);
+                        }
+                    }
+                    DrawCommand::MeshIndirect { shader_id, mesh, draw_count, .. } => {
+                        let (Some(shader), Some(mesh)) = (self.custom_shaders.get(*shader_id), self.get_mesh(*mesh)) else {
+                            continue;
+                        };
+                        let Some((argument_buffer, length, _)) = indirect_arguments.get(&index)
+                            .and_then(|&(compute, slot)| self.compute_shaders[compute].storage_buffer(slot)) else {
+                            continue;
+                        };
+                        // Never read arguments past the end of the buffer
+                        let draw_count = (*draw_count).min((length / DrawIndexedIndirect::SIZE) as u32);
+                        if draw_count == 0 || !bindings.bind(&mut render_pass, *shader_id, shader, &staged_bindings) {
+                            continue;
+                        }
+
+                        if let (Some(vertex_buffer), Some(index_buffer)) = (mesh.vertex_buffer(), mesh.index_buffer())
+                            && Self::set_draw_buffers(&mut render_pass, shader, vertex_buffer, index_buffer, instance_buffer, instance_range)
+                        {
+                            // The triangles and instances drawn are only known on the GPU
+                            if draw_count == 1 {
+                                render_pass.draw_indexed_indirect(argument_buffer, 0);
+                                stats.draw_calls += 1;
+                            } else if device.features().contains(Features::MULTI_DRAW_INDIRECT) {
+                                render_pass.multi_draw_indexed_indirect(argument_buffer, 0, draw_count);
+                                stats.draw_calls += 1;
+                            } else {
+                                for draw in 0..draw_count as u64 {
+                                    render_pass.draw_indexed_indirect(argument_buffer, draw * DrawIndexedIndirect::SIZE);
                                 }
-                                
-                                // Draw using indices
-                                render_pass.draw_indexed(0..*index_count, 0, 0..*instance_count);
+                                stats.draw_calls += draw_count;
                             }
                         }
                     }
+                    // Storage updates, swaps and dispatches were handled before the render pass
+                    DrawCommand::DebugLines { .. }
+                    | DrawCommand::Shapes2d { .. }
+                    | DrawCommand::UpdateStorage { .. }
+                    | DrawCommand::Dispatch { .. }
+                    | DrawCommand::UpdateComputeStorage { .. }
+                    | DrawCommand::SwapStorage { .. } => {}
                 }
             }
+            stats.pipelines_bound = bindings.pipelines_bound;
+        }
+
+        if self.show_stats_overlay {
+            let overlay = self.stats_overlay.get_or_insert_with(|| StatsOverlay::new(device, format));
+            overlay.draw(device, &mut encoder, view, self.target_size);
+        }
+        if let Some(timer) = &mut self.gpu_timer {
+            timer.resolve(&mut encoder, !staged_dispatches.is_empty());
         }
+        stats.encode_time = started.elapsed();
         
         queue.submit(std::iter::once(encoder.finish()));
-        output.present();
-        
+        if let Some(timer) = &mut self.gpu_timer {
+            timer.after_submit();
+            stats.gpu_time = timer.last();
+        }
+        if let Some(overlay) = &mut self.stats_overlay {
+            overlay.record(&stats);
+        }
+        log::debug!(target: LOG_FRAME, "Frame: {}", stats);
+        self.frame_stats = stats;
+    }
+
+    /// Get the statistics of the last rendered frame
+    ///
+    /// Picks up a GPU time measurement that finished since the frame was submitted.
+    pub fn frame_stats(&mut self) -> FrameStats {
+        if let (Some(timer), Some(device)) = (&mut self.gpu_timer, &self.device) {
+            timer.collect(device);
+            self.frame_stats.gpu_time = timer.last();
+        }
+        self.frame_stats
+    }
+
+    /// Show or hide the graph of recent CPU and GPU frame times
+    pub fn set_stats_overlay(&mut self, visible: bool) {
+        self.show_stats_overlay = visible;
+    }
+
+    /// Enable or disable merging and sorting of draws
+    pub fn set_draw_batching(&mut self, enabled: bool) {
+        self.draw_batching = enabled;
+    }
+
+    /// Get the size in pixels of the render target
+    pub fn target_size(&self) -> (u32, u32) {
+        self.target_size
+    }
+
+    /// Get the glyph atlas text is drawn with, rasterizing the bundled font if none was set
+    pub fn font(&mut self) -> Arc<FontAtlas> {
+        if let Some(font) = &self.font {
+            return font.clone();
+        }
+        let font = self.rasterize_font(&Font::bundled(), DEFAULT_FONT_SIZE)
+            .expect("The bundled font fits in the smallest texture limit");
+        self.font = Some(font.clone());
+        font
+    }
+
+    /// Rasterize a font with lines `pixel_size` high and draw text with it
+    ///
+    /// Fails, keeping the current font, if the size isn't positive or the
+    /// glyphs don't fit in a texture of the device.
+    pub fn set_font(&mut self, font: &Font, pixel_size: f32) -> Result<(), String> {
+        self.font = Some(self.rasterize_font(font, pixel_size)?);
         Ok(())
     }
+
+    /// Rasterize a font's glyph atlas and upload its texture
+    fn rasterize_font(&mut self, font: &Font, pixel_size: f32) -> Result<Arc<FontAtlas>, String> {
+        let (atlas, image) = FontAtlas::new(font, pixel_size, self.max_texture_dimension())?;
+        let texture = self.upload_texture(image, TextureOptions { generate_mipmaps: false, srgb: true })?;
+        Ok(Arc::new(atlas.with_texture(texture)))
+    }
     
+    /// Bind a compute shader's pipeline and the bind groups of one dispatch
+    ///
+    /// Returns false if the shader isn't ready to dispatch.
+    fn bind_compute(compute_pass: &mut wgpu::ComputePass<'_>, shader: &ComputeShader, bindings: &ComputeBindings) -> bool {
+        let Some(pipeline) = shader.pipeline() else {
+            return false;
+        };
+        compute_pass.set_pipeline(pipeline);
+        for group in 0..shader.bind_group_count() {
+            if let Some(bind_group) = shader.bind_group(group, &bindings.storage_slots) {
+                compute_pass.set_bind_group(group as u32, bind_group, &bindings.uniform_offsets[group]);
+            }
+        }
+        true
+    }
+
+    /// Record an indexed, instanced draw with the shader's pipeline already bound
+    #[allow(clippy::too_many_arguments)]
+    fn draw_instanced(
+        render_pass: &mut wgpu::RenderPass<'_>,
+        shader: &CustomShader,
+        vertex_buffer: &wgpu::Buffer,
+        index_buffer: &wgpu::Buffer,
+        index_count: u32,
+        instance_buffer: Option<&wgpu::Buffer>,
+        instance_range: std::ops::Range<wgpu::BufferAddress>,
+        instance_count: u32,
+    ) {
+        if Self::set_draw_buffers(render_pass, shader, vertex_buffer, index_buffer, instance_buffer, instance_range) {
+            // Draw using indices
+            render_pass.draw_indexed(0..index_count, 0, 0..instance_count);
+        }
+    }
+
+    /// Set the vertex, instance and index buffers of a draw
+    ///
+    /// Returns false if the shader needs instance data and there is none.
+    fn set_draw_buffers(
+        render_pass: &mut wgpu::RenderPass<'_>,
+        shader: &CustomShader,
+        vertex_buffer: &wgpu::Buffer,
+        index_buffer: &wgpu::Buffer,
+        instance_buffer: Option<&wgpu::Buffer>,
+        instance_range: std::ops::Range<wgpu::BufferAddress>,
+    ) -> bool {
+        render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+
+        // Only shaders with instance attributes have a second vertex buffer slot
+        if shader.has_instance_layout() {
+            match instance_buffer {
+                Some(buffer) if !instance_range.is_empty() => {
+                    render_pass.set_vertex_buffer(1, buffer.slice(instance_range));
+                }
+                _ => return false, // Nothing to draw without instance data
+            }
+        }
+
+        render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        true
+    }
+
     /// Create a depth texture suitable for depth testing
     fn create_depth_texture(&self, device: &Device, width: u32, height: u32) -> TextureView {
         let size = Extent3d {
@@ -326,7 +1132,7 @@ impl RenderContext {
         };
         
         let desc = TextureDescriptor {
-            label: Some("Depth Texture"),
+            label: Some(&label(&["frame", "depth"])),
             size,
             mip_level_count: 1,
             sample_count: 1,
@@ -370,4 +1176,60 @@ mod tests {
         assert_eq!(context.clear_color.b, 0.7);
         assert_eq!(context.clear_color.a, 0.8);
     }
+
+    #[test]
+    fn test_upload_texture_rejects_images_the_device_cannot_hold() {
+        let options = TextureOptions { generate_mipmaps: false, srgb: true };
+        let oversized = || image::RgbaImage::new(Limits::default().max_texture_dimension_2d + 1, 1);
+
+        // Without a device the default limits apply, so placeholders never fail later
+        let mut context = RenderContext::new();
+        let error = context.upload_texture(oversized(), options).unwrap_err();
+        assert!(error.contains("texture limit"), "{}", error);
+        assert!(context.upload_texture(image::RgbaImage::new(0, 0), options).unwrap_err().contains("empty"));
+        assert_eq!(context.textures.len(), 1);
+
+        if context.initialize_headless(16, 16, TextureFormat::Rgba8UnormSrgb).is_err() {
+            eprintln!("Skipping texture limit test: no adapter available");
+            return;
+        }
+        let max_dimension = context.max_texture_dimension();
+        let error = context.upload_texture(image::RgbaImage::new(max_dimension + 1, 1), options).unwrap_err();
+        assert!(error.contains(&format!("{} pixel texture limit", max_dimension)), "{}", error);
+        assert!(context.upload_texture(image::RgbaImage::new(2, 2), options).is_ok());
+    }
+
+    #[test]
+    fn test_deferred_shader_errors_are_recorded() {
+        let source = r#"
+            @group(0) @binding(0) var<uniform> tint: vec4<f32>;
+
+            @vertex
+            fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+                return vec4<f32>(f32(index), 0.0, 0.0, 1.0);
+            }
+
+            @fragment
+            fn fs_main() -> @location(0) vec4<f32> {
+                return tint;
+            }
+        "#;
+        let Ok(mut descriptor) = ShaderDescriptor::from_wgsl("Tint", source, &[]) else {
+            panic!("shader should reflect");
+        };
+        descriptor.uniforms.clear();
+
+        // Without a device only the WGSL can be checked, so registration succeeds
+        let mut context = RenderContext::new();
+        let id = context.register_descriptor(descriptor).unwrap();
+        assert!(context.shader_errors().is_empty());
+
+        if context.initialize_headless(16, 16, TextureFormat::Rgba8UnormSrgb).is_err() {
+            eprintln!("Skipping deferred shader test: no adapter available");
+            return;
+        }
+        let errors = context.shader_errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[&id].shader, "Tint");
+    }
 }
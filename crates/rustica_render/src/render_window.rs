@@ -1,6 +1,8 @@
 // Window with rendering capabilities
 
-use std::time::Instant;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use image::RgbaImage;
 use winit::{
     application::ApplicationHandler,
     event::WindowEvent,
@@ -10,8 +12,17 @@ use winit::{
 use rustica_window::WindowApp;
 
 use crate::canvas::Canvas;
+use crate::diagnostics::LOG_DEVICE;
+use crate::font::Font;
+use crate::frame_stats::FrameStats;
 use crate::render_context::RenderContext;
-use crate::custom_shader::{CustomShader, ShaderDescriptor};
+use crate::shader_error::ShaderError;
+use crate::compute_shader::{ComputeHandle, ComputeShaderDescriptor};
+use crate::custom_shader::ShaderDescriptor;
+use crate::lod_mesh::LodMesh;
+use crate::mesh::MeshHandle;
+use crate::texture::{self, TextureHandle, TextureOptions};
+use rustica_foundation::geometry::{Geometry, Vertex};
 
 /// A window with rendering capabilities
 pub struct RenderWindow {
@@ -41,19 +52,104 @@ impl RenderWindow {
     }
     
     /// Register a custom shader and return its ID
-    pub fn register_shader(&mut self, descriptor: ShaderDescriptor) -> usize {
-        if let (Some(device), Some(config)) = (&self.render_context.device, &self.render_context.config) {
-            let shader = CustomShader::new(device, config.format, descriptor);
-            self.render_context.register_shader(shader)
-        } else {
-            // If the device isn't initialized yet, create a placeholder shader
-            // that will be properly initialized later
-            let shader = CustomShader::new_placeholder(descriptor);
-            self.render_context.register_shader(shader)
-        }
+    ///
+    /// The descriptor and WGSL are checked right away; errors that need the
+    /// device are reported when the window opens, after which the shader is
+    /// unavailable (see `Canvas::shader_error`).
+    pub fn register_shader(&mut self, descriptor: ShaderDescriptor) -> Result<usize, ShaderError> {
+        // If the device isn't initialized yet, a placeholder shader is created
+        // that will be properly initialized later
+        self.render_context.register_descriptor(descriptor)
+    }
+    
+    /// Register a compute shader and return its handle
+    ///
+    /// Dispatch it with `Canvas::dispatch`; its storage buffers persist between frames.
+    pub fn register_compute_shader(&mut self, descriptor: ComputeShaderDescriptor) -> Result<ComputeHandle, ShaderError> {
+        self.render_context.register_compute_descriptor(descriptor)
+    }
+
+    /// Reload shaders from their WGSL files when the files change
+    ///
+    /// Files are checked at most once per interval, before a frame is drawn.
+    /// A shader keeps its ID and, if the new source doesn't compile, its
+    /// previous pipeline. Only shaders with a `source_path` are watched.
+    pub fn watch_shaders(&mut self, interval: Duration) {
+        self.render_context.watch_shaders(interval);
+    }
+
+    /// Upload geometry to the GPU once and return a handle for drawing it
+    ///
+    /// The vertex and index buffers stay alive for the lifetime of the window.
+    /// Use the handle with `InstancedShaderDrawBuilder::draw_mesh` to avoid
+    /// re-uploading the geometry every frame.
+    pub fn upload_geometry<V: Vertex>(&mut self, geometry: &Geometry<V>) -> MeshHandle {
+        self.render_context.upload_mesh(
+            bytemuck::cast_slice(&geometry.vertices),
+            bytemuck::cast_slice(&geometry.indices),
+            geometry.indices.len() as u32,
+        )
     }
     
+    /// Upload the levels of a mesh with several resolutions
+    ///
+    /// Each level is a geometry and the smallest screen size it is drawn at,
+    /// as a fraction of the viewport height (see `LodMesh`).
+    pub fn upload_lod_geometry<V: Vertex>(&mut self, levels: &[(&Geometry<V>, f32)]) -> LodMesh {
+        levels.iter().fold(LodMesh::new(), |lod, (geometry, min_screen_size)| {
+            lod.with_level(self.upload_geometry(geometry), *min_screen_size)
+        })
+    }
+
+    /// Upload an image as a texture and return a handle for drawing with it
+    ///
+    /// Fails if the image is empty or larger than the device's texture size limit.
+    pub fn upload_texture(&mut self, image: RgbaImage, options: TextureOptions) -> Result<TextureHandle, String> {
+        self.render_context.upload_texture(image, options)
+    }
+
+    /// Decode a PNG or JPEG image from memory and upload it as a texture
+    pub fn load_texture(&mut self, bytes: &[u8], options: TextureOptions) -> Result<TextureHandle, String> {
+        self.upload_texture(texture::decode_image(bytes)?, options)
+    }
+
+    /// Load a PNG or JPEG file and upload it as a texture
+    pub fn load_texture_file<P: AsRef<Path>>(&mut self, path: P, options: TextureOptions) -> Result<TextureHandle, String> {
+        self.upload_texture(texture::load_image(path)?, options)
+    }
+
+    /// Get the statistics of the last rendered frame
+    pub fn frame_stats(&mut self) -> FrameStats {
+        self.render_context.frame_stats()
+    }
+
+    /// Show or hide a graph of recent CPU and GPU frame times in the top left corner
+    pub fn show_stats_overlay(&mut self, visible: bool) {
+        self.render_context.set_stats_overlay(visible);
+    }
+
+    /// Enable or disable automatic draw batching (enabled by default)
+    ///
+    /// Each frame, opaque draws with the same shader, geometry, uniforms and
+    /// textures are merged into one instanced draw, and draws are grouped by
+    /// pipeline. Blended draws and draws without depth test or depth writes
+    /// keep their recorded order; disable batching if other draws rely on it.
+    pub fn set_draw_batching(&mut self, enabled: bool) {
+        self.render_context.set_draw_batching(enabled);
+    }
+
+    /// Draw `Canvas::text` with a font rasterized with lines `pixel_size` high
+    ///
+    /// Text is sharpest at this size, so pick the largest size it is drawn at.
+    /// Fails if the glyphs don't fit in a texture, keeping the current font.
+    pub fn set_font(&mut self, font: &Font, pixel_size: f32) -> Result<(), String> {
+        self.render_context.set_font(font, pixel_size)
+    }
+
     /// Set a callback function to be called each frame
+    ///
+    /// The window is owned by its event loop while it runs, so the callback
+    /// reads compute results back with `Canvas::read_storage`.
     pub fn with_frame_callback<F>(mut self, callback: F) -> Self 
     where 
         F: FnMut(&mut Canvas) + 'static 
@@ -75,19 +171,29 @@ impl RenderWindow {
             // Get the elapsed time
             let elapsed = Instant::now().duration_since(self.render_context.start_time);
             
+            // Swap in shaders whose files changed before the frame uses them
+            self.render_context.reload_changed_shaders();
+
             // Create a canvas and collect draw commands
+            let frame_stats = self.render_context.frame_stats();
             let mut canvas = Canvas::new(elapsed);
+            canvas.frame_stats = frame_stats;
+            canvas.font = Some(self.render_context.font());
+            canvas.target_size = self.render_context.target_size();
             
             // Set the queue reference if available
             if let Some(queue) = &self.render_context.queue {
                 canvas.set_queue(queue);
             }
+            canvas.shader_errors = self.render_context.shader_errors();
+            canvas.compute_shader_errors = self.render_context.compute_shader_errors();
+            canvas.storage_source = Some(&self.render_context);
             
             // Execute the callback
             callback(&mut canvas);
             
             // Process the draw commands
-            self.render_context.process_draw_commands(&canvas.commands)?;
+            self.render_context.process_draw_commands(canvas.take_commands())?;
         }
         
         Ok(())
@@ -98,7 +204,7 @@ impl ApplicationHandler for RenderWindow {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
         self.window_app.resumed(event_loop);
         if let Err(e) = self.render_context.initialize(&self.window_app) {
-            eprintln!("Render context initialization error: {:?}", e);
+            log::error!(target: LOG_DEVICE, "Render context initialization error: {:?}", e);
         }
     }
 
@@ -125,7 +231,7 @@ impl ApplicationHandler for RenderWindow {
                             }
                         }
                         Err(wgpu::SurfaceError::OutOfMemory) => event_loop.exit(),
-                        Err(e) => eprintln!("Render error: {:?}", e),
+                        Err(e) => log::error!(target: LOG_DEVICE, "Render error: {:?}", e),
                     }
                 }
             }
@@ -157,4 +263,54 @@ mod tests {
         // We can't directly test the clear color as it's in a private field,
         // but we can at least verify the method doesn't panic
     }
+
+    #[repr(C)]
+    #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+    struct TestVertex {
+        position: [f32; 3],
+    }
+
+    impl Vertex for TestVertex {
+        fn layout() -> wgpu::VertexBufferLayout<'static> {
+            const ATTRIBUTES: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![0 => Float32x3];
+            wgpu::VertexBufferLayout {
+                array_stride: std::mem::size_of::<TestVertex>() as wgpu::BufferAddress,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &ATTRIBUTES,
+            }
+        }
+    }
+
+    #[test]
+    fn test_upload_geometry_before_initialization() {
+        let mut window = RenderWindow::new("Test Render Window", 800, 600);
+        let geometry = Geometry {
+            vertices: vec![
+                TestVertex { position: [0.0, 0.0, 0.0] },
+                TestVertex { position: [1.0, 0.0, 0.0] },
+                TestVertex { position: [0.0, 1.0, 0.0] },
+            ],
+            indices: vec![0, 1, 2],
+        };
+
+        let first = window.upload_geometry(&geometry);
+        let second = window.upload_geometry(&geometry);
+        assert_ne!(first, second);
+
+        let mesh = window.render_context.get_mesh(first).expect("Mesh should be registered");
+        assert!(!mesh.is_initialized());
+        assert_eq!(mesh.index_count(), 3);
+    }
+
+    #[test]
+    fn test_register_shader_before_initialization_checks_wgsl() {
+        let mut window = RenderWindow::new("Test Render Window", 800, 600);
+        let source = "@vertex\nfn vs_main() -> @builtin(position) vec4<f32> {\n    return vec4<f32>(0.0);\n}\n\n@fragment\nfn fs_main() -> @location(0) vec4<f32> {\n    return vec4<f32>(1.0);\n}\n";
+        let Ok(mut descriptor) = ShaderDescriptor::from_wgsl("Broken", source, &[]) else {
+            panic!("shader should reflect");
+        };
+        descriptor.shader_source = source.replace("vec4<f32>(0.0);", "vec4<f32>(0.0)");
+        let error = window.register_shader(descriptor).unwrap_err();
+        assert_eq!(error.line, Some(4));
+    }
 }
@@ -0,0 +1,213 @@
+// Pipeline drawing the 2D shapes of a frame over the scene
+
+use std::collections::HashMap;
+use std::ops::Range;
+use glam::Vec3;
+use wgpu::{
+    AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BlendState, ColorTargetState, ColorWrites, CompareFunction,
+    DepthStencilState, Device, FilterMode, FragmentState, MultisampleState, PipelineLayoutDescriptor, PrimitiveState,
+    Queue, RenderPass, RenderPipeline, RenderPipelineDescriptor, Sampler, SamplerBindingType, SamplerDescriptor,
+    ShaderModuleDescriptor, ShaderSource, ShaderStages, TextureFormat, TextureSampleType, TextureViewDimension,
+    VertexBufferLayout, VertexState, VertexStepMode,
+};
+
+use crate::diagnostics::label;
+use crate::draw_2d::{Batch2d, Vertex2d};
+use crate::instance_buffer::InstanceBuffer;
+use crate::texture::{GpuTexture, TextureHandle};
+
+const SHADER: &str = r#"
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+@group(0) @binding(0) var shape_texture: texture_2d<f32>;
+@group(0) @binding(1) var shape_sampler: sampler;
+
+@vertex
+fn vs_main(@location(0) position: vec2<f32>, @location(1) uv: vec2<f32>, @location(2) color: vec4<f32>) -> VertexOutput {
+    return VertexOutput(vec4<f32>(position, 0.0, 1.0), uv, color);
+}
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(shape_texture, shape_sampler, input.uv) * input.color;
+}
+"#;
+
+/// Draws the shapes collected by `Draw2d` in the frame's render pass
+pub(crate) struct Renderer2d {
+    pipeline: RenderPipeline,
+    bind_group_layout: BindGroupLayout,
+    sampler: Sampler,
+    /// Bind group of each texture drawn so far (textures are never replaced once uploaded)
+    bind_groups: HashMap<TextureHandle, BindGroup>,
+    /// Vertex buffer of the shapes, reused across frames
+    vertices: InstanceBuffer,
+    /// Vertex ranges of the current frame, one draw each
+    frame_draws: Vec<(TextureHandle, Range<u32>)>,
+}
+
+impl Renderer2d {
+    /// Create the pipeline for a color target format and the frame's depth buffer
+    pub fn new(device: &Device, format: TextureFormat) -> Self {
+        let module = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some(&label(&["2d", "module"])),
+            source: ShaderSource::Wgsl(SHADER.into()),
+        });
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some(&label(&["2d", "bind group layout"])),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some(&label(&["2d", "pipeline layout"])),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some(&label(&["2d", "pipeline"])),
+            layout: Some(&layout),
+            vertex: VertexState {
+                module: &module,
+                entry_point: Some("vs_main"),
+                buffers: &[VertexBufferLayout {
+                    array_stride: std::mem::size_of::<Vertex2d>() as u64,
+                    step_mode: VertexStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Float32x4],
+                }],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(FragmentState {
+                module: &module,
+                entry_point: Some("fs_main"),
+                targets: &[Some(ColorTargetState {
+                    format,
+                    blend: Some(BlendState::ALPHA_BLENDING),
+                    write_mask: ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            // Strokes and polylines may wind either way
+            primitive: PrimitiveState::default(),
+            // Shapes are drawn over the scene in the order they were recorded
+            depth_stencil: Some(DepthStencilState {
+                format: TextureFormat::Depth32Float,
+                depth_write_enabled: false,
+                depth_compare: CompareFunction::Always,
+                stencil: Default::default(),
+                bias: Default::default(),
+            }),
+            multisample: MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some(&label(&["2d", "sampler"])),
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            pipeline,
+            bind_group_layout,
+            sampler,
+            bind_groups: HashMap::new(),
+            vertices: InstanceBuffer::with_label(label(&["2d", "vertices"])),
+            frame_draws: Vec::new(),
+        }
+    }
+
+    /// Project the shapes of a frame to clip space and upload them
+    ///
+    /// Consecutive batches with the same texture become one draw. Batches whose
+    /// texture isn't uploaded are skipped. Returns the number of bytes written
+    /// and the skipped textures.
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        target_size: (u32, u32),
+        vertices: &[Vertex2d],
+        batches: &[Batch2d],
+        textures: &[GpuTexture],
+    ) -> (u64, Vec<TextureHandle>) {
+        let mut skipped = Vec::new();
+        let mut projected = Vec::with_capacity(vertices.len());
+        self.frame_draws.clear();
+        let mut start = 0;
+        for batch in batches {
+            let batch_vertices = &vertices[start..(start + batch.vertex_count as usize).min(vertices.len())];
+            start += batch_vertices.len();
+            let Some(view) = textures.get(batch.texture.0).and_then(GpuTexture::view) else {
+                skipped.push(batch.texture);
+                continue;
+            };
+            self.bind_groups.entry(batch.texture).or_insert_with(|| device.create_bind_group(&BindGroupDescriptor {
+                label: Some(&label(&["2d", "bind group", &batch.texture.0.to_string()])),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    BindGroupEntry { binding: 0, resource: BindingResource::TextureView(view) },
+                    BindGroupEntry { binding: 1, resource: BindingResource::Sampler(&self.sampler) },
+                ],
+            }));
+
+            let projection = batch.space.projection(target_size);
+            let first = projected.len() as u32;
+            projected.extend(batch_vertices.iter().map(|vertex| {
+                let [x, y] = vertex.position;
+                Vertex2d { position: projection.transform_point3(Vec3::new(x, y, 0.0)).truncate().to_array(), ..*vertex }
+            }));
+            let end = projected.len() as u32;
+            match self.frame_draws.last_mut() {
+                Some((texture, range)) if *texture == batch.texture && range.end == first => range.end = end,
+                _ => self.frame_draws.push((batch.texture, first..end)),
+            }
+        }
+
+        if projected.is_empty() {
+            self.frame_draws.clear();
+        }
+        self.vertices.clear();
+        self.vertices.push(bytemuck::cast_slice(&projected));
+        self.vertices.upload(device, queue);
+        (std::mem::size_of_val(projected.as_slice()) as u64, skipped)
+    }
+
+    /// Draw the prepared shapes, returning the number of draw calls
+    pub fn draw(&self, render_pass: &mut RenderPass<'_>) -> u32 {
+        let Some(buffer) = self.vertices.buffer().filter(|_| !self.frame_draws.is_empty()) else {
+            return 0;
+        };
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_vertex_buffer(0, buffer.slice(..));
+        for (texture, range) in &self.frame_draws {
+            render_pass.set_bind_group(0, &self.bind_groups[texture], &[]);
+            render_pass.draw(range.clone(), 0..1);
+        }
+        self.frame_draws.len() as u32
+    }
+}
@@ -1,5 +1,9 @@
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 use std::env;
+use std::sync::OnceLock;
+
+use rustica_wgsl_preprocessor::{PreprocessedWgsl, WgslPreprocessor};
+use crate::shader_error::ShaderError;
 
 /// Resource manager for finding assets like shader files
 pub struct ResourceManager {
@@ -11,54 +15,91 @@ impl ResourceManager {
     pub fn new() -> Self {
         let executable_path = env::current_exe().expect("Failed to get executable path");
         let executable_dir = executable_path.parent().expect("Failed to get executable directory").to_path_buf();
-        
+
         Self { executable_dir }
     }
-    
-    /// Find a shader file by name (without extension)
-    pub fn find_shader(&self, name: &str) -> Option<PathBuf> {
-        // List of possible locations to check, in order of priority
-        let locations = [
+
+    /// Get the directories searched for shader files, in order of priority
+    pub fn shader_search_paths(&self) -> Vec<PathBuf> {
+        vec![
             // 1. Check in 'shaders' directory next to the executable (release mode)
-            self.executable_dir.join("shaders").join(format!("{}.wgsl", name)),
-            
+            self.executable_dir.join("shaders"),
+
             // 2. Check in the workspace target directory (debug mode)
-            self.executable_dir.join("..").join("shaders").join(format!("{}.wgsl", name)),
-            
+            self.executable_dir.join("..").join("shaders"),
+
             // 3. Check in source (during development)
-            PathBuf::from("src").join("shaders").join(format!("{}.wgsl", name)),
-        ];
-        
+            PathBuf::from("src").join("shaders"),
+        ]
+    }
+
+    /// Find a shader file by name (without extension)
+    pub fn find_shader(&self, name: &str) -> Option<PathBuf> {
         // Try each location and return the first one that exists
-        for location in &locations {
-            if location.exists() {
-                return Some(location.clone());
-            }
-        }
-        
-        None
+        self.shader_search_paths().into_iter()
+            .map(|directory| directory.join(format!("{}.wgsl", name)))
+            .find(|location| location.exists())
+    }
+
+    /// Create a preprocessor that resolves `#include` in the shader search paths
+    pub fn preprocessor(&self) -> WgslPreprocessor {
+        self.shader_search_paths().into_iter()
+            .fold(WgslPreprocessor::new(), WgslPreprocessor::with_search_path)
     }
-    
-    /// Load a shader as a string by name
+
+    /// Load a shader by name and expand its `#include`, `#define` and `#ifdef` directives
+    ///
+    /// The defines select a variant of the shader. Put the returned source map
+    /// in the shader descriptor so errors point at the original files.
+    pub fn preprocess_shader(&self, name: &str, defines: &[(&str, &str)]) -> Result<PreprocessedWgsl, ShaderError> {
+        let path = self.find_shader(name)
+            .ok_or_else(|| ShaderError::new(name, None, format!("Shader file '{}' not found", name)))?;
+        defines.iter()
+            .fold(self.preprocessor(), |preprocessor, (name, value)| preprocessor.with_define(name, value))
+            .process_file(path)
+            .map_err(ShaderError::from)
+    }
+
+    /// Load a shader as a string by name, with its directives expanded
     pub fn load_shader(&self, name: &str) -> Result<String, String> {
-        match self.find_shader(name) {
-            Some(path) => std::fs::read_to_string(&path)
-                .map_err(|e| format!("Failed to read shader '{}' from {:?}: {}", name, path, e)),
-            None => Err(format!("Shader file '{}' not found", name)),
-        }
+        self.preprocess_shader(name, &[])
+            .map(|preprocessed| preprocessed.source)
+            .map_err(|e| format!("Failed to load shader '{}': {}", name, e))
+    }
+}
+
+impl Default for ResourceManager {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 /// Get the default resource manager (singleton)
 pub fn get_resource_manager() -> &'static ResourceManager {
-    static mut INSTANCE: Option<ResourceManager> = None;
-    static INIT: std::sync::Once = std::sync::Once::new();
-    
-    unsafe {
-        INIT.call_once(|| {
-            INSTANCE = Some(ResourceManager::new());
-        });
-        
-        INSTANCE.as_ref().unwrap()
+    static INSTANCE: OnceLock<ResourceManager> = OnceLock::new();
+    INSTANCE.get_or_init(ResourceManager::new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preprocess_shader_from_search_path() {
+        let manager = ResourceManager::new();
+        let directory = &manager.shader_search_paths()[0];
+        std::fs::create_dir_all(directory).unwrap();
+        let name = format!("resource_test_{}", std::process::id());
+        let path = directory.join(format!("{}.wgsl", name));
+        std::fs::write(&path, "#include \"lighting.wgsl\"\n#ifdef SHINY\nconst SHININESS: f32 = 32.0;\n#endif\n").unwrap();
+
+        let plain = manager.preprocess_shader(&name, &[]).unwrap();
+        let shiny = manager.preprocess_shader(&name, &[("SHINY", "")]).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(plain.source.contains("fn lambert(") && !plain.source.contains("SHININESS"));
+        assert!(shiny.source.contains("SHININESS"));
+        assert_eq!(shiny.source_map.files()[0], path.display().to_string());
+        assert!(manager.load_shader("no_such_shader").is_err());
     }
-}
\ No newline at end of file
+}
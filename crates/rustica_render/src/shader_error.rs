@@ -0,0 +1,159 @@
+// Errors from compiling shaders, with the location of the problem in the WGSL
+
+use std::fmt;
+use wgpu::naga;
+use wgpu::{Device, ErrorFilter};
+
+use rustica_wgsl_preprocessor::{PreprocessError, SourceMap};
+
+/// A shader that failed to compile or whose layout doesn't fit its WGSL
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShaderError {
+    /// Name of the shader
+    pub shader: String,
+    /// Path of the WGSL file, if the source was loaded from one
+    pub file: Option<String>,
+    /// 1-based line of the problem in the WGSL (None if it isn't tied to a line)
+    pub line: Option<u32>,
+    /// 1-based column of the problem in the WGSL
+    pub column: Option<u32>,
+    /// Description of the problem
+    pub message: String,
+}
+
+impl ShaderError {
+    /// Create an error that isn't tied to a location in the source
+    pub fn new(shader: &str, file: Option<&str>, message: impl Into<String>) -> Self {
+        Self {
+            shader: shader.to_string(),
+            file: file.map(str::to_string),
+            line: None,
+            column: None,
+            message: message.into(),
+        }
+    }
+
+    /// Parse and validate WGSL with naga, reporting the first problem with its location
+    pub fn check_wgsl(shader: &str, file: Option<&str>, source: &str) -> Result<(), ShaderError> {
+        let module = naga::front::wgsl::parse_str(source).map_err(|e| {
+            Self::new(shader, file, e.message()).at(e.location(source))
+        })?;
+
+        naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::all())
+            .validate(&module)
+            .map_err(|e| Self::new(shader, file, error_chain(e.as_inner())).at(e.location(source)))?;
+        Ok(())
+    }
+
+    /// Run `create` with a validation error scope, turning the first wgpu error into a `ShaderError`
+    ///
+    /// Without the scope, wgpu panics on invalid layouts and pipelines.
+    pub fn capture<T>(device: &Device, shader: &str, file: Option<&str>, create: impl FnOnce() -> T) -> Result<T, ShaderError> {
+        device.push_error_scope(ErrorFilter::Validation);
+        let value = create();
+        match pollster::block_on(device.pop_error_scope()) {
+            None => Ok(value),
+            Some(wgpu::Error::Validation { description, .. }) => Err(Self::new(shader, file, description.trim())),
+            Some(error) => Err(Self::new(shader, file, error.to_string())),
+        }
+    }
+
+    /// Point the error at the original file and line of preprocessed WGSL
+    pub fn remap(mut self, source_map: Option<&SourceMap>) -> Self {
+        if let (Some(source_map), Some(line)) = (source_map, self.line)
+            && let Some((file, original)) = source_map.locate(line)
+        {
+            self.file = Some(file.to_string());
+            self.line = Some(original);
+        }
+        self
+    }
+
+    /// Attach a location in the source
+    fn at(mut self, location: Option<naga::SourceLocation>) -> Self {
+        if let Some(location) = location {
+            self.line = Some(location.line_number);
+            self.column = Some(location.line_position);
+        }
+        self
+    }
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Shader '{}'", self.shader)?;
+        match (&self.file, self.line, self.column) {
+            (file, Some(line), Some(column)) => write!(f, " at {}:{}:{}", file.as_deref().unwrap_or("<source>"), line, column)?,
+            (file, Some(line), None) => write!(f, " at {}:{}", file.as_deref().unwrap_or("<source>"), line)?,
+            (Some(file), _, _) => write!(f, " ({})", file)?,
+            _ => {}
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
+impl From<PreprocessError> for ShaderError {
+    fn from(error: PreprocessError) -> Self {
+        Self {
+            shader: error.shader,
+            file: error.file,
+            line: error.line,
+            column: None,
+            message: error.message,
+        }
+    }
+}
+
+/// Join an error and its sources into one message
+fn error_chain(error: &dyn std::error::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(error) = source {
+        message.push_str(": ");
+        message.push_str(&error.to_string());
+        source = error.source();
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_error_has_line_and_column() {
+        let source = "@vertex\nfn vs_main() -> @builtin(position) vec4<f32> {\n    return vec4<f32>(1.0, 2.0 3.0, 1.0);\n}\n";
+        let error = ShaderError::check_wgsl("Broken", Some("broken.wgsl"), source).unwrap_err();
+        assert_eq!((error.line, error.column), (Some(3), Some(31)));
+        assert!(error.to_string().starts_with("Shader 'Broken' at broken.wgsl:3:31: expected"), "{}", error);
+    }
+
+    #[test]
+    fn test_validation_error_names_the_problem() {
+        let source = "@fragment\nfn fs_main() -> @location(0) vec4<f32> {\n    return 1.0;\n}\n";
+        let error = ShaderError::check_wgsl("Mistyped", None, source).unwrap_err();
+        assert!(error.message.contains("fs_main"), "{}", error);
+    }
+
+    #[test]
+    fn test_valid_wgsl_passes() {
+        let source = "@fragment\nfn fs_main() -> @location(0) vec4<f32> {\n    return vec4<f32>(1.0);\n}\n";
+        assert!(ShaderError::check_wgsl("Valid", None, source).is_ok());
+        assert_eq!(ShaderError::new("Valid", Some("valid.wgsl"), "oops").to_string(), "Shader 'Valid' (valid.wgsl): oops");
+    }
+
+    #[test]
+    fn test_wgsl_errors_point_at_included_file() {
+        let preprocessed = crate::WgslPreprocessor::new()
+            .embed("/shaders/main.wgsl", "#include \"helper.wgsl\"\nfn main() {}\n")
+            .embed("/shaders/helper.wgsl", "// Helper\nfn helper() -> f32 {\n    return 1.0\n}\n")
+            .process_file("/shaders/main.wgsl")
+            .unwrap();
+        let error = ShaderError::check_wgsl("Main", Some("/shaders/main.wgsl"), &preprocessed.source)
+            .unwrap_err()
+            .remap(Some(&preprocessed.source_map));
+        assert_eq!((error.file.as_deref(), error.line), (Some("/shaders/helper.wgsl"), Some(4)));
+    }
+}
@@ -0,0 +1,152 @@
+// Feature flags selecting variants of a shader
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// A feature a shader can be compiled with
+///
+/// A flag is either on or off and defines `NAME` when on. A feature with
+/// values always has one of them, the first by default, and defines
+/// `NAME_VALUE` for the selected value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShaderFeature {
+    /// Name of the feature, as used with `#ifdef`
+    pub name: String,
+    /// Values of the feature (empty for a flag)
+    pub values: Vec<String>,
+}
+
+impl ShaderFeature {
+    /// Create a flag that defines `name` when enabled
+    pub fn flag(name: &str) -> Self {
+        Self { name: name.to_string(), values: Vec::new() }
+    }
+
+    /// Create a feature with one of several values, the first being the default
+    pub fn choice(name: &str, values: &[&str]) -> Self {
+        Self { name: name.to_string(), values: values.iter().map(|value| value.to_string()).collect() }
+    }
+
+    /// Check if the feature is a flag rather than a choice of values
+    pub fn is_flag(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+/// The features selected for a draw, identifying one variant of a shader
+///
+/// Flags are stored with an empty value.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct FeatureSet(BTreeMap<String, String>);
+
+impl FeatureSet {
+    /// Create a set without any features selected
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enable a flag
+    pub fn with_flag(mut self, name: &str) -> Self {
+        self.enable(name);
+        self
+    }
+
+    /// Select the value of a feature
+    pub fn with_value(mut self, name: &str, value: &str) -> Self {
+        self.select(name, value);
+        self
+    }
+
+    /// Enable a flag
+    pub fn enable(&mut self, name: &str) {
+        self.0.insert(name.to_string(), String::new());
+    }
+
+    /// Select the value of a feature
+    pub fn select(&mut self, name: &str, value: &str) {
+        self.0.insert(name.to_string(), value.to_string());
+    }
+
+    /// Check if no features are selected
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Check the selection against the features of a shader and fill in default values
+    ///
+    /// Fails on unknown features, on values that aren't among the feature's
+    /// values, and on values given to flags.
+    pub fn resolve(&self, features: &[ShaderFeature]) -> Result<FeatureSet, String> {
+        if let Some(name) = self.0.keys().find(|name| !features.iter().any(|feature| &feature.name == *name)) {
+            return Err(format!("unknown feature '{}'", name));
+        }
+
+        let mut resolved = BTreeMap::new();
+        for feature in features {
+            match (self.0.get(&feature.name), feature.is_flag()) {
+                (None, true) => {}
+                (Some(value), true) if value.is_empty() => {
+                    resolved.insert(feature.name.clone(), String::new());
+                }
+                (Some(value), true) => {
+                    return Err(format!("feature '{}' is a flag and can't be set to '{}'", feature.name, value));
+                }
+                (value, false) => {
+                    let value = value.unwrap_or(&feature.values[0]);
+                    if !feature.values.contains(value) {
+                        return Err(format!(
+                            "feature '{}' can't be '{}' (expected one of {})",
+                            feature.name, value, feature.values.join(", ")
+                        ));
+                    }
+                    resolved.insert(feature.name.clone(), value.clone());
+                }
+            }
+        }
+        Ok(FeatureSet(resolved))
+    }
+
+    /// Get the names the preprocessor defines for the selected features
+    pub fn defines(&self) -> Vec<String> {
+        self.0.iter()
+            .map(|(name, value)| if value.is_empty() { name.clone() } else { format!("{}_{}", name, value) })
+            .collect()
+    }
+}
+
+impl fmt::Display for FeatureSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "default");
+        }
+        let features = self.0.iter()
+            .map(|(name, value)| if value.is_empty() { name.clone() } else { format!("{}={}", name, value) })
+            .collect::<Vec<_>>();
+        write!(f, "{}", features.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_fills_defaults_and_rejects_unknown_values() {
+        let features = [
+            ShaderFeature::flag("USE_TEXTURE"),
+            ShaderFeature::choice("LIGHTING", &["PHONG", "UNLIT"]),
+        ];
+
+        let default = FeatureSet::new().resolve(&features).unwrap();
+        assert_eq!(default.defines(), vec!["LIGHTING_PHONG"]);
+
+        let textured = FeatureSet::new().with_flag("USE_TEXTURE").with_value("LIGHTING", "UNLIT");
+        let resolved = textured.resolve(&features).unwrap();
+        assert_eq!(resolved.defines(), vec!["LIGHTING_UNLIT", "USE_TEXTURE"]);
+        assert_eq!(resolved.to_string(), "LIGHTING=UNLIT USE_TEXTURE");
+
+        assert!(FeatureSet::new().with_flag("INSTANCED").resolve(&features).is_err());
+        assert!(FeatureSet::new().with_value("LIGHTING", "TOON").resolve(&features).is_err());
+        assert!(FeatureSet::new().with_value("USE_TEXTURE", "YES").resolve(&features).is_err());
+    }
+}
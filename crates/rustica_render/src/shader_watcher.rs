@@ -0,0 +1,133 @@
+// Polling of shader source files for hot reloading
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::compute_shader::ComputeHandle;
+use rustica_wgsl_preprocessor::SourceMap;
+use crate::shader_error::ShaderError;
+
+/// A shader whose source files are watched
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchedShader {
+    /// A render shader, by ID
+    Render(usize),
+    /// A compute shader
+    Compute(ComputeHandle),
+}
+
+/// A watched file and its modification time when it was last checked
+struct WatchedFile {
+    shader: WatchedShader,
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+/// Checks the modification times of shader files at a fixed interval
+///
+/// Polling needs no platform file notification support and is cheap for the
+/// handful of files a scene uses.
+pub struct ShaderWatcher {
+    files: Vec<WatchedFile>,
+    interval: Duration,
+    last_poll: Option<Instant>,
+}
+
+impl ShaderWatcher {
+    /// Create a watcher that checks its files at most once per interval
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            files: Vec::new(),
+            interval,
+            last_poll: None,
+        }
+    }
+
+    /// Start watching the source file of a shader and every file it includes
+    ///
+    /// Only changes made after this call are reported.
+    pub fn watch(&mut self, shader: WatchedShader, path: Option<&str>, source_map: Option<&SourceMap>) {
+        let included = source_map.map_or(&[][..], SourceMap::files);
+        for path in path.into_iter().chain(included.iter().map(String::as_str)) {
+            let path = PathBuf::from(path);
+            if !self.files.iter().any(|file| file.shader == shader && file.path == path) {
+                let modified = Self::modified(&path);
+                self.files.push(WatchedFile { shader, path, modified });
+            }
+        }
+    }
+
+    /// Get every shader with a file that changed since the last poll
+    ///
+    /// Returns nothing until the interval has passed.
+    pub fn poll(&mut self, now: Instant) -> Vec<WatchedShader> {
+        if self.last_poll.is_some_and(|last| now.duration_since(last) < self.interval) {
+            return Vec::new();
+        }
+        self.last_poll = Some(now);
+
+        let mut changed = Vec::new();
+        for file in &mut self.files {
+            let modified = Self::modified(&file.path);
+            if modified.is_some() && modified != file.modified {
+                file.modified = modified;
+                if !changed.contains(&file.shader) {
+                    changed.push(file.shader);
+                }
+            }
+        }
+        changed
+    }
+
+    fn modified(path: &PathBuf) -> Option<SystemTime> {
+        fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+    }
+}
+
+/// Load the current source of a watched shader
+///
+/// Preprocessed shaders are expanded again, so changes to included files are
+/// picked up; other shaders are read from their source path.
+pub fn load_source(shader: &str, path: Option<&str>, source_map: Option<&SourceMap>) -> Result<(String, Option<SourceMap>), ShaderError> {
+    if let Some(result) = source_map.and_then(SourceMap::reprocess) {
+        return result
+            .map(|preprocessed| (preprocessed.source, Some(preprocessed.source_map)))
+            .map_err(ShaderError::from);
+    }
+
+    let path = path.ok_or_else(|| ShaderError::new(shader, None, "the shader wasn't loaded from a file"))?;
+    fs::read_to_string(path)
+        .map(|source| (source, None))
+        .map_err(|e| ShaderError::new(shader, Some(path), format!("failed to read the file: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    #[test]
+    fn test_poll_reports_changed_shaders_once() {
+        let path = std::env::temp_dir().join(format!("rustica_watch_{}.wgsl", std::process::id()));
+        fs::write(&path, "// first").unwrap();
+        let mut watcher = ShaderWatcher::new(Duration::from_secs(1));
+        watcher.watch(WatchedShader::Render(3), path.to_str(), None);
+
+        let start = Instant::now();
+        assert!(watcher.poll(start).is_empty());
+
+        // Set the time explicitly, since coarse file systems may not see the write
+        fs::write(&path, "// second").unwrap();
+        File::options().write(true).open(&path).unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(5)).unwrap();
+
+        // Within the interval nothing is checked
+        assert!(watcher.poll(start + Duration::from_millis(500)).is_empty());
+        assert_eq!(watcher.poll(start + Duration::from_secs(2)), vec![WatchedShader::Render(3)]);
+        assert!(watcher.poll(start + Duration::from_secs(4)).is_empty());
+        assert_eq!(load_source("Watched", path.to_str(), None).unwrap().0, "// second");
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,7 +0,0 @@
-// Shader module for the rendering system
-
-// This module has been simplified to remove built-in shaders.
-// All shaders are now created through the custom shader API with ShaderDescriptor.
-
-// No built-in shaders or rendering pipelines remain, as each prototype now
-// defines its own vertex types and shaders.
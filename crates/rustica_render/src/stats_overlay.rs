@@ -0,0 +1,179 @@
+// On-screen graph of recent frame times
+
+use std::collections::VecDeque;
+use wgpu::util::DeviceExt;
+use wgpu::{
+    BlendState, ColorTargetState, ColorWrites, CommandEncoder, Device, FragmentState, LoadOp, MultisampleState,
+    Operations, PipelineLayoutDescriptor, PrimitiveState, RenderPassColorAttachment, RenderPassDescriptor,
+    RenderPipeline, RenderPipelineDescriptor, ShaderModuleDescriptor, ShaderSource, StoreOp, TextureFormat,
+    TextureView, VertexBufferLayout, VertexState, VertexStepMode,
+};
+
+use crate::diagnostics::label;
+use crate::frame_stats::FrameStats;
+
+/// Number of frames shown in the graph
+const HISTORY: usize = 120;
+
+/// Width in pixels of each frame's bars
+const BAR_WIDTH: f32 = 2.0;
+
+/// Height in pixels of the graph
+const GRAPH_HEIGHT: f32 = 64.0;
+
+/// Distance in pixels from the top left corner of the target
+const MARGIN: f32 = 8.0;
+
+/// Frame time shown at full graph height, in milliseconds
+const SCALE_MS: f32 = 33.3;
+
+const BACKGROUND: [f32; 4] = [0.0, 0.0, 0.0, 0.6];
+const CPU_COLOR: [f32; 4] = [0.2, 0.8, 0.3, 0.9];
+const GPU_COLOR: [f32; 4] = [0.9, 0.5, 0.1, 0.9];
+const TARGET_LINE_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.5];
+
+const SHADER: &str = r#"
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+}
+
+@vertex
+fn vs_main(@location(0) position: vec2<f32>, @location(1) color: vec4<f32>) -> VertexOutput {
+    return VertexOutput(vec4<f32>(position, 0.0, 1.0), color);
+}
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    return input.color;
+}
+"#;
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct OverlayVertex {
+    position: [f32; 2],
+    color: [f32; 4],
+}
+
+/// Draws the CPU encode time (green) and GPU time (orange) of recent frames
+///
+/// Bars are scaled so the full height is 33 ms; the line marks 16.7 ms (60 fps).
+pub(crate) struct StatsOverlay {
+    pipeline: RenderPipeline,
+    /// CPU and GPU times of recent frames in milliseconds, oldest first
+    history: VecDeque<(f32, Option<f32>)>,
+}
+
+impl StatsOverlay {
+    /// Create the overlay's pipeline for a color target format
+    pub fn new(device: &Device, format: TextureFormat) -> Self {
+        let module = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some(&label(&["stats overlay", "module"])),
+            source: ShaderSource::Wgsl(SHADER.into()),
+        });
+        let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some(&label(&["stats overlay", "pipeline layout"])),
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some(&label(&["stats overlay", "pipeline"])),
+            layout: Some(&layout),
+            vertex: VertexState {
+                module: &module,
+                entry_point: Some("vs_main"),
+                buffers: &[VertexBufferLayout {
+                    array_stride: std::mem::size_of::<OverlayVertex>() as u64,
+                    step_mode: VertexStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x4],
+                }],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(FragmentState {
+                module: &module,
+                entry_point: Some("fs_main"),
+                targets: &[Some(ColorTargetState {
+                    format,
+                    blend: Some(BlendState::ALPHA_BLENDING),
+                    write_mask: ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        Self {
+            pipeline,
+            history: VecDeque::with_capacity(HISTORY),
+        }
+    }
+
+    /// Add the times of a finished frame to the graph
+    pub fn record(&mut self, stats: &FrameStats) {
+        if self.history.len() == HISTORY {
+            self.history.pop_front();
+        }
+        let milliseconds = |duration: std::time::Duration| duration.as_secs_f32() * 1000.0;
+        self.history.push_back((milliseconds(stats.encode_time), stats.gpu_time.map(milliseconds)));
+    }
+
+    /// Draw the graph over the contents of the target
+    pub fn draw(&self, device: &Device, encoder: &mut CommandEncoder, view: &TextureView, target_size: (u32, u32)) {
+        let vertices = self.vertices(target_size);
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&label(&["stats overlay", "vertices"])),
+            contents: bytemuck::cast_slice(&vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some(&label(&["stats overlay", "render pass"])),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: Operations { load: LoadOp::Load, store: StoreOp::Store },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+        render_pass.draw(0..vertices.len() as u32, 0..1);
+    }
+
+    /// Build the triangles of the background, the bars and the 60 fps line
+    fn vertices(&self, (width, height): (u32, u32)) -> Vec<OverlayVertex> {
+        let (width, height) = (width.max(1) as f32, height.max(1) as f32);
+        let mut vertices = Vec::new();
+        // Rectangles are given in pixels from the top left corner of the target
+        let mut rectangle = |left: f32, top: f32, right: f32, bottom: f32, color: [f32; 4]| {
+            let x = |pixels: f32| pixels / width * 2.0 - 1.0;
+            let y = |pixels: f32| 1.0 - pixels / height * 2.0;
+            let corners = [[x(left), y(top)], [x(left), y(bottom)], [x(right), y(bottom)], [x(right), y(top)]];
+            for index in [0, 1, 2, 0, 2, 3] {
+                vertices.push(OverlayVertex { position: corners[index], color });
+            }
+        };
+
+        let bottom = MARGIN + GRAPH_HEIGHT;
+        let bar_height = |milliseconds: f32| (milliseconds / SCALE_MS).min(1.0) * GRAPH_HEIGHT;
+        rectangle(MARGIN, MARGIN, MARGIN + HISTORY as f32 * BAR_WIDTH, bottom, BACKGROUND);
+        for (index, &(cpu, gpu)) in self.history.iter().enumerate() {
+            let left = MARGIN + index as f32 * BAR_WIDTH;
+            let half = BAR_WIDTH / 2.0;
+            rectangle(left, bottom - bar_height(cpu), left + half, bottom, CPU_COLOR);
+            if let Some(gpu) = gpu {
+                rectangle(left + half, bottom - bar_height(gpu), left + BAR_WIDTH, bottom, GPU_COLOR);
+            }
+        }
+        let target_line = bottom - bar_height(1000.0 / 60.0);
+        rectangle(MARGIN, target_line, MARGIN + HISTORY as f32 * BAR_WIDTH, target_line + 1.0, TARGET_LINE_COLOR);
+        vertices
+    }
+}
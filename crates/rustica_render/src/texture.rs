@@ -0,0 +1,262 @@
+// GPU textures, mip chains and sampler configuration
+
+use std::path::Path;
+use image::{imageops, RgbaImage};
+use wgpu::{
+    AddressMode, Device, Extent3d, FilterMode, Origin3d, Queue, SamplerDescriptor, TexelCopyBufferLayout,
+    TexelCopyTextureInfo, Texture, TextureAspect, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureUsages, TextureView, TextureViewDescriptor,
+};
+
+use crate::diagnostics::label;
+
+/// Handle to a texture that has been uploaded to the GPU
+///
+/// Returned by `RenderWindow::load_texture` and friends and passed to
+/// `InstancedShaderDrawBuilder::texture`. The texture stays alive for the
+/// lifetime of the window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextureHandle(pub(crate) usize);
+
+impl TextureHandle {
+    /// 1x1 opaque white texture bound to texture slots a draw doesn't set
+    pub const WHITE: TextureHandle = TextureHandle(0);
+
+    /// Get the index of the texture in the registry
+    pub fn id(&self) -> usize {
+        self.0
+    }
+}
+
+/// How a texture is uploaded
+#[derive(Debug, Clone, Copy)]
+pub struct TextureOptions {
+    /// Generate a full mip chain (down to 1x1) from the base image
+    pub generate_mipmaps: bool,
+    /// Treat the color data as sRGB (false for data textures like normal maps)
+    pub srgb: bool,
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        Self {
+            generate_mipmaps: true,
+            srgb: true,
+        }
+    }
+}
+
+/// Filtering and addressing of a sampler
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SamplerConfig {
+    /// Addressing of coordinates outside 0..1, for both u and v
+    pub address_mode: AddressMode,
+    /// Filter used when a texel covers more than one pixel
+    pub mag_filter: FilterMode,
+    /// Filter used when a pixel covers more than one texel
+    pub min_filter: FilterMode,
+    /// Filter used between mip levels
+    pub mipmap_filter: FilterMode,
+}
+
+impl Default for SamplerConfig {
+    fn default() -> Self {
+        Self {
+            address_mode: AddressMode::Repeat,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Linear,
+        }
+    }
+}
+
+impl SamplerConfig {
+    /// Nearest-neighbour sampling, e.g. for pixel art
+    pub fn nearest() -> Self {
+        Self {
+            mag_filter: FilterMode::Nearest,
+            min_filter: FilterMode::Nearest,
+            mipmap_filter: FilterMode::Nearest,
+            ..Default::default()
+        }
+    }
+
+    /// Get the wgpu sampler descriptor for this configuration
+    pub fn descriptor<'a>(&self, label: Option<&'a str>) -> SamplerDescriptor<'a> {
+        SamplerDescriptor {
+            label,
+            address_mode_u: self.address_mode,
+            address_mode_v: self.address_mode,
+            address_mode_w: self.address_mode,
+            mag_filter: self.mag_filter,
+            min_filter: self.min_filter,
+            mipmap_filter: self.mipmap_filter,
+            ..Default::default()
+        }
+    }
+}
+
+/// Decode a PNG or JPEG image from memory
+pub fn decode_image(bytes: &[u8]) -> Result<RgbaImage, String> {
+    image::load_from_memory(bytes)
+        .map(|image| image.to_rgba8())
+        .map_err(|e| format!("Failed to decode image: {}", e))
+}
+
+/// Load a PNG or JPEG image from a file
+pub fn load_image<P: AsRef<Path>>(path: P) -> Result<RgbaImage, String> {
+    let path = path.as_ref();
+    image::open(path)
+        .map(|image| image.to_rgba8())
+        .map_err(|e| format!("Failed to load image {:?}: {}", path, e))
+}
+
+/// Build the mip chain of an image, halving each level down to 1x1
+///
+/// The first level is the image itself.
+pub fn mip_chain(image: &RgbaImage) -> Vec<RgbaImage> {
+    let mut levels = vec![image.clone()];
+    while let Some(last) = levels.last() && (last.width() > 1 || last.height() > 1) {
+        let width = (last.width() / 2).max(1);
+        let height = (last.height() / 2).max(1);
+        levels.push(imageops::resize(last, width, height, imageops::FilterType::Triangle));
+    }
+    levels
+}
+
+/// A texture and its view that persist between frames
+pub struct GpuTexture {
+    /// Texture (None until the device is available)
+    texture: Option<Texture>,
+    /// View over all mip levels (None until the device is available)
+    view: Option<TextureView>,
+    /// Mip levels and sRGB flag (for deferred initialization)
+    pending: Option<(Vec<RgbaImage>, bool)>,
+}
+
+impl GpuTexture {
+    /// Create a new texture and upload its mip levels to the GPU
+    pub fn new(device: &Device, queue: &Queue, levels: &[RgbaImage], srgb: bool) -> Self {
+        let (width, height) = levels[0].dimensions();
+        let format = if srgb { TextureFormat::Rgba8UnormSrgb } else { TextureFormat::Rgba8Unorm };
+
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some(&label(&["texture", &format!("{}x{}", width, height)])),
+            size: Extent3d { width, height, depth_or_array_layers: 1 },
+            mip_level_count: levels.len() as u32,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        for (mip_level, level) in levels.iter().enumerate() {
+            queue.write_texture(
+                TexelCopyTextureInfo {
+                    texture: &texture,
+                    mip_level: mip_level as u32,
+                    origin: Origin3d::ZERO,
+                    aspect: TextureAspect::All,
+                },
+                level.as_raw(),
+                TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(4 * level.width()),
+                    rows_per_image: Some(level.height()),
+                },
+                Extent3d { width: level.width(), height: level.height(), depth_or_array_layers: 1 },
+            );
+        }
+
+        let view = texture.create_view(&TextureViewDescriptor::default());
+        Self {
+            texture: Some(texture),
+            view: Some(view),
+            pending: None,
+        }
+    }
+
+    /// Create a placeholder texture that will be uploaded once a device exists
+    pub fn new_placeholder(levels: Vec<RgbaImage>, srgb: bool) -> Self {
+        Self {
+            texture: None,
+            view: None,
+            pending: Some((levels, srgb)),
+        }
+    }
+
+    /// Prepare the mip levels of an image according to the upload options
+    pub fn levels(image: RgbaImage, options: &TextureOptions) -> Vec<RgbaImage> {
+        if options.generate_mipmaps {
+            mip_chain(&image)
+        } else {
+            vec![image]
+        }
+    }
+
+    /// Upload the pending data of a placeholder texture
+    pub fn initialize(&mut self, device: &Device, queue: &Queue) -> bool {
+        if self.is_initialized() {
+            return false; // Already uploaded
+        }
+
+        if let Some((levels, srgb)) = self.pending.take() {
+            *self = Self::new(device, queue, &levels, srgb);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Get the texture
+    pub fn texture(&self) -> Option<&Texture> {
+        self.texture.as_ref()
+    }
+
+    /// Get the view over all mip levels
+    pub fn view(&self) -> Option<&TextureView> {
+        self.view.as_ref()
+    }
+
+    /// Check if the texture has been uploaded to the GPU
+    pub fn is_initialized(&self) -> bool {
+        self.view.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageFormat, Rgba};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_mip_chain_halves_down_to_one_pixel() {
+        let image = RgbaImage::from_pixel(8, 2, Rgba([255, 0, 0, 255]));
+        let sizes = mip_chain(&image).iter().map(RgbaImage::dimensions).collect::<Vec<_>>();
+        assert_eq!(sizes, vec![(8, 2), (4, 1), (2, 1), (1, 1)]);
+    }
+
+    #[test]
+    fn test_decode_png_and_jpeg() {
+        let image = RgbaImage::from_pixel(4, 4, Rgba([200, 100, 50, 255]));
+        for format in [ImageFormat::Png, ImageFormat::Jpeg] {
+            let mut bytes = Cursor::new(Vec::new());
+            // JPEG has no alpha channel
+            image::DynamicImage::ImageRgba8(image.clone()).to_rgb8().write_to(&mut bytes, format).unwrap();
+
+            let decoded = decode_image(bytes.get_ref()).unwrap();
+            assert_eq!(decoded.dimensions(), (4, 4));
+            assert!(decoded.get_pixel(1, 1).0.iter().zip([200, 100, 50, 255]).all(|(a, b)| a.abs_diff(b) < 8));
+        }
+        assert!(decode_image(b"not an image").is_err());
+    }
+
+    #[test]
+    fn test_placeholder_texture_is_not_initialized() {
+        let levels = GpuTexture::levels(RgbaImage::new(4, 4), &TextureOptions::default());
+        assert_eq!(levels.len(), 3);
+        assert!(!GpuTexture::new_placeholder(levels, true).is_initialized());
+    }
+}
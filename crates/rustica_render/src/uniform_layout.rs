@@ -0,0 +1,259 @@
+// Packed uniform blocks laid out with WGSL uniform address-space rules
+
+use std::collections::HashSet;
+
+/// Type of a field in a packed uniform block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UniformType {
+    /// `f32`
+    F32,
+    /// `i32`
+    I32,
+    /// `u32`
+    U32,
+    /// `vec2<f32>`
+    Vec2,
+    /// `vec3<f32>`
+    Vec3,
+    /// `vec4<f32>`
+    Vec4,
+    /// `mat3x3<f32>` (columns padded to 16 bytes in the buffer)
+    Mat3,
+    /// `mat4x4<f32>`
+    Mat4,
+}
+
+impl UniformType {
+    /// Alignment of the type in the uniform address space
+    pub fn align(&self) -> u64 {
+        match self {
+            UniformType::F32 | UniformType::I32 | UniformType::U32 => 4,
+            UniformType::Vec2 => 8,
+            UniformType::Vec3 | UniformType::Vec4 | UniformType::Mat3 | UniformType::Mat4 => 16,
+        }
+    }
+
+    /// Size of the type in the uniform buffer, including column padding
+    pub fn size(&self) -> u64 {
+        match self {
+            UniformType::F32 | UniformType::I32 | UniformType::U32 => 4,
+            UniformType::Vec2 => 8,
+            UniformType::Vec3 => 12,
+            UniformType::Vec4 => 16,
+            UniformType::Mat3 => 48,
+            UniformType::Mat4 => 64,
+        }
+    }
+
+    /// Size of the tightly packed CPU value (e.g. `[[f32; 3]; 3]` for `Mat3`)
+    pub fn value_size(&self) -> u64 {
+        match self {
+            UniformType::Mat3 => 36,
+            _ => self.size(),
+        }
+    }
+
+    /// Get the WGSL name of the type
+    pub fn wgsl_name(&self) -> &'static str {
+        match self {
+            UniformType::F32 => "f32",
+            UniformType::I32 => "i32",
+            UniformType::U32 => "u32",
+            UniformType::Vec2 => "vec2<f32>",
+            UniformType::Vec3 => "vec3<f32>",
+            UniformType::Vec4 => "vec4<f32>",
+            UniformType::Mat3 => "mat3x3<f32>",
+            UniformType::Mat4 => "mat4x4<f32>",
+        }
+    }
+}
+
+/// A field of a packed uniform block
+#[derive(Debug, Clone)]
+pub struct UniformField {
+    /// Name of the field, as used with `InstancedShaderDrawBuilder::uniform`
+    pub name: String,
+    /// Type of the field
+    pub ty: UniformType,
+    /// Byte offset of the field within the block
+    pub offset: u64,
+}
+
+/// Several uniforms packed into one struct buffer at a single binding
+///
+/// Offsets follow the WGSL uniform address-space rules, so the block matches a
+/// WGSL declaration such as `var<uniform> globals: Globals` with the fields in
+/// the same order.
+#[derive(Debug, Clone)]
+pub struct UniformBlock {
+    /// Bind group index of the block
+    pub group: u32,
+    /// Binding index of the block within its group
+    pub binding: u32,
+    /// Fields in declaration order
+    pub fields: Vec<UniformField>,
+    /// Total size of the block, rounded up to the struct alignment
+    pub size: u64,
+}
+
+impl UniformBlock {
+    /// Lay out the given fields in order at the given binding of group 0
+    pub fn new<S: Into<String>>(binding: u32, fields: Vec<(S, UniformType)>) -> Self {
+        let mut offset = 0u64;
+        let mut struct_align = 16; // Uniform structs are at least 16-byte aligned
+        let fields = fields.into_iter().map(|(name, ty)| {
+            let field_offset = offset.next_multiple_of(ty.align());
+            offset = field_offset + ty.size();
+            struct_align = struct_align.max(ty.align());
+            UniformField {
+                name: name.into(),
+                ty,
+                offset: field_offset,
+            }
+        }).collect();
+
+        Self {
+            group: 0,
+            binding,
+            fields,
+            size: offset.next_multiple_of(struct_align),
+        }
+    }
+
+    /// Move the block to another bind group
+    pub fn with_group(mut self, group: u32) -> Self {
+        self.group = group;
+        self
+    }
+
+    /// Get a field by name
+    pub fn field(&self, name: &str) -> Option<&UniformField> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    /// Check that the block is non-empty, has unique names and a consistent layout
+    pub fn validate(&self) -> Result<(), String> {
+        if self.fields.is_empty() {
+            return Err(format!("Uniform block at group {} binding {} has no fields", self.group, self.binding));
+        }
+
+        let mut names = HashSet::new();
+        let mut end = 0;
+        for field in &self.fields {
+            if !names.insert(field.name.as_str()) {
+                return Err(format!("Uniform block field '{}' is declared more than once", field.name));
+            }
+            if !field.offset.is_multiple_of(field.ty.align()) || field.offset < end {
+                return Err(format!(
+                    "Uniform block field '{}' at offset {} violates the alignment of {} ({} bytes)",
+                    field.name, field.offset, field.ty.wgsl_name(), field.ty.align()
+                ));
+            }
+            end = field.offset + field.ty.size();
+        }
+
+        if self.size < end || !self.size.is_multiple_of(16) {
+            return Err(format!("Uniform block size {} must cover {} bytes and be a multiple of 16", self.size, end));
+        }
+        Ok(())
+    }
+
+    /// Pack the values of one draw into a block
+    ///
+    /// `lookup` returns the raw bytes of a field by name; fields it doesn't
+    /// know about are left zeroed.
+    pub fn pack<'a, F>(&self, lookup: F) -> Result<Vec<u8>, String>
+    where
+        F: Fn(&str) -> Option<&'a [u8]>,
+    {
+        let mut block = vec![0u8; self.size as usize];
+        for field in &self.fields {
+            let Some(value) = lookup(&field.name) else {
+                continue;
+            };
+
+            if value.len() as u64 != field.ty.value_size() {
+                return Err(format!(
+                    "Uniform '{}' is {} bytes, but the block declares it as {} ({} bytes)",
+                    field.name, value.len(), field.ty.wgsl_name(), field.ty.value_size()
+                ));
+            }
+
+            let offset = field.offset as usize;
+            if field.ty == UniformType::Mat3 {
+                // Each column of a mat3x3 is padded to a vec4
+                for (column, data) in value.chunks(12).enumerate() {
+                    let start = offset + column * 16;
+                    block[start..start + 12].copy_from_slice(data);
+                }
+            } else {
+                block[offset..offset + value.len()].copy_from_slice(value);
+            }
+        }
+        Ok(block)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_standard_layout() {
+        let block = UniformBlock::new(0, vec![
+            ("view", UniformType::Mat4),
+            ("projection", UniformType::Mat4),
+            ("time", UniformType::F32),
+        ]);
+
+        assert_eq!(block.field("projection").unwrap().offset, 64);
+        assert_eq!(block.field("time").unwrap().offset, 128);
+        assert_eq!(block.size, 144); // Rounded up to the 16-byte struct alignment
+        assert!(block.validate().is_ok());
+    }
+
+    #[test]
+    fn test_vec3_alignment() {
+        let block = UniformBlock::new(0, vec![
+            ("time", UniformType::F32),
+            ("light_dir", UniformType::Vec3),
+            ("intensity", UniformType::F32),
+            ("offset", UniformType::Vec2),
+            ("normal_matrix", UniformType::Mat3),
+        ]);
+
+        // vec3 aligns to 16, but a following scalar packs into its last 4 bytes
+        assert_eq!(block.field("light_dir").unwrap().offset, 16);
+        assert_eq!(block.field("intensity").unwrap().offset, 28);
+        assert_eq!(block.field("offset").unwrap().offset, 32);
+        assert_eq!(block.field("normal_matrix").unwrap().offset, 48);
+        assert_eq!(block.size, 96);
+    }
+
+    #[test]
+    fn test_validate_rejects_duplicates() {
+        let block = UniformBlock::new(0, vec![("time", UniformType::F32), ("time", UniformType::F32)]);
+        assert!(block.validate().unwrap_err().contains("more than once"));
+    }
+
+    #[test]
+    fn test_pack_pads_mat3_columns_and_checks_sizes() {
+        let block = UniformBlock::new(0, vec![("time", UniformType::F32), ("basis", UniformType::Mat3)]);
+        let basis: [[f32; 3]; 3] = [[1.0, 2.0, 3.0], [4.0, 5.0, 6.0], [7.0, 8.0, 9.0]];
+
+        let time = 0.5f32;
+        let packed = block.pack(|name| match name {
+            "time" => Some(bytemuck::bytes_of(&time)),
+            "basis" => Some(bytemuck::bytes_of(&basis)),
+            _ => None,
+        }).unwrap();
+        let floats: &[f32] = bytemuck::cast_slice(&packed);
+        assert_eq!(floats[0], 0.5);
+        assert_eq!(&floats[4..8], &[1.0, 2.0, 3.0, 0.0]);
+        assert_eq!(&floats[12..15], &[7.0, 8.0, 9.0]);
+
+        let wrong_time = [1.0f32, 2.0];
+        let error = block.pack(|name| (name == "time").then(|| bytemuck::bytes_of(&wrong_time))).unwrap_err();
+        assert!(error.contains("'time' is 8 bytes"));
+    }
+}
@@ -0,0 +1,114 @@
+// Per-frame ring of uniform blocks addressed with dynamic offsets
+
+use wgpu::{Buffer, BufferAddress, BufferDescriptor, BufferUsages, Device, Queue};
+
+use crate::diagnostics::label;
+use crate::instance_buffer::grown_capacity;
+
+/// Uniform data for every draw of a frame, packed into one GPU buffer
+///
+/// Each draw appends its uniform values as blocks aligned to the device's
+/// `min_uniform_buffer_offset_alignment`, and binds them with dynamic offsets.
+/// This way every draw reads its own values even though all draws of a frame
+/// are submitted in a single render pass.
+pub struct UniformRing {
+    /// GPU buffer (None until the first upload)
+    buffer: Option<Buffer>,
+    /// Size of the GPU buffer in bytes
+    capacity: BufferAddress,
+    /// Required alignment of each block
+    alignment: BufferAddress,
+    /// CPU-side data for the current frame
+    staging: Vec<u8>,
+}
+
+impl UniformRing {
+    /// Create an empty ring with blocks aligned to `alignment` bytes
+    pub fn new(alignment: BufferAddress) -> Self {
+        Self {
+            buffer: None,
+            capacity: 0,
+            alignment: alignment.max(1),
+            staging: Vec::new(),
+        }
+    }
+
+    /// Discard the data of the previous frame, keeping all allocations
+    pub fn clear(&mut self) {
+        self.staging.clear();
+    }
+
+    /// Append a block and return its dynamic offset
+    pub fn push(&mut self, data: &[u8]) -> u32 {
+        let offset = self.staging.len() as BufferAddress;
+        self.staging.extend_from_slice(data);
+
+        // Pad up to the next block boundary
+        let aligned = (self.staging.len() as BufferAddress).next_multiple_of(self.alignment);
+        self.staging.resize(aligned as usize, 0);
+
+        offset as u32
+    }
+
+    /// Make sure the GPU buffer exists and can hold `size` bytes
+    ///
+    /// Returns true if the buffer was (re)created, in which case bind groups
+    /// referencing the old buffer must be rebuilt.
+    pub fn reserve(&mut self, device: &Device, size: BufferAddress) -> bool {
+        if self.buffer.is_some() && size <= self.capacity {
+            return false;
+        }
+
+        self.capacity = grown_capacity(self.capacity, size);
+        self.buffer = Some(device.create_buffer(&BufferDescriptor {
+            label: Some(&label(&["frame", "uniform ring"])),
+            size: self.capacity,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        }));
+        true
+    }
+
+    /// Write the staged blocks to the GPU, growing the buffer if needed
+    ///
+    /// Returns true if the buffer was reallocated.
+    pub fn upload(&mut self, device: &Device, queue: &Queue) -> bool {
+        let grown = self.reserve(device, self.staging.len() as BufferAddress);
+        if let (Some(buffer), false) = (&self.buffer, self.staging.is_empty()) {
+            queue.write_buffer(buffer, 0, &self.staging);
+        }
+        grown
+    }
+
+    /// Get the number of bytes staged for the current frame
+    pub fn staged_bytes(&self) -> u64 {
+        self.staging.len() as u64
+    }
+
+    /// Get the GPU buffer
+    pub fn buffer(&self) -> Option<&Buffer> {
+        self.buffer.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocks_are_aligned() {
+        let mut ring = UniformRing::new(256);
+        assert_eq!(ring.push(&[0; 64]), 0);
+        assert_eq!(ring.push(&[0; 4]), 256);
+        assert_eq!(ring.push(&[0; 300]), 512);
+        assert_eq!(ring.push(&[0; 4]), 1024);
+    }
+
+    #[test]
+    fn test_clear_restarts_at_zero() {
+        let mut ring = UniformRing::new(256);
+        ring.push(&[0; 16]);
+        ring.clear();
+        assert_eq!(ring.push(&[0; 16]), 0);
+    }
+}
@@ -3,7 +3,7 @@ use quote::{format_ident, quote, ToTokens};
 use syn::{parse_macro_input, DeriveInput, Fields, Data, Meta, Lit, Expr, ExprLit, Path, Attribute, Field, Type};
 use syn::punctuated::Punctuated;
 use syn::token::Comma;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 // --- Helper Structs for Parsed Attributes ---
 
@@ -16,6 +16,14 @@ enum ShaderSource {
 #[derive(Debug, Default)]
 struct ShaderAttr {
     source: Option<ShaderSource>,
+    uniform_block: bool,
+    /// `PipelineState` field initializers from pipeline parameters
+    pipeline_fields: Vec<proc_macro2::TokenStream>,
+    pipeline_parameters: HashSet<String>,
+    depth_bias: Option<i32>,
+    depth_bias_slope_scale: Option<f32>,
+    /// `ShaderFeature` expressions from the `features` parameter
+    features: Vec<proc_macro2::TokenStream>,
 }
 
 #[derive(Debug, Default)]
@@ -27,18 +35,48 @@ struct VertexAttrParams {
 
 #[derive(Debug, Default)]
 struct UniformAttrParams {
+    group: Option<u32>,
     binding: Option<u32>,
 }
 
+#[derive(Debug, Default)]
+struct SamplerAttrParams {
+    group: Option<u32>,
+    binding: Option<u32>,
+    /// `SamplerConfig` field initializers
+    config_fields: Vec<proc_macro2::TokenStream>,
+}
+
+#[derive(Debug, Default)]
+struct StorageAttrParams {
+    group: Option<u32>,
+    binding: Option<u32>,
+    read_only: bool,
+}
+
 #[derive(Debug, Default)]
 struct InstanceAttrParams {
     location: Option<u32>,
+    format: Option<String>,
+}
+
+/// An `#[instance]` field and the shader locations it occupies
+struct InstanceField {
+    ident: syn::Ident,
+    ty: Type,
+    /// First location; matrices take one more per extra column
+    location: u32,
+    /// Vertex format and byte offset within the field of each location
+    formats: Vec<(proc_macro2::TokenStream, u64)>,
 }
 
 #[derive(Debug)]
 enum FieldAttr {
     Vertex(VertexAttrParams),
     Uniform(UniformAttrParams),
+    Texture(UniformAttrParams),
+    Sampler(SamplerAttrParams),
+    Storage(StorageAttrParams),
     Instance(InstanceAttrParams),
 }
 
@@ -70,7 +108,13 @@ fn parse_shader_attribute(attrs: &[Attribute]) -> Result<ShaderAttr, syn::Error>
                             return Err(syn::Error::new_spanned(nv.value, "Expected string literal for 'inline'"));
                         }
                     } else {
-                        return Err(syn::Error::new_spanned(nv.path, "Unknown parameter in #[shader] attribute"));
+                        parse_pipeline_parameter(&mut shader_attr, &nv)?;
+                    }
+                } else if let Meta::Path(path) = &meta {
+                    if path.is_ident("uniform_block") {
+                        shader_attr.uniform_block = true;
+                    } else {
+                        return Err(syn::Error::new_spanned(path, "Unknown flag in #[shader] attribute"));
                     }
                 } else {
                      return Err(syn::Error::new_spanned(meta, "Unsupported attribute format in #[shader]"));
@@ -85,6 +129,197 @@ fn parse_shader_attribute(attrs: &[Attribute]) -> Result<ShaderAttr, syn::Error>
     Ok(shader_attr)
 }
 
+/// Parse a `#[shader(...)]` parameter configuring the pipeline state
+fn parse_pipeline_parameter(shader_attr: &mut ShaderAttr, nv: &syn::MetaNameValue) -> Result<(), syn::Error> {
+    let Some(name) = nv.path.get_ident().map(|ident| ident.to_string()) else {
+        return Err(syn::Error::new_spanned(&nv.path, "Unknown parameter in #[shader] attribute"));
+    };
+    if shader_attr.pipeline_parameters.contains(&name) {
+        return Err(syn::Error::new_spanned(&nv.path, format!("Duplicate '{}' parameter in #[shader] attribute", name)));
+    }
+
+    // Map a string value onto one of the allowed variants
+    let choice = |options: &[(&str, proc_macro2::TokenStream)]| -> Result<proc_macro2::TokenStream, syn::Error> {
+        let allowed = options.iter().map(|(value, _)| format!("\"{}\"", value)).collect::<Vec<_>>().join(", ");
+        let Expr::Lit(ExprLit { lit: Lit::Str(s), .. }) = &nv.value else {
+            return Err(syn::Error::new_spanned(&nv.value, format!("Expected string literal for '{}' (one of {})", name, allowed)));
+        };
+        options.iter()
+            .find(|(value, _)| *value == s.value())
+            .map(|(_, tokens)| tokens.clone())
+            .ok_or_else(|| syn::Error::new_spanned(s, format!("Unknown {} '{}', expected one of {}", name, s.value(), allowed)))
+    };
+    let boolean = || match &nv.value {
+        Expr::Lit(ExprLit { lit: Lit::Bool(b), .. }) => Ok(b.value),
+        other => Err(syn::Error::new_spanned(other, format!("Expected boolean literal for '{}'", name))),
+    };
+    let string = || match &nv.value {
+        Expr::Lit(ExprLit { lit: Lit::Str(s), .. }) if !s.value().is_empty() => Ok(s.value()),
+        other => Err(syn::Error::new_spanned(other, format!("Expected non-empty string literal for '{}'", name))),
+    };
+
+    let field = match name.as_str() {
+        "topology" => {
+            let topology = choice(&[
+                ("triangle_list", quote! { TriangleList }),
+                ("triangle_strip", quote! { TriangleStrip }),
+                ("line_list", quote! { LineList }),
+                ("line_strip", quote! { LineStrip }),
+                ("point_list", quote! { PointList }),
+            ])?;
+            Some(quote! { topology: wgpu::PrimitiveTopology::#topology })
+        }
+        "cull_mode" => {
+            let cull_mode = choice(&[
+                ("back", quote! { Some(wgpu::Face::Back) }),
+                ("front", quote! { Some(wgpu::Face::Front) }),
+                ("none", quote! { None }),
+            ])?;
+            Some(quote! { cull_mode: #cull_mode })
+        }
+        "front_face" => {
+            let front_face = choice(&[("ccw", quote! { Ccw }), ("cw", quote! { Cw })])?;
+            Some(quote! { front_face: wgpu::FrontFace::#front_face })
+        }
+        "polygon_mode" => {
+            let polygon_mode = choice(&[
+                ("fill", quote! { Fill }),
+                ("line", quote! { Line }),
+                ("point", quote! { Point }),
+            ])?;
+            Some(quote! { polygon_mode: wgpu::PolygonMode::#polygon_mode })
+        }
+        "blend" => {
+            let blend = choice(&[
+                ("replace", quote! { Replace }),
+                ("alpha", quote! { Alpha }),
+                ("additive", quote! { Additive }),
+                ("premultiplied", quote! { Premultiplied }),
+            ])?;
+            Some(quote! { blend: rustica_render::BlendMode::#blend })
+        }
+        "depth_compare" => {
+            let compare = choice(&[
+                ("never", quote! { Never }),
+                ("less", quote! { Less }),
+                ("equal", quote! { Equal }),
+                ("less_equal", quote! { LessEqual }),
+                ("greater", quote! { Greater }),
+                ("not_equal", quote! { NotEqual }),
+                ("greater_equal", quote! { GreaterEqual }),
+                ("always", quote! { Always }),
+            ])?;
+            Some(quote! { depth_compare: wgpu::CompareFunction::#compare })
+        }
+        "depth_test" => {
+            let enabled = boolean()?;
+            Some(quote! { depth_test: #enabled })
+        }
+        "depth_write" => {
+            let enabled = boolean()?;
+            Some(quote! { depth_write: #enabled })
+        }
+        "vertex_entry" => {
+            let entry = string()?;
+            Some(quote! { vertex_entry: #entry.to_string() })
+        }
+        "fragment_entry" => {
+            let entry = string()?;
+            Some(quote! { fragment_entry: #entry.to_string() })
+        }
+        "depth_bias" => {
+            let (negative, lit) = signed_literal(&nv.value);
+            let Some(Lit::Int(int_lit)) = lit else {
+                return Err(syn::Error::new_spanned(&nv.value, "Expected integer literal for 'depth_bias'"));
+            };
+            let value = int_lit.base10_parse::<i32>()?;
+            shader_attr.depth_bias = Some(if negative { -value } else { value });
+            None
+        }
+        "depth_bias_slope_scale" => {
+            let (negative, value) = match signed_literal(&nv.value) {
+                (negative, Some(Lit::Float(lit))) => (negative, lit.base10_parse::<f32>()?),
+                (negative, Some(Lit::Int(lit))) => (negative, lit.base10_parse::<f32>()?),
+                _ => return Err(syn::Error::new_spanned(&nv.value, "Expected number for 'depth_bias_slope_scale'")),
+            };
+            shader_attr.depth_bias_slope_scale = Some(if negative { -value } else { value });
+            None
+        }
+        "features" => {
+            let Expr::Lit(ExprLit { lit: Lit::Str(s), .. }) = &nv.value else {
+                return Err(syn::Error::new_spanned(&nv.value, "Expected string literal for 'features'"));
+            };
+            shader_attr.features = parse_features(s)?;
+            None
+        }
+        _ => return Err(syn::Error::new_spanned(&nv.path, "Unknown parameter in #[shader] attribute")),
+    };
+
+    shader_attr.pipeline_fields.extend(field);
+    shader_attr.pipeline_parameters.insert(name);
+    Ok(())
+}
+
+/// Parse a feature list like `"USE_TEXTURE, LIGHTING=PHONG|UNLIT"` into `ShaderFeature` expressions
+///
+/// Plain names are flags; names with values default to the first value.
+fn parse_features(list: &syn::LitStr) -> Result<Vec<proc_macro2::TokenStream>, syn::Error> {
+    let is_identifier = |name: &str| {
+        name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+
+    let mut names = HashSet::new();
+    let mut features = Vec::new();
+    for entry in list.value().split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+        let (name, values) = match entry.split_once('=') {
+            Some((name, values)) => (name.trim(), values.split('|').map(str::trim).collect::<Vec<_>>()),
+            None => (entry, Vec::new()),
+        };
+        if !is_identifier(name) || !values.iter().all(|value| is_identifier(value)) {
+            return Err(syn::Error::new_spanned(list, format!("Invalid feature '{}', expected NAME or NAME=VALUE|VALUE", entry)));
+        }
+        if !names.insert(name.to_string()) {
+            return Err(syn::Error::new_spanned(list, format!("Duplicate feature '{}'", name)));
+        }
+        features.push(if values.is_empty() {
+            quote! { rustica_render::ShaderFeature::flag(#name) }
+        } else {
+            quote! { rustica_render::ShaderFeature::choice(#name, &[ #(#values),* ]) }
+        });
+    }
+    Ok(features)
+}
+
+/// Split an optionally negated literal expression into its sign and literal
+fn signed_literal(expr: &Expr) -> (bool, Option<&Lit>) {
+    match expr {
+        Expr::Lit(ExprLit { lit, .. }) => (false, Some(lit)),
+        Expr::Unary(syn::ExprUnary { op: syn::UnOp::Neg(_), expr, .. }) => match expr.as_ref() {
+            Expr::Lit(ExprLit { lit, .. }) => (true, Some(lit)),
+            _ => (true, None),
+        },
+        _ => (false, None),
+    }
+}
+
+/// Parse a `group` or `binding` parameter, returning false for other parameters
+fn parse_group_or_binding(nv: &syn::MetaNameValue, group: &mut Option<u32>, binding: &mut Option<u32>) -> Result<bool, syn::Error> {
+    let target = if nv.path.is_ident("group") {
+        group
+    } else if nv.path.is_ident("binding") {
+        binding
+    } else {
+        return Ok(false);
+    };
+
+    if let Expr::Lit(ExprLit { lit: Lit::Int(int_lit), .. }) = &nv.value {
+        *target = Some(int_lit.base10_parse::<u32>()?);
+        Ok(true)
+    } else {
+        Err(syn::Error::new_spanned(&nv.value, format!("Expected integer literal for '{}'", nv.path.to_token_stream())))
+    }
+}
 
 fn parse_field_attribute(field: &Field) -> Result<Option<FieldAttr>, syn::Error> {
     let mut field_attr_opt: Option<FieldAttr> = None;
@@ -130,11 +365,77 @@ fn parse_field_attribute(field: &Field) -> Result<Option<FieldAttr>, syn::Error>
                              if let Expr::Lit(ExprLit { lit: Lit::Int(int_lit), .. }) = &nv.value {
                                 params.binding = Some(int_lit.base10_parse::<u32>()?);
                             } else { return Err(syn::Error::new_spanned(nv.value, "Expected integer literal for 'binding'")); }
+                        } else if nv.path.is_ident("group") {
+                             if let Expr::Lit(ExprLit { lit: Lit::Int(int_lit), .. }) = &nv.value {
+                                params.group = Some(int_lit.base10_parse::<u32>()?);
+                            } else { return Err(syn::Error::new_spanned(nv.value, "Expected integer literal for 'group'")); }
                         } else { return Err(syn::Error::new_spanned(nv.path, "Unknown parameter in #[uniform] attribute")); }
                     } else { return Err(syn::Error::new_spanned(meta, "Unsupported attribute format in #[uniform]")); }
                 }
                 current_attr = Some(FieldAttr::Uniform(params));
             }
+            "texture" => {
+                let mut params = UniformAttrParams::default();
+                let args = attr.parse_args_with(Punctuated::<Meta, Comma>::parse_terminated).unwrap_or_default();
+                for meta in args {
+                    if let Meta::NameValue(nv) = meta {
+                        if !parse_group_or_binding(&nv, &mut params.group, &mut params.binding)? {
+                            return Err(syn::Error::new_spanned(nv.path, "Unknown parameter in #[texture] attribute"));
+                        }
+                    } else { return Err(syn::Error::new_spanned(meta, "Unsupported attribute format in #[texture]")); }
+                }
+                current_attr = Some(FieldAttr::Texture(params));
+            }
+            "sampler" => {
+                let mut params = SamplerAttrParams::default();
+                let args = attr.parse_args_with(Punctuated::<Meta, Comma>::parse_terminated).unwrap_or_default();
+                for meta in args {
+                    if let Meta::NameValue(nv) = meta {
+                        if parse_group_or_binding(&nv, &mut params.group, &mut params.binding)? {
+                            continue;
+                        }
+                        let Expr::Lit(ExprLit { lit: Lit::Str(value), .. }) = &nv.value else {
+                            return Err(syn::Error::new_spanned(nv.value, "Expected string literal for sampler parameter"));
+                        };
+                        if nv.path.is_ident("filter") {
+                            let filter = match value.value().as_str() {
+                                "linear" => quote! { Linear },
+                                "nearest" => quote! { Nearest },
+                                other => return Err(syn::Error::new_spanned(value, format!("Unknown filter '{}', expected \"linear\" or \"nearest\"", other))),
+                            };
+                            params.config_fields.push(quote! { mag_filter: wgpu::FilterMode::#filter });
+                            params.config_fields.push(quote! { min_filter: wgpu::FilterMode::#filter });
+                            params.config_fields.push(quote! { mipmap_filter: wgpu::FilterMode::#filter });
+                        } else if nv.path.is_ident("address_mode") {
+                            let address_mode = match value.value().as_str() {
+                                "repeat" => quote! { Repeat },
+                                "clamp_to_edge" => quote! { ClampToEdge },
+                                "mirror_repeat" => quote! { MirrorRepeat },
+                                other => return Err(syn::Error::new_spanned(value, format!("Unknown address mode '{}', expected \"repeat\", \"clamp_to_edge\" or \"mirror_repeat\"", other))),
+                            };
+                            params.config_fields.push(quote! { address_mode: wgpu::AddressMode::#address_mode });
+                        } else { return Err(syn::Error::new_spanned(nv.path, "Unknown parameter in #[sampler] attribute")); }
+                    } else { return Err(syn::Error::new_spanned(meta, "Unsupported attribute format in #[sampler]")); }
+                }
+                current_attr = Some(FieldAttr::Sampler(params));
+            }
+            "storage" => {
+                let mut params = StorageAttrParams::default();
+                let args = attr.parse_args_with(Punctuated::<Meta, Comma>::parse_terminated).unwrap_or_default();
+                for meta in args {
+                    match meta {
+                        Meta::NameValue(nv) => {
+                            if !parse_group_or_binding(&nv, &mut params.group, &mut params.binding)? {
+                                return Err(syn::Error::new_spanned(nv.path, "Unknown parameter in #[storage] attribute"));
+                            }
+                        }
+                        Meta::Path(path) if path.is_ident("read_only") => params.read_only = true,
+                        Meta::Path(path) if path.is_ident("read_write") => params.read_only = false,
+                        other => return Err(syn::Error::new_spanned(other, "Unsupported attribute format in #[storage], expected group, binding, read_only or read_write")),
+                    }
+                }
+                current_attr = Some(FieldAttr::Storage(params));
+            }
             "instance" => {
                  let mut params = InstanceAttrParams::default();
                  let args = attr.parse_args_with(Punctuated::<Meta, Comma>::parse_terminated).unwrap_or_default();
@@ -144,6 +445,10 @@ fn parse_field_attribute(field: &Field) -> Result<Option<FieldAttr>, syn::Error>
                              if let Expr::Lit(ExprLit { lit: Lit::Int(int_lit), .. }) = &nv.value {
                                 params.location = Some(int_lit.base10_parse::<u32>()?);
                             } else { return Err(syn::Error::new_spanned(nv.value, "Expected integer literal for 'location'")); }
+                        } else if nv.path.is_ident("format") {
+                            if let Expr::Lit(ExprLit { lit: Lit::Str(fmt), .. }) = &nv.value {
+                                params.format = Some(fmt.value());
+                            } else { return Err(syn::Error::new_spanned(nv.value, "Expected string literal for 'format'")); }
                         } else { return Err(syn::Error::new_spanned(nv.path, "Unknown parameter in #[instance] attribute")); }
                     } else { return Err(syn::Error::new_spanned(meta, "Unsupported attribute format in #[instance]")); }
                 }
@@ -155,7 +460,7 @@ fn parse_field_attribute(field: &Field) -> Result<Option<FieldAttr>, syn::Error>
         if let Some(parsed_attr) = current_attr {
             if field_attr_opt.is_some() {
                 // Found another relevant attribute on the same field
-                return Err(syn::Error::new_spanned(attr, "Field cannot have multiple #[vertex/uniform/instance] attributes"));
+                return Err(syn::Error::new_spanned(attr, "Field cannot have multiple #[vertex/uniform/texture/sampler/storage/instance] attributes"));
             }
             field_attr_opt = Some(parsed_attr);
         }
@@ -165,6 +470,148 @@ fn parse_field_attribute(field: &Field) -> Result<Option<FieldAttr>, syn::Error>
 }
 
 
+/// Resolve a shader file path the way `include_str!` does, relative to the file with the derive
+///
+/// The absolute path lets the renderer reload the file at runtime.
+fn resolve_source_path(path: &str) -> String {
+    proc_macro::Span::call_site().local_file()
+        .and_then(|file| Some(std::env::current_dir().ok()?.join(file.parent()?).join(path)))
+        .map(|path| path.display().to_string())
+        .unwrap_or_else(|| path.to_string())
+}
+
+/// Build the expression for the source of a WGSL file and its source map
+///
+/// Files with preprocessor directives are expanded here first, so a broken
+/// directive is a compile error pointing at its file and line. The descriptor
+/// expands them again from copies embedded with `include_str!`, which keeps the
+/// source map it needs for reloading; other includes come from the engine's
+/// shader library.
+fn file_source_expr(include_path: &str, path: &str) -> proc_macro2::TokenStream {
+    let Ok(source) = std::fs::read_to_string(path) else {
+        // include_str! reports the missing file
+        return quote! { (String::from(include_str!(#include_path)), None) };
+    };
+    if !source.lines().any(|line| line.trim_start().starts_with('#')) {
+        return quote! { (String::from(include_str!(#include_path)), None) };
+    }
+    if let Err(error) = rustica_wgsl_preprocessor::WgslPreprocessor::new().process_file(path) {
+        let message = error.to_string();
+        return quote! { compile_error!(#message) };
+    }
+
+    let mut includes = Vec::new();
+    let mut pending = vec![(std::path::PathBuf::from(path), source)];
+    while let Some((file, source)) = pending.pop() {
+        let included = source.lines()
+            .filter_map(|line| line.trim_start().strip_prefix("#include"))
+            .filter_map(|name| Some(file.parent()?.join(name.trim().trim_matches('"'))));
+        for included in included {
+            if let Ok(source) = std::fs::read_to_string(&included) {
+                let included = included.display().to_string();
+                if included != path && !includes.contains(&included) {
+                    includes.push(included.clone());
+                    pending.push((included.into(), source));
+                }
+            }
+        }
+    }
+
+    quote! {
+        {
+            let preprocessed = rustica_render::WgslPreprocessor::new()
+                .embed(#path, include_str!(#include_path))
+                #(.embed(#includes, include_str!(#includes)))*
+                .process_file(#path)
+                .expect("the shader was expanded when it was derived");
+            (preprocessed.source, Some(preprocessed.source_map))
+        }
+    }
+}
+
+/// Get the element type of a `#[storage]` field (`T` for `Vec<T>`, otherwise the field type)
+fn storage_element_type(ty: &Type) -> Type {
+    if let Type::Path(type_path) = ty
+        && let Some(segment) = type_path.path.segments.last()
+        && segment.ident == "Vec"
+        && let syn::PathArguments::AngleBracketed(args) = &segment.arguments
+        && let Some(syn::GenericArgument::Type(element)) = args.args.first()
+    {
+        return element.clone();
+    }
+    ty.clone()
+}
+
+/// Use the requested binding of a group, or auto-assign the next free one
+///
+/// Returns None if the requested binding is already taken.
+fn assign_binding(group: u32, requested: Option<u32>, used_bindings: &mut HashSet<(u32, u32)>, binding_counters: &mut HashMap<u32, u32>) -> Option<u32> {
+    if let Some(binding) = requested {
+        return used_bindings.insert((group, binding)).then_some(binding);
+    }
+
+    let binding_counter = binding_counters.entry(group).or_insert(0);
+    while used_bindings.contains(&(group, *binding_counter)) {
+        *binding_counter += 1;
+    }
+    let assigned = *binding_counter;
+    used_bindings.insert((group, assigned));
+    *binding_counter += 1; // Increment for the next potential auto-assignment
+    Some(assigned)
+}
+
+/// Infer the vertex formats an `#[instance]` field occupies, one per shader location.
+///
+/// Matrices span one location per column, so `[[f32; 4]; 4]` yields four `Float32x4`
+/// attributes. Each entry holds the format and its byte offset within the field.
+fn infer_instance_formats(ty: &Type, format: &Option<String>) -> Option<Vec<(proc_macro2::TokenStream, u64)>> {
+    if let Some(fmt_str) = format {
+        let format_ident = format_ident!("{}", fmt_str);
+        return Some(vec![(quote! { wgpu::VertexFormat::#format_ident }, 0)]);
+    }
+
+    let single = |format: proc_macro2::TokenStream| Some(vec![(format, 0)]);
+    let columns = |format: proc_macro2::TokenStream, count: u64, column_size: u64| {
+        Some((0..count).map(|i| (format.clone(), i * column_size)).collect())
+    };
+
+    // Strip whitespace so nested array types match regardless of token spacing
+    match ty.to_token_stream().to_string().replace(' ', "").as_str() {
+        "f32" => single(quote! { wgpu::VertexFormat::Float32 }),
+        "[f32;2]" => single(quote! { wgpu::VertexFormat::Float32x2 }),
+        "[f32;3]" => single(quote! { wgpu::VertexFormat::Float32x3 }),
+        "[f32;4]" => single(quote! { wgpu::VertexFormat::Float32x4 }),
+        "u32" => single(quote! { wgpu::VertexFormat::Uint32 }),
+        "[u32;2]" => single(quote! { wgpu::VertexFormat::Uint32x2 }),
+        "[u32;3]" => single(quote! { wgpu::VertexFormat::Uint32x3 }),
+        "[u32;4]" => single(quote! { wgpu::VertexFormat::Uint32x4 }),
+        "i32" => single(quote! { wgpu::VertexFormat::Sint32 }),
+        "[i32;2]" => single(quote! { wgpu::VertexFormat::Sint32x2 }),
+        "[i32;3]" => single(quote! { wgpu::VertexFormat::Sint32x3 }),
+        "[i32;4]" => single(quote! { wgpu::VertexFormat::Sint32x4 }),
+        "[[f32;2];2]" => columns(quote! { wgpu::VertexFormat::Float32x2 }, 2, 8),
+        "[[f32;3];3]" => columns(quote! { wgpu::VertexFormat::Float32x3 }, 3, 12),
+        "[[f32;4];4]" => columns(quote! { wgpu::VertexFormat::Float32x4 }, 4, 16),
+        _ => None,
+    }
+}
+
+/// Map a `#[uniform]` field type to its type in a packed uniform block.
+fn infer_uniform_type(ty: &Type) -> Option<proc_macro2::TokenStream> {
+    let uniform_type = match ty.to_token_stream().to_string().replace(' ', "").as_str() {
+        "f32" => quote! { F32 },
+        "i32" => quote! { I32 },
+        "u32" => quote! { U32 },
+        "[f32;2]" => quote! { Vec2 },
+        "[f32;3]" => quote! { Vec3 },
+        "[f32;4]" => quote! { Vec4 },
+        "[[f32;3];3]" => quote! { Mat3 },
+        "[[f32;4];4]" => quote! { Mat4 },
+        _ => return None,
+    };
+    Some(quote! { rustica_render::UniformType::#uniform_type })
+}
+
 /// `#[derive(ShaderProperties)]` macro splits one struct into multiple data blocks: Vertex, Uniforms, Instances
 ///
 /// This macro automatically:
@@ -175,10 +622,31 @@ fn parse_field_attribute(field: &Field) -> Result<Option<FieldAttr>, syn::Error>
 ///
 /// # Automatic Assignment
 /// - For `#[vertex]` and `#[instance]` fields without a `location`, a unique location is auto-assigned
-/// - For `#[uniform]` fields without a `binding`, a unique binding point is auto-assigned
+/// - For `#[uniform]`, `#[texture]`, `#[sampler]` and `#[storage]` fields without a `binding`, a unique binding point is auto-assigned within the field's `group` (default 0)
 /// - Auto-assignment skips over any explicitly assigned values to avoid conflicts
+/// - Matrix `#[instance]` fields occupy one location per column (`[[f32; 4]; 4]` uses four)
 /// - Compile-time errors are generated if duplicate bindings or locations are detected
 ///
+/// # Packed Uniforms
+/// With `#[shader(file = "...", uniform_block)]` all `#[uniform]` fields are packed,
+/// in declaration order, into a single struct at binding 0 using the WGSL uniform
+/// layout rules, matching a WGSL `var<uniform> globals: Globals` declaration.
+/// Fields can't set a `group` or `binding` in this mode.
+///
+/// # Textures and Samplers
+/// `#[texture]` fields declare a `texture_2d<f32>` binding, set per draw with
+/// `InstancedShaderDrawBuilder::texture` (a 1x1 white texture is bound otherwise).
+/// `#[sampler]` fields declare a filtering sampler owned by the shader, configured
+/// with `filter = "linear" | "nearest"` and
+/// `address_mode = "repeat" | "clamp_to_edge" | "mirror_repeat"`. The field types
+/// aren't used; `TextureHandle` and `SamplerConfig` read naturally.
+///
+/// # Storage Buffers
+/// `#[storage]` fields declare a runtime-sized `var<storage>` array, filled with
+/// `Canvas::update_storage`. Add `read_only` for `var<storage, read>`; writable
+/// buffers are only visible to the fragment stage. A `Vec<T>` field uses `T` as
+/// the element type.
+///
 /// # Example
 /// ```ignore
 /// #[derive(ShaderProperties)]
@@ -213,9 +681,10 @@ fn parse_field_attribute(field: &Field) -> Result<Option<FieldAttr>, syn::Error>
 /// // - MyShaderVertex { position: [f32; 3], normal: [f32; 3], color: [f32; 4] }
 /// // - MyShaderInstances { model_matrix: [[f32; 4]; 4] }
 /// // - MyShaderUniforms { model: [[f32; 4]; 4], view_proj: [[f32; 4]; 4] }
+/// // - Instance attributes at locations 3..=6 with offsets taken from MyShaderInstances
 /// // - MyShaderVertexFactory for creating vertices
 /// ```
-#[proc_macro_derive(ShaderProperties, attributes(shader, vertex, uniform, instance))]
+#[proc_macro_derive(ShaderProperties, attributes(shader, vertex, uniform, texture, sampler, storage, instance))]
 pub fn derive_shader_properties(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
     let name = &input.ident;
@@ -236,15 +705,24 @@ pub fn derive_shader_properties(input: TokenStream) -> TokenStream {
 
     // Prepare lists to hold categorized field information
     let mut vertex_fields: Vec<(syn::Ident, Type, u32, Option<String>, Option<Path>)> = Vec::new();
-    let mut uniform_fields: Vec<(syn::Ident, Type, u32)> = Vec::new();
-    let mut instance_fields: Vec<(syn::Ident, Type, u32)> = Vec::new();
+    let mut uniform_fields: Vec<(syn::Ident, Type, u32, u32)> = Vec::new();
+    let mut texture_fields: Vec<(syn::Ident, u32, u32)> = Vec::new();
+    let mut sampler_fields: Vec<(syn::Ident, u32, u32, Vec<proc_macro2::TokenStream>)> = Vec::new();
+    let mut storage_fields: Vec<(syn::Ident, Type, u32, u32, bool)> = Vec::new();
+    let mut instance_fields: Vec<InstanceField> = Vec::new();
 
-    // State for auto-assignment
-    let mut binding_counter: u32 = 0;
+    // State for auto-assignment (bindings are counted per group)
+    let mut binding_counters: HashMap<u32, u32> = HashMap::new();
     let mut location_counter: u32 = 0;
-    let mut used_bindings: HashSet<u32> = HashSet::new();
+    let mut used_bindings: HashSet<(u32, u32)> = HashSet::new();
     let mut used_locations: HashSet<u32> = HashSet::new();
 
+    // A packed uniform block occupies group 0 binding 0
+    let has_uniforms = fields.iter().any(|field| field.attrs.iter().any(|attr| attr.path().is_ident("uniform")));
+    if shader_attr.uniform_block && has_uniforms {
+        used_bindings.insert((0, 0));
+    }
+
     // Process each field
     for field in fields {
         let ident = field.ident.as_ref().expect("Expected named fields").clone();
@@ -280,44 +758,93 @@ pub fn derive_shader_properties(input: TokenStream) -> TokenStream {
                 vertex_fields.push((ident, ty, loc, params.format, params.semantic));
             }
             FieldAttr::Instance(params) => {
-                 let loc = if let Some(user_loc) = params.location {
-                    if !used_locations.insert(user_loc) {
-                        return syn::Error::new_spanned(
-                            &field.ident,
-                            format!("Duplicate location assignment: {}. Each vertex/instance attribute must have a unique location.", user_loc)
-                        ).to_compile_error().into();
+                let formats = match infer_instance_formats(&ty, &params.format) {
+                    Some(formats) => formats,
+                    None => return syn::Error::new_spanned(
+                        &ty,
+                        format!("Cannot infer VertexFormat for type '{}'. Please specify format explicitly using #[instance(format = \"...\")]", ty.to_token_stream())
+                    ).to_compile_error().into(),
+                };
+                // Matrices occupy one location per column
+                let span = formats.len() as u32;
+
+                let loc = if let Some(user_loc) = params.location {
+                    for slot in user_loc..user_loc + span {
+                        if !used_locations.insert(slot) {
+                            return syn::Error::new_spanned(
+                                &field.ident,
+                                format!("Duplicate location assignment: {}. Each vertex/instance attribute must have a unique location.", slot)
+                            ).to_compile_error().into();
+                        }
                     }
                     user_loc
                 } else {
-                    while used_locations.contains(&location_counter) {
+                    while (location_counter..location_counter + span).any(|slot| used_locations.contains(&slot)) {
                         location_counter += 1;
                     }
-                     let assigned_loc = location_counter;
-                    used_locations.insert(assigned_loc);
-                    location_counter += 1;
+                    let assigned_loc = location_counter;
+                    used_locations.extend(assigned_loc..assigned_loc + span);
+                    location_counter += span;
                     assigned_loc
                 };
-                instance_fields.push((ident, ty, loc));
+                instance_fields.push(InstanceField { ident, ty, location: loc, formats });
             }
             FieldAttr::Uniform(params) => {
-                let bind = if let Some(user_bind) = params.binding {
-                    if !used_bindings.insert(user_bind) {
+                if shader_attr.uniform_block {
+                    if params.binding.is_some() || params.group.is_some() {
                         return syn::Error::new_spanned(
                             &field.ident,
-                            format!("Duplicate binding assignment: {}. Each uniform must have a unique binding.", user_bind)
+                            "#[uniform] fields can't set a group or binding when uniforms are packed with #[shader(uniform_block)]"
                         ).to_compile_error().into();
                     }
-                    user_bind
-                } else {
-                    while used_bindings.contains(&binding_counter) {
-                        binding_counter += 1;
+                    if infer_uniform_type(&ty).is_none() {
+                        return syn::Error::new_spanned(
+                            &ty,
+                            format!("Type '{}' can't be packed into a uniform block. Supported types are f32, i32, u32, [f32; 2], [f32; 3], [f32; 4], [[f32; 3]; 3] and [[f32; 4]; 4]", ty.to_token_stream())
+                        ).to_compile_error().into();
                     }
-                    let assigned_bind = binding_counter;
-                    used_bindings.insert(assigned_bind);
-                    binding_counter += 1; // Increment for the next potential auto-assignment
-                    assigned_bind
+                    uniform_fields.push((ident, ty, 0, 0));
+                    continue;
+                }
+
+                let group = params.group.unwrap_or(0);
+                let Some(bind) = assign_binding(group, params.binding, &mut used_bindings, &mut binding_counters) else {
+                    return syn::Error::new_spanned(
+                        &field.ident,
+                        format!("Duplicate binding assignment: {}. Each uniform must have a unique binding.", params.binding.unwrap_or_default())
+                    ).to_compile_error().into();
                 };
-                uniform_fields.push((ident, ty, bind));
+                uniform_fields.push((ident, ty, group, bind));
+            }
+            FieldAttr::Texture(params) => {
+                let group = params.group.unwrap_or(0);
+                let Some(bind) = assign_binding(group, params.binding, &mut used_bindings, &mut binding_counters) else {
+                    return syn::Error::new_spanned(
+                        &field.ident,
+                        format!("Duplicate binding assignment: {}. Each texture must have a unique binding.", params.binding.unwrap_or_default())
+                    ).to_compile_error().into();
+                };
+                texture_fields.push((ident, group, bind));
+            }
+            FieldAttr::Sampler(params) => {
+                let group = params.group.unwrap_or(0);
+                let Some(bind) = assign_binding(group, params.binding, &mut used_bindings, &mut binding_counters) else {
+                    return syn::Error::new_spanned(
+                        &field.ident,
+                        format!("Duplicate binding assignment: {}. Each sampler must have a unique binding.", params.binding.unwrap_or_default())
+                    ).to_compile_error().into();
+                };
+                sampler_fields.push((ident, group, bind, params.config_fields));
+            }
+            FieldAttr::Storage(params) => {
+                let group = params.group.unwrap_or(0);
+                let Some(bind) = assign_binding(group, params.binding, &mut used_bindings, &mut binding_counters) else {
+                    return syn::Error::new_spanned(
+                        &field.ident,
+                        format!("Duplicate binding assignment: {}. Each storage buffer must have a unique binding.", params.binding.unwrap_or_default())
+                    ).to_compile_error().into();
+                };
+                storage_fields.push((ident, storage_element_type(&ty), group, bind, params.read_only));
             }
         }
     }
@@ -383,8 +910,28 @@ pub fn derive_shader_properties(input: TokenStream) -> TokenStream {
         }
     }).collect::<Vec<_>>();
 
-    // Create instance attribute definitions (similar logic, simpler as format/offset are often standard for matrices)
-    // TODO: Add instance attribute generation if needed, following vertex pattern but with VertexStepMode::Instance
+    // Create instance attribute definitions, one per shader location.
+    // Offsets come from the generated #[repr(C)] struct so padding is accounted for.
+    let instance_attribute_defs = instance_fields.iter().flat_map(|InstanceField { ident: field_ident, location: loc, formats, .. }| {
+        let instance_name = format_ident!("{}Instances", name);
+        formats.iter().enumerate().map(move |(column, (format_expr, column_offset))| {
+            let field_str = if formats.len() > 1 {
+                format!("{}[{}]", field_ident, column)
+            } else {
+                field_ident.to_string()
+            };
+            let location = loc + column as u32;
+            quote! {
+                rustica_foundation::geometry::VertexAttribute {
+                    name: #field_str.to_string(),
+                    location: #location,
+                    format: #format_expr,
+                    offset: (std::mem::offset_of!(#instance_name, #field_ident) as u64) + #column_offset,
+                    semantic: None,
+                }
+            }
+        })
+    }).collect::<Vec<_>>();
 
     // Now we need to properly store the semantic information from the attributes
     // Create a way to map our vertex fields to semantics for our custom VertexAttribute
@@ -545,7 +1092,7 @@ pub fn derive_shader_properties(input: TokenStream) -> TokenStream {
 
     // Create uniform struct definition
      let uniform_def = if !uniform_fields.is_empty() {
-        let uniform_struct_fields = uniform_fields.iter().map(|(ident, ty, _)| quote! { pub #ident: #ty });
+        let uniform_struct_fields = uniform_fields.iter().map(|(ident, ty, _, _)| quote! { pub #ident: #ty });
          quote! {
              #[repr(C)] // <-- ADDED THIS LINE
              #[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
@@ -564,14 +1111,22 @@ pub fn derive_shader_properties(input: TokenStream) -> TokenStream {
 
      // Create instance struct definition
      let instance_def = if !instance_fields.is_empty() {
-        let instance_struct_fields = instance_fields.iter().map(|(ident, ty, _)| quote! { pub #ident: #ty });
+        let instance_struct_fields = instance_fields.iter().map(|InstanceField { ident, ty, .. }| quote! { pub #ident: #ty });
          quote! {
              #[repr(C)] // <-- ADDED THIS LINE
              #[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
              pub struct #instance_name {
                  #(#instance_struct_fields,)*
              }
-             // TODO: Add Instance trait implementation similar to Vertex if needed
+
+             // Instance attributes laid out from the real struct, matrices split into columns
+             impl rustica_foundation::geometry::VertexAttributeProvider for #instance_name {
+                 fn attributes() -> Vec<rustica_foundation::geometry::VertexAttribute> {
+                     vec![
+                         #(#instance_attribute_defs),*
+                     ]
+                 }
+             }
          }
     } else {
          // Define an empty struct if no instance fields
@@ -579,7 +1134,12 @@ pub fn derive_shader_properties(input: TokenStream) -> TokenStream {
              #[repr(C)] // <-- ADDED THIS LINE
              #[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
              pub struct #instance_name;
-             // TODO: Add Instance trait implementation similar to Vertex if needed
+
+             impl rustica_foundation::geometry::VertexAttributeProvider for #instance_name {
+                 fn attributes() -> Vec<rustica_foundation::geometry::VertexAttribute> {
+                     Vec::new() // No attributes
+                 }
+             }
          }
     };
 
@@ -587,28 +1147,96 @@ pub fn derive_shader_properties(input: TokenStream) -> TokenStream {
     // --- Generate ShaderDescriptor and Associated Functions ---
 
     // Determine shader source code based on parsed attribute
-    let shader_source_expr = match shader_attr.source {
-        Some(ShaderSource::Inline(s)) => quote! { String::from(#s) },
-        Some(ShaderSource::File(p)) => quote! { String::from(include_str!(#p)) },
+    // The file path is kept so shader errors can point at it and the file can be watched
+    let (shader_source_expr, source_path_expr) = match shader_attr.source {
+        Some(ShaderSource::Inline(s)) => (quote! { (String::from(#s), None) }, quote! { None }),
+        Some(ShaderSource::File(p)) => {
+            let path = resolve_source_path(&p);
+            (file_source_expr(&p, &path), quote! { Some(String::from(#path)) })
+        }
         None => {
             // Default path if #[shader] attribute is missing or doesn't specify source
             // Consider making this an error if explicit source is desired.
             let default_path = format!("./src/shaders/{}.wgsl", name);
-            quote! { String::from(include_str!(#default_path)) }
+            let path = resolve_source_path(&default_path);
+            (file_source_expr(&default_path, &path), quote! { Some(String::from(#path)) })
         }
     };
 
-    // Generate uniform parameter descriptions
-    let uniform_param_exprs = uniform_fields.iter().map(|(ident, ty, binding)| {
-         quote! {
-             rustica_render::UniformParameter {
-                 name: stringify!(#ident).to_string(),
-                 binding: #binding,
-                 size: std::mem::size_of::<#ty>() as u64, // Consider alignment
+    // Generate uniform parameter descriptions, or a single packed block
+    let (uniform_param_exprs, uniform_block_expr) = if shader_attr.uniform_block && !uniform_fields.is_empty() {
+        let block_fields = uniform_fields.iter().map(|(ident, ty, _, _)| {
+            let uniform_type = infer_uniform_type(ty);
+            quote! { (stringify!(#ident), #uniform_type) }
+        });
+        (Vec::new(), quote! { Some(rustica_render::UniformBlock::new(0, vec![ #(#block_fields),* ])) })
+    } else {
+        let params = uniform_fields.iter().map(|(ident, ty, group, binding)| {
+             quote! {
+                 rustica_render::UniformParameter {
+                     name: stringify!(#ident).to_string(),
+                     group: #group,
+                     binding: #binding,
+                     size: std::mem::size_of::<#ty>() as u64, // Consider alignment
+                 }
              }
-         }
+        }).collect::<Vec<_>>();
+        (params, quote! { None })
+    };
+
+    // Texture and sampler parameter descriptions
+    let texture_param_exprs = texture_fields.iter().map(|(ident, group, binding)| {
+        quote! {
+            rustica_render::TextureParameter {
+                name: stringify!(#ident).to_string(),
+                group: #group,
+                binding: #binding,
+            }
+        }
+    });
+    let sampler_param_exprs = sampler_fields.iter().map(|(ident, group, binding, config_fields)| {
+        quote! {
+            rustica_render::SamplerParameter {
+                name: stringify!(#ident).to_string(),
+                group: #group,
+                binding: #binding,
+                config: rustica_render::SamplerConfig {
+                    #(#config_fields,)*
+                    ..Default::default()
+                },
+            }
+        }
+    });
+
+    let storage_param_exprs = storage_fields.iter().map(|(ident, element_ty, group, binding, read_only)| {
+        quote! {
+            rustica_render::StorageParameter {
+                name: stringify!(#ident).to_string(),
+                group: #group,
+                binding: #binding,
+                read_only: #read_only,
+                element_size: std::mem::size_of::<#element_ty>() as u64,
+            }
+        }
     });
 
+    // Pipeline state, with unconfigured settings left at their defaults
+    let mut pipeline_fields = shader_attr.pipeline_fields.clone();
+    if shader_attr.depth_bias.is_some() || shader_attr.depth_bias_slope_scale.is_some() {
+        let constant = shader_attr.depth_bias.unwrap_or(0);
+        let slope_scale = shader_attr.depth_bias_slope_scale.unwrap_or(0.0);
+        pipeline_fields.push(quote! {
+            depth_bias: wgpu::DepthBiasState { constant: #constant, slope_scale: #slope_scale, clamp: 0.0 }
+        });
+    }
+    let shader_features = &shader_attr.features;
+    let pipeline_state_expr = quote! {
+        rustica_render::PipelineState {
+            #(#pipeline_fields,)*
+            ..Default::default()
+        }
+    };
+
     // --- Final Assembly of Generated Code ---
 
     let expanded = quote! {
@@ -622,13 +1250,23 @@ pub fn derive_shader_properties(input: TokenStream) -> TokenStream {
          impl #name {
              /// Returns the shader descriptor containing metadata.
              pub fn descriptor() -> rustica_render::ShaderDescriptor {
+                 let (shader_source, source_map): (String, Option<rustica_render::SourceMap>) = #shader_source_expr;
                  rustica_render::ShaderDescriptor {
                      name: stringify!(#name).to_string(),
-                     shader_source: #shader_source_expr,
+                     shader_source,
+                     source_path: #source_path_expr,
+                     source_map,
+                     features: vec![ #(#shader_features),* ],
                      // Use the VertexAttributeProvider trait method to get attributes
                      vertex_attributes: <#vertex_name as rustica_foundation::geometry::VertexAttributeProvider>::attributes(),
                      uniforms: vec![ #(#uniform_param_exprs),* ],
-                     // TODO: Add instance attributes if implemented
+                     uniform_block: #uniform_block_expr,
+                     textures: vec![ #(#texture_param_exprs),* ],
+                     samplers: vec![ #(#sampler_param_exprs),* ],
+                     storage: vec![ #(#storage_param_exprs),* ],
+                     instance_attributes: <#instance_name as rustica_foundation::geometry::VertexAttributeProvider>::attributes(),
+                     instance_stride: std::mem::size_of::<#instance_name>() as u64,
+                     pipeline_state: #pipeline_state_expr,
                  }
              }
 
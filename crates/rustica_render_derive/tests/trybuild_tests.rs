@@ -29,6 +29,13 @@ fn test_duplicate_uniform_locations() {
     t.compile_fail("tests/ui/duplicate_binding_error.rs");
 }
 
+// Test for preprocessor errors in shader files
+#[test]
+fn test_shader_file_directive_errors() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/shader_file_directive_error.rs");
+}
+
 // Test for instance attribute layout
 #[test]
 fn test_instance_attributes() {
@@ -42,3 +49,38 @@ fn test_texture_bindings() {
     let t = trybuild::TestCases::new();
     t.pass("tests/ui/texture_binding_support.rs");
 }
+
+// Test for packed uniform block layout
+#[test]
+fn test_uniform_block_layout() {
+    let t = trybuild::TestCases::new();
+    t.pass("tests/ui/uniform_block_layout.rs");
+}
+
+// Test for uniform bind groups
+#[test]
+fn test_uniform_groups() {
+    let t = trybuild::TestCases::new();
+    t.pass("tests/ui/uniform_groups.rs");
+}
+
+// Test for pipeline state configuration
+#[test]
+fn test_pipeline_state() {
+    let t = trybuild::TestCases::new();
+    t.pass("tests/ui/pipeline_state_config.rs");
+}
+
+// Test for shader feature declarations
+#[test]
+fn test_shader_features() {
+    let t = trybuild::TestCases::new();
+    t.pass("tests/ui/shader_features.rs");
+}
+
+// Test for storage buffer bindings
+#[test]
+fn test_storage_bindings() {
+    let t = trybuild::TestCases::new();
+    t.pass("tests/ui/storage_binding_support.rs");
+}
@@ -51,6 +51,27 @@ fn main() {
     
     // Check that the descriptor properly captures the instance attributes
     let descriptor = InstanceAttributeShader::descriptor();
+    assert_eq!(descriptor.instance_stride, 80, "Instance stride should match the instance struct size");
+    assert_eq!(descriptor.instance_attributes.len(), 6, "Matrix columns should each get an attribute");
+    
+    // The matrix occupies locations 2..=5, one Float32x4 column each
+    for column in 0..4 {
+        let attribute = &descriptor.instance_attributes[column];
+        assert_eq!(attribute.location, 2 + column as u32);
+        assert_eq!(attribute.offset, 16 * column as u64);
+        assert_eq!(attribute.format, wgpu::VertexFormat::Float32x4);
+    }
+    
+    // Explicit locations are honored and offsets follow the Rust struct
+    let color = &descriptor.instance_attributes[4];
+    assert_eq!(color.location, 6);
+    assert_eq!(color.offset, 64);
+    assert_eq!(color.format, wgpu::VertexFormat::Float32x3);
+    
+    let id = &descriptor.instance_attributes[5];
+    assert_eq!(id.location, 7);
+    assert_eq!(id.offset, 76);
+    assert_eq!(id.format, wgpu::VertexFormat::Uint32);
     
     // Verify we can create a geometry builder for this shader type
     let builder = InstanceAttributeShader::geometry_builder();
@@ -0,0 +1,57 @@
+// Test for pipeline state configuration
+// This test verifies that #[shader(...)] parameters configure topology, culling,
+// blending, depth and entry points, and that unset ones keep their defaults
+
+use rustica_render_derive::ShaderProperties;
+use rustica_foundation::Vertex;
+use rustica_render::BlendMode;
+use wgpu;
+
+#[derive(ShaderProperties)]
+#[shader(
+    inline = "test shader for pipeline state",
+    topology = "line_strip",
+    cull_mode = "none",
+    front_face = "cw",
+    blend = "alpha",
+    depth_write = false,
+    depth_compare = "less_equal",
+    depth_bias = -2,
+    depth_bias_slope_scale = 1.5,
+    vertex_entry = "vertex_main",
+    fragment_entry = "fragment_main"
+)]
+struct LineShader {
+    #[vertex(location = 0)]
+    position: [f32; 3],
+}
+
+#[derive(ShaderProperties)]
+#[shader(inline = "test shader with default pipeline state")]
+struct DefaultShader {
+    #[vertex(location = 0)]
+    position: [f32; 3],
+}
+
+fn main() {
+    let state = LineShader::descriptor().pipeline_state;
+    assert_eq!(state.topology, wgpu::PrimitiveTopology::LineStrip);
+    assert_eq!(state.cull_mode, None);
+    assert_eq!(state.front_face, wgpu::FrontFace::Cw);
+    assert_eq!(state.polygon_mode, wgpu::PolygonMode::Fill);
+    assert_eq!(state.blend, BlendMode::Alpha);
+    assert!(state.depth_test);
+    assert!(!state.depth_write);
+    assert_eq!(state.depth_compare, wgpu::CompareFunction::LessEqual);
+    assert_eq!(state.depth_bias.constant, -2);
+    assert_eq!(state.depth_bias.slope_scale, 1.5);
+    assert_eq!(state.vertex_entry, "vertex_main");
+    assert_eq!(state.fragment_entry, "fragment_main");
+
+    let state = DefaultShader::descriptor().pipeline_state;
+    assert_eq!(state.topology, wgpu::PrimitiveTopology::TriangleList);
+    assert_eq!(state.cull_mode, Some(wgpu::Face::Back));
+    assert_eq!(state.blend, BlendMode::Replace);
+    assert_eq!(state.depth_compare, wgpu::CompareFunction::Less);
+    assert_eq!(state.vertex_entry, "vs_main");
+}
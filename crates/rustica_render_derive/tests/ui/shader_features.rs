@@ -0,0 +1,29 @@
+// Test for shader feature declarations
+// This test verifies that the `features` parameter declares flags and
+// features with values, the first value being the default
+
+use rustica_render_derive::ShaderProperties;
+use rustica_foundation::Vertex;
+use rustica_render::{FeatureSet, ShaderFeature};
+
+#[derive(ShaderProperties)]
+#[shader(
+    inline = "test shader with features",
+    features = "USE_TEXTURE, INSTANCED, LIGHTING = PHONG | UNLIT"
+)]
+struct FeatureShader {
+    #[vertex(location = 0)]
+    position: [f32; 3],
+}
+
+fn main() {
+    let features = FeatureShader::descriptor().features;
+    assert_eq!(features, vec![
+        ShaderFeature::flag("USE_TEXTURE"),
+        ShaderFeature::flag("INSTANCED"),
+        ShaderFeature::choice("LIGHTING", &["PHONG", "UNLIT"]),
+    ]);
+
+    let resolved = FeatureSet::new().with_flag("INSTANCED").resolve(&features).unwrap();
+    assert_eq!(resolved.defines(), vec!["INSTANCED", "LIGHTING_PHONG"]);
+}
@@ -0,0 +1,15 @@
+// Test for preprocessor errors in shader files
+// This test verifies that a broken directive in a #[shader(file = ...)] WGSL
+// fails the build with the file and line, instead of panicking at runtime
+
+use rustica_render_derive::ShaderProperties;
+use rustica_foundation::Vertex;
+
+#[derive(ShaderProperties)]
+#[shader(file = "shaders/unterminated_ifdef.wgsl")] // Error: #ifdef without #endif
+struct UnterminatedIfdefShader {
+    #[vertex(location = 0)]
+    position: [f32; 3],
+}
+
+fn main() {} // Required for trybuild
@@ -0,0 +1,6 @@
+// An #ifdef without its #endif
+#ifdef HIGHLIGHT
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return vec4<f32>(1.0, 1.0, 0.0, 1.0);
+}
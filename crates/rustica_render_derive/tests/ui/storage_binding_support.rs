@@ -3,8 +3,17 @@
 // storage bindings (SSBOs), which are important for compute shaders
 
 use rustica_render_derive::ShaderProperties;
+use rustica_foundation::Vertex;
+use rustica_render::TextureHandle;
 use wgpu;
 
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Particle {
+    position: [f32; 4],
+    velocity: [f32; 4],
+}
+
 #[derive(ShaderProperties)]
 #[shader(inline = "test shader for storage bindings")]
 struct StorageBindingShader {
@@ -19,69 +28,40 @@ struct StorageBindingShader {
     #[uniform(binding = 0)]
     view_proj: [[f32; 4]; 4],
     
-    // Storage buffer binding (simulated as u32 since we're just testing binding indices)
+    // Read-only storage buffer of particles
     #[storage(binding = 1, read_only)]
-    particles_in: u32,
+    particles_in: Vec<Particle>,
     
     // Output storage buffer (read_write)
     #[storage(binding = 2)]
-    particles_out: u32,
+    particles_out: Vec<Particle>,
     
     // Auto-assigned storage binding
-    #[storage]
+    #[storage(read_write)]
     indirect_args: u32,
     
     // Texture binding after storage bindings
-    #[uniform(binding = 4)]
-    color_texture: u32,
+    #[texture(binding = 4)]
+    color_texture: TextureHandle,
 }
 
 fn main() {
     // Get the shader descriptor
     let descriptor = StorageBindingShader::descriptor();
+    assert!(descriptor.validate().is_ok());
     
-    // Verify number of uniform and storage bindings
-    assert_eq!(descriptor.uniforms.len(), 5, "Should have 5 total bindings: 1 uniform, 3 storage, 1 texture");
-    
-    // Check binding indices
-    let mut found_view_proj = false;
-    let mut found_particles_in = false;
-    let mut found_particles_out = false;
-    let mut found_indirect_args = false;
-    let mut found_color_texture = false;
-    
-    for binding in &descriptor.uniforms {
-        match binding.name.as_str() {
-            "view_proj" => {
-                found_view_proj = true;
-                assert_eq!(binding.binding, 0, "view_proj should have binding 0");
-            },
-            "particles_in" => {
-                found_particles_in = true;
-                assert_eq!(binding.binding, 1, "particles_in should have binding 1");
-            },
-            "particles_out" => {
-                found_particles_out = true;
-                assert_eq!(binding.binding, 2, "particles_out should have binding 2");
-            },
-            "indirect_args" => {
-                found_indirect_args = true;
-                assert_eq!(binding.binding, 3, "indirect_args should have binding 3 (auto-assigned)");
-            },
-            "color_texture" => {
-                found_color_texture = true;
-                assert_eq!(binding.binding, 4, "color_texture should have binding 4");
-            },
-            _ => panic!("Unexpected binding name: {}", binding.name),
-        }
-    }
-    
-    // Verify all bindings were found
-    assert!(found_view_proj, "view_proj binding not found");
-    assert!(found_particles_in, "particles_in binding not found");
-    assert!(found_particles_out, "particles_out binding not found");
-    assert!(found_indirect_args, "indirect_args binding not found");
-    assert!(found_color_texture, "color_texture binding not found");
+    // Storage buffers and textures aren't uniforms
+    assert_eq!(descriptor.uniforms.len(), 1, "Should have 1 uniform");
+    assert_eq!(descriptor.uniforms[0].binding, 0, "view_proj should have binding 0");
+    assert_eq!(descriptor.textures[0].binding, 4, "color_texture should have binding 4");
     
-    println!("Storage binding test passed!");
+    // Check storage bindings, access and element sizes
+    let storage = descriptor.storage.iter()
+        .map(|storage| (storage.name.as_str(), storage.binding, storage.read_only, storage.element_size))
+        .collect::<Vec<_>>();
+    assert_eq!(storage, vec![
+        ("particles_in", 1, true, 32),
+        ("particles_out", 2, false, 32),
+        ("indirect_args", 3, false, 4), // Auto-assigned
+    ]);
 }
@@ -1,9 +1,10 @@
 // Test for texture binding support
-// This test verifies that texture samplers can be properly defined
-// in shader properties and are assigned correct bindings
+// This test verifies that textures and samplers can be defined in shader
+// properties and share auto-assigned bindings with uniforms
 
 use rustica_render_derive::ShaderProperties;
 use rustica_foundation::Vertex;
+use rustica_render::{SamplerConfig, TextureHandle};
 use wgpu;
 
 #[derive(ShaderProperties)]
@@ -20,63 +21,52 @@ struct TextureBindingShader {
     #[uniform(binding = 0)]
     view_projection: [[f32; 4]; 4],
     
-    // Texture sampler binding - should be treated as a regular uniform
-    // with appropriate binding number
-    #[uniform(binding = 1)]
-    texture: u32, // In WGSL, this would be a texture_2d<f32>
+    // Texture binding with an explicit binding number
+    #[texture(binding = 1)]
+    albedo: TextureHandle,
     
-    // Texture sampler binding - automatically assigned binding
-    #[uniform]
-    sampler: u32, // In WGSL, this would be a sampler
+    // Sampler binding - automatically assigned binding
+    #[sampler(filter = "nearest", address_mode = "clamp_to_edge")]
+    albedo_sampler: SamplerConfig,
     
     // Another uniform after textures
     #[uniform]
     color_tint: [f32; 4],
+
+    // Texture and sampler in a second group
+    #[texture(group = 1)]
+    detail: TextureHandle,
+
+    #[sampler(group = 1)]
+    detail_sampler: SamplerConfig,
 }
 
 fn main() {
     // Get the shader descriptor
     let descriptor = TextureBindingShader::descriptor();
+    assert!(descriptor.validate().is_ok());
     
-    // Verify number of uniforms
-    assert_eq!(descriptor.uniforms.len(), 4, "Should have 4 uniforms including textures and samplers");
-    
-    // Check uniform bindings
-    let mut found_view_proj = false;
-    let mut found_texture = false;
-    let mut found_sampler = false;
-    let mut found_color_tint = false;
-    
-    for uniform in &descriptor.uniforms {
-        match uniform.name.as_str() {
-            "view_projection" => {
-                found_view_proj = true;
-                assert_eq!(uniform.binding, 0, "view_projection should have binding 0");
-            },
-            "texture" => {
-                found_texture = true;
-                assert_eq!(uniform.binding, 1, "texture should have binding 1");
-            },
-            "sampler" => {
-                found_sampler = true;
-                assert_eq!(uniform.binding, 2, "sampler should have binding 2");
-            },
-            "color_tint" => {
-                found_color_tint = true;
-                assert_eq!(uniform.binding, 3, "color_tint should have binding 3");
-            },
-            _ => panic!("Unexpected uniform name: {}", uniform.name),
-        }
-    }
-    
-    // Verify all uniforms were found
-    assert!(found_view_proj, "view_projection uniform not found");
-    assert!(found_texture, "texture uniform not found");
-    assert!(found_sampler, "sampler uniform not found");
-    assert!(found_color_tint, "color_tint uniform not found");
+    // Textures and samplers aren't uniforms
+    assert_eq!(descriptor.uniforms.len(), 2, "Should have 2 uniforms");
+    assert_eq!(descriptor.uniforms[0].binding, 0, "view_projection should have binding 0");
+    assert_eq!(descriptor.uniforms[1].name, "color_tint");
+    assert_eq!(descriptor.uniforms[1].binding, 3, "color_tint should have binding 3");
     
-    // Verify automatic binding assignment worked as expected
-    // (sampler got binding 2, and color_tint got binding 3)
+    // Check texture bindings
+    assert_eq!(descriptor.textures.len(), 2);
+    assert_eq!(descriptor.textures[0].name, "albedo");
+    assert_eq!((descriptor.textures[0].group, descriptor.textures[0].binding), (0, 1));
+    assert_eq!(descriptor.textures[1].name, "detail");
+    assert_eq!((descriptor.textures[1].group, descriptor.textures[1].binding), (1, 0));
     
-    println!("Texture binding support test passed successfully!");
+    // Check sampler bindings and configuration
+    assert_eq!(descriptor.samplers.len(), 2);
+    assert_eq!(descriptor.samplers[0].name, "albedo_sampler");
+    assert_eq!((descriptor.samplers[0].group, descriptor.samplers[0].binding), (0, 2));
+    assert_eq!(descriptor.samplers[0].config, SamplerConfig {
+        address_mode: wgpu::AddressMode::ClampToEdge,
+        ..SamplerConfig::nearest()
+    });
+    assert_eq!((descriptor.samplers[1].group, descriptor.samplers[1].binding), (1, 1));
+    assert_eq!(descriptor.samplers[1].config, SamplerConfig::default());
 }
@@ -0,0 +1,63 @@
+// Test for packed uniform blocks
+// This test verifies that #[shader(uniform_block)] packs all uniforms into one
+// binding with WGSL uniform address-space offsets
+
+use rustica_render_derive::ShaderProperties;
+use rustica_foundation::Vertex;
+use rustica_render::UniformType;
+use wgpu;
+
+#[derive(ShaderProperties)]
+#[shader(inline = "test shader for uniform blocks", uniform_block)]
+struct UniformBlockShader {
+    #[vertex(location = 0)]
+    position: [f32; 3],
+
+    // Offset 0
+    #[uniform]
+    view_proj: [[f32; 4]; 4],
+
+    // Offset 64
+    #[uniform]
+    time: f32,
+
+    // vec3 is 16-byte aligned: offset 80
+    #[uniform]
+    light_dir: [f32; 3],
+
+    // Packs into the last 4 bytes of the vec3: offset 92
+    #[uniform]
+    intensity: f32,
+
+    // Offset 96
+    #[uniform]
+    normal_matrix: [[f32; 3]; 3],
+}
+
+fn main() {
+    let descriptor = UniformBlockShader::descriptor();
+
+    // No separate bindings are generated
+    assert!(descriptor.uniforms.is_empty());
+    assert!(descriptor.validate().is_ok());
+
+    let block = descriptor.uniform_block.expect("uniforms should be packed into a block");
+    assert_eq!(block.binding, 0);
+    assert_eq!(block.fields.len(), 5);
+
+    let expected = [
+        ("view_proj", UniformType::Mat4, 0),
+        ("time", UniformType::F32, 64),
+        ("light_dir", UniformType::Vec3, 80),
+        ("intensity", UniformType::F32, 92),
+        ("normal_matrix", UniformType::Mat3, 96),
+    ];
+    for (field, (name, ty, offset)) in block.fields.iter().zip(expected) {
+        assert_eq!(field.name, name);
+        assert_eq!(field.ty, ty, "{} has the wrong type", name);
+        assert_eq!(field.offset, offset, "{} has the wrong offset", name);
+    }
+
+    // mat3x3 occupies 48 bytes, and the struct rounds up to 16 bytes
+    assert_eq!(block.size, 144);
+}
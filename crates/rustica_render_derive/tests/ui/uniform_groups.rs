@@ -0,0 +1,46 @@
+// Test for uniform bind groups
+// This test verifies that uniforms carry their group and that bindings are
+// auto-assigned independently in each group
+
+use rustica_render_derive::ShaderProperties;
+use rustica_foundation::Vertex;
+use wgpu;
+
+#[derive(ShaderProperties)]
+#[shader(inline = "test shader for uniform groups")]
+struct UniformGroupsShader {
+    #[vertex(location = 0)]
+    position: [f32; 3],
+
+    // Per-frame camera data in group 0 (binding 0)
+    #[uniform]
+    view_proj: [[f32; 4]; 4],
+
+    // Per-object data in group 1 (binding 0, auto-assigned within the group)
+    #[uniform(group = 1)]
+    model: [[f32; 4]; 4],
+
+    // Light and material in group 2
+    #[uniform(group = 2, binding = 1)]
+    shininess: f32,
+
+    #[uniform(group = 2)]
+    light_color: [f32; 4],
+}
+
+fn main() {
+    let descriptor = UniformGroupsShader::descriptor();
+    assert!(descriptor.validate().is_ok());
+    assert_eq!(descriptor.uniforms.len(), 4);
+
+    for uniform in &descriptor.uniforms {
+        let expected = match uniform.name.as_str() {
+            "view_proj" => (0, 0),
+            "model" => (1, 0),
+            "shininess" => (2, 1),
+            "light_color" => (2, 0),
+            _ => panic!("Unexpected uniform name: {}", uniform.name),
+        };
+        assert_eq!((uniform.group, uniform.binding), expected, "{} has the wrong group or binding", uniform.name);
+    }
+}
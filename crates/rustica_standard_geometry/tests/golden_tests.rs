@@ -0,0 +1,112 @@
+// Golden-image regression tests for the standard shader and geometry
+//
+// Run with RUSTICA_BLESS=1 to regenerate the reference images in tests/golden.
+
+use glam::{Mat4, Vec3};
+use rustica_foundation::geometry::Geometry;
+use rustica_graphics::Camera;
+use rustica_render::golden::GoldenTest;
+use rustica_render::HeadlessRenderer;
+use rustica_standard_geometry::{create_utah_teapot, GeometryFactory};
+use rustica_standard_shader::{StandardShader, StandardShaderInstances, StandardShaderVertexFactory};
+
+const WIDTH: u32 = 128;
+const HEIGHT: u32 = 96;
+
+fn golden() -> GoldenTest {
+    GoldenTest::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden"))
+}
+
+/// Create a renderer, or None when the machine has no adapter at all
+fn renderer() -> Option<HeadlessRenderer> {
+    match HeadlessRenderer::new(WIDTH, HEIGHT) {
+        Ok(mut renderer) => {
+            renderer.set_clear_color(0.1, 0.1, 0.15, 1.0);
+            Some(renderer)
+        }
+        Err(e) => {
+            eprintln!("Skipping golden test: no adapter available ({})", e);
+            None
+        }
+    }
+}
+
+fn camera(position: Vec3) -> (Mat4, Mat4) {
+    let mut camera = Camera::perspective(WIDTH as f32 / HEIGHT as f32);
+    camera.look_at_from(position, Vec3::ZERO);
+    (camera.view_matrix(), camera.projection_matrix())
+}
+
+fn instance(model: Mat4, color: [f32; 3]) -> StandardShaderInstances {
+    StandardShaderInstances {
+        model_matrix: model.to_cols_array_2d(),
+        instance_color: color,
+    }
+}
+
+#[test]
+fn golden_standard_shader_triangle() {
+    let Some(mut renderer) = renderer() else { return };
+    let shader_id = renderer.register_shader(StandardShader::descriptor()).unwrap();
+    let (view, projection) = camera(Vec3::new(0.0, 0.0, 3.0));
+
+    let vertices = [
+        StandardShaderVertexFactory::create_vertex([0.0, 0.5, 0.0], [0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.5, 0.0]),
+        StandardShaderVertexFactory::create_vertex([-0.5, -0.5, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0], [0.0, 1.0]),
+        StandardShaderVertexFactory::create_vertex([0.5, -0.5, 0.0], [0.0, 0.0, 1.0], [0.0, 0.0, 1.0], [1.0, 1.0]),
+    ];
+    let geometry = Geometry { vertices: vertices.to_vec(), indices: vec![0, 1, 2] };
+
+    let image = renderer.render(|canvas| {
+        canvas.draw_with_instances(shader_id)
+            .uniform("view", view)
+            .uniform("projection", projection)
+            .uniform("time", 0.0f32)
+            .pump_geometry(&geometry, &[instance(Mat4::IDENTITY, [1.0, 1.0, 1.0])]);
+    });
+
+    golden().assert("standard_shader_triangle", &image);
+}
+
+#[test]
+fn golden_uv_sphere() {
+    let Some(mut renderer) = renderer() else { return };
+    let shader_id = renderer.register_shader(StandardShader::descriptor()).unwrap();
+    let sphere = renderer.upload_geometry(&GeometryFactory::uv_sphere(0.5, 32, 16, Vec3::ONE));
+    let (view, projection) = camera(Vec3::new(0.0, 1.0, 4.0));
+
+    let instances = [
+        instance(Mat4::from_translation(Vec3::new(-0.7, 0.0, 0.0)), [0.9, 0.3, 0.3]),
+        instance(Mat4::from_translation(Vec3::new(0.7, 0.0, 0.0)), [0.3, 0.4, 0.9]),
+    ];
+
+    let image = renderer.render(|canvas| {
+        canvas.draw_with_instances(shader_id)
+            .uniform("view", view)
+            .uniform("projection", projection)
+            .uniform("time", 0.0f32)
+            .draw_mesh(sphere, &instances);
+    });
+
+    golden().assert("uv_sphere", &image);
+}
+
+#[test]
+fn golden_utah_teapot() {
+    let Some(mut renderer) = renderer() else { return };
+    let shader_id = renderer.register_shader(StandardShader::descriptor()).unwrap();
+    let teapot = renderer.upload_geometry(&create_utah_teapot(8, 1.0, Vec3::ONE));
+    let (view, projection) = camera(Vec3::new(0.0, 3.0, 8.0));
+
+    let instances = [instance(Mat4::from_rotation_y(0.6), [0.8, 0.2, 0.2])];
+
+    let image = renderer.render(|canvas| {
+        canvas.draw_with_instances(shader_id)
+            .uniform("view", view)
+            .uniform("projection", projection)
+            .uniform("time", 0.0f32)
+            .draw_mesh(teapot, &instances);
+    });
+
+    golden().assert("utah_teapot", &image);
+}
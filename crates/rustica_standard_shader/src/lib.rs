@@ -3,14 +3,14 @@
 //! This crate provides a reusable standard shader implementation that supports
 //! common features like vertex attributes, instancing, lighting, and animations.
 
-use rustica_render::Vertex;
+use rustica_render::{SamplerConfig, TextureHandle, Vertex};
 use rustica_render_derive::ShaderProperties;
 use glam::{Mat4, Vec3};
 use bytemuck::{Pod, Zeroable};
 
 // Define our standard shader using the ShaderProperties derive macro
 #[derive(ShaderProperties)]
-#[shader(file = "shaders/standard_shader.wgsl")]
+#[shader(file = "shaders/standard_shader.wgsl", uniform_block)]
 pub struct StandardShader {
     // Vertex attributes
     #[vertex(location = 0)]
@@ -32,15 +32,22 @@ pub struct StandardShader {
     #[instance(location = 8)]
     pub instance_color: [f32; 3],
 
-    // Uniforms
-    #[uniform(binding = 0)]
+    // Uniforms, packed in this order into the `Globals` block at binding 0
+    #[uniform]
     pub view: [[f32; 4]; 4],
     
-    #[uniform(binding = 1)]
+    #[uniform]
     pub projection: [[f32; 4]; 4],
     
-    #[uniform(binding = 2)]
+    #[uniform]
     pub time: f32,
+
+    // Base color texture, modulating the vertex and instance colors (white unless set)
+    #[texture]
+    pub albedo: TextureHandle,
+
+    #[sampler]
+    pub albedo_sampler: SamplerConfig,
 }
 
 /// Create a prelude module to simplify imports
@@ -50,4 +57,28 @@ pub mod prelude {
         StandardShaderVertex,
         StandardShaderInstances,        
     };
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_descriptor_matches_wgsl() {
+        if let Err(report) = StandardShader::descriptor().check_source() {
+            panic!("{}", report);
+        }
+    }
+
+    #[test]
+    fn test_descriptor_points_at_wgsl_file() {
+        // The path is what hot reloading watches, so it must exist at runtime
+        let descriptor = StandardShader::descriptor();
+        let path = descriptor.source_path.expect("file shaders keep their path");
+        assert!(std::path::Path::new(&path).is_file(), "{}", path);
+        assert!(path.ends_with("standard_shader.wgsl"));
+
+        // The lighting code comes from the engine's shader library
+        let source_map = descriptor.source_map.expect("the shader is preprocessed");
+        assert!(source_map.files().iter().any(|file| file.ends_with("lighting.wgsl")));
+    }
+}
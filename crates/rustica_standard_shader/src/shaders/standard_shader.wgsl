@@ -1,5 +1,7 @@
 // Standard shader for the Rustica engine
 
+#include "lighting.wgsl"
+
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) normal: vec3<f32>,
@@ -15,17 +17,20 @@ struct InstanceInput {
     @location(8) instance_color: vec3<f32>,
 };
 
+struct Globals {
+    view: mat4x4<f32>,
+    projection: mat4x4<f32>,
+    time: f32,
+};
+
 @group(0) @binding(0)
-var<uniform> view: mat4x4<f32>;
+var<uniform> globals: Globals;
 
 @group(0) @binding(1)
-var<uniform> projection: mat4x4<f32>;
+var albedo: texture_2d<f32>;
 
 @group(0) @binding(2)
-var<uniform> time: f32;
-
-@group(0) @binding(3)
-var<uniform> _padding: vec3<f32>;
+var albedo_sampler: sampler;
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
@@ -54,8 +59,8 @@ fn vs_main(
     // Calculate world position
     let world_position = model * vec4<f32>(vertex.position, 1.0);
     
-    // Calculate final position - Updated to use separate uniform variables
-    output.clip_position = projection * view * world_position;
+    // Calculate final position
+    output.clip_position = globals.projection * globals.view * world_position;
     
     // Transform normal to world space
     output.world_normal = normalize((model * vec4<f32>(vertex.normal, 0.0)).xyz);
@@ -76,17 +81,18 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     let normal = normalize(in.world_normal);
     
     // Calculate diffuse component
-    let diffuse = max(dot(normal, light_dir), 0.0);
+    let diffuse = lambert(normal, light_dir);
     
     // Calculate ambient component
     let ambient = 0.2;
     
     // Combine lighting with vertex color and instance color
     let lighting = ambient + diffuse;
-    let combined_color = in.color * in.instance_color;
+    let albedo_color = textureSample(albedo, albedo_sampler, in.uv).rgb;
+    let combined_color = in.color * in.instance_color * albedo_color;
     
-    // Add a simple animation effect based on time - Updated to use direct time variable
-    let time_effect = sin(time + in.world_position.x + in.world_position.z) * 0.1 + 0.9;
+    // Add a simple animation effect based on time
+    let time_effect = sin(globals.time + in.world_position.x + in.world_position.z) * 0.1 + 0.9;
     
     return vec4<f32>(combined_color * lighting * time_effect, 1.0);
 }
@@ -0,0 +1,42 @@
+// Errors from expanding the directives of WGSL sources
+
+use std::fmt;
+
+/// A directive that couldn't be expanded, or a file that couldn't be read
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreprocessError {
+    /// Name of the shader
+    pub shader: String,
+    /// Path of the file with the problem
+    pub file: Option<String>,
+    /// 1-based line of the directive (None if it isn't tied to a line)
+    pub line: Option<u32>,
+    /// Description of the problem
+    pub message: String,
+}
+
+impl PreprocessError {
+    /// Create an error that isn't tied to a line
+    pub fn new(shader: &str, file: Option<&str>, message: impl Into<String>) -> Self {
+        Self {
+            shader: shader.to_string(),
+            file: file.map(str::to_string),
+            line: None,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for PreprocessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Shader '{}'", self.shader)?;
+        match (&self.file, self.line) {
+            (file, Some(line)) => write!(f, " at {}:{}", file.as_deref().unwrap_or("<source>"), line)?,
+            (Some(file), None) => write!(f, " ({})", file)?,
+            _ => {}
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl std::error::Error for PreprocessError {}
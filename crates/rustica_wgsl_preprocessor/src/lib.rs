@@ -0,0 +1,13 @@
+// WGSL preprocessor for Rustica engine
+//
+// Kept free of dependencies so build scripts can expand shaders without
+// building the renderer.
+
+// Re-export public API
+pub use error::PreprocessError;
+pub use preprocessor::{PreprocessedWgsl, SourceMap, WgslPreprocessor};
+
+// Internal modules
+mod error;
+mod preprocessor;
+mod shaders; // Library of WGSL snippets for #include
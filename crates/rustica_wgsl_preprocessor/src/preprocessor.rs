@@ -0,0 +1,329 @@
+// WGSL preprocessing: #include, #define and #ifdef, with a source map back to the original files
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::error::PreprocessError;
+use crate::shaders::LIBRARY;
+
+/// Expands the directives of WGSL sources
+///
+/// Supported directives, each on its own line:
+/// - `#include "file.wgsl"` inserts a file once, found next to the including
+///   file, then in the search paths, then in the engine's shader library
+/// - `#define NAME [value]` and `#undef NAME`; defined values replace the
+///   identifier in the code that follows
+/// - `#ifdef NAME`, `#ifndef NAME`, `#else` and `#endif`, which can be nested
+#[derive(Debug, Clone, Default)]
+pub struct WgslPreprocessor {
+    search_paths: Vec<PathBuf>,
+    defines: BTreeMap<String, String>,
+    embedded: HashMap<PathBuf, String>,
+}
+
+/// WGSL with its directives expanded
+#[derive(Debug, Clone)]
+pub struct PreprocessedWgsl {
+    /// The expanded source
+    pub source: String,
+    /// Where each line of the expanded source came from
+    pub source_map: SourceMap,
+}
+
+/// Maps lines of expanded WGSL to the file and line they came from
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    files: Vec<String>,
+    /// File index and 1-based line of every expanded line
+    lines: Vec<(usize, u32)>,
+    /// Preprocessor and root file, to expand the source again on reload
+    origin: Option<(WgslPreprocessor, PathBuf)>,
+}
+
+impl SourceMap {
+    /// Get the file and line a 1-based line of the expanded source came from
+    pub fn locate(&self, line: u32) -> Option<(&str, u32)> {
+        let &(file, line) = self.lines.get((line as usize).checked_sub(1)?)?;
+        Some((&self.files[file], line))
+    }
+
+    /// Get every file that contributed to the source, the root file first
+    pub fn files(&self) -> &[String] {
+        &self.files
+    }
+
+    /// Expand the root file again from disk, picking up changes to any of its files
+    ///
+    /// Returns None if the source wasn't expanded from a file.
+    pub fn reprocess(&self) -> Option<Result<PreprocessedWgsl, PreprocessError>> {
+        let (preprocessor, root) = self.origin.as_ref()?;
+        // Embedded copies would hide the changes
+        let preprocessor = WgslPreprocessor { embedded: HashMap::new(), ..preprocessor.clone() };
+        Some(preprocessor.process_file(root))
+    }
+
+    /// Expand the root file again with additional names defined
+    ///
+    /// Used to build variants of a shader. Returns None if the source wasn't
+    /// expanded from a file.
+    pub fn reprocess_with(&self, defines: &[String]) -> Option<Result<PreprocessedWgsl, PreprocessError>> {
+        let (preprocessor, root) = self.origin.as_ref()?;
+        let preprocessor = defines.iter()
+            .fold(preprocessor.clone(), |preprocessor, name| preprocessor.with_define(name, ""));
+        Some(preprocessor.process_file(root))
+    }
+}
+
+impl WgslPreprocessor {
+    /// Create a preprocessor without search paths or defines
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a directory to look for included files in
+    pub fn with_search_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.search_paths.push(path.into());
+        self
+    }
+
+    /// Define a name (with an optional value) before the source is expanded
+    pub fn with_define(mut self, name: &str, value: &str) -> Self {
+        self.defines.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Provide the contents of a file without reading it from disk
+    ///
+    /// Used for shaders baked into the binary with `include_str!`.
+    pub fn embed(mut self, path: impl AsRef<Path>, source: &str) -> Self {
+        self.embedded.insert(normalize(path.as_ref()), source.to_string());
+        self
+    }
+
+    /// Expand a WGSL file
+    pub fn process_file(&self, path: impl AsRef<Path>) -> Result<PreprocessedWgsl, PreprocessError> {
+        let path = normalize(path.as_ref());
+        let shader = path.file_stem().map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+        let source = self.read(&path).ok_or_else(|| {
+            PreprocessError::new(&shader, Some(&path.display().to_string()), "the file can't be read")
+        })?;
+
+        let mut preprocessed = self.process(&shader, &path, &source)?;
+        preprocessed.source_map.origin = Some((self.clone(), path));
+        Ok(preprocessed)
+    }
+
+    /// Expand WGSL that was loaded from `path` (used to resolve relative includes)
+    pub fn process_source(&self, shader: &str, path: impl AsRef<Path>, source: &str) -> Result<PreprocessedWgsl, PreprocessError> {
+        self.process(shader, &normalize(path.as_ref()), source)
+    }
+
+    fn process(&self, shader: &str, path: &Path, source: &str) -> Result<PreprocessedWgsl, PreprocessError> {
+        let mut expansion = Expansion {
+            preprocessor: self,
+            shader,
+            defines: self.defines.clone(),
+            included: HashSet::from([path.to_path_buf()]),
+            output: String::new(),
+            source_map: SourceMap::default(),
+        };
+        expansion.expand(path, source)?;
+        Ok(PreprocessedWgsl {
+            source: expansion.output,
+            source_map: expansion.source_map,
+        })
+    }
+
+    /// Find an included file, returning its path and contents
+    fn resolve(&self, name: &str, directory: Option<&Path>) -> Option<(PathBuf, String)> {
+        let candidates = directory.into_iter().chain(self.search_paths.iter().map(PathBuf::as_path));
+        for candidate in candidates.map(|directory| normalize(&directory.join(name))) {
+            if let Some(source) = self.read(&candidate) {
+                return Some((candidate, source));
+            }
+        }
+
+        LIBRARY.iter()
+            .find(|(library_name, _)| *library_name == name)
+            .map(|(library_name, source)| (PathBuf::from(format!("<rustica>/{}", library_name)), source.to_string()))
+    }
+
+    fn read(&self, path: &Path) -> Option<String> {
+        self.embedded.get(path).cloned().or_else(|| fs::read_to_string(path).ok())
+    }
+}
+
+/// An `#ifdef` or `#ifndef` block being expanded
+struct Condition {
+    active: bool,
+    has_else: bool,
+    line: u32,
+}
+
+/// State of one expansion, shared by the root file and its includes
+struct Expansion<'a> {
+    preprocessor: &'a WgslPreprocessor,
+    shader: &'a str,
+    defines: BTreeMap<String, String>,
+    included: HashSet<PathBuf>,
+    output: String,
+    source_map: SourceMap,
+}
+
+impl Expansion<'_> {
+    fn expand(&mut self, path: &Path, source: &str) -> Result<(), PreprocessError> {
+        let file = path.display().to_string();
+        let file_index = self.source_map.files.len();
+        self.source_map.files.push(file.clone());
+        let shader = self.shader;
+        let error = |line: u32, message: String| {
+            let mut error = PreprocessError::new(shader, Some(&file), message);
+            error.line = Some(line);
+            error
+        };
+
+        let mut conditions: Vec<Condition> = Vec::new();
+        for (index, text) in source.lines().enumerate() {
+            let line = index as u32 + 1;
+            let active = conditions.iter().all(|condition| condition.active);
+
+            let Some(directive) = text.trim_start().strip_prefix('#') else {
+                if active {
+                    self.output.push_str(&self.substitute(text));
+                    self.output.push('\n');
+                    self.source_map.lines.push((file_index, line));
+                }
+                continue;
+            };
+
+            let (keyword, argument) = directive.split_once(char::is_whitespace).unwrap_or((directive, ""));
+            let argument = argument.trim();
+            match keyword {
+                "ifdef" | "ifndef" => {
+                    let defined = self.defines.contains_key(argument);
+                    conditions.push(Condition { active: defined == (keyword == "ifdef"), has_else: false, line });
+                }
+                "else" => {
+                    let condition = conditions.last_mut().ok_or_else(|| error(line, "#else without #ifdef".to_string()))?;
+                    if condition.has_else {
+                        return Err(error(line, "#else appears twice in one block".to_string()));
+                    }
+                    condition.active = !condition.active;
+                    condition.has_else = true;
+                }
+                "endif" => {
+                    conditions.pop().ok_or_else(|| error(line, "#endif without #ifdef".to_string()))?;
+                }
+                _ if !active => {}
+                "define" => {
+                    let (name, value) = argument.split_once(char::is_whitespace).unwrap_or((argument, ""));
+                    if name.is_empty() {
+                        return Err(error(line, "#define needs a name".to_string()));
+                    }
+                    self.defines.insert(name.to_string(), value.trim().to_string());
+                }
+                "undef" => {
+                    self.defines.remove(argument);
+                }
+                "include" => {
+                    let name = argument.strip_prefix('"').and_then(|name| name.strip_suffix('"'))
+                        .ok_or_else(|| error(line, format!("expected a quoted file name after #include, found '{}'", argument)))?;
+                    let (included, source) = self.preprocessor.resolve(name, path.parent())
+                        .ok_or_else(|| error(line, format!("can't find included file '{}'", name)))?;
+                    // Each file is included once, which also breaks include cycles
+                    if self.included.insert(included.clone()) {
+                        self.expand(&included, &source)?;
+                    }
+                }
+                _ => return Err(error(line, format!("unknown directive #{}", keyword))),
+            }
+        }
+
+        match conditions.last() {
+            Some(condition) => Err(error(condition.line, "#ifdef without #endif".to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// Replace identifiers that have a defined value
+    fn substitute(&self, text: &str) -> String {
+        if self.defines.values().all(String::is_empty) {
+            return text.to_string();
+        }
+
+        let mut output = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find(|c: char| c.is_alphabetic() || c == '_') {
+            output.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let end = rest.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len());
+            let identifier = &rest[..end];
+            match self.defines.get(identifier) {
+                Some(value) if !value.is_empty() => output.push_str(value),
+                _ => output.push_str(identifier),
+            }
+            rest = &rest[end..];
+        }
+        output.push_str(rest);
+        output
+    }
+}
+
+/// Remove `.` and `..` components so the same file always has the same path
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if matches!(normalized.components().next_back(), Some(Component::Normal(_))) => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preprocessor() -> WgslPreprocessor {
+        WgslPreprocessor::new()
+            .embed("/shaders/main.wgsl", "#include \"common.wgsl\"\n#ifdef FANCY\nfn fancy() {}\n#else\nfn plain() {}\n#endif\nfn main() {}\n")
+            .embed("/shaders/common.wgsl", "#include \"main.wgsl\"\nconst SCALE: f32 = FACTOR;\n")
+            .with_define("FACTOR", "2.0")
+    }
+
+    #[test]
+    fn test_include_define_and_ifdef() {
+        let plain = preprocessor().process_file("/shaders/main.wgsl").unwrap();
+        assert_eq!(plain.source, "const SCALE: f32 = 2.0;\nfn plain() {}\nfn main() {}\n");
+
+        let fancy = preprocessor().with_define("FANCY", "").process_file("/shaders/./main.wgsl").unwrap();
+        assert_eq!(fancy.source, "const SCALE: f32 = 2.0;\nfn fancy() {}\nfn main() {}\n");
+    }
+
+    #[test]
+    fn test_source_map_points_at_original_lines() {
+        let preprocessed = preprocessor().process_file("/shaders/main.wgsl").unwrap();
+        let map = &preprocessed.source_map;
+        assert_eq!(map.files(), ["/shaders/main.wgsl", "/shaders/common.wgsl"]);
+        assert_eq!(map.locate(1), Some(("/shaders/common.wgsl", 2)));
+        assert_eq!(map.locate(2), Some(("/shaders/main.wgsl", 5)));
+        assert_eq!(map.locate(3), Some(("/shaders/main.wgsl", 7)));
+        assert_eq!(map.locate(4), None);
+    }
+
+    #[test]
+    fn test_library_include_and_errors() {
+        let lit = WgslPreprocessor::new().process_source("Lit", "lit.wgsl", "#include \"lighting.wgsl\"\n").unwrap();
+        assert!(lit.source.contains("fn lambert("));
+
+        let error = WgslPreprocessor::new().process_source("Broken", "broken.wgsl", "\n#ifdef A\n").unwrap_err();
+        assert_eq!((error.file.as_deref(), error.line), (Some("broken.wgsl"), Some(2)));
+        let error = WgslPreprocessor::new().process_source("Missing", "missing.wgsl", "#include \"nowhere.wgsl\"\n").unwrap_err();
+        assert!(error.to_string().contains("nowhere.wgsl"), "{}", error);
+    }
+}
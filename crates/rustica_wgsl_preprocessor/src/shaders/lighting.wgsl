@@ -0,0 +1,13 @@
+// Lighting functions shared by the engine's shaders
+// Include with: #include "lighting.wgsl"
+
+// Diffuse (Lambert) term of a light, with normalized vectors
+fn lambert(normal: vec3<f32>, light_dir: vec3<f32>) -> f32 {
+    return max(dot(normal, light_dir), 0.0);
+}
+
+// Specular (Phong) term of a light seen from view_dir, with normalized vectors
+fn phong_specular(normal: vec3<f32>, light_dir: vec3<f32>, view_dir: vec3<f32>, shininess: f32) -> f32 {
+    let reflect_dir = reflect(-light_dir, normal);
+    return pow(max(dot(view_dir, reflect_dir), 0.0), shininess);
+}
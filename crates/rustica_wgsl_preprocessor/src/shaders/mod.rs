@@ -0,0 +1,10 @@
+// Library of WGSL snippets shipped with the engine
+
+// Shaders are created through the custom shader API with ShaderDescriptor;
+// each prototype defines its own vertex types and shaders. The engine only
+// ships a library of WGSL snippets that shaders can `#include`.
+
+/// Files that `#include` finds when no file of that name exists on disk
+pub(crate) const LIBRARY: &[(&str, &str)] = &[
+    ("lighting.wgsl", include_str!("lighting.wgsl")),
+];